@@ -1,79 +1,32 @@
 use std::net::Ipv4Addr;
 
+/// 格式为 网段/掩码位数,网关 例如 192.168.0.0/24,10.26.0.3，解析规则和vnt的配置一致
 pub fn ips_parse(ips: &Vec<String>) -> Result<Vec<(u32, u32, Ipv4Addr)>, String> {
     let mut in_ips_c = vec![];
     for x in ips {
-        let mut split = x.split(",");
-        let net = if let Some(net) = split.next() {
-            net
-        } else {
-            return Err("ipv4/mask,ipv4".to_string());
-        };
-        let ip = if let Some(ip) = split.next() {
-            ip
-        } else {
-            return Err("ipv4/mask,ipv4".to_string());
-        };
-        let ip = if let Ok(ip) = ip.parse::<Ipv4Addr>() {
-            ip
-        } else {
-            return Err("not ipv4".to_string());
-        };
-        let mut split = net.split("/");
-        let dest = if let Some(dest) = split.next() {
-            dest
-        } else {
-            return Err("no ipv4/mask".to_string());
-        };
-        let mask = if let Some(mask) = split.next() {
-            mask
-        } else {
-            return Err("no netmask".to_string());
-        };
-        let dest = if let Ok(dest) = dest.parse::<Ipv4Addr>() {
-            dest
-        } else {
-            return Err("not ipv4".to_string());
-        };
-        let mask = to_ip(mask)?;
-        in_ips_c.push((u32::from_be_bytes(dest.octets()), mask, ip));
+        match vnt::core::parse_in_ip(x) {
+            Some(v) => in_ips_c.push(v),
+            None => return Err(format!("'{}' is not ipv4/mask,ipv4", x)),
+        }
     }
     Ok(in_ips_c)
 }
 
+/// 格式为 网段/掩码位数 例如 192.168.0.0/24
 pub fn out_ips_parse(ips: &Vec<String>) -> Result<Vec<(u32, u32)>, String> {
     let mut in_ips_c = vec![];
     for x in ips {
-        let mut split = x.split("/");
-        let dest = if let Some(dest) = split.next() {
-            dest
-        } else {
-            return Err("no ipv4/mask".to_string());
-        };
-        let mask = if let Some(mask) = split.next() {
-            mask
-        } else {
-            return Err("no netmask".to_string());
-        };
-        let dest = if let Ok(dest) = dest.parse::<Ipv4Addr>() {
-            dest
-        } else {
-            return Err("not ipv4".to_string());
-        };
-        let mask = to_ip(mask)?;
-        in_ips_c.push((u32::from_be_bytes(dest.octets()), mask));
+        match vnt::core::parse_out_ip(x) {
+            Some(v) => in_ips_c.push(v),
+            None => return Err(format!("'{}' is not ipv4/mask", x)),
+        }
     }
     Ok(in_ips_c)
 }
 
+/// 掩码位数转换为掩码
 pub fn to_ip(mask: &str) -> Result<u32, String> {
-    if let Ok(m) = mask.parse::<u32>() {
-        let mut mask = 0 as u32;
-        for i in 0..m {
-            mask = mask | (1 << (31 - i));
-        }
-        Ok(mask)
-    } else {
-        Err("not netmask".to_string())
-    }
+    vnt::core::parse_out_ip(&format!("0.0.0.0/{}", mask))
+        .map(|(_, mask)| mask)
+        .ok_or_else(|| "not netmask".to_string())
 }
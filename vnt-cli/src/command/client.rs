@@ -1,81 +1,154 @@
 use std::io;
-use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4, UdpSocket};
-use std::str::FromStr;
-use std::time::Duration;
+use std::io::{BufRead, BufReader, Write};
 
-use crate::command::entity::{DeviceItem, Info, RouteItem};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+
+use crate::command::entity::{DeviceItem, EventItem, Info, RouteItem};
+use crate::command::rpc::*;
+
+#[cfg(unix)]
+type Stream = std::os::unix::net::UnixStream;
+#[cfg(windows)]
+type Stream = std::fs::File;
 
 pub struct CommandClient {
-    udp: UdpSocket,
+    reader: BufReader<Stream>,
+    writer: Stream,
+    id: u64,
 }
 
 impl CommandClient {
+    #[cfg(unix)]
     pub fn new() -> io::Result<Self> {
-        let path_buf = crate::app_home()?.join("command-port");
+        let path_buf = crate::app_home()?.join(SOCKET_NAME);
         if !path_buf.exists() {
             return Err(io::Error::new(io::ErrorKind::Other, "not started"));
         }
-        let port = std::fs::read_to_string(path_buf)?;
-        let port = match u16::from_str(&port) {
-            Ok(port) => port,
-            Err(_) => {
+        let stream = Stream::connect(path_buf)?;
+        stream.set_read_timeout(Some(std::time::Duration::from_secs(2)))?;
+        Self::with_stream(stream)
+    }
+    #[cfg(windows)]
+    pub fn new() -> io::Result<Self> {
+        let stream = match std::fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(PIPE_NAME)
+        {
+            Ok(stream) => stream,
+            Err(e) => {
                 return Err(io::Error::new(
                     io::ErrorKind::Other,
-                    "'command-port' file error",
+                    format!("not started {}", e),
                 ));
             }
         };
-        let udp = UdpSocket::bind("127.0.0.1:0")?;
-        udp.set_read_timeout(Some(Duration::from_secs(2)))?;
-        udp.connect(SocketAddr::V4(SocketAddrV4::new(
-            Ipv4Addr::new(127, 0, 0, 1),
-            port,
-        )))?;
-        Ok(Self { udp })
+        Self::with_stream(stream)
+    }
+    fn with_stream(stream: Stream) -> io::Result<Self> {
+        let writer = stream.try_clone()?;
+        Ok(Self {
+            reader: BufReader::new(stream),
+            writer,
+            id: 0,
+        })
     }
 }
 
 impl CommandClient {
-    pub fn list(&self) -> io::Result<Vec<DeviceItem>> {
-        self.udp.send(b"list")?;
-        let mut buf = [0; 10240];
-        let len = self.udp.recv(&mut buf)?;
-        match serde_json::from_slice::<Vec<DeviceItem>>(&buf[..len]) {
-            Ok(val) => Ok(val),
-            Err(e) => {
-                log::error!("{:?}", e);
-                Err(io::Error::new(io::ErrorKind::Other, "data error"))
+    pub fn list(&mut self) -> io::Result<Vec<DeviceItem>> {
+        self.call_stream(METHOD_LIST)
+    }
+    pub fn route(&mut self) -> io::Result<Vec<RouteItem>> {
+        self.call_stream(METHOD_ROUTE)
+    }
+    pub fn info(&mut self) -> io::Result<Info> {
+        let mut list = self.call(METHOD_INFO, Value::Null)?;
+        Self::parse(list.pop().unwrap_or(Value::Null))
+    }
+    pub fn stop(&mut self) -> io::Result<String> {
+        let mut list = self.call(METHOD_STOP, Value::Null)?;
+        Self::parse(list.pop().unwrap_or(Value::Null))
+    }
+    /// 订阅事件，每收到一个事件调用一次f，直到后台停止
+    pub fn subscribe<F: FnMut(EventItem)>(&mut self, mut f: F) -> io::Result<()> {
+        #[cfg(unix)]
+        self.reader.get_ref().set_read_timeout(None)?;
+        self.send(METHOD_SUBSCRIBE, Value::Null)?;
+        loop {
+            let response = self.read()?;
+            match response.result {
+                Some(val) if !val.is_null() => f(Self::parse(val)?),
+                _ => {}
+            }
+            if !response.more {
+                return Ok(());
             }
         }
     }
-    pub fn route(&self) -> io::Result<Vec<RouteItem>> {
-        self.udp.send(b"route")?;
-        let mut buf = [0; 10240];
-        let len = self.udp.recv(&mut buf)?;
-        match serde_json::from_slice::<Vec<RouteItem>>(&buf[..len]) {
-            Ok(val) => Ok(val),
+    fn call_stream<T: DeserializeOwned>(&mut self, method: &str) -> io::Result<Vec<T>> {
+        let list = self.call(method, json!({ "stream": true }))?;
+        let mut rs = Vec::with_capacity(list.len());
+        for val in list {
+            //最后一条是结束标记
+            if !val.is_null() {
+                rs.push(Self::parse(val)?);
+            }
+        }
+        Ok(rs)
+    }
+    /// 发送请求并读取所有响应，流式响应会返回多条
+    fn call(&mut self, method: &str, params: Value) -> io::Result<Vec<Value>> {
+        self.send(method, params)?;
+        let mut results = Vec::new();
+        loop {
+            let response = self.read()?;
+            results.push(response.result.unwrap_or(Value::Null));
+            if !response.more {
+                return Ok(results);
+            }
+        }
+    }
+    fn send(&mut self, method: &str, params: Value) -> io::Result<()> {
+        self.id += 1;
+        let request = Request::new(self.id, method, params);
+        let mut buf = match serde_json::to_vec(&request) {
+            Ok(buf) => buf,
+            Err(e) => return Err(io::Error::new(io::ErrorKind::Other, format!("{:?}", e))),
+        };
+        buf.push(b'\n');
+        self.writer.write_all(&buf)?;
+        self.writer.flush()
+    }
+    /// 读取一条响应，错误响应转换为io::Error
+    fn read(&mut self) -> io::Result<Response> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "closed"));
+        }
+        let response = match serde_json::from_str::<Response>(&line) {
+            Ok(response) => response,
             Err(e) => {
-                log::error!("{:?}", e);
-                Err(io::Error::new(io::ErrorKind::Other, "data error"))
+                log::error!("{:?},{:?}", line, e);
+                return Err(io::Error::new(io::ErrorKind::Other, "data error"));
             }
+        };
+        if let Some(error) = response.error {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!("{}({})", error.message, error.code),
+            ));
         }
+        Ok(response)
     }
-    pub fn info(&self) -> io::Result<Info> {
-        self.udp.send(b"info")?;
-        let mut buf = [0; 10240];
-        let len = self.udp.recv(&mut buf)?;
-        match serde_json::from_slice::<Info>(&buf[..len]) {
+    fn parse<T: DeserializeOwned>(val: Value) -> io::Result<T> {
+        match serde_json::from_value::<T>(val) {
             Ok(val) => Ok(val),
             Err(e) => {
-                log::error!("{:?},{:?}", &buf[..len], e);
+                log::error!("{:?}", e);
                 Err(io::Error::new(io::ErrorKind::Other, "data error"))
             }
         }
     }
-    pub fn stop(&self) -> io::Result<String> {
-        self.udp.send(b"stop")?;
-        let mut buf = [0; 10240];
-        let len = self.udp.recv(&mut buf)?;
-        Ok(String::from_utf8(buf[..len].to_vec()).unwrap())
-    }
 }
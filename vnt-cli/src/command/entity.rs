@@ -3,14 +3,18 @@ use serde::{Deserialize, Serialize};
 pub struct Info {
     pub name: String,
     pub virtual_ip: String,
+    pub virtual_ipv6: String,
     pub virtual_gateway: String,
     pub virtual_netmask: String,
     pub connect_status: String,
     pub relay_server: String,
     pub nat_type: String,
+    pub nat_behavior: String,
     pub public_ips: String,
     pub local_addr: String,
     pub ipv6_addr: String,
+    pub mapped_addr: String,
+    pub replay_drop: String,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -19,7 +23,10 @@ pub struct RouteItem {
     pub next_hop: String,
     pub metric: String,
     pub rt: String,
+    pub mtu: String,
     pub interface: String,
+    pub tx: String,
+    pub rx: String,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -35,4 +42,37 @@ pub struct DeviceItem {
     pub status: String,
     pub client_secret: bool,
     pub current_client_secret: bool,
+    pub tx: String,
+    pub rx: String,
+    pub decrypt_failures: String,
+    pub drops: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct EventItem {
+    pub event: String,
+    pub virtual_ip: String,
+    pub detail: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct StatsItem {
+    /// 对端虚拟ip或者通道地址
+    pub key: String,
+    pub tx_bytes: u64,
+    pub tx_packets: u64,
+    pub rx_bytes: u64,
+    pub rx_packets: u64,
+    pub decrypt_failures: u64,
+    pub drops: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct StatsInfo {
+    pub punch_attempts: u64,
+    pub punch_packets: u64,
+    pub birthday_attempts: u64,
+    pub birthday_successes: u64,
+    pub peers: Vec<StatsItem>,
+    pub routes: Vec<StatsItem>,
 }
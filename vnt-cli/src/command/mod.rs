@@ -1,10 +1,13 @@
-use crate::command::entity::{DeviceItem, Info, RouteItem};
+use crate::command::entity::{DeviceItem, EventItem, Info, RouteItem, StatsInfo, StatsItem};
 use crate::console_out;
 use std::io;
+use vnt::channel::stats::TrafficStats;
+use vnt::core::event::VntEvent;
 use vnt::core::Vnt;
 
 pub mod client;
 pub mod entity;
+pub mod rpc;
 pub mod server;
 
 pub enum CommandEnum {
@@ -13,6 +16,7 @@ pub enum CommandEnum {
     All,
     Info,
     Stop,
+    Events,
 }
 
 pub fn command(cmd: CommandEnum) {
@@ -22,7 +26,7 @@ pub fn command(cmd: CommandEnum) {
 }
 
 fn command_(cmd: CommandEnum) -> io::Result<()> {
-    let command_client = client::CommandClient::new()?;
+    let mut command_client = client::CommandClient::new()?;
     match cmd {
         CommandEnum::Route => {
             let list = command_client.route()?;
@@ -43,12 +47,16 @@ fn command_(cmd: CommandEnum) -> io::Result<()> {
         CommandEnum::Stop => {
             command_client.stop()?;
         }
+        CommandEnum::Events => {
+            command_client.subscribe(console_out::console_event)?;
+        }
     }
     Ok(())
 }
 
 pub fn command_route(vnt: &Vnt) -> Vec<RouteItem> {
     let route_table = vnt.route_table();
+    let stats = vnt.stats();
     let mut route_list = Vec::with_capacity(route_table.len());
     for (destination, route) in route_table {
         let next_hop = vnt
@@ -60,13 +68,28 @@ pub fn command_route(vnt: &Vnt) -> Vec<RouteItem> {
         } else {
             route.rt.to_string()
         };
-        let interface = route.addr.to_string();
+        let mtu = if route.mtu == 0 {
+            "".to_string()
+        } else {
+            route.mtu.to_string()
+        };
+        let interface = if route.protocol().is_tcp() {
+            format!("tcp:{}", route.addr)
+        } else if route.protocol().is_quic() {
+            format!("quic:{}", route.addr)
+        } else {
+            route.addr.to_string()
+        };
+        let traffic = stats.route(&route.route_key()).unwrap_or_default();
         let item = RouteItem {
             destination: destination.to_string(),
             next_hop,
             metric,
             rt,
+            mtu,
             interface,
+            tx: format_bytes(traffic.tx_bytes),
+            rx: format_bytes(traffic.rx_bytes),
         };
         route_list.push(item);
     }
@@ -78,6 +101,7 @@ pub fn command_list(vnt: &Vnt) -> Vec<DeviceItem> {
     let device_list = vnt.device_list();
     let mut list = Vec::new();
     let current_client_secret = vnt.client_encrypt();
+    let stats = vnt.stats();
     for peer in device_list {
         let name = peer.name;
         let virtual_ip = peer.virtual_ip.to_string();
@@ -118,6 +142,7 @@ pub fn command_list(vnt: &Vnt) -> Vec<DeviceItem> {
         };
         let status = format!("{:?}", peer.status);
         let client_secret = peer.client_secret;
+        let traffic = stats.peer(&peer.virtual_ip).unwrap_or_default();
         let item = DeviceItem {
             name,
             virtual_ip,
@@ -130,6 +155,10 @@ pub fn command_list(vnt: &Vnt) -> Vec<DeviceItem> {
             status,
             client_secret,
             current_client_secret,
+            tx: format_bytes(traffic.tx_bytes),
+            rx: format_bytes(traffic.rx_bytes),
+            decrypt_failures: traffic.decrypt_failures.to_string(),
+            drops: traffic.drops.to_string(),
         };
         list.push(item);
     }
@@ -141,11 +170,25 @@ pub fn command_info(vnt: &Vnt) -> Info {
     let nat_info = vnt.nat_info();
     let name = vnt.name().to_string();
     let virtual_ip = current_device.virtual_ip().to_string();
+    let virtual_ipv6 = if let Some(ipv6) = current_device.virtual_ipv6() {
+        format!("{}/{}", ipv6, current_device.virtual_ipv6_prefix)
+    } else {
+        "None".to_string()
+    };
     let virtual_gateway = current_device.virtual_gateway().to_string();
     let virtual_netmask = current_device.virtual_netmask.to_string();
     let connect_status = format!("{:?}", vnt.connection_status());
     let relay_server = current_device.connect_server.to_string();
     let nat_type = format!("{:?}", nat_info.nat_type);
+    let behavior = nat_info.behavior;
+    let nat_behavior = format!(
+        "mapping:{:?},filtering:{:?},hairpin:{},port:{:?}({})",
+        behavior.mapping,
+        behavior.filtering,
+        behavior.hairpin,
+        behavior.port_allocation,
+        behavior.port_step
+    );
     let public_ips: Vec<String> = nat_info.public_ips.iter().map(|v| v.to_string()).collect();
     let public_ips = public_ips.join(",");
     let local_addr = nat_info.local_ipv4_addr.to_string();
@@ -154,16 +197,103 @@ pub fn command_info(vnt: &Vnt) -> Info {
     } else {
         nat_info.ipv6_addr.ip().to_string()
     };
+    let mapped_addr = nat_info
+        .mapped_addr
+        .map_or("None".to_string(), |v| v.to_string());
+    let replay_drop = vnt.replay_drop_count().to_string();
     Info {
         name,
         virtual_ip,
+        virtual_ipv6,
         virtual_gateway,
         virtual_netmask,
         connect_status,
         relay_server,
         nat_type,
+        nat_behavior,
         public_ips,
         local_addr,
         ipv6_addr,
+        mapped_addr,
+        replay_drop,
+    }
+}
+
+pub fn command_stats(vnt: &Vnt) -> StatsInfo {
+    let stats = vnt.stats();
+    let peers = stats
+        .peers
+        .iter()
+        .map(|(ip, traffic)| stats_item(ip.to_string(), traffic))
+        .collect();
+    let routes = stats
+        .routes
+        .iter()
+        .map(|(route_key, traffic)| stats_item(route_key.addr.to_string(), traffic))
+        .collect();
+    StatsInfo {
+        punch_attempts: stats.punch_attempts,
+        punch_packets: stats.punch_packets,
+        birthday_attempts: stats.birthday_attempts,
+        birthday_successes: stats.birthday_successes,
+        peers,
+        routes,
+    }
+}
+
+fn stats_item(key: String, traffic: &TrafficStats) -> StatsItem {
+    StatsItem {
+        key,
+        tx_bytes: traffic.tx_bytes,
+        tx_packets: traffic.tx_packets,
+        rx_bytes: traffic.rx_bytes,
+        rx_packets: traffic.rx_packets,
+        decrypt_failures: traffic.decrypt_failures,
+        drops: traffic.drops,
+    }
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut value = bytes as f64;
+    let mut index = 0;
+    while value >= 1024.0 && index < UNITS.len() - 1 {
+        value /= 1024.0;
+        index += 1;
+    }
+    if index == 0 {
+        format!("{}{}", bytes, UNITS[0])
+    } else {
+        format!("{:.2}{}", value, UNITS[index])
+    }
+}
+
+pub fn event_item(event: VntEvent) -> EventItem {
+    let (event, virtual_ip, detail) = match event {
+        VntEvent::ConnectStatus(status) => ("connect_status", None, format!("{:?}", status)),
+        VntEvent::PeerOnline(peer) => ("peer_online", Some(peer.virtual_ip), peer.name),
+        VntEvent::PeerOffline(peer) => ("peer_offline", Some(peer.virtual_ip), peer.name),
+        VntEvent::RouteAdd(ip, route) => (
+            "route_add",
+            Some(ip),
+            format!("{},metric:{}", route.addr, route.metric),
+        ),
+        VntEvent::RouteRemove(ip, route_key) => {
+            ("route_remove", Some(ip), route_key.addr.to_string())
+        }
+        VntEvent::NatChange(nat_info) => {
+            let public_ips: Vec<String> =
+                nat_info.public_ips.iter().map(|v| v.to_string()).collect();
+            (
+                "nat_change",
+                None,
+                format!("{:?},{}", nat_info.nat_type, public_ips.join(",")),
+            )
+        }
+    };
+    EventItem {
+        event: event.to_string(),
+        virtual_ip: virtual_ip.map_or(String::new(), |v| v.to_string()),
+        detail,
     }
 }
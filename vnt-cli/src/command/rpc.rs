@@ -0,0 +1,117 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/* 本地控制接口协议，JSON-RPC 2.0，每条消息一行
+   请求 {"jsonrpc":"2.0","id":1,"method":"v1.list","params":{"stream":true}}
+   响应 {"jsonrpc":"2.0","id":1,"result":...} 或者 {"jsonrpc":"2.0","id":1,"error":{"code":-32601,"message":"..."}}
+   流式响应：同一个id连续返回多条，more=true表示后面还有数据，最后一条more=false
+*/
+
+pub const JSONRPC_VERSION: &str = "2.0";
+/// 接口版本，方法名使用"v1."前缀，不兼容修改时增加版本
+pub const API_VERSION: u32 = 1;
+
+pub const METHOD_VERSION: &str = "version";
+pub const METHOD_LIST: &str = "v1.list";
+pub const METHOD_ROUTE: &str = "v1.route";
+pub const METHOD_INFO: &str = "v1.info";
+pub const METHOD_STOP: &str = "v1.stop";
+pub const METHOD_STATS: &str = "v1.stats";
+/// 订阅事件，持续流式返回，直到连接断开或者vnt停止
+pub const METHOD_SUBSCRIBE: &str = "v1.subscribe";
+
+pub const PARSE_ERROR: i32 = -32700;
+pub const INVALID_REQUEST: i32 = -32600;
+pub const METHOD_NOT_FOUND: i32 = -32601;
+pub const INTERNAL_ERROR: i32 = -32603;
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Request {
+    pub jsonrpc: String,
+    #[serde(default)]
+    pub id: Value,
+    pub method: String,
+    #[serde(default, skip_serializing_if = "Value::is_null")]
+    pub params: Value,
+}
+
+impl Request {
+    pub fn new(id: u64, method: &str, params: Value) -> Self {
+        Self {
+            jsonrpc: JSONRPC_VERSION.to_string(),
+            id: Value::from(id),
+            method: method.to_string(),
+            params,
+        }
+    }
+    /// 请求流式返回，列表的每一项单独响应
+    pub fn is_stream(&self) -> bool {
+        self.params
+            .get("stream")
+            .and_then(|v| v.as_bool())
+            .unwrap_or(false)
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct RpcError {
+    pub code: i32,
+    pub message: String,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct Response {
+    pub jsonrpc: String,
+    pub id: Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub result: Option<Value>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<RpcError>,
+    #[serde(default, skip_serializing_if = "is_false")]
+    pub more: bool,
+}
+
+fn is_false(v: &bool) -> bool {
+    !*v
+}
+
+impl Response {
+    pub fn ok(id: Value, result: Value) -> Self {
+        Self {
+            jsonrpc: JSONRPC_VERSION.to_string(),
+            id,
+            result: Some(result),
+            error: None,
+            more: false,
+        }
+    }
+    /// 流式响应中的一条
+    pub fn part(id: Value, result: Value) -> Self {
+        Self {
+            more: true,
+            ..Self::ok(id, result)
+        }
+    }
+    pub fn err(id: Value, code: i32, message: String) -> Self {
+        Self {
+            jsonrpc: JSONRPC_VERSION.to_string(),
+            id,
+            result: None,
+            error: Some(RpcError { code, message }),
+            more: false,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct VersionInfo {
+    pub api_version: u32,
+    pub vnt_version: String,
+}
+
+/// 本地控制接口的unix socket，位于app_home下，只有当前用户可以访问
+#[cfg(unix)]
+pub const SOCKET_NAME: &str = "command.sock";
+/// 本地控制接口的命名管道，拒绝远程连接
+#[cfg(windows)]
+pub const PIPE_NAME: &str = r"\\.\pipe\vnt-cli-command";
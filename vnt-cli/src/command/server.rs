@@ -1,9 +1,15 @@
 use std::io;
-use std::io::Write;
-use tokio::net::UdpSocket;
 
+use serde::Serialize;
+use serde_json::Value;
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader};
+use tokio::sync::broadcast::error::RecvError;
+
+use vnt::core::event::EventReceiver;
 use vnt::core::Vnt;
 
+use crate::command::rpc::*;
+
 pub struct CommandServer {}
 
 impl CommandServer {
@@ -13,60 +19,271 @@ impl CommandServer {
 }
 
 impl CommandServer {
+    #[cfg(unix)]
     pub async fn start(self, vnt: Vnt) -> io::Result<()> {
-        let udp = UdpSocket::bind("127.0.0.1:0").await?;
-        let path_buf = crate::app_home()?.join("command-port");
-        let mut file = std::fs::File::create(path_buf)?;
-        file.write_all(udp.local_addr()?.port().to_string().as_bytes())?;
-        file.sync_all()?;
-        let mut buf = [0u8; 64];
+        use std::os::unix::fs::PermissionsExt;
+        let home = crate::app_home()?;
+        //目录和socket都只允许当前用户访问，避免其他本地用户控制vpn
+        std::fs::set_permissions(&home, std::fs::Permissions::from_mode(0o700))?;
+        let path_buf = home.join(SOCKET_NAME);
+        if path_buf.exists() {
+            std::fs::remove_file(&path_buf)?;
+        }
+        let listener = tokio::net::UnixListener::bind(&path_buf)?;
+        std::fs::set_permissions(&path_buf, std::fs::Permissions::from_mode(0o600))?;
         loop {
-            let (len, addr) = udp.recv_from(&mut buf).await?;
-            match std::str::from_utf8(&buf[..len]) {
-                Ok(cmd) => {
-                    if let Ok(out) = command(cmd, &vnt) {
-                        let _ = udp.send_to(out.as_bytes(), addr).await;
-                        if "stopped" == &out {
-                            break;
-                        }
-                    }
+            let (stream, _) = listener.accept().await?;
+            let vnt = vnt.clone();
+            tokio::spawn(async move {
+                if let Err(e) = handle(stream, vnt).await {
+                    log::warn!("command connection {:?}", e);
                 }
-                Err(e) => {
-                    log::warn!("{:?}", e);
+            });
+        }
+    }
+    #[cfg(windows)]
+    pub async fn start(self, vnt: Vnt) -> io::Result<()> {
+        use tokio::net::windows::named_pipe::ServerOptions;
+        let mut server = ServerOptions::new()
+            .first_pipe_instance(true)
+            .reject_remote_clients(true)
+            .create(PIPE_NAME)?;
+        loop {
+            server.connect().await?;
+            let stream = server;
+            server = ServerOptions::new()
+                .reject_remote_clients(true)
+                .create(PIPE_NAME)?;
+            let vnt = vnt.clone();
+            tokio::spawn(async move {
+                if let Err(e) = handle(stream, vnt).await {
+                    log::warn!("command connection {:?}", e);
                 }
+            });
+        }
+    }
+}
+
+async fn handle<S: AsyncRead + AsyncWrite + Unpin>(stream: S, vnt: Vnt) -> io::Result<()> {
+    let (read, mut write) = tokio::io::split(stream);
+    let mut lines = BufReader::new(read).lines();
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let request = match parse_request(&line) {
+            Ok(request) => request,
+            Err(response) => {
+                send(&mut write, &response).await?;
+                continue;
             }
+        };
+        if request.method == METHOD_SUBSCRIBE {
+            //订阅后连接只用于推送事件
+            return subscribe(&mut write, request.id, vnt.subscribe()).await;
+        }
+        for response in dispatch(&request, &vnt) {
+            send(&mut write, &response).await?;
+        }
+        if request.method == METHOD_STOP {
+            break;
         }
-        Ok(())
     }
+    Ok(())
 }
 
-fn command(cmd: &str, vnt: &Vnt) -> io::Result<String> {
-    let out_str = match cmd {
-        "route" => match serde_json::to_string(&crate::command::command_route(vnt)) {
-            Ok(str) => str,
-            Err(e) => {
-                format!("{:?}", e)
+/// 解析一行请求，格式或者版本错误时返回错误响应
+fn parse_request(line: &str) -> Result<Request, Response> {
+    let request = match serde_json::from_str::<Request>(line) {
+        Ok(request) => request,
+        Err(e) => {
+            return Err(Response::err(Value::Null, PARSE_ERROR, format!("{}", e)));
+        }
+    };
+    if request.jsonrpc != JSONRPC_VERSION {
+        return Err(Response::err(
+            request.id,
+            INVALID_REQUEST,
+            format!("jsonrpc version '{}' not supported", request.jsonrpc),
+        ));
+    }
+    Ok(request)
+}
+
+async fn subscribe<W: AsyncWrite + Unpin>(
+    write: &mut W,
+    id: Value,
+    mut receiver: EventReceiver,
+) -> io::Result<()> {
+    send(write, &Response::part(id.clone(), Value::Null)).await?;
+    loop {
+        match receiver.recv().await {
+            Ok(event) => {
+                let item = crate::command::event_item(event);
+                let response = match serde_json::to_value(item) {
+                    Ok(val) => Response::part(id.clone(), val),
+                    Err(e) => Response::err(id.clone(), INTERNAL_ERROR, format!("{:?}", e)),
+                };
+                send(write, &response).await?;
             }
-        },
-        "list" => match serde_json::to_string(&crate::command::command_list(vnt)) {
-            Ok(str) => str,
-            Err(e) => {
-                format!("{:?}", e)
+            Err(RecvError::Lagged(n)) => {
+                log::warn!("事件推送太慢,丢失{}个事件", n);
+            }
+            Err(RecvError::Closed) => {
+                return send(write, &Response::ok(id, Value::Null)).await;
             }
+        }
+    }
+}
+
+async fn send<W: AsyncWrite + Unpin>(write: &mut W, response: &Response) -> io::Result<()> {
+    let mut buf = match serde_json::to_vec(response) {
+        Ok(buf) => buf,
+        Err(e) => return Err(io::Error::new(io::ErrorKind::Other, format!("{:?}", e))),
+    };
+    buf.push(b'\n');
+    write.write_all(&buf).await?;
+    write.flush().await
+}
+
+fn dispatch(request: &Request, vnt: &Vnt) -> Vec<Response> {
+    let id = request.id.clone();
+    match request.method.as_str() {
+        METHOD_VERSION => vec![result(
+            id,
+            &VersionInfo {
+                api_version: API_VERSION,
+                vnt_version: vnt::VNT_VERSION.to_string(),
+            },
+        )],
+        METHOD_LIST => list_result(id, crate::command::command_list(vnt), request.is_stream()),
+        METHOD_ROUTE => list_result(id, crate::command::command_route(vnt), request.is_stream()),
+        METHOD_INFO => vec![result(id, &crate::command::command_info(vnt))],
+        METHOD_STATS => vec![result(id, &crate::command::command_stats(vnt))],
+        METHOD_STOP => match vnt.stop() {
+            Ok(_) => vec![Response::ok(id, Value::from("stopped"))],
+            Err(e) => vec![Response::err(id, INTERNAL_ERROR, format!("{}", e))],
         },
-        "info" => match serde_json::to_string(&crate::command::command_info(vnt)) {
-            Ok(str) => str,
+        method => vec![method_not_found(id, method)],
+    }
+}
+
+fn method_not_found(id: Value, method: &str) -> Response {
+    let message = if method.starts_with('v') && !method.starts_with("v1.") {
+        format!(
+            "method '{}' not found, api version is v{}",
+            method, API_VERSION
+        )
+    } else {
+        format!("method '{}' not found", method)
+    };
+    Response::err(id, METHOD_NOT_FOUND, message)
+}
+
+fn result<T: Serialize>(id: Value, val: &T) -> Response {
+    match serde_json::to_value(val) {
+        Ok(val) => Response::ok(id, val),
+        Err(e) => Response::err(id, INTERNAL_ERROR, format!("{:?}", e)),
+    }
+}
+
+fn list_result<T: Serialize>(id: Value, list: Vec<T>, stream: bool) -> Vec<Response> {
+    if !stream {
+        return vec![result(id, &list)];
+    }
+    let mut responses = Vec::with_capacity(list.len() + 1);
+    for item in list {
+        match serde_json::to_value(item) {
+            Ok(val) => responses.push(Response::part(id.clone(), val)),
             Err(e) => {
-                format!("{:?}", e)
+                responses.push(Response::err(id, INTERNAL_ERROR, format!("{:?}", e)));
+                return responses;
             }
-        },
-        "stop" => {
-            vnt.stop()?;
-            "stopped".to_string()
-        }
-        _ => {
-            format!("command '{}' not found. \n Try to enter: 'help'\n", cmd)
         }
-    };
-    Ok(out_str)
+    }
+    responses.push(Response::ok(id, Value::Null));
+    responses
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn error_code(response: &Response) -> i32 {
+        response.error.as_ref().unwrap().code
+    }
+
+    #[test]
+    fn parse_error() {
+        let response = parse_request("{\"jsonrpc\":").unwrap_err();
+        assert_eq!(error_code(&response), PARSE_ERROR);
+        assert_eq!(response.id, Value::Null);
+        let response = parse_request("{\"jsonrpc\":\"2.0\",\"id\":1}").unwrap_err();
+        assert_eq!(error_code(&response), PARSE_ERROR);
+    }
+
+    #[test]
+    fn invalid_version() {
+        let response =
+            parse_request("{\"jsonrpc\":\"1.0\",\"id\":7,\"method\":\"v1.list\"}").unwrap_err();
+        assert_eq!(error_code(&response), INVALID_REQUEST);
+        assert_eq!(response.id, Value::from(7));
+    }
+
+    #[test]
+    fn parse_ok() {
+        let request = parse_request(
+            "{\"jsonrpc\":\"2.0\",\"id\":\"a\",\"method\":\"v1.route\",\"params\":{\"stream\":true}}",
+        )
+        .unwrap();
+        assert_eq!(request.id, Value::from("a"));
+        assert_eq!(request.method, METHOD_ROUTE);
+        assert!(request.is_stream());
+        let request = parse_request("{\"jsonrpc\":\"2.0\",\"method\":\"version\"}").unwrap();
+        assert_eq!(request.id, Value::Null);
+        assert!(!request.is_stream());
+    }
+
+    #[test]
+    fn not_found() {
+        let response = method_not_found(Value::from(1), "v1.unknown");
+        assert_eq!(error_code(&response), METHOD_NOT_FOUND);
+        assert!(!response.error.unwrap().message.contains("api version"));
+        // 不支持的接口版本提示当前版本
+        let response = method_not_found(Value::from(1), "v2.list");
+        assert!(response
+            .error
+            .unwrap()
+            .message
+            .contains("api version is v1"));
+    }
+
+    #[test]
+    fn list_stream() {
+        let responses = list_result(Value::from(3), vec![1, 2], true);
+        assert_eq!(responses.len(), 3);
+        assert!(responses[0].more && responses[1].more && !responses[2].more);
+        assert_eq!(responses[1].result, Some(Value::from(2)));
+        assert_eq!(responses[2].result, Some(Value::Null));
+        let responses = list_result(Value::from(3), vec![1, 2], false);
+        assert_eq!(responses.len(), 1);
+        assert!(!responses[0].more);
+        assert_eq!(responses[0].result, Some(serde_json::json!([1, 2])));
+    }
+
+    #[test]
+    fn response_json() {
+        let json =
+            serde_json::to_string(&Response::ok(Value::from(1), Value::from("stopped"))).unwrap();
+        assert_eq!(
+            json,
+            "{\"jsonrpc\":\"2.0\",\"id\":1,\"result\":\"stopped\"}"
+        );
+        let json = serde_json::to_string(&Response::part(Value::from(1), Value::Null)).unwrap();
+        assert!(json.contains("\"more\":true"));
+        let json =
+            serde_json::to_string(&Response::err(Value::Null, PARSE_ERROR, "e".into())).unwrap();
+        assert!(!json.contains("result"));
+        assert!(json.contains("-32700"));
+    }
 }
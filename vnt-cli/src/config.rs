@@ -0,0 +1,96 @@
+use std::net::SocketAddr;
+use std::path::Path;
+use std::str::FromStr;
+
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use vnt::core::{Config, ConfigBuilder};
+
+/// 只有命令行使用的字段，其余字段交给ConfigBuilder解析
+const CLI_FIELDS: [&str; 3] = ["thread", "disable_cmd", "metrics"];
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+struct CliOptions {
+    /// --thread
+    thread: Option<usize>,
+    /// -c
+    disable_cmd: bool,
+    /// --metrics
+    metrics: Option<String>,
+}
+
+/// 配置文件，根据扩展名使用toml或yaml解析，字段和命令行参数一一对应
+#[derive(Debug)]
+pub struct FileConfig {
+    builder: ConfigBuilder,
+    options: CliOptions,
+}
+
+impl FileConfig {
+    pub fn read(path: &str) -> Result<FileConfig, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("read config file '{}' failed: {}", path, e))?;
+        let extension = Path::new(path)
+            .extension()
+            .and_then(|v| v.to_str())
+            .unwrap_or("")
+            .to_lowercase();
+        let value = match extension.as_str() {
+            "toml" => toml::from_str::<Value>(&content)
+                .map_err(|e| format!("config file '{}' error: {}", path, e))?,
+            "yaml" | "yml" => serde_yaml::from_str::<Value>(&content)
+                .map_err(|e| format!("config file '{}' error: {}", path, e))?,
+            _ => {
+                return Err(format!(
+                    "config file '{}' error: unsupported extension, use .toml/.yaml/.yml",
+                    path
+                ))
+            }
+        };
+        let mut map = match value {
+            Value::Object(map) => map,
+            _ => return Err(format!("config file '{}' error: not a table", path)),
+        };
+        let mut cli = Map::new();
+        for key in CLI_FIELDS {
+            if let Some(v) = map.remove(key) {
+                cli.insert(key.to_string(), v);
+            }
+        }
+        // 和命令行参数保持一致的默认值
+        if !map.contains_key("device_id") {
+            map.insert("device_id".to_string(), crate::default_device_id().into());
+        }
+        if !map.contains_key("name") {
+            map.insert("name".to_string(), os_info::get().to_string().into());
+        }
+        let options = serde_json::from_value::<CliOptions>(Value::Object(cli))
+            .map_err(|e| format!("config file '{}' error: {}", path, e))?;
+        let builder = serde_json::from_value::<ConfigBuilder>(Value::Object(map))
+            .map_err(|e| format!("config file '{}' error: {}", path, e))?;
+        Ok(FileConfig { builder, options })
+    }
+    pub fn to_config(&self) -> Result<Config, String> {
+        self.builder.clone().build().map_err(|e| e.to_string())
+    }
+    pub fn thread_num(&self) -> Result<usize, String> {
+        match self.options.thread {
+            None => Ok(std::thread::available_parallelism().unwrap().get() * 2),
+            Some(0) => Err("thread invalid, must be a positive integer".to_string()),
+            Some(thread) => Ok(thread),
+        }
+    }
+    pub fn metrics_addr(&self) -> Result<Option<SocketAddr>, String> {
+        match &self.options.metrics {
+            None => Ok(None),
+            Some(addr) => SocketAddr::from_str(addr)
+                .map(Some)
+                .map_err(|e| format!("metrics '{}' invalid: {}", addr, e)),
+        }
+    }
+    pub fn disable_cmd(&self) -> bool {
+        self.options.disable_cmd
+    }
+}
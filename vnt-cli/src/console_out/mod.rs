@@ -1,12 +1,13 @@
 use console::{style, Style};
 
-use crate::command::entity::{DeviceItem, Info, RouteItem};
+use crate::command::entity::{DeviceItem, EventItem, Info, RouteItem};
 
 pub mod table;
 
 pub fn console_info(status: Info) {
     println!("Name: {}", style(status.name).green());
     println!("Virtual ip: {}", style(status.virtual_ip).green());
+    println!("Virtual ipv6: {}", style(status.virtual_ipv6).green());
     println!("Virtual gateway: {}", style(status.virtual_gateway).green());
     println!("Virtual netmask: {}", style(status.virtual_netmask).green());
     println!(
@@ -14,10 +15,22 @@ pub fn console_info(status: Info) {
         style(status.connect_status).green()
     );
     println!("NAT type: {}", style(status.nat_type).green());
+    println!("NAT behavior: {}", style(status.nat_behavior).green());
     println!("Relay server: {}", style(status.relay_server).green());
     println!("Public ips: {}", style(status.public_ips).green());
     println!("Local addr: {}", style(status.local_addr).green());
     println!("IPv6: {}", style(status.ipv6_addr).green());
+    println!("Mapped addr: {}", style(status.mapped_addr).green());
+    println!("Replay drop: {}", style(status.replay_drop).green());
+}
+
+pub fn console_event(item: EventItem) {
+    println!(
+        "{} {} {}",
+        style(item.event).green(),
+        item.virtual_ip,
+        item.detail
+    );
 }
 
 pub fn console_route_table(mut list: Vec<RouteItem>) {
@@ -33,7 +46,10 @@ pub fn console_route_table(mut list: Vec<RouteItem>) {
         ("Next Hop".to_string(), Style::new()),
         ("Metric".to_string(), Style::new()),
         ("Rt".to_string(), Style::new()),
+        ("Mtu".to_string(), Style::new()),
         ("Interface".to_string(), Style::new()),
+        ("Tx".to_string(), Style::new()),
+        ("Rx".to_string(), Style::new()),
     ]);
     for item in list {
         out_list.push(vec![
@@ -41,7 +57,10 @@ pub fn console_route_table(mut list: Vec<RouteItem>) {
             (item.next_hop, Style::new().green()),
             (item.metric, Style::new().green()),
             (item.rt, Style::new().green()),
+            (item.mtu, Style::new().green()),
             (item.interface, Style::new().green()),
+            (item.tx, Style::new().green()),
+            (item.rx, Style::new().green()),
         ]);
     }
 
@@ -63,6 +82,8 @@ pub fn console_device_list(mut list: Vec<DeviceItem>) {
         ("Status".to_string(), Style::new()),
         ("P2P/Relay".to_string(), Style::new()),
         ("Rt".to_string(), Style::new()),
+        ("Tx".to_string(), Style::new()),
+        ("Rx".to_string(), Style::new()),
     ]);
     for item in list {
         if &item.status == "Online" {
@@ -74,6 +95,8 @@ pub fn console_device_list(mut list: Vec<DeviceItem>) {
                     (item.status, Style::new().red()),
                     ("".to_string(), Style::new().red()),
                     ("".to_string(), Style::new().red()),
+                    (item.tx, Style::new().red()),
+                    (item.rx, Style::new().red()),
                 ]);
             } else {
                 if &item.nat_traversal_type == "p2p" {
@@ -83,6 +106,8 @@ pub fn console_device_list(mut list: Vec<DeviceItem>) {
                         (item.status, Style::new().green()),
                         (item.nat_traversal_type, Style::new().green()),
                         (item.rt, Style::new().green()),
+                        (item.tx, Style::new().green()),
+                        (item.rx, Style::new().green()),
                     ]);
                 } else {
                     out_list.push(vec![
@@ -91,6 +116,8 @@ pub fn console_device_list(mut list: Vec<DeviceItem>) {
                         (item.status, Style::new().yellow()),
                         (item.nat_traversal_type, Style::new().yellow()),
                         (item.rt, Style::new().yellow()),
+                        (item.tx, Style::new().yellow()),
+                        (item.rx, Style::new().yellow()),
                     ]);
                 }
             }
@@ -101,6 +128,8 @@ pub fn console_device_list(mut list: Vec<DeviceItem>) {
                 (item.status, Style::new().color256(102)),
                 ("".to_string(), Style::new().color256(102)),
                 ("".to_string(), Style::new().color256(102)),
+                (item.tx, Style::new().color256(102)),
+                (item.rx, Style::new().color256(102)),
             ]);
         }
     }
@@ -126,6 +155,10 @@ pub fn console_device_list_all(mut list: Vec<DeviceItem>) {
         ("Public Ips".to_string(), Style::new()),
         ("Local Ip".to_string(), Style::new()),
         ("IPv6".to_string(), Style::new()),
+        ("Tx".to_string(), Style::new()),
+        ("Rx".to_string(), Style::new()),
+        ("Decrypt Err".to_string(), Style::new()),
+        ("Drops".to_string(), Style::new()),
     ]);
     for item in list {
         if &item.status == "Online" {
@@ -140,6 +173,10 @@ pub fn console_device_list_all(mut list: Vec<DeviceItem>) {
                     (item.public_ips, Style::new().green()),
                     (item.local_ip, Style::new().green()),
                     (item.ipv6, Style::new().green()),
+                    (item.tx, Style::new().green()),
+                    (item.rx, Style::new().green()),
+                    (item.decrypt_failures, Style::new().green()),
+                    (item.drops, Style::new().green()),
                 ]);
             } else {
                 out_list.push(vec![
@@ -152,6 +189,10 @@ pub fn console_device_list_all(mut list: Vec<DeviceItem>) {
                     (item.public_ips, Style::new().yellow()),
                     (item.local_ip, Style::new().yellow()),
                     (item.ipv6, Style::new().yellow()),
+                    (item.tx, Style::new().yellow()),
+                    (item.rx, Style::new().yellow()),
+                    (item.decrypt_failures, Style::new().yellow()),
+                    (item.drops, Style::new().yellow()),
                 ]);
             }
         } else {
@@ -165,6 +206,10 @@ pub fn console_device_list_all(mut list: Vec<DeviceItem>) {
                 ("".to_string(), Style::new().color256(102)),
                 ("".to_string(), Style::new().color256(102)),
                 ("".to_string(), Style::new().color256(102)),
+                (item.tx, Style::new().color256(102)),
+                (item.rx, Style::new().color256(102)),
+                (item.decrypt_failures, Style::new().color256(102)),
+                (item.drops, Style::new().color256(102)),
             ]);
         }
     }
@@ -0,0 +1,283 @@
+use std::fmt::Write;
+use std::io;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+use vnt::channel::stats::TrafficStats;
+use vnt::channel::ConnectProtocol;
+use vnt::core::Vnt;
+use vnt::handle::PeerDeviceStatus;
+
+/// 请求头的最大长度，只需要解析请求行
+const MAX_REQUEST_LEN: usize = 8192;
+/// 读取请求头的超时时间
+const READ_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 指标名、说明和取值函数
+type Counter = (&'static str, &'static str, fn(&TrafficStats) -> u64);
+
+/// 提供OpenMetrics格式的监控数据，只处理 GET /metrics
+pub async fn start(addr: SocketAddr, vnt: Vnt) -> io::Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    log::info!("metrics 监听地址:{}", addr);
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let vnt = vnt.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle(stream, &vnt).await {
+                log::warn!("metrics {:?}", e);
+            }
+        });
+    }
+}
+
+async fn handle(mut stream: TcpStream, vnt: &Vnt) -> io::Result<()> {
+    let mut buf = vec![0u8; MAX_REQUEST_LEN];
+    //慢速或者不发送请求的连接超时关闭，避免占用连接
+    let len = match tokio::time::timeout(READ_TIMEOUT, read_request(&mut stream, &mut buf)).await {
+        Ok(rs) => rs?,
+        Err(_) => {
+            return write_response(&mut stream, "408 Request Timeout", "", "").await;
+        }
+    };
+    let len = match len {
+        Some(len) => len,
+        None => return Ok(()),
+    };
+    if len == buf.len() {
+        return write_response(&mut stream, "431 Request Header Fields Too Large", "", "").await;
+    }
+    let request_line = buf[..len].split(|v| *v == b'\r').next().unwrap_or_default();
+    let mut parts = request_line.split(|v| *v == b' ');
+    let method = parts.next().unwrap_or_default();
+    let path = parts.next().unwrap_or_default();
+    if method != b"GET" {
+        return write_response(&mut stream, "405 Method Not Allowed", "", "").await;
+    }
+    if path != b"/metrics" {
+        return write_response(&mut stream, "404 Not Found", "", "").await;
+    }
+    let body = render(vnt);
+    write_response(
+        &mut stream,
+        "200 OK",
+        "application/openmetrics-text; version=1.0.0; charset=utf-8",
+        &body,
+    )
+    .await
+}
+
+/// 读取请求头，返回读取的长度，连接关闭时返回None，超过缓冲区长度时返回缓冲区长度
+async fn read_request(stream: &mut TcpStream, buf: &mut [u8]) -> io::Result<Option<usize>> {
+    let mut len = 0;
+    loop {
+        let n = stream.read(&mut buf[len..]).await?;
+        if n == 0 {
+            return Ok(None);
+        }
+        len += n;
+        if buf[..len].windows(4).any(|v| v == b"\r\n\r\n") || len == buf.len() {
+            return Ok(Some(len));
+        }
+    }
+}
+
+async fn write_response(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &str,
+) -> io::Result<()> {
+    let mut response = format!(
+        "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+        status,
+        body.len()
+    );
+    if !content_type.is_empty() {
+        response.push_str("Content-Type: ");
+        response.push_str(content_type);
+        response.push_str("\r\n");
+    }
+    response.push_str("\r\n");
+    response.push_str(body);
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn protocol(protocol: ConnectProtocol) -> &'static str {
+    match protocol {
+        ConnectProtocol::Udp => "udp",
+        ConnectProtocol::Tcp => "tcp",
+        ConnectProtocol::Quic => "quic",
+    }
+}
+
+fn render(vnt: &Vnt) -> String {
+    let mut out = String::with_capacity(4096);
+    let current_device = vnt.current_device();
+    let nat_info = vnt.nat_info();
+    let stats = vnt.stats();
+
+    let _ = writeln!(out, "# TYPE vnt info");
+    let _ = writeln!(out, "# HELP vnt 当前设备信息");
+    let _ = writeln!(
+        out,
+        "vnt_info{{version=\"{}\",name=\"{}\",virtual_ip=\"{}\"}} 1",
+        vnt::VNT_VERSION,
+        escape(vnt.name()),
+        current_device.virtual_ip()
+    );
+
+    let _ = writeln!(out, "# TYPE vnt_connected gauge");
+    let _ = writeln!(out, "# HELP vnt_connected 和服务端的连接状态，1表示已注册");
+    let connect_status = vnt.connection_status();
+    let _ = writeln!(out, "vnt_connected {}", connect_status.online() as u8);
+
+    let _ = writeln!(out, "# TYPE vnt_connect_status gauge");
+    let _ = writeln!(
+        out,
+        "# HELP vnt_connect_status 和服务端连接状态机的当前状态"
+    );
+    let _ = writeln!(
+        out,
+        "vnt_connect_status{{status=\"{:?}\"}} 1",
+        connect_status
+    );
+
+    let _ = writeln!(out, "# TYPE vnt_nat_type gauge");
+    let _ = writeln!(out, "# HELP vnt_nat_type 当前的NAT类型");
+    let _ = writeln!(out, "vnt_nat_type{{type=\"{:?}\"}} 1", nat_info.nat_type);
+
+    let mut p2p = 0;
+    let mut relay = 0;
+    let mut offline = 0;
+    let mut rtt = Vec::new();
+    for peer in vnt.device_list() {
+        if peer.status != PeerDeviceStatus::Online {
+            offline += 1;
+            continue;
+        }
+        match vnt.route(&peer.virtual_ip) {
+            Some(route) => {
+                if route.is_p2p() {
+                    p2p += 1;
+                } else {
+                    relay += 1;
+                }
+                if route.rt >= 0 {
+                    rtt.push((peer.virtual_ip, route.rt));
+                }
+            }
+            None => relay += 1,
+        }
+    }
+    let _ = writeln!(out, "# TYPE vnt_peers gauge");
+    let _ = writeln!(out, "# HELP vnt_peers 其他设备数量");
+    let _ = writeln!(out, "vnt_peers{{state=\"p2p\"}} {}", p2p);
+    let _ = writeln!(out, "vnt_peers{{state=\"relay\"}} {}", relay);
+    let _ = writeln!(out, "vnt_peers{{state=\"offline\"}} {}", offline);
+
+    let _ = writeln!(out, "# TYPE vnt_peer_rtt_milliseconds gauge");
+    let _ = writeln!(out, "# UNIT vnt_peer_rtt_milliseconds milliseconds");
+    let _ = writeln!(out, "# HELP vnt_peer_rtt_milliseconds 到对端的延迟");
+    for (ip, rt) in rtt {
+        let _ = writeln!(out, "vnt_peer_rtt_milliseconds{{peer=\"{}\"}} {}", ip, rt);
+    }
+
+    let _ = writeln!(out, "# TYPE vnt_punch_attempts counter");
+    let _ = writeln!(out, "# HELP vnt_punch_attempts 打洞次数");
+    let _ = writeln!(out, "vnt_punch_attempts_total {}", stats.punch_attempts);
+    let _ = writeln!(out, "# TYPE vnt_punch_packets counter");
+    let _ = writeln!(out, "# HELP vnt_punch_packets 打洞发送的数据包数量");
+    let _ = writeln!(out, "vnt_punch_packets_total {}", stats.punch_packets);
+    let _ = writeln!(out, "# TYPE vnt_birthday_punch_attempts counter");
+    let _ = writeln!(out, "# HELP vnt_birthday_punch_attempts 生日攻击打洞次数");
+    let _ = writeln!(
+        out,
+        "vnt_birthday_punch_attempts_total {}",
+        stats.birthday_attempts
+    );
+    let _ = writeln!(out, "# TYPE vnt_birthday_punch_successes counter");
+    let _ = writeln!(
+        out,
+        "# HELP vnt_birthday_punch_successes 生日攻击打洞成功次数"
+    );
+    let _ = writeln!(
+        out,
+        "vnt_birthday_punch_successes_total {}",
+        stats.birthday_successes
+    );
+
+    let peer_counters: [Counter; 6] = [
+        (
+            "vnt_peer_transmit_bytes",
+            "发送到对端的字节数",
+            |v| v.tx_bytes,
+        ),
+        (
+            "vnt_peer_transmit_packets",
+            "发送到对端的数据包数量",
+            |v| v.tx_packets,
+        ),
+        (
+            "vnt_peer_receive_bytes",
+            "从对端接收的字节数",
+            |v| v.rx_bytes,
+        ),
+        (
+            "vnt_peer_receive_packets",
+            "从对端接收的数据包数量",
+            |v| v.rx_packets,
+        ),
+        (
+            "vnt_peer_decrypt_failures",
+            "解密失败的数据包数量",
+            |v| v.decrypt_failures,
+        ),
+        ("vnt_peer_drops", "丢弃的数据包数量", |v| v.drops),
+    ];
+    for (name, help, get) in peer_counters.iter() {
+        let _ = writeln!(out, "# TYPE {} counter", name);
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        for (ip, traffic) in &stats.peers {
+            let _ = writeln!(out, "{}_total{{peer=\"{}\"}} {}", name, ip, get(traffic));
+        }
+    }
+    let route_counters: [Counter; 2] = [
+        (
+            "vnt_route_transmit_bytes",
+            "通道发送的字节数",
+            |v| v.tx_bytes,
+        ),
+        ("vnt_route_receive_bytes", "通道接收的字节数", |v| {
+            v.rx_bytes
+        }),
+    ];
+    for (name, help, get) in route_counters.iter() {
+        let _ = writeln!(out, "# TYPE {} counter", name);
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        for (route_key, traffic) in &stats.routes {
+            let _ = writeln!(
+                out,
+                "{}_total{{index=\"{}\",protocol=\"{}\",addr=\"{}\"}} {}",
+                name,
+                route_key.index(),
+                protocol(route_key.protocol()),
+                route_key.addr,
+                get(traffic)
+            );
+        }
+    }
+    out.push_str("# EOF\n");
+    out
+}
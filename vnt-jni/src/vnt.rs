@@ -4,6 +4,7 @@ use jni::sys::{jboolean, jbyte, jint, jlong, jobject, jobjectArray, jsize};
 use jni::JNIEnv;
 use std::ptr;
 use vnt::channel::Route;
+use vnt::core::event::VntEvent;
 use vnt::core::sync::VntSync;
 use vnt::handle::PeerDeviceInfo;
 
@@ -140,3 +141,81 @@ fn peer_device_info_parse(
     )?;
     Ok(rs.as_raw())
 }
+
+/// 订阅事件，事件在vnt的runtime的阻塞线程中回调
+/// callback需要实现 void onEvent(String event, int virtualIp, String detail)
+#[no_mangle]
+pub unsafe extern "C" fn Java_top_wherewego_vnt_jni_Vnt_subscribe0(
+    mut env: JNIEnv,
+    _class: JClass,
+    raw_vnt: jlong,
+    callback: JObject,
+) {
+    let vnt = raw_vnt as *mut VntSync;
+    let vnt = &*vnt;
+    let mut events = match vnt.subscribe_sync() {
+        Ok(events) => events,
+        Err(e) => {
+            env.throw_new("java/lang/RuntimeException", format!("error:{:?}", e))
+                .expect("throw");
+            return;
+        }
+    };
+    let (jvm, callback) = match env
+        .get_java_vm()
+        .and_then(|jvm| Ok((jvm, env.new_global_ref(callback)?)))
+    {
+        Ok(v) => v,
+        Err(e) => {
+            env.throw_new("java/lang/RuntimeException", format!("error:{:?}", e))
+                .expect("throw");
+            return;
+        }
+    };
+    vnt.spawn_blocking(move || {
+        let mut env = match jvm.attach_current_thread() {
+            Ok(env) => env,
+            Err(_) => return,
+        };
+        while let Some(event) = events.next_event() {
+            let (event, virtual_ip, detail) = event_parse(event);
+            //线程一直附加在jvm上，局部引用不会自动释放，每次回调使用单独的局部帧
+            let rs = env.with_local_frame(4, |env| -> Result<(), Error> {
+                let event = env.new_string(event)?;
+                let detail = env.new_string(detail)?;
+                env.call_method(
+                    &callback,
+                    "onEvent",
+                    "(Ljava/lang/String;ILjava/lang/String;)V",
+                    &[
+                        JValue::Object(&event.into()),
+                        JValue::Int(virtual_ip as jint),
+                        JValue::Object(&detail.into()),
+                    ],
+                )?;
+                Ok(())
+            });
+            if rs.is_err() {
+                let _ = env.exception_clear();
+                break;
+            }
+        }
+    });
+}
+
+fn event_parse(event: VntEvent) -> (&'static str, u32, String) {
+    match event {
+        VntEvent::ConnectStatus(status) => ("connect_status", 0, format!("{:?}", status)),
+        VntEvent::PeerOnline(peer) => ("peer_online", u32::from(peer.virtual_ip), peer.name),
+        VntEvent::PeerOffline(peer) => ("peer_offline", u32::from(peer.virtual_ip), peer.name),
+        VntEvent::RouteAdd(ip, route) => (
+            "route_add",
+            u32::from(ip),
+            format!("{},metric:{}", route.addr, route.metric),
+        ),
+        VntEvent::RouteRemove(ip, route_key) => {
+            ("route_remove", u32::from(ip), route_key.addr.to_string())
+        }
+        VntEvent::NatChange(nat_info) => ("nat_change", 0, format!("{:?}", nat_info)),
+    }
+}
@@ -1,4 +1,3 @@
-use std::net::ToSocketAddrs;
 use std::ptr;
 use std::str::FromStr;
 
@@ -8,7 +7,6 @@ use jni::objects::{JClass, JObject, JString, JValue};
 use jni::sys::jboolean;
 use jni::sys::{jint, jlong, jobject};
 use jni::JNIEnv;
-use vnt::channel::punch::PunchModel;
 use vnt::cipher::CipherModel;
 use vnt::core::sync::VntUtilSync;
 use vnt::core::Config;
@@ -67,25 +65,6 @@ fn new_sync(env: &mut JNIEnv, config: JObject) -> Result<VntUtilSync, Error> {
     let tcp = env.get_field(&config, "tcp", "Z")?.z()?;
     let finger = env.get_field(&config, "finger", "Z")?.z()?;
 
-    let server_address = match server_address_str.to_socket_addrs() {
-        Ok(mut rs) => {
-            if let Some(addr) = rs.next() {
-                addr
-            } else {
-                env.throw_new("java/lang/RuntimeException", "server address err")
-                    .expect("throw");
-                return Err(Error::JavaException);
-            }
-        }
-        Err(e) => {
-            env.throw_new(
-                "java/lang/RuntimeException",
-                format!("server address {}", e),
-            )
-            .expect("throw");
-            return Err(Error::JavaException);
-        }
-    };
     let cipher_model = match CipherModel::from_str(&cipher_model) {
         Ok(cipher_model) => cipher_model,
         Err(e) => {
@@ -94,32 +73,52 @@ fn new_sync(env: &mut JNIEnv, config: JObject) -> Result<VntUtilSync, Error> {
             return Err(Error::JavaException);
         }
     };
+    let server_list = server_address_str
+        .split(",")
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+        .collect();
     let mut stun_server = Vec::new();
     for addr in stun_server_str.split(",") {
         stun_server.push(addr.trim().to_string());
     }
-    let config = Config::new(
-        false,
-        token,
-        device_id,
-        name,
-        server_address,
-        server_address_str,
-        stun_server,
-        vec![],
-        vec![],
-        password,
-        false,
-        None,
-        tcp,
-        None,
-        false,
-        false,
-        1,
-        cipher_model,
-        finger,
-        PunchModel::All,
-    );
+    let config = Config::builder()
+        .token(token)
+        .device_id(device_id)
+        .name(name)
+        .server_list(server_list)
+        .stun_server(stun_server)
+        .password(password)
+        .tcp(tcp)
+        .cipher_model(cipher_model)
+        .finger(finger)
+        .build();
+    let config = match config {
+        Ok(config) => config,
+        Err(e) => {
+            env.throw_new("java/lang/RuntimeException", format!("config {}", e))
+                .expect("throw");
+            return Err(Error::JavaException);
+        }
+    };
+    start_sync(env, config)
+}
+
+/// 使用json格式的配置，字段和vnt::core::Config的序列化格式一致
+fn new_sync_json(env: &mut JNIEnv, config: JString) -> Result<VntUtilSync, Error> {
+    let config: String = env.get_string(&config)?.into();
+    let config = match serde_json::from_str::<Config>(&config) {
+        Ok(config) => config,
+        Err(e) => {
+            env.throw_new("java/lang/RuntimeException", format!("config {}", e))
+                .expect("throw");
+            return Err(Error::JavaException);
+        }
+    };
+    start_sync(env, config)
+}
+
+fn start_sync(env: &mut JNIEnv, config: Config) -> Result<VntUtilSync, Error> {
     match VntUtilSync::new(config) {
         Ok(vnt_util) => Ok(vnt_util),
         Err(e) => {
@@ -149,6 +148,22 @@ pub unsafe extern "C" fn Java_top_wherewego_vnt_jni_VntUtil_new0(
     return 0;
 }
 
+#[no_mangle]
+pub unsafe extern "C" fn Java_top_wherewego_vnt_jni_VntUtil_newJson0(
+    mut env: JNIEnv,
+    _class: JClass,
+    config: JString,
+) -> jlong {
+    match new_sync_json(&mut env, config) {
+        Ok(vnt_util) => {
+            let ptr = Box::into_raw(Box::new(vnt_util));
+            return ptr as jlong;
+        }
+        Err(_) => {}
+    }
+    return 0;
+}
+
 #[no_mangle]
 pub unsafe extern "C" fn Java_top_wherewego_vnt_jni_VntUtil_connect0(
     mut env: JNIEnv,
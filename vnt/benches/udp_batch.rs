@@ -0,0 +1,89 @@
+//! 在回环地址上对比逐个收发和批量收发(linux下为sendmmsg+UDP_SEGMENT、recvmmsg+UDP_GRO)的吞吐量
+//!
+//! cargo bench -p vnt --bench udp_batch
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+
+use socket2::SockRef;
+use tokio::net::UdpSocket;
+use vnt::channel::batch::{self, RecvBatch, BATCH_SIZE};
+
+const PACKET_LEN: usize = 1400;
+const PACKET_NUM: usize = 200_000;
+
+#[tokio::main]
+async fn main() {
+    for (name, batch_send, batch_recv) in [
+        ("send_to/recv_from", false, false),
+        ("batch send/recv_from", true, false),
+        ("send_to/batch recv", false, true),
+        ("batch send/batch recv", true, true),
+    ] {
+        let (send_elapsed, count, recv_elapsed) = run(batch_send, batch_recv).await;
+        println!(
+            "{:<24} send {:>9.0} pps, recv {:>6}/{} packets {:>9.0} pps {:>7.1} Mbps",
+            name,
+            PACKET_NUM as f64 / send_elapsed.as_secs_f64(),
+            count,
+            PACKET_NUM,
+            count as f64 / recv_elapsed.as_secs_f64(),
+            (count * PACKET_LEN * 8) as f64 / recv_elapsed.as_secs_f64() / 1_000_000.0
+        );
+    }
+}
+
+/// 返回发送耗时、接收数量、接收耗时，回环上接收不及时会丢包
+async fn run(batch_send: bool, batch_recv: bool) -> (Duration, usize, Duration) {
+    let receiver = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let _ = SockRef::from(&receiver).set_recv_buffer_size(8 * 1024 * 1024);
+    let sender = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let addr: SocketAddr = receiver.local_addr().unwrap();
+    let recv_task = tokio::spawn(async move {
+        let mut count = 0;
+        let mut batch = RecvBatch::new(&receiver);
+        let mut buf = [0; 4096];
+        let start = Instant::now();
+        let mut last = start;
+        while count < PACKET_NUM {
+            //一段时间收不到数据视为发送结束，剩下的已经丢弃
+            let rs = if batch_recv {
+                tokio::time::timeout(Duration::from_millis(500), batch.recv(&receiver))
+                    .await
+                    .map(|rs| rs.map(|_| batch.packets().count()))
+            } else {
+                tokio::time::timeout(Duration::from_millis(500), receiver.recv_from(&mut buf))
+                    .await
+                    .map(|rs| rs.map(|_| 1))
+            };
+            match rs {
+                Ok(Ok(n)) => {
+                    count += n;
+                    last = Instant::now();
+                }
+                Ok(Err(e)) => panic!("{:?}", e),
+                Err(_) => break,
+            }
+        }
+        (count, last - start)
+    });
+    let data = vec![1u8; PACKET_LEN];
+    let packets: Vec<(&[u8], SocketAddr)> = vec![(&data[..], addr); BATCH_SIZE];
+    let mut sent = 0;
+    let start = Instant::now();
+    while sent < PACKET_NUM {
+        let num = BATCH_SIZE.min(PACKET_NUM - sent);
+        if batch_send {
+            batch::send_batch(&sender, &packets[..num]).await.unwrap();
+        } else {
+            for (buf, addr) in &packets[..num] {
+                sender.send_to(buf, *addr).await.unwrap();
+            }
+        }
+        sent += num;
+        //让出给接收任务，减少回环上的丢包
+        tokio::task::yield_now().await;
+    }
+    let send_elapsed = start.elapsed();
+    let (count, recv_elapsed) = recv_task.await.unwrap();
+    (send_elapsed, count, recv_elapsed)
+}
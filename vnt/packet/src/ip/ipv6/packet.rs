@@ -0,0 +1,141 @@
+use std::net::Ipv6Addr;
+use std::{fmt, io};
+
+use crate::ip::ipv4::protocol::Protocol;
+
+/// ipv6协议
+/*
+RFC:  8200   https://www.rfc-editor.org/rfc/rfc8200
+
+    0                                            15                                              31
+    0  1  2  3  4  5  6  7  8  9  0  1  2  3  4  5  6  7  8  9  0  1  2  3  4  5  6  7  8  9  0  1
+   +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+   |  版本(4) |     通信类别(8)     |                          流标签(20)                            |
+   +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+   |                 载荷长度(16)                  |      下一个头部(8)      |       跳数限制(8)       |
+   +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+   |                                                                                               |
+   |                                        源ip地址(128)                                           |
+   |                                                                                               |
+   +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+   |                                                                                               |
+   |                                       目的ip地址(128)                                          |
+   |                                                                                               |
+   +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+
+                                               数据体
+ 注：ipv6固定头部40字节，扩展头部通过"下一个头部"串联，不计入固定头部
+*/
+pub struct IpV6Packet<B> {
+    pub buffer: B,
+}
+
+pub const IPV6_HEAD_LEN: usize = 40;
+
+impl<B: AsRef<[u8]>> IpV6Packet<B> {
+    pub fn unchecked(buffer: B) -> Self {
+        Self { buffer }
+    }
+    pub fn new(buffer: B) -> io::Result<Self> {
+        if buffer.as_ref().len() < IPV6_HEAD_LEN {
+            Err(io::Error::new(io::ErrorKind::InvalidData, "len < 40"))?;
+        }
+        if buffer.as_ref()[0] >> 4 != 6 {
+            Err(io::Error::new(io::ErrorKind::InvalidData, "not ipv6"))?;
+        }
+        Ok(Self::unchecked(buffer))
+    }
+}
+
+impl<B: AsRef<[u8]>> IpV6Packet<B> {
+    pub fn header(&self) -> &[u8] {
+        &self.buffer.as_ref()[..IPV6_HEAD_LEN]
+    }
+    pub fn payload(&self) -> &[u8] {
+        &self.buffer.as_ref()[IPV6_HEAD_LEN..]
+    }
+}
+
+impl<B: AsRef<[u8]> + AsMut<[u8]>> IpV6Packet<B> {
+    pub fn header_mut(&mut self) -> &mut [u8] {
+        &mut self.buffer.as_mut()[..IPV6_HEAD_LEN]
+    }
+    pub fn payload_mut(&mut self) -> &mut [u8] {
+        &mut self.buffer.as_mut()[IPV6_HEAD_LEN..]
+    }
+    pub fn set_next_header(&mut self, value: Protocol) {
+        self.header_mut()[6] = value.into();
+    }
+    pub fn set_hop_limit(&mut self, value: u8) {
+        self.header_mut()[7] = value;
+    }
+    pub fn set_payload_length(&mut self, value: u16) {
+        self.header_mut()[4..6].copy_from_slice(&value.to_be_bytes());
+    }
+    pub fn set_source_ip(&mut self, value: Ipv6Addr) {
+        self.header_mut()[8..24].copy_from_slice(&value.octets());
+    }
+    pub fn set_destination_ip(&mut self, value: Ipv6Addr) {
+        self.header_mut()[24..40].copy_from_slice(&value.octets());
+    }
+}
+
+impl<B: AsRef<[u8]>> IpV6Packet<B> {
+    /// 版本号，ipv6的为6
+    pub fn version(&self) -> u8 {
+        self.buffer.as_ref()[0] >> 4
+    }
+
+    /// 通信类别，相当于ipv4的服务类型
+    pub fn traffic_class(&self) -> u8 {
+        (self.buffer.as_ref()[0] << 4) | (self.buffer.as_ref()[1] >> 4)
+    }
+
+    /// 流标签 20位
+    pub fn flow_label(&self) -> u32 {
+        u32::from_be_bytes(self.buffer.as_ref()[0..4].try_into().unwrap()) & 0xfffff
+    }
+
+    /// 载荷长度，不包含固定头部，包含扩展头部
+    pub fn payload_length(&self) -> u16 {
+        u16::from_be_bytes(self.buffer.as_ref()[4..6].try_into().unwrap())
+    }
+
+    /// 下一个头部，取值和ipv4的协议字段相同
+    pub fn next_header(&self) -> Protocol {
+        self.buffer.as_ref()[6].into()
+    }
+
+    /// 跳数限制，相当于ipv4的生存时间
+    pub fn hop_limit(&self) -> u8 {
+        self.buffer.as_ref()[7]
+    }
+
+    /// 源ip.
+    pub fn source_ip(&self) -> Ipv6Addr {
+        let octets: [u8; 16] = self.buffer.as_ref()[8..24].try_into().unwrap();
+        Ipv6Addr::from(octets)
+    }
+
+    /// 目标ip.
+    pub fn destination_ip(&self) -> Ipv6Addr {
+        let octets: [u8; 16] = self.buffer.as_ref()[24..40].try_into().unwrap();
+        Ipv6Addr::from(octets)
+    }
+}
+
+impl<B: AsRef<[u8]>> fmt::Debug for IpV6Packet<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ip::v6::Packet")
+            .field("version", &self.version())
+            .field("traffic_class", &self.traffic_class())
+            .field("flow_label", &self.flow_label())
+            .field("payload_length", &self.payload_length())
+            .field("next_header", &self.next_header())
+            .field("hop_limit", &self.hop_limit())
+            .field("source", &self.source_ip())
+            .field("destination", &self.destination_ip())
+            .field("payload", &self.payload())
+            .finish()
+    }
+}
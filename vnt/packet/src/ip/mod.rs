@@ -1,16 +1,20 @@
 use ipv4::packet::IpV4Packet;
+use ipv6::packet::IpV6Packet;
 use std::io;
 
 pub mod ipv4;
+pub mod ipv6;
 
 pub enum IpPacket<B> {
     V4(IpV4Packet<B>),
+    V6(IpV6Packet<B>),
 }
 
 impl<B: AsRef<[u8]>> IpPacket<B> {
     pub fn new(buffer: B) -> io::Result<Self> {
         match buffer.as_ref()[0] >> 4 {
             4 => Ok(IpPacket::V4(IpV4Packet::new(buffer)?)),
+            6 => Ok(IpPacket::V6(IpV6Packet::new(buffer)?)),
             _ => Err(io::Error::from(io::ErrorKind::InvalidData)),
         }
     }
@@ -1,4 +1,4 @@
-use std::net::Ipv4Addr;
+use std::net::{Ipv4Addr, Ipv6Addr};
 
 use byteorder::BigEndian;
 use byteorder::ReadBytesExt;
@@ -129,6 +129,56 @@ pub fn ipv4_cal_checksum(
     !sum as u16
 }
 
+/// ipv6上层协议校验和计算方式
+/// ipv6伪首部 用于参与计算首部校验和
+/*
+   0      7 8     15 16    23 24    31
+   +--------+--------+--------+--------+
+   |                                   |
+   +          source address(128)      +
+   |                                   |
+   +--------+--------+--------+--------+
+   |                                   |
+   +       destination address(128)    +
+   |                                   |
+   +--------+--------+--------+--------+
+   |      upper-layer packet length    |
+   +--------+--------+--------+--------+
+   |         zero             |next hdr|
+   +--------+--------+--------+--------+
+*/
+pub fn ipv6_cal_checksum(
+    buffer: &[u8],
+    src_ip: &Ipv6Addr,
+    dest_ip: &Ipv6Addr,
+    protocol: u8,
+) -> u16 {
+    use std::io::Cursor;
+    let length = buffer.len();
+    let mut sum = 0;
+    for v in src_ip.segments() {
+        sum += v as u32;
+    }
+    for v in dest_ip.segments() {
+        sum += v as u32;
+    }
+    sum += (length as u32) >> 16;
+    sum += (length as u32) & 0xffff;
+    sum += u32c(0, protocol);
+    let mut buffer = Cursor::new(buffer);
+    while let Ok(value) = buffer.read_u16::<BigEndian>() {
+        sum += u32::from(value);
+    }
+    if length & 1 == 1 {
+        //奇数,说明还有一位
+        sum += u32c(buffer.read_u8().unwrap(), 0);
+    }
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !sum as u16
+}
+
 #[inline]
 fn u32c(x: u8, y: u8) -> u32 {
     ((x as u32) << 8) | y as u32
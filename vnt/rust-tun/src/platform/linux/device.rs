@@ -81,7 +81,12 @@ impl Device {
                 } else {
                     IFF_NO_PI
                 }
-                | if queues_num > 1 { IFF_MULTI_QUEUE } else { 0 };
+                | if queues_num > 1 { IFF_MULTI_QUEUE } else { 0 }
+                | if config.platform.vnet_hdr {
+                    IFF_VNET_HDR
+                } else {
+                    0
+                };
 
             for _ in 0..queues_num {
                 let tun = Fd::new(libc::open(b"/dev/net/tun\0".as_ptr() as *const _, O_RDWR))
@@ -91,9 +96,18 @@ impl Device {
                     return Err(io::Error::last_os_error().into());
                 }
 
+                // The offload flags belong to the device, set them once.
+                if config.platform.vnet_hdr
+                    && queues.is_empty()
+                    && tunsetoffload(tun.0, TUN_F_CSUM | TUN_F_TSO4 | TUN_F_TSO6) < 0
+                {
+                    return Err(io::Error::last_os_error().into());
+                }
+
                 queues.push(Queue {
                     tun: Arc::new(tun),
                     pi_enabled: config.platform.packet_information,
+                    vnet_hdr: config.platform.vnet_hdr,
                 });
             }
 
@@ -163,6 +177,11 @@ impl Device {
         self.queues[0].has_packet_information()
     }
 
+    /// Return whether packets are prefixed with a virtio-net header
+    pub fn has_vnet_hdr(&self) -> bool {
+        self.queues[0].has_vnet_hdr()
+    }
+
     /// Set non-blocking mode
     pub fn set_nonblock(&self) -> io::Result<()> {
         self.queues[0].set_nonblock()
@@ -356,6 +375,7 @@ impl D for Device {
 pub struct Queue {
     tun: Arc<Fd>,
     pi_enabled: bool,
+    vnet_hdr: bool,
 }
 
 impl Queue {
@@ -363,6 +383,10 @@ impl Queue {
         self.pi_enabled
     }
 
+    pub fn has_vnet_hdr(&self) -> bool {
+        self.vnet_hdr
+    }
+
     pub fn set_nonblock(&self) -> io::Result<()> {
         self.tun.set_nonblock()
     }
@@ -26,6 +26,7 @@ use crate::error::*;
 #[derive(Copy, Clone, Default, Debug)]
 pub struct Configuration {
     pub(crate) packet_information: bool,
+    pub(crate) vnet_hdr: bool,
 }
 
 impl Configuration {
@@ -35,6 +36,14 @@ impl Configuration {
         self.packet_information = value;
         self
     }
+
+    /// Enable or disable the virtio-net header (IFF_VNET_HDR), when enabled each
+    /// packet is prefixed with a `virtio_net_hdr` and checksum/TSO offload is
+    /// turned on, so reads may return TCP packets larger than the MTU.
+    pub fn vnet_hdr(&mut self, value: bool) -> &mut Self {
+        self.vnet_hdr = value;
+        self
+    }
 }
 
 /// Create a TUN device with the given name.
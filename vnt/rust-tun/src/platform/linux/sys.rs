@@ -27,6 +27,14 @@ pub const IFF_TUN: c_short = 0x0001;
 pub const IFF_TAP: c_short = 0x0002;
 pub const IFF_NO_PI: c_short = 0x1000;
 pub const IFF_MULTI_QUEUE: c_short = 0x0100;
+pub const IFF_VNET_HDR: c_short = 0x4000;
+
+pub const TUN_F_CSUM: c_uint = 0x01;
+pub const TUN_F_TSO4: c_uint = 0x02;
+pub const TUN_F_TSO6: c_uint = 0x04;
+
+/// Length of `struct virtio_net_hdr`, the default header size of IFF_VNET_HDR.
+pub const VIRTIO_NET_HDR_LEN: usize = 10;
 
 #[repr(C)]
 #[derive(Copy, Clone)]
@@ -109,3 +117,15 @@ ioctl!(write tunsetiff with b'T', 202; c_int);
 ioctl!(write tunsetpersist with b'T', 203; c_int);
 ioctl!(write tunsetowner with b'T', 204; c_int);
 ioctl!(write tunsetgroup with b'T', 206; c_int);
+
+/// TUNSETOFFLOAD takes the flags by value, so it can not be declared with `ioctl!`.
+///
+/// # Safety
+/// `fd` must be an open tun device file descriptor.
+pub unsafe fn tunsetoffload(fd: c_int, flags: c_uint) -> c_int {
+    libc::ioctl(
+        fd,
+        iow!(b'T', 208, std::mem::size_of::<c_uint>()) as c_ulong,
+        flags as c_ulong,
+    )
+}
@@ -13,7 +13,6 @@
 //  0. You just DO WHAT THE FUCK YOU WANT TO.
 
 use std::io;
-use std::mem;
 use std::os::unix::io::{AsRawFd, RawFd};
 use std::sync::Arc;
 
@@ -42,14 +41,12 @@ impl Reader {
     }
 
     pub fn read_vectored(&self, bufs: &mut [io::IoSliceMut<'_>]) -> io::Result<usize> {
+        // The tun fd is not a socket, recvmsg would fail with ENOTSOCK.
         unsafe {
-            let mut msg: libc::msghdr = mem::zeroed();
-            // msg.msg_name: NULL
-            // msg.msg_namelen: 0
-            msg.msg_iov = bufs.as_mut_ptr().cast();
-            msg.msg_iovlen = bufs.len().min(libc::c_int::MAX as usize) as _;
+            let iov = bufs.as_mut_ptr().cast();
+            let iovcnt = bufs.len().min(libc::c_int::MAX as usize) as _;
 
-            let n = libc::recvmsg(self.0.as_raw_fd(), &mut msg, 0);
+            let n = libc::readv(self.0.as_raw_fd(), iov, iovcnt);
             if n < 0 {
                 return Err(io::Error::last_os_error());
             }
@@ -73,14 +70,12 @@ impl Writer {
     }
 
     pub fn write_vectored(&self, bufs: &[io::IoSlice<'_>]) -> io::Result<usize> {
+        // The tun fd is not a socket, sendmsg would fail with ENOTSOCK.
         unsafe {
-            let mut msg: libc::msghdr = mem::zeroed();
-            // msg.msg_name = NULL
-            // msg.msg_namelen = 0
-            msg.msg_iov = bufs.as_ptr() as *mut _;
-            msg.msg_iovlen = bufs.len().min(libc::c_int::MAX as usize) as _;
+            let iov = bufs.as_ptr().cast();
+            let iovcnt = bufs.len().min(libc::c_int::MAX as usize) as _;
 
-            let n = libc::sendmsg(self.0.as_raw_fd(), &msg, 0);
+            let n = libc::writev(self.0.as_raw_fd(), iov, iovcnt);
             if n < 0 {
                 return Err(io::Error::last_os_error());
             }
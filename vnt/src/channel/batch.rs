@@ -0,0 +1,344 @@
+use std::io;
+use std::net::SocketAddr;
+
+use tokio::net::UdpSocket;
+
+/// 单次批量发送的最大数据包数量
+pub const BATCH_SIZE: usize = 32;
+/// 不使用gro时每个数据包的接收缓冲区
+const BUF_SIZE: usize = 4096;
+
+/// 批量接收udp数据，linux下使用recvmmsg并开启UDP_GRO，
+/// 其他平台每次只接收一个数据包
+pub struct RecvBatch {
+    bufs: Vec<Vec<u8>>,
+    /// 数据长度、来源地址、gro合并时每一段的长度
+    msgs: Vec<(usize, SocketAddr, usize)>,
+}
+
+impl RecvBatch {
+    pub fn new(udp: &UdpSocket) -> Self {
+        #[cfg(target_os = "linux")]
+        let bufs = if linux::set_gro(udp) {
+            //合并后的数据最大为64k，缓冲区也需要这么大
+            vec![vec![0; 65535]; linux::RECV_BATCH_GRO]
+        } else {
+            vec![vec![0; BUF_SIZE]; BATCH_SIZE]
+        };
+        #[cfg(not(target_os = "linux"))]
+        let bufs = {
+            let _ = udp;
+            vec![vec![0; BUF_SIZE]]
+        };
+        Self {
+            msgs: Vec::with_capacity(bufs.len()),
+            bufs,
+        }
+    }
+    /// 接收一批数据，返回消息数量
+    pub async fn recv(&mut self, udp: &UdpSocket) -> io::Result<usize> {
+        #[cfg(target_os = "linux")]
+        {
+            use tokio::io::Interest;
+            loop {
+                udp.readable().await?;
+                match udp.try_io(Interest::READABLE, || {
+                    linux::recvmmsg(udp, &mut self.bufs, &mut self.msgs)
+                }) {
+                    Ok(n) => return Ok(n),
+                    Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+        #[cfg(not(target_os = "linux"))]
+        {
+            self.msgs.clear();
+            let (len, addr) = udp.recv_from(&mut self.bufs[0]).await?;
+            self.msgs.push((len, addr, len));
+            Ok(1)
+        }
+    }
+    /// 收到的数据包，gro合并的数据按分段拆开
+    pub fn packets(&self) -> impl Iterator<Item = (&[u8], SocketAddr)> + '_ {
+        self.msgs
+            .iter()
+            .zip(self.bufs.iter())
+            .flat_map(|((len, addr, segment), buf)| {
+                buf[..*len]
+                    .chunks((*segment).max(1))
+                    .map(move |data| (data, *addr))
+            })
+    }
+}
+
+/// 批量发送，linux下使用sendmmsg，目标和长度相同的连续数据包通过UDP_SEGMENT合并发送，
+/// 单个数据包发送失败不影响其他数据包，返回最后一个错误
+pub async fn send_batch(udp: &UdpSocket, packets: &[(&[u8], SocketAddr)]) -> io::Result<()> {
+    #[cfg(target_os = "linux")]
+    {
+        use tokio::io::Interest;
+        let mut offset = 0;
+        let mut last_err = None;
+        while offset < packets.len() {
+            udp.writable().await?;
+            match udp.try_io(Interest::WRITABLE, || {
+                linux::sendmmsg(udp, &packets[offset..])
+            }) {
+                Ok(n) => offset += n,
+                //WouldBlock等待可写，Interrupted是关闭gso后重新分组发送
+                Err(e)
+                    if e.kind() == io::ErrorKind::WouldBlock
+                        || e.kind() == io::ErrorKind::Interrupted =>
+                {
+                    continue
+                }
+                Err(e) => {
+                    //跳过出错的消息
+                    offset += linux::gso_group_len(&packets[offset..]);
+                    last_err = Some(e);
+                }
+            }
+        }
+        match last_err {
+            None => Ok(()),
+            Some(e) => Err(e),
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let mut rs = Ok(());
+        for (buf, addr) in packets {
+            if let Err(e) = udp.send_to(buf, *addr).await {
+                rs = Err(e);
+            }
+        }
+        rs
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod linux {
+    use std::io;
+    use std::mem;
+    use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
+    use std::os::fd::AsRawFd;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use socket2::SockAddr;
+    use tokio::net::UdpSocket;
+
+    /// 开启gro时单次接收的消息数量
+    pub const RECV_BATCH_GRO: usize = 8;
+    // 部分libc版本没有这两个常量，值来自linux/udp.h
+    const UDP_SEGMENT: libc::c_int = 103;
+    const UDP_GRO: libc::c_int = 104;
+    /// 内核限制的单次gso最大分段数
+    const MAX_GSO_SEGMENTS: usize = 64;
+    const MAX_GSO_LEN: usize = 65000;
+    /// 控制消息缓冲区，u64保证对齐
+    type Control = [u64; 8];
+
+    /// 网卡或内核不支持gso时关闭，之后只使用sendmmsg
+    static GSO_ENABLE: AtomicBool = AtomicBool::new(true);
+
+    pub fn set_gro(udp: &UdpSocket) -> bool {
+        let enable: libc::c_int = 1;
+        unsafe {
+            libc::setsockopt(
+                udp.as_raw_fd(),
+                libc::SOL_UDP,
+                UDP_GRO,
+                &enable as *const _ as *const libc::c_void,
+                mem::size_of::<libc::c_int>() as libc::socklen_t,
+            ) == 0
+        }
+    }
+
+    pub fn recvmmsg(
+        udp: &UdpSocket,
+        bufs: &mut [Vec<u8>],
+        msgs: &mut Vec<(usize, SocketAddr, usize)>,
+    ) -> io::Result<usize> {
+        let num = bufs.len();
+        let mut addrs: Vec<libc::sockaddr_storage> = vec![unsafe { mem::zeroed() }; num];
+        let mut controls: Vec<Control> = vec![[0; 8]; num];
+        let mut iovs: Vec<libc::iovec> = bufs
+            .iter_mut()
+            .map(|buf| libc::iovec {
+                iov_base: buf.as_mut_ptr() as *mut libc::c_void,
+                iov_len: buf.len(),
+            })
+            .collect();
+        let mut hdrs: Vec<libc::mmsghdr> = Vec::with_capacity(num);
+        for i in 0..num {
+            let mut hdr: libc::mmsghdr = unsafe { mem::zeroed() };
+            hdr.msg_hdr.msg_name = &mut addrs[i] as *mut _ as *mut libc::c_void;
+            hdr.msg_hdr.msg_namelen = mem::size_of::<libc::sockaddr_storage>() as _;
+            hdr.msg_hdr.msg_iov = &mut iovs[i];
+            hdr.msg_hdr.msg_iovlen = 1;
+            hdr.msg_hdr.msg_control = controls[i].as_mut_ptr() as *mut libc::c_void;
+            hdr.msg_hdr.msg_controllen = mem::size_of::<Control>() as _;
+            hdrs.push(hdr);
+        }
+        let rs = unsafe {
+            libc::recvmmsg(
+                udp.as_raw_fd(),
+                hdrs.as_mut_ptr(),
+                num as _,
+                0,
+                std::ptr::null_mut(),
+            )
+        };
+        if rs < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        msgs.clear();
+        for (hdr, addr) in hdrs.iter().zip(addrs.iter()).take(rs as usize) {
+            //和缓冲区按位置对应，无效的消息长度记为0，不会拆分出数据包
+            match to_socket_addr(addr) {
+                Some(addr) if hdr.msg_hdr.msg_flags & libc::MSG_TRUNC == 0 => {
+                    let len = hdr.msg_len as usize;
+                    let segment = gro_segment(&hdr.msg_hdr).unwrap_or(len);
+                    msgs.push((len, addr, segment));
+                }
+                _ => msgs.push((0, SocketAddr::from(([0, 0, 0, 0], 0)), 0)),
+            }
+        }
+        Ok(msgs.len())
+    }
+
+    fn gro_segment(hdr: &libc::msghdr) -> Option<usize> {
+        unsafe {
+            let mut cmsg = libc::CMSG_FIRSTHDR(hdr);
+            while !cmsg.is_null() {
+                if (*cmsg).cmsg_level == libc::SOL_UDP && (*cmsg).cmsg_type == UDP_GRO {
+                    let segment =
+                        std::ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const libc::c_int);
+                    return Some(segment as usize);
+                }
+                cmsg = libc::CMSG_NXTHDR(hdr, cmsg);
+            }
+        }
+        None
+    }
+
+    fn to_socket_addr(storage: &libc::sockaddr_storage) -> Option<SocketAddr> {
+        match storage.ss_family as libc::c_int {
+            libc::AF_INET => {
+                let addr = unsafe { &*(storage as *const _ as *const libc::sockaddr_in) };
+                Some(SocketAddr::V4(SocketAddrV4::new(
+                    Ipv4Addr::from(u32::from_be(addr.sin_addr.s_addr)),
+                    u16::from_be(addr.sin_port),
+                )))
+            }
+            libc::AF_INET6 => {
+                let addr = unsafe { &*(storage as *const _ as *const libc::sockaddr_in6) };
+                Some(SocketAddr::V6(SocketAddrV6::new(
+                    Ipv6Addr::from(addr.sin6_addr.s6_addr),
+                    u16::from_be(addr.sin6_port),
+                    addr.sin6_flowinfo,
+                    addr.sin6_scope_id,
+                )))
+            }
+            _ => None,
+        }
+    }
+
+    /// 开头可以合并为一次gso发送的数据包数量，
+    /// 目标相同，除最后一个外长度都相同，最后一个不能更长
+    pub fn gso_group_len(packets: &[(&[u8], SocketAddr)]) -> usize {
+        if packets.is_empty() {
+            return 0;
+        }
+        if !GSO_ENABLE.load(Ordering::Relaxed) {
+            return 1;
+        }
+        let (first, addr) = packets[0];
+        let mut total = first.len();
+        let mut count = 1;
+        for (buf, dest) in &packets[1..] {
+            if count == MAX_GSO_SEGMENTS
+                || *dest != addr
+                || buf.len() > first.len()
+                || total + buf.len() > MAX_GSO_LEN
+            {
+                break;
+            }
+            total += buf.len();
+            count += 1;
+            if buf.len() < first.len() {
+                break;
+            }
+        }
+        count
+    }
+
+    /// 返回已发送的数据包数量
+    pub fn sendmmsg(udp: &UdpSocket, packets: &[(&[u8], SocketAddr)]) -> io::Result<usize> {
+        let mut groups = Vec::new();
+        let mut offset = 0;
+        while offset < packets.len() {
+            let len = gso_group_len(&packets[offset..]);
+            groups.push(offset..offset + len);
+            offset += len;
+        }
+        let mut iovs: Vec<libc::iovec> = packets
+            .iter()
+            .map(|(buf, _)| libc::iovec {
+                iov_base: buf.as_ptr() as *mut libc::c_void,
+                iov_len: buf.len(),
+            })
+            .collect();
+        let addrs: Vec<SockAddr> = groups
+            .iter()
+            .map(|range| SockAddr::from(packets[range.start].1))
+            .collect();
+        let mut controls: Vec<Control> = vec![[0; 8]; groups.len()];
+        let mut hdrs: Vec<libc::mmsghdr> = Vec::with_capacity(groups.len());
+        for (i, range) in groups.iter().enumerate() {
+            let mut hdr: libc::mmsghdr = unsafe { mem::zeroed() };
+            hdr.msg_hdr.msg_name = addrs[i].as_ptr() as *mut libc::c_void;
+            hdr.msg_hdr.msg_namelen = addrs[i].len();
+            hdr.msg_hdr.msg_iov = iovs[range.start..].as_mut_ptr();
+            hdr.msg_hdr.msg_iovlen = range.len() as _;
+            if range.len() > 1 {
+                let segment = packets[range.start].0.len() as u16;
+                hdr.msg_hdr.msg_control = controls[i].as_mut_ptr() as *mut libc::c_void;
+                hdr.msg_hdr.msg_controllen =
+                    unsafe { libc::CMSG_SPACE(mem::size_of::<u16>() as u32) } as _;
+                unsafe {
+                    let cmsg = libc::CMSG_FIRSTHDR(&hdr.msg_hdr);
+                    (*cmsg).cmsg_level = libc::SOL_UDP;
+                    (*cmsg).cmsg_type = UDP_SEGMENT;
+                    (*cmsg).cmsg_len = libc::CMSG_LEN(mem::size_of::<u16>() as u32) as _;
+                    std::ptr::write_unaligned(libc::CMSG_DATA(cmsg) as *mut u16, segment);
+                }
+            }
+            hdrs.push(hdr);
+        }
+        let rs = unsafe { libc::sendmmsg(udp.as_raw_fd(), hdrs.as_mut_ptr(), hdrs.len() as _, 0) };
+        if rs < 0 {
+            let err = io::Error::last_os_error();
+            if groups[0].len() > 1 && gso_unsupported(&err) {
+                log::warn!("udp gso不可用,改为逐个发送:{:?}", err);
+                GSO_ENABLE.store(false, Ordering::Relaxed);
+                //不能返回WouldBlock，会清除可写状态
+                return Err(io::Error::from(io::ErrorKind::Interrupted));
+            }
+            return Err(err);
+        }
+        Ok(groups
+            .iter()
+            .take(rs as usize)
+            .map(|range| range.len())
+            .sum())
+    }
+
+    fn gso_unsupported(err: &io::Error) -> bool {
+        matches!(
+            err.raw_os_error(),
+            Some(libc::EIO) | Some(libc::EINVAL) | Some(libc::ENOPROTOOPT) | Some(libc::EOPNOTSUPP)
+        )
+    }
+}
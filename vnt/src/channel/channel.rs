@@ -1,21 +1,35 @@
 use std::io;
-use std::net::{Ipv4Addr, SocketAddr};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use byte_pool::{Block, BytePool};
 use crossbeam_utils::atomic::AtomicCell;
 use dashmap::DashMap;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::tcp::OwnedReadHalf;
-use tokio::net::{TcpStream, UdpSocket};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt, ReadHalf};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
 use tokio::sync::watch::{channel, Receiver, Sender};
+use tokio::sync::Notify;
 
+use crate::channel::batch;
+#[cfg(target_os = "linux")]
+use crate::channel::batch::RecvBatch;
+use crate::channel::proxy::Socks5Udp;
 use crate::channel::punch::NatType;
+use crate::channel::quic_channel::{self, QuicChannel};
+use crate::channel::replay_window::ReplayWindow;
+use crate::channel::session::PeerSession;
+use crate::channel::stats::{Stats, VntStats};
+use crate::channel::tcp_channel::{self, ServerConnector, ServerStream};
 use crate::channel::{Route, RouteKey, Status};
+use crate::cipher::{decrypted_sequence, Cipher};
+use crate::core::event::{event_channel, send_event, EventReceiver, EventSender, VntEvent};
 use crate::core::status::VntWorker;
 use crate::handle::recv_handler::ChannelDataHandler;
-use crate::handle::CurrentDeviceInfo;
+use crate::handle::{CurrentDeviceInfo, PeerDeviceInfo, PeerDeviceStatus};
+use crate::protocol::{control_packet, NetPacket, Protocol};
+use crate::util::backoff::Backoff;
 
 lazy_static::lazy_static! {
     static ref POOL:BytePool = BytePool::new();
@@ -27,12 +41,39 @@ pub struct ContextInner {
     //在udp的基础上，可以选择使用tcp和服务端通信
     pub(crate) main_tcp_channel: Option<tokio::sync::mpsc::Sender<Vec<u8>>>,
     pub(crate) route_table: DashMap<Ipv4Addr, Vec<Route>>,
+    //虚拟ipv6到虚拟ipv4的映射，路由仍然以ipv4为节点标识，没有分配虚拟ipv4的节点无法使用ipv6
+    pub(crate) ipv6_table: DashMap<Ipv6Addr, Ipv4Addr>,
     pub(crate) route_table_time: DashMap<(RouteKey, Ipv4Addr), Instant>,
+    //通过路由通告学到的中继路由，值为最后一次收到通告的时间
+    pub(crate) route_advertise_time: DashMap<(RouteKey, Ipv4Addr), Instant>,
+    //正在进行的路径mtu探测，值为对端确认收到的最大长度
+    pub(crate) mtu_probe_table: DashMap<(RouteKey, Ipv4Addr), u16>,
+    //和各个对端协商出的会话密钥
+    pub(crate) session_table: DashMap<Ipv4Addr, PeerSession>,
+    //未使用会话密钥时，按来源虚拟ip的防重放窗口
+    pub(crate) replay_table: DashMap<Ipv4Addr, ReplayWindow>,
+    //因重放或者过旧而丢弃的数据包数量
+    pub(crate) replay_drop_count: AtomicU64,
+    pub(crate) event_sender: EventSender,
+    //收发数据统计
+    pub(crate) stats: Stats,
     pub(crate) status_receiver: Receiver<Status>,
     pub(crate) status_sender: Sender<Status>,
     pub(crate) udp_map: DashMap<usize, Arc<UdpSocket>>,
+    //tcp、quic打洞建立的连接，值为写数据的队列
+    pub(crate) conn_map: DashMap<usize, tokio::sync::mpsc::Sender<Vec<u8>>>,
+    pub(crate) conn_index: AtomicUsize,
+    //请求向对端发起tcp连接，连接成功后先发送附带的数据
+    pub(crate) tcp_punch_sender: Option<tokio::sync::mpsc::Sender<(SocketAddr, Vec<u8>)>>,
+    pub(crate) quic_punch_sender: Option<tokio::sync::mpsc::Sender<(SocketAddr, Vec<u8>)>>,
+    //主通道的ipv4数据经过socks5代理转发
+    pub(crate) socks5_udp: Option<Socks5Udp>,
+    /// 超过路径mtu的数据包是否分片发送
+    pub(crate) fragment: bool,
     pub(crate) channel_num: usize,
     current_device: Arc<AtomicCell<CurrentDeviceInfo>>,
+    //服务端地址变化时通知tcp、quic的服务端连接断开重连
+    server_changed: Notify,
 }
 
 #[derive(Clone)]
@@ -45,6 +86,10 @@ impl Context {
         main_channel: Arc<UdpSocket>,
         main_channel_ipv6: Option<Arc<UdpSocket>>,
         main_tcp_channel: Option<tokio::sync::mpsc::Sender<Vec<u8>>>,
+        tcp_punch_sender: Option<tokio::sync::mpsc::Sender<(SocketAddr, Vec<u8>)>>,
+        quic_punch_sender: Option<tokio::sync::mpsc::Sender<(SocketAddr, Vec<u8>)>>,
+        socks5_udp: Option<Socks5Udp>,
+        fragment: bool,
         current_device: Arc<AtomicCell<CurrentDeviceInfo>>,
         _channel_num: usize,
     ) -> Self {
@@ -56,12 +101,27 @@ impl Context {
             main_channel_ipv6,
             main_tcp_channel,
             route_table: DashMap::with_capacity(16),
+            ipv6_table: DashMap::with_capacity(16),
             route_table_time: DashMap::with_capacity(16),
+            route_advertise_time: DashMap::with_capacity(16),
+            mtu_probe_table: DashMap::with_capacity(16),
+            session_table: DashMap::with_capacity(16),
+            replay_table: DashMap::with_capacity(16),
+            replay_drop_count: AtomicU64::new(0),
+            event_sender: event_channel(),
+            stats: Stats::new(),
             status_receiver,
             status_sender,
             udp_map: DashMap::new(),
+            conn_map: DashMap::new(),
+            conn_index: AtomicUsize::new(0),
+            tcp_punch_sender,
+            quic_punch_sender,
+            socks5_udp,
+            fragment,
             channel_num,
             current_device,
+            server_changed: Notify::new(),
         });
         Self { inner }
     }
@@ -77,9 +137,16 @@ impl Context {
     pub fn close(&self) {
         let _ = self.inner.status_sender.send(Status::Close);
     }
+    /// 切换了服务端地址，和旧服务端的tcp、quic连接需要断开重连
+    pub fn server_changed(&self) {
+        self.inner.server_changed.notify_one();
+    }
     pub fn is_main_tcp(&self) -> bool {
         self.inner.main_tcp_channel.is_some()
     }
+    pub fn is_fragment(&self) -> bool {
+        self.inner.fragment
+    }
     pub fn switch(&self, nat_type: NatType) {
         match nat_type {
             NatType::Symmetric => {
@@ -103,27 +170,100 @@ impl Context {
         if let Some(ipv6) = &self.inner.main_channel_ipv6 {
             ipv6.local_addr().map(|k| k.port())
         } else {
-            Err(io::Error::new(io::ErrorKind::Other, "not ipv6"))
+            Err(io::Error::other("not ipv6"))
         }
     }
     pub async fn send_main_udp(&self, buf: &[u8], addr: SocketAddr) -> io::Result<usize> {
+        let rs = self.send_main_udp_(buf, addr).await;
+        if rs.is_ok() {
+            self.inner.stats.tx(buf, &RouteKey::new(0, addr));
+        }
+        rs
+    }
+    /// 打洞使用，目标地址大多是猜测的，不计入通道统计
+    pub async fn send_punch_udp(&self, buf: &[u8], addr: SocketAddr) -> io::Result<usize> {
+        self.inner.stats.punch_packet();
+        self.send_main_udp_(buf, addr).await
+    }
+    pub fn record_punch(&self) {
+        self.inner.stats.punch_attempt()
+    }
+    /// 向对端发起tcp连接，连接成功后发送buf
+    pub fn punch_tcp(&self, buf: &[u8], addr: SocketAddr) {
+        if let Some(sender) = &self.inner.tcp_punch_sender {
+            if sender.try_send((addr, buf.to_vec())).is_ok() {
+                self.inner.stats.punch_packet();
+            }
+        }
+    }
+    /// 向对端发起quic连接，连接成功后发送buf
+    pub fn punch_quic(&self, buf: &[u8], addr: SocketAddr) {
+        if let Some(sender) = &self.inner.quic_punch_sender {
+            if sender.try_send((addr, buf.to_vec())).is_ok() {
+                self.inner.stats.punch_packet();
+            }
+        }
+    }
+    pub fn record_birthday_attempt(&self) {
+        self.inner.stats.birthday_attempt()
+    }
+    pub fn record_birthday_success(&self) {
+        self.inner.stats.birthday_success()
+    }
+    async fn send_main_udp_(&self, buf: &[u8], addr: SocketAddr) -> io::Result<usize> {
         if addr.is_ipv6() {
             if let Some(udp_ipv6) = &self.inner.main_channel_ipv6 {
                 udp_ipv6.send_to(buf, addr).await
             } else {
-                Err(io::Error::new(io::ErrorKind::Other, "not ipv6"))
+                Err(io::Error::other("not ipv6"))
             }
+        } else if let Some((packet, relay)) = self.socks5_encode(buf, addr) {
+            self.inner.main_channel.send_to(&packet, relay).await?;
+            Ok(buf.len())
         } else {
             self.inner.main_channel.send_to(buf, addr).await
         }
     }
+    /// 配置了socks5代理时，主通道的ipv4数据加上udp头后发给代理
+    fn socks5_encode(&self, buf: &[u8], addr: SocketAddr) -> Option<(Vec<u8>, SocketAddr)> {
+        let socks5_udp = self.inner.socks5_udp.as_ref()?;
+        Some((socks5_udp.encode(buf, addr), socks5_udp.relay_addr()))
+    }
+    /// 去掉代理转发数据的udp头，数据移动到start位置，返回结束位置和真实的来源地址，
+    /// 不是代理转发的数据原样返回，无效数据返回None
+    fn socks5_decode(
+        &self,
+        buf: &mut [u8],
+        start: usize,
+        end: usize,
+        addr: SocketAddr,
+    ) -> Option<(usize, SocketAddr)> {
+        match &self.inner.socks5_udp {
+            Some(socks5_udp) if socks5_udp.relay_addr() == addr => {
+                let (source, head_len) = Socks5Udp::decode(&buf[start..end])?;
+                buf.copy_within(start + head_len..end, start);
+                Some((end - head_len, source))
+            }
+            _ => Some((end, addr)),
+        }
+    }
     pub fn try_send_main_udp(&self, buf: &[u8], addr: SocketAddr) -> io::Result<usize> {
+        let rs = self.try_send_main_udp_(buf, addr);
+        if rs.is_ok() {
+            self.inner.stats.tx(buf, &RouteKey::new(0, addr));
+        }
+        rs
+    }
+    fn try_send_main_udp_(&self, buf: &[u8], addr: SocketAddr) -> io::Result<usize> {
         if addr.is_ipv6() {
             if let Some(udp_ipv6) = &self.inner.main_channel_ipv6 {
                 udp_ipv6.try_send_to(buf, addr)
             } else {
-                Err(io::Error::new(io::ErrorKind::Other, "not ipv6"))
+                Err(io::Error::other("not ipv6"))
             }
+        } else if let Some((packet, relay)) = self.socks5_encode(buf, addr) {
+            self.inner.main_channel.try_send_to(&packet, relay)?;
+            Ok(buf.len())
         } else {
             self.inner.main_channel.try_send_to(buf, addr)
         }
@@ -131,9 +271,10 @@ impl Context {
     pub async fn send_main(&self, buf: &[u8], addr: SocketAddr) -> io::Result<usize> {
         if let Some(sender) = &self.inner.main_tcp_channel {
             if sender.send(buf.to_vec()).await.is_ok() {
+                self.inner.stats.tx(buf, &RouteKey::new(0, addr));
                 Ok(buf.len())
             } else {
-                Err(io::Error::new(io::ErrorKind::Other, "send_main err"))
+                Err(io::Error::other("send_main err"))
             }
         } else {
             self.send_main_udp(buf, addr).await
@@ -142,9 +283,10 @@ impl Context {
     pub fn try_send_main(&self, buf: &[u8], addr: SocketAddr) -> io::Result<usize> {
         if let Some(sender) = &self.inner.main_tcp_channel {
             if sender.try_send(buf.to_vec()).is_ok() {
+                self.inner.stats.tx(buf, &RouteKey::new(0, addr));
                 Ok(buf.len())
             } else {
-                Err(io::Error::new(io::ErrorKind::Other, "try_send_main err"))
+                Err(io::Error::other("try_send_main err"))
             }
         } else {
             self.try_send_main_udp(buf, addr)
@@ -157,11 +299,39 @@ impl Context {
             drop(udp_ref);
             //使用ipv6的udp发送ipv4报文会出错
             let _ = udp.send_to(buf, addr).await;
+            self.inner.stats.punch_packet();
+        }
+        Ok(())
+    }
+
+    /// 轮流使用各个ipv4端口发送，每个端口都会在NAT上留下一个映射
+    pub(crate) async fn send_spray(&self, buf: &[u8], addrs: &[SocketAddr]) -> io::Result<()> {
+        let udps: Vec<Arc<UdpSocket>> = self
+            .inner
+            .udp_map
+            .iter()
+            .map(|v| v.value().clone())
+            .filter(|udp| udp.local_addr().map_or(false, |addr| addr.is_ipv4()))
+            .collect();
+        if udps.is_empty() {
+            return Err(io::Error::other("not ipv4 channel"));
+        }
+        for (index, addr) in addrs.iter().enumerate() {
+            let _ = udps[index % udps.len()].send_to(buf, *addr).await;
+            self.inner.stats.punch_packet();
+            if (index + 1) % udps.len() == 0 {
+                tokio::time::sleep(Duration::from_millis(2)).await;
+            }
         }
         Ok(())
     }
 
     pub async fn send_by_id(&self, buf: &[u8], id: &Ipv4Addr) -> io::Result<usize> {
+        let route_key = self.route_key_by_id(id)?;
+        self.send_by_key(buf, &route_key).await
+    }
+    /// 发往目标客户端使用的通道
+    pub fn route_key_by_id(&self, id: &Ipv4Addr) -> io::Result<RouteKey> {
         if let Some(v) = self.inner.route_table.get(id) {
             if v.value().is_empty() {
                 return Err(io::Error::new(io::ErrorKind::NotFound, "route not found"));
@@ -180,10 +350,62 @@ impl Context {
                     }
                 }
             }
-            return self.send_by_key(buf, &route.route_key()).await;
+            return Ok(route.route_key());
         }
         Err(io::Error::new(io::ErrorKind::NotFound, "route not found"))
     }
+    /// 可以直接批量发送的udp通道，经过socks5代理的主通道不能直接发送
+    pub(crate) fn batch_udp(&self, route_key: &RouteKey) -> Option<Arc<UdpSocket>> {
+        if !route_key.protocol().is_udp() {
+            return None;
+        }
+        let udp = self.inner.udp_map.get(&route_key.index)?.value().clone();
+        if Arc::ptr_eq(&udp, &self.inner.main_channel) && self.inner.socks5_udp.is_some() {
+            return None;
+        }
+        Some(udp)
+    }
+    /// 按通道分组后批量发送，不能批量发送的通道逐个发送
+    pub async fn send_batch(&self, packets: &[(Vec<u8>, RouteKey)]) -> io::Result<()> {
+        let mut rs = Ok(());
+        let mut groups: Vec<(usize, Arc<UdpSocket>, Vec<usize>)> = Vec::new();
+        for (i, (buf, route_key)) in packets.iter().enumerate() {
+            if let Some((_, _, list)) = groups
+                .iter_mut()
+                .find(|(index, _, _)| *index == route_key.index && route_key.protocol().is_udp())
+            {
+                list.push(i);
+            } else if let Some(udp) = self.batch_udp(route_key) {
+                groups.push((route_key.index, udp, vec![i]));
+            } else if let Err(e) = self.send_by_key(buf, route_key).await {
+                rs = Err(e);
+            }
+        }
+        for (_, udp, list) in groups {
+            let batch: Vec<(&[u8], SocketAddr)> = list
+                .iter()
+                .map(|i| (&packets[*i].0[..], packets[*i].1.addr))
+                .collect();
+            for chunk in batch.chunks(batch::BATCH_SIZE) {
+                if let Err(e) = batch::send_batch(&udp, chunk).await {
+                    rs = Err(e);
+                }
+            }
+            for i in list {
+                self.inner.stats.tx(&packets[i].0, &packets[i].1);
+            }
+        }
+        rs
+    }
+
+    /// 按虚拟ipv4或者虚拟ipv6发送到对端
+    pub async fn send_by_ip(&self, buf: &[u8], ip: &IpAddr) -> io::Result<usize> {
+        if let Some(id) = self.ip_to_id(ip) {
+            self.send_by_id(buf, &id).await
+        } else {
+            Err(io::Error::new(io::ErrorKind::NotFound, "route not found"))
+        }
+    }
 
     pub fn try_send_by_id(&self, buf: &[u8], id: &Ipv4Addr) -> io::Result<usize> {
         if let Some(v) = self.inner.route_table.get(id) {
@@ -192,13 +414,31 @@ impl Context {
             }
             let route = v.value()[0];
             drop(v);
-            if let Some(udp) = self.inner.udp_map.get(&route.index) {
-                return udp.value().try_send_to(buf, route.addr);
-            }
+            return self.try_send_by_key(buf, &route.route_key());
         }
         Err(io::Error::new(io::ErrorKind::NotFound, "route not found"))
     }
     pub async fn send_by_key(&self, buf: &[u8], route_key: &RouteKey) -> io::Result<usize> {
+        let rs = self.send_by_key_(buf, route_key).await;
+        if rs.is_ok() {
+            self.inner.stats.tx(buf, route_key);
+        }
+        rs
+    }
+    async fn send_by_key_(&self, buf: &[u8], route_key: &RouteKey) -> io::Result<usize> {
+        if !route_key.protocol().is_udp() {
+            let sender = self
+                .inner
+                .conn_map
+                .get(&route_key.index)
+                .map(|v| v.value().clone());
+            if let Some(sender) = sender {
+                if sender.send(buf.to_vec()).await.is_ok() {
+                    return Ok(buf.len());
+                }
+            }
+            return Err(io::Error::new(io::ErrorKind::NotFound, "route not found"));
+        }
         if route_key.index == 0 {
             if let Some(sender) = &self.inner.main_tcp_channel {
                 let mut vec = vec![0; 4 + buf.len()];
@@ -206,18 +446,41 @@ impl Context {
                 return if sender.send(vec).await.is_ok() {
                     Ok(buf.len())
                 } else {
-                    Err(io::Error::new(io::ErrorKind::Other, "send_by_key err"))
+                    Err(io::Error::other("send_by_key err"))
                 };
             }
         }
         if let Some(udp_ref) = self.inner.udp_map.get(&route_key.index) {
             let udp = udp_ref.value().clone();
             drop(udp_ref);
+            if Arc::ptr_eq(&udp, &self.inner.main_channel) {
+                if let Some((packet, relay)) = self.socks5_encode(buf, route_key.addr) {
+                    udp.send_to(&packet, relay).await?;
+                    return Ok(buf.len());
+                }
+            }
             return udp.send_to(buf, route_key.addr).await;
         }
         Err(io::Error::new(io::ErrorKind::NotFound, "route not found"))
     }
     pub fn try_send_by_key(&self, buf: &[u8], route_key: &RouteKey) -> io::Result<usize> {
+        let rs = self.try_send_by_key_(buf, route_key);
+        if rs.is_ok() {
+            self.inner.stats.tx(buf, route_key);
+        }
+        rs
+    }
+    fn try_send_by_key_(&self, buf: &[u8], route_key: &RouteKey) -> io::Result<usize> {
+        if !route_key.protocol().is_udp() {
+            if let Some(sender) = self.inner.conn_map.get(&route_key.index) {
+                return if sender.value().try_send(buf.to_vec()).is_ok() {
+                    Ok(buf.len())
+                } else {
+                    Err(io::Error::other("try_send_by_key err"))
+                };
+            }
+            return Err(io::Error::new(io::ErrorKind::NotFound, "route not found"));
+        }
         if route_key.index == 0 {
             if let Some(sender) = &self.inner.main_tcp_channel {
                 let mut vec = vec![0; 4 + buf.len()];
@@ -225,11 +488,17 @@ impl Context {
                 return if sender.try_send(vec).is_ok() {
                     Ok(buf.len())
                 } else {
-                    Err(io::Error::new(io::ErrorKind::Other, "try_send_by_key err"))
+                    Err(io::Error::other("try_send_by_key err"))
                 };
             }
         }
         if let Some(udp) = self.inner.udp_map.get(&route_key.index) {
+            if Arc::ptr_eq(udp.value(), &self.inner.main_channel) {
+                if let Some((packet, relay)) = self.socks5_encode(buf, route_key.addr) {
+                    udp.value().try_send_to(&packet, relay)?;
+                    return Ok(buf.len());
+                }
+            }
             return udp.value().try_send_to(buf, route_key.addr);
         }
         Err(io::Error::new(io::ErrorKind::NotFound, "route not found"))
@@ -249,14 +518,15 @@ impl Context {
             .or_insert_with(|| Vec::with_capacity(4));
         let mut exist = false;
         for x in list.iter_mut() {
-            if x.metric < route.metric {
-                //不能比当前的路径更长
+            if x.is_p2p() && x.metric < route.metric {
+                //已经直连了，不使用中继
                 return;
             }
             if x.route_key() == key {
                 if only_if_absent {
                     return;
                 }
+                // 探测到的mtu保留，不被心跳更新覆盖
                 x.metric = route.metric;
                 x.rt = route.rt;
                 exist = true;
@@ -266,21 +536,78 @@ impl Context {
         if exist {
             list.sort_by_key(|k| k.sort_key());
         } else {
+            let mut removed = Vec::new();
             if route.metric == 1 {
                 //添加了直连的则排除非直连的
-                list.retain(|k| k.metric == 1);
+                list.retain(|k| {
+                    if k.metric != 1 {
+                        removed.push(*k);
+                    }
+                    k.metric == 1
+                });
             }
             list.push(route);
             list.sort_by_key(|k| k.sort_key());
             let max_len = self.inner.channel_num + 1;
             if list.len() > max_len {
-                list.truncate(max_len);
+                removed.extend(list.drain(max_len..));
+            }
+            drop(list);
+            let mut added = true;
+            for x in removed {
+                if x.route_key() == key {
+                    //新路由排在最后被挤掉了
+                    added = false;
+                    continue;
+                }
+                self.inner.route_table_time.remove(&(x.route_key(), id));
+                send_event(
+                    &self.inner.event_sender,
+                    VntEvent::RouteRemove(id, x.route_key()),
+                );
+                self.remove_route_stats(&x.route_key());
             }
+            if !added {
+                return;
+            }
+            send_event(&self.inner.event_sender, VntEvent::RouteAdd(id, route));
         }
         self.inner
             .route_table_time
             .insert((key, id), Instant::now());
     }
+    /// 添加路由通告学到的中继路由，已存在时刷新跳数和延迟，
+    /// 不刷新通信时间，长时间不通信的中继路由仍然不会被选用
+    pub fn add_advertised_route(&self, id: Ipv4Addr, route: Route) {
+        let key = route.route_key();
+        self.inner
+            .route_advertise_time
+            .insert((key, id), Instant::now());
+        if let Some(mut list) = self.inner.route_table.get_mut(&id) {
+            if let Some(x) = list.iter_mut().find(|x| x.route_key() == key) {
+                x.metric = route.metric;
+                x.rt = route.rt;
+                list.sort_by_key(|k| k.sort_key());
+                return;
+            }
+        }
+        self.add_route_if_absent(id, route);
+    }
+    /// 移除超过timeout没有再次通告的中继路由
+    pub fn expire_advertised_routes(&self, timeout: Duration) {
+        let mut expired = Vec::new();
+        self.inner.route_advertise_time.retain(|(key, id), time| {
+            if time.elapsed() > timeout {
+                expired.push((*id, *key));
+                false
+            } else {
+                true
+            }
+        });
+        for (id, key) in expired {
+            self.remove_route(&id, key);
+        }
+    }
     pub fn route(&self, id: &Ipv4Addr) -> Option<Vec<Route>> {
         if let Some(v) = self.inner.route_table.get(id) {
             Some(v.value().clone())
@@ -295,6 +622,43 @@ impl Context {
             None
         }
     }
+    pub fn route_by_ip(&self, ip: &IpAddr) -> Option<Vec<Route>> {
+        self.route(&self.ip_to_id(ip)?)
+    }
+    /// 虚拟ipv6转换为节点标识
+    pub fn ipv6_to_id(&self, ipv6: &Ipv6Addr) -> Option<Ipv4Addr> {
+        self.inner.ipv6_table.get(ipv6).map(|v| *v.value())
+    }
+    /// 虚拟ip转换为节点标识，虚拟ipv4就是节点标识
+    pub fn ip_to_id(&self, ip: &IpAddr) -> Option<Ipv4Addr> {
+        match ip {
+            IpAddr::V4(ip) => Some(*ip),
+            IpAddr::V6(ip) => self.ipv6_to_id(ip),
+        }
+    }
+    /// 对端离线或者被移除后，清理会话密钥和防重放窗口
+    pub fn update_session_table(&self, device_list: &[PeerDeviceInfo]) {
+        let online = |id: &Ipv4Addr| {
+            device_list
+                .iter()
+                .any(|info| info.virtual_ip == *id && info.status == PeerDeviceStatus::Online)
+        };
+        self.inner.session_table.retain(|id, _| online(id));
+        self.inner.replay_table.retain(|id, _| online(id));
+    }
+    /// 根据设备列表刷新ipv6映射
+    pub fn update_ipv6_table(&self, device_list: &[PeerDeviceInfo]) {
+        self.inner.ipv6_table.retain(|ipv6, id| {
+            device_list
+                .iter()
+                .any(|info| info.virtual_ip == *id && info.virtual_ipv6 == Some(*ipv6))
+        });
+        for info in device_list {
+            if let Some(ipv6) = info.virtual_ipv6 {
+                self.inner.ipv6_table.insert(ipv6, info.virtual_ip);
+            }
+        }
+    }
     pub fn route_to_id(&self, route_key: &RouteKey) -> Option<Ipv4Addr> {
         for x in self.inner.route_table.iter() {
             for route in x.value() {
@@ -344,6 +708,11 @@ impl Context {
         if let Some((_, routes)) = self.inner.route_table.remove(id) {
             for x in routes {
                 self.inner.route_table_time.remove(&(x.route_key(), *id));
+                send_event(
+                    &self.inner.event_sender,
+                    VntEvent::RouteRemove(*id, x.route_key()),
+                );
+                self.remove_route_stats(&x.route_key());
             }
         }
     }
@@ -351,16 +720,208 @@ impl Context {
         if let Some(v) = self.inner.route_table.get(id) {
             let mut routes = v.value().clone();
             drop(v);
+            let len = routes.len();
             routes.retain(|x| x.route_key() != route_key);
+            if routes.len() != len {
+                send_event(
+                    &self.inner.event_sender,
+                    VntEvent::RouteRemove(*id, route_key),
+                );
+            }
             self.inner.route_table.insert(*id, routes);
         }
         self.inner.route_table_time.remove(&(route_key, *id));
+        self.remove_route_stats(&route_key);
+    }
+    /// 其他对端还在使用该通道时保留统计
+    fn remove_route_stats(&self, route_key: &RouteKey) {
+        let in_use = self
+            .inner
+            .route_table
+            .iter()
+            .any(|v| v.value().iter().any(|x| x.route_key() == *route_key));
+        if !in_use {
+            self.inner.stats.remove_route(route_key);
+        }
+    }
+    /// 有会话密钥时使用会话密钥加密，否则使用默认的加密方式
+    pub fn encrypt_by_id<B: AsRef<[u8]> + AsMut<[u8]>>(
+        &self,
+        id: &Ipv4Addr,
+        cipher: &Cipher,
+        net_packet: &mut NetPacket<B>,
+    ) -> io::Result<()> {
+        if let Some(session) = self.inner.session_table.get(id) {
+            if let Some((session_cipher, parity)) = session.value().send_cipher() {
+                net_packet.set_session_key_flag(true, parity);
+                return session_cipher.encrypt_ipv4(net_packet);
+            }
+        }
+        net_packet.set_session_key_flag(false, 0);
+        cipher.encrypt_ipv4(net_packet)
+    }
+    /// 根据数据包头部的会话密钥标志选择解密方式
+    ///
+    /// 和对端有会话密钥后，发给本机的ip数据只接受会话密钥加密的，
+    /// 密码只用于控制包和广播，避免被降级成密码加密
+    pub fn decrypt_by_id<B: AsRef<[u8]> + AsMut<[u8]>>(
+        &self,
+        id: &Ipv4Addr,
+        local_ip: &Ipv4Addr,
+        cipher: &Cipher,
+        net_packet: &mut NetPacket<B>,
+    ) -> io::Result<()> {
+        let is_encrypt = net_packet.is_encrypt();
+        if !net_packet.is_session_key() {
+            if is_encrypt
+                && net_packet.protocol() == Protocol::IpTurn
+                && net_packet.destination() == *local_ip
+                && self
+                    .inner
+                    .session_table
+                    .get(id)
+                    .map_or(false, |session| session.is_established())
+            {
+                self.inner.stats.decrypt_failure(id);
+                return Err(io::Error::other(format!(
+                    "session key required from {}",
+                    id
+                )));
+            }
+            if let Err(e) = cipher.decrypt_ipv4(net_packet) {
+                self.inner.stats.decrypt_failure(id);
+                return Err(e);
+            }
+            //aes_cbc/aes_ecb的序号没有认证，不做防重放；
+            //协商包有自己的轮次和随机数校验，不经过窗口，对端重启后序号变小也能重新协商
+            if is_encrypt && cipher.is_authenticated() && !is_key_exchange(net_packet) {
+                let seq = decrypted_sequence(net_packet);
+                let pass = seq.map_or(false, |seq| {
+                    self.inner
+                        .replay_table
+                        .entry(*id)
+                        .or_insert_with(ReplayWindow::new)
+                        .check_and_update(seq)
+                });
+                if !pass {
+                    return Err(self.replay_error(id));
+                }
+            }
+            return Ok(());
+        }
+        let parity = net_packet.session_key_parity();
+        if let Some(mut session) = self.inner.session_table.get_mut(id) {
+            let rs = if let Some(session_cipher) = session.value().recv_cipher(parity) {
+                session_cipher
+                    .decrypt_ipv4(net_packet)
+                    .map(|_| session_cipher.is_authenticated())
+            } else {
+                Err(io::Error::other("session key not found"))
+            };
+            let authenticated = match rs {
+                Ok(authenticated) => authenticated,
+                Err(e) => {
+                    self.inner.stats.decrypt_failure(id);
+                    return Err(e);
+                }
+            };
+            if authenticated {
+                let seq = decrypted_sequence(net_packet);
+                if !seq.map_or(false, |seq| session.value_mut().check_replay(parity, seq)) {
+                    return Err(self.replay_error(id));
+                }
+            }
+            if session.value().need_switch(parity) {
+                session.value_mut().switch();
+            }
+        } else {
+            self.inner.stats.decrypt_failure(id);
+            return Err(io::Error::other("session key not found"));
+        }
+        Ok(())
+    }
+    /// 对端重启或者重新协商后，以对端告知的序号重建使用密码加密时的防重放窗口
+    pub fn reset_replay_window(&self, id: Ipv4Addr, floor: u32) {
+        self.inner
+            .replay_table
+            .insert(id, ReplayWindow::with_floor(floor));
+    }
+    fn replay_error(&self, id: &Ipv4Addr) -> io::Error {
+        self.inner.replay_drop_count.fetch_add(1, Ordering::Relaxed);
+        self.inner.stats.drop_packet(id);
+        io::Error::other(format!("replay packet from {}", id))
+    }
+    pub fn event_sender(&self) -> EventSender {
+        self.inner.event_sender.clone()
+    }
+    pub fn send_event(&self, event: VntEvent) {
+        send_event(&self.inner.event_sender, event)
+    }
+    /// 订阅事件，只能收到订阅之后发生的事件
+    pub fn subscribe(&self) -> EventReceiver {
+        self.inner.event_sender.subscribe()
+    }
+    /// 收到的数据，len为解密前vnt数据包的长度，id为对端虚拟ip
+    pub fn record_rx(&self, len: usize, route_key: &RouteKey, id: Option<&Ipv4Addr>) {
+        self.inner.stats.rx(len, route_key, id)
+    }
+    /// 根据设备列表更新需要统计的对端
+    pub fn update_stats_peers(&self, device_list: &[PeerDeviceInfo]) {
+        let ids: Vec<Ipv4Addr> = device_list.iter().map(|info| info.virtual_ip).collect();
+        self.inner.stats.update_peers(&ids);
+    }
+    pub fn record_drop(&self, id: &Ipv4Addr) {
+        self.inner.stats.drop_packet(id)
+    }
+    pub fn stats(&self) -> VntStats {
+        self.inner.stats.load()
+    }
+    /// 因重放或者过旧而丢弃的数据包数量
+    pub fn replay_drop_count(&self) -> u64 {
+        self.inner.replay_drop_count.load(Ordering::Relaxed)
     }
     pub fn update_read_time(&self, id: &Ipv4Addr, route_key: &RouteKey) {
         if let Some(mut time) = self.inner.route_table_time.get_mut(&(*route_key, *id)) {
             *time.value_mut() = Instant::now();
         }
     }
+    /// 优先路由的路径mtu，未探测时返回None
+    pub fn route_mtu(&self, id: &Ipv4Addr) -> Option<u16> {
+        let route = self.route_one(id)?;
+        if route.mtu == 0 {
+            None
+        } else {
+            Some(route.mtu)
+        }
+    }
+    pub fn set_route_mtu(&self, id: &Ipv4Addr, route_key: &RouteKey, mtu: u16) {
+        if let Some(mut list) = self.inner.route_table.get_mut(id) {
+            for route in list.iter_mut() {
+                if route.route_key() == *route_key {
+                    route.mtu = mtu;
+                }
+            }
+        }
+    }
+    /// 开始一轮路径mtu探测，之前的结果清空
+    pub fn start_mtu_probe(&self, id: Ipv4Addr, route_key: RouteKey) {
+        self.inner.mtu_probe_table.insert((route_key, id), 0);
+    }
+    /// 收到探测响应，只记录进行中的探测
+    pub fn update_mtu_probe(&self, id: Ipv4Addr, route_key: RouteKey, mtu: u16) {
+        if let Some(mut max) = self.inner.mtu_probe_table.get_mut(&(route_key, id)) {
+            if *max.value() < mtu {
+                *max.value_mut() = mtu;
+            }
+        }
+    }
+    /// 结束探测，返回对端确认收到的最大长度，0表示都没有收到
+    pub fn finish_mtu_probe(&self, id: Ipv4Addr, route_key: RouteKey) -> u16 {
+        self.inner
+            .mtu_probe_table
+            .remove(&(route_key, id))
+            .map_or(0, |(_, mtu)| mtu)
+    }
 }
 
 pub struct Channel {
@@ -390,6 +951,16 @@ impl BufSenderGroup {
     }
 }
 
+/// 会话密钥协商的请求和响应
+fn is_key_exchange<B: AsRef<[u8]>>(net_packet: &NetPacket<B>) -> bool {
+    net_packet.protocol() == Protocol::Control
+        && matches!(
+            control_packet::Protocol::from(net_packet.transport_protocol()),
+            control_packet::Protocol::KeyExchangeRequest
+                | control_packet::Protocol::KeyExchangeResponse
+        )
+}
+
 fn buf_channel_group(size: usize) -> (BufSenderGroup, BufReceiverGroup) {
     let mut buf_sender_group = Vec::with_capacity(size);
     let mut buf_receiver_group = Vec::with_capacity(size);
@@ -406,14 +977,13 @@ fn buf_channel_group(size: usize) -> (BufSenderGroup, BufReceiverGroup) {
 }
 
 impl Channel {
-    async fn tcp_handle(
-        mut tcp_r: OwnedReadHalf,
+    async fn tcp_handle<R: AsyncRead + Unpin>(
+        mut tcp_r: R,
         mut buf_sender: BufSenderGroup,
         head_reserve: usize,
+        key: RouteKey,
     ) -> io::Result<()> {
         let mut head = [0; 4];
-        let addr = tcp_r.peer_addr()?;
-        let key = RouteKey::new(0, addr);
         loop {
             let mut buf = POOL.alloc(4096);
             tcp_r.read_exact(&mut head).await?;
@@ -431,64 +1001,106 @@ impl Channel {
                 .send((buf, head_reserve, head_reserve + len, key))
                 .await
             {
-                return Err(io::Error::new(
-                    io::ErrorKind::Other,
-                    "buf_sender发送数据失败",
-                ));
+                return Err(io::Error::other("buf_sender发送数据失败"));
             }
         }
     }
+    fn spawn_tcp_handle(
+        tcp_r: ReadHalf<ServerStream>,
+        buf_sender: BufSenderGroup,
+        head_reserve: usize,
+        key: RouteKey,
+        broken: Arc<AtomicBool>,
+    ) {
+        tokio::spawn(async move {
+            if let Err(e) = Self::tcp_handle(tcp_r, buf_sender, head_reserve, key).await {
+                log::info!("tcp链接断开:{:?}", e);
+            }
+            broken.store(true, Ordering::Relaxed);
+        });
+    }
     async fn start_tcp(
         mut worker: VntWorker,
-        tcp_stream: TcpStream,
+        tcp_stream: ServerStream,
         mut receiver: tokio::sync::mpsc::Receiver<Vec<u8>>,
-        current_device: Arc<AtomicCell<CurrentDeviceInfo>>,
+        context: Context,
         buf_sender: BufSenderGroup,
         head_reserve: usize,
+        connector: ServerConnector,
     ) {
-        let (tcp_r, mut tcp_w) = tcp_stream.into_split();
-        {
-            let buf_sender = buf_sender.clone();
-            tokio::spawn(async move {
-                if let Err(e) = Self::tcp_handle(tcp_r, buf_sender, head_reserve).await {
-                    log::info!("tcp链接断开:{:?}", e);
-                }
-            });
-        }
+        let current_device = &context.inner.current_device;
+        let server_changed = &context.inner.server_changed;
+        let (tcp_r, mut tcp_w) = tokio::io::split(tcp_stream);
+        // 经过代理或websocket时对端地址不是服务端，统一使用服务端地址
+        let mut connect_server = current_device.load().connect_server;
+        let key = RouteKey::new(0, connect_server);
+        // 读取端断开后标记，下次发送时重连，每个连接使用独立的标记
+        let mut broken = Arc::new(AtomicBool::new(false));
+        Self::spawn_tcp_handle(tcp_r, buf_sender.clone(), head_reserve, key, broken.clone());
+        let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(30));
+        let mut retry_time: Option<Instant> = None;
         let mut head = [0; 4];
         loop {
             tokio::select! {
                 _=worker.stop_wait()=>{
                     break;
                 }
+                _=server_changed.notified()=>{
+                    // 断开旧服务端的连接，下次发送时连接新地址
+                    log::info!("服务端地址变化,断开连接:{}", connect_server);
+                    let _ = tcp_w.shutdown().await;
+                    broken.store(true, Ordering::Relaxed);
+                    retry_time = None;
+                    backoff.reset();
+                }
                 rs=receiver.recv()=>{
                     if let Some(data) = rs{
-                        let len = data.len();
-                        head[2] = (len >> 8) as u8;
-                        head[3] = (len & 0xFF) as u8;
-                        let mut err = false;
-                        if let Err(e) = tcp_w.write_all(&head).await{
+                        let mut err = broken.swap(false, Ordering::Relaxed);
+                        if !err && connect_server != current_device.load().connect_server {
+                            // 通知和数据同时到达时，保证数据不会发往旧服务端
                             err = true;
-                            log::info!("发送失败,需要重连:{:?}",e);
-                        }else if let Err(e) = tcp_w.write_all(&data).await{
-                            err = true;
-                            log::info!("发送失败,需要重连:{:?}",e);
+                            retry_time = None;
+                        }
+                        if !err {
+                            let len = data.len();
+                            head[2] = (len >> 8) as u8;
+                            head[3] = (len & 0xFF) as u8;
+                            if let Err(e) = tcp_w.write_all(&head).await{
+                                err = true;
+                                log::info!("发送失败,需要重连:{:?}",e);
+                            }else if let Err(e) = tcp_w.write_all(&data).await{
+                                err = true;
+                                log::info!("发送失败,需要重连:{:?}",e);
+                            }else if let Err(e) = tcp_w.flush().await{
+                                err = true;
+                                log::info!("发送失败,需要重连:{:?}",e);
+                            }
                         }
                         if err {
+                            if let Some(time) = retry_time {
+                                if Instant::now() < time {
+                                    // 退避时间内不重连，保持断开状态
+                                    broken.store(true, Ordering::Relaxed);
+                                    continue;
+                                }
+                            }
                             let _ = tcp_w.shutdown().await;
-                            match TcpStream::connect(current_device.load().connect_server).await {
+                            connect_server = current_device.load().connect_server;
+                            match connector.connect(connect_server).await {
                                 Ok(tcp_stream) => {
-                                    let (r, w) = tcp_stream.into_split();
+                                    let (r, w) = tokio::io::split(tcp_stream);
                                     tcp_w = w;
-                                    let buf_sender = buf_sender.clone();
-                                    tokio::spawn(async move {
-                                        if let Err(e) = Self::tcp_handle(r, buf_sender, head_reserve).await {
-                                            log::info!("tcp 链接断开:{:?}",e);
-                                        }
-                                    });
+                                    broken = Arc::new(AtomicBool::new(false));
+                                    let key = RouteKey::new(0, connect_server);
+                                    Self::spawn_tcp_handle(r, buf_sender.clone(), head_reserve, key, broken.clone());
+                                    backoff.reset();
+                                    retry_time = None;
                                 }
                                 Err(e) => {
-                                    log::info!("重连失败:{:?}",e);
+                                    let wait = backoff.next_delay();
+                                    log::info!("重连失败:{:?},{:?}后重试",e,wait);
+                                    retry_time = Some(Instant::now() + wait);
+                                    broken.store(true, Ordering::Relaxed);
                                 }
                             };
                         }
@@ -501,10 +1113,306 @@ impl Channel {
         worker.stop_all();
     }
 
+    /// 和服务端的quic连接，数据使用数据报收发，断开后按退避时间重连
+    async fn start_quic_server(
+        mut worker: VntWorker,
+        endpoint: quinn::Endpoint,
+        mut connection: quinn::Connection,
+        mut receiver: tokio::sync::mpsc::Receiver<Vec<u8>>,
+        context: Context,
+        buf_sender: BufSenderGroup,
+        head_reserve: usize,
+    ) {
+        let current_device = &context.inner.current_device;
+        let server_changed = &context.inner.server_changed;
+        let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(30));
+        'outer: loop {
+            let key = RouteKey::new(0, connection.remote_address());
+            {
+                let connection = connection.clone();
+                let buf_sender = buf_sender.clone();
+                tokio::spawn(async move {
+                    if let Err(e) =
+                        Self::quic_handle(connection, buf_sender, head_reserve, key).await
+                    {
+                        log::info!("quic链接断开:{:?}", e);
+                    }
+                });
+            }
+            tokio::select! {
+                _=worker.stop_wait()=>{
+                    connection.close(0u32.into(), b"");
+                    break;
+                }
+                _=server_changed.notified()=>{
+                    log::info!("服务端地址变化,断开连接:{}", connection.remote_address());
+                    connection.close(0u32.into(), b"");
+                    backoff.reset();
+                }
+                // 发送数据的前4个字节是tcp通道的头部，quic不需要
+                rs=quic_channel::write_loop(&connection, &mut receiver, 4)=>{
+                    if let Err(e) = rs {
+                        log::info!("quic链接断开,需要重连:{:?}", e);
+                    } else {
+                        break;
+                    }
+                }
+            }
+            loop {
+                tokio::select! {
+                    _=worker.stop_wait()=>{
+                        break 'outer;
+                    }
+                    rs=quic_channel::connect(&endpoint, current_device.load().connect_server)=>{
+                        match rs {
+                            Ok(new_connection) => {
+                                connection = new_connection;
+                                backoff.reset();
+                                break;
+                            }
+                            Err(e) => {
+                                let wait = backoff.next_delay();
+                                log::info!("重连失败:{:?},{:?}后重试", e, wait);
+                                tokio::time::sleep(wait).await;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        worker.stop_all();
+    }
+
+    /// 接受对端的quic连接，或者按请求向对端发起连接，双方同时发起时可以穿透NAT
+    async fn start_quic_punch(
+        mut worker: VntWorker,
+        context: Context,
+        endpoint: quinn::Endpoint,
+        mut receiver: tokio::sync::mpsc::Receiver<(SocketAddr, Vec<u8>)>,
+        buf_sender: BufSenderGroup,
+        head_reserve: usize,
+    ) {
+        loop {
+            tokio::select! {
+                _=worker.stop_wait()=>{
+                    break;
+                }
+                rs=endpoint.accept()=>{
+                    let connecting = match rs {
+                        Some(connecting) => connecting,
+                        None => break,
+                    };
+                    let context = context.clone();
+                    let buf_sender = buf_sender.clone();
+                    tokio::spawn(async move {
+                        match connecting.await {
+                            Ok(connection) => {
+                                log::info!("quic打洞连接 {}", connection.remote_address());
+                                Self::add_quic_connection(&context, connection, None, buf_sender, head_reserve);
+                            }
+                            Err(e) => {
+                                log::debug!("quic打洞accept {:?}", e);
+                            }
+                        }
+                    });
+                }
+                rs=receiver.recv()=>{
+                    let (addr, buf) = match rs {
+                        Some(v) => v,
+                        None => break,
+                    };
+                    let context = context.clone();
+                    let buf_sender = buf_sender.clone();
+                    let endpoint = endpoint.clone();
+                    tokio::spawn(async move {
+                        match tokio::time::timeout(quic_channel::CONNECT_TIMEOUT, quic_channel::connect(&endpoint, addr)).await {
+                            Ok(Ok(connection)) => {
+                                log::info!("quic打洞成功 {}", addr);
+                                Self::add_quic_connection(&context, connection, Some(buf), buf_sender, head_reserve);
+                            }
+                            Ok(Err(e)) => {
+                                log::debug!("quic打洞失败 {} {:?}", addr, e);
+                            }
+                            Err(_) => {
+                                log::debug!("quic打洞超时 {}", addr);
+                            }
+                        }
+                    });
+                }
+            }
+        }
+        worker.stop_all();
+    }
+
+    /// 把quic连接注册成通道，first为连接后首先发送的数据
+    fn add_quic_connection(
+        context: &Context,
+        connection: quinn::Connection,
+        first: Option<Vec<u8>>,
+        buf_sender: BufSenderGroup,
+        head_reserve: usize,
+    ) {
+        let addr = connection.remote_address();
+        let index = context.inner.conn_index.fetch_add(1, Ordering::Relaxed);
+        let route_key = RouteKey::new_quic(index, addr);
+        let (sender, mut receiver) = tokio::sync::mpsc::channel(100);
+        if let Some(first) = first {
+            let _ = sender.try_send(first);
+        }
+        context.inner.conn_map.insert(index, sender);
+        {
+            let connection = connection.clone();
+            tokio::spawn(async move {
+                if let Err(e) = quic_channel::write_loop(&connection, &mut receiver, 0).await {
+                    log::info!("quic打洞连接写入失败 {} {:?}", addr, e);
+                }
+            });
+        }
+        let context = context.clone();
+        tokio::spawn(async move {
+            if let Err(e) = Self::quic_handle(connection, buf_sender, head_reserve, route_key).await
+            {
+                log::info!("quic打洞连接断开 {} {:?}", addr, e);
+            }
+            context.inner.conn_map.remove(&index);
+            if let Some(id) = context.route_to_id(&route_key) {
+                context.remove_route(&id, route_key);
+            }
+        });
+    }
+
+    async fn quic_handle(
+        connection: quinn::Connection,
+        mut buf_sender: BufSenderGroup,
+        head_reserve: usize,
+        key: RouteKey,
+    ) -> io::Result<()> {
+        loop {
+            let data = connection.read_datagram().await.map_err(io::Error::from)?;
+            let len = data.len();
+            let mut buf = POOL.alloc(4096);
+            if len < 12 || head_reserve + len > buf.len() {
+                log::debug!("quic数据长度错误 {} {}", key.addr, len);
+                continue;
+            }
+            buf[head_reserve..head_reserve + len].copy_from_slice(&data);
+            if !buf_sender
+                .send((buf, head_reserve, head_reserve + len, key))
+                .await
+            {
+                return Err(io::Error::other("buf_sender发送数据失败"));
+            }
+        }
+    }
+
+    /// 接受对端的tcp连接，或者按请求向对端发起连接，双方同时发起时可以穿透NAT
+    async fn start_tcp_punch(
+        mut worker: VntWorker,
+        context: Context,
+        tcp_listener: TcpListener,
+        mut receiver: tokio::sync::mpsc::Receiver<(SocketAddr, Vec<u8>)>,
+        buf_sender: BufSenderGroup,
+        head_reserve: usize,
+    ) {
+        let local_port = match tcp_listener.local_addr() {
+            Ok(addr) => addr.port(),
+            Err(e) => {
+                log::error!("tcp打洞监听失败:{:?}", e);
+                return;
+            }
+        };
+        loop {
+            tokio::select! {
+                _=worker.stop_wait()=>{
+                    break;
+                }
+                rs=tcp_listener.accept()=>{
+                    match rs {
+                        Ok((tcp_stream, addr)) => {
+                            log::info!("tcp打洞连接 {}", addr);
+                            Self::add_tcp_stream(&context, tcp_stream, addr, None, buf_sender.clone(), head_reserve);
+                        }
+                        Err(e) => {
+                            log::warn!("tcp打洞accept {:?}", e);
+                        }
+                    }
+                }
+                rs=receiver.recv()=>{
+                    let (addr, buf) = match rs {
+                        Some(v) => v,
+                        None => break,
+                    };
+                    let context = context.clone();
+                    let buf_sender = buf_sender.clone();
+                    tokio::spawn(async move {
+                        match tokio::time::timeout(tcp_channel::CONNECT_TIMEOUT, tcp_channel::connect(addr, local_port)).await {
+                            Ok(Ok(tcp_stream)) => {
+                                log::info!("tcp打洞成功 {}", addr);
+                                Self::add_tcp_stream(&context, tcp_stream, addr, Some(buf), buf_sender, head_reserve);
+                            }
+                            Ok(Err(e)) => {
+                                log::debug!("tcp打洞失败 {} {:?}", addr, e);
+                            }
+                            Err(_) => {
+                                log::debug!("tcp打洞超时 {}", addr);
+                            }
+                        }
+                    });
+                }
+            }
+        }
+        context.inner.conn_map.clear();
+        worker.stop_all();
+    }
+
+    /// 把连接注册成通道，first为连接后首先发送的数据
+    fn add_tcp_stream(
+        context: &Context,
+        tcp_stream: TcpStream,
+        addr: SocketAddr,
+        first: Option<Vec<u8>>,
+        buf_sender: BufSenderGroup,
+        head_reserve: usize,
+    ) {
+        let _ = tcp_stream.set_nodelay(true);
+        let index = context.inner.conn_index.fetch_add(1, Ordering::Relaxed);
+        let route_key = RouteKey::new_tcp(index, addr);
+        let (tcp_r, tcp_w) = tcp_stream.into_split();
+        let (sender, receiver) = tokio::sync::mpsc::channel(100);
+        if let Some(first) = first {
+            let _ = sender.try_send(first);
+        }
+        context.inner.conn_map.insert(index, sender);
+        tokio::spawn(async move {
+            if let Err(e) = tcp_channel::write_loop(tcp_w, receiver).await {
+                log::info!("tcp打洞连接写入失败 {} {:?}", addr, e);
+            }
+        });
+        let context = context.clone();
+        tokio::spawn(async move {
+            if let Err(e) = Self::tcp_handle(tcp_r, buf_sender, head_reserve, route_key).await {
+                log::info!("tcp打洞连接断开 {} {:?}", addr, e);
+            }
+            context.inner.conn_map.remove(&index);
+            if let Some(id) = context.route_to_id(&route_key) {
+                context.remove_route(&id, route_key);
+            }
+        });
+    }
+
     pub async fn start(
         self,
         mut worker: VntWorker,
-        tcp: Option<(TcpStream, tokio::sync::mpsc::Receiver<Vec<u8>>)>,
+        tcp: Option<(
+            ServerStream,
+            tokio::sync::mpsc::Receiver<Vec<u8>>,
+            ServerConnector,
+        )>,
+        tcp_punch: Option<(
+            TcpListener,
+            tokio::sync::mpsc::Receiver<(SocketAddr, Vec<u8>)>,
+        )>, //tcp打洞，监听端口和发起连接的请求
+        quic: Option<QuicChannel>,
         head_reserve: usize,          //头部预留字节
         symmetric_channel_num: usize, //对称网络，则再加一组监听，提升打洞成功率
         relay: bool,
@@ -513,16 +1421,24 @@ impl Channel {
         let handler = self.handler.clone();
         let context = self.context;
         let main_channel = context.inner.main_channel.clone();
-        let buf_sender = if parallel > 1 || tcp.is_some() {
+        let buf_sender = if parallel > 1 || tcp.is_some() || tcp_punch.is_some() || quic.is_some() {
             let (buf_sender, buf_receiver) = buf_channel_group(parallel);
             for mut buf_receiver in buf_receiver.0 {
                 let context = context.clone();
+                #[cfg(not(target_os = "linux"))]
                 let handler = handler.clone();
+                #[cfg(target_os = "linux")]
+                let handler = handler.batch();
                 tokio::spawn(async move {
                     while let Some((mut buf, start, end, route_key)) = buf_receiver.recv().await {
                         handler
                             .handle(&mut buf, start, end, route_key, &context)
                             .await;
+                        // 没有待处理的数据时写入合并的tcp包
+                        #[cfg(target_os = "linux")]
+                        if buf_receiver.is_empty() {
+                            handler.flush();
+                        }
                     }
                 });
             }
@@ -530,12 +1446,46 @@ impl Channel {
         } else {
             None
         };
-        if let Some((tcp_stream, receiver)) = tcp {
+        if let Some((tcp_stream, receiver, connector)) = tcp {
             tokio::spawn(Self::start_tcp(
                 worker.worker("main_channel_tcp"),
                 tcp_stream,
                 receiver,
-                context.inner.current_device.clone(),
+                context.clone(),
+                buf_sender.clone().unwrap(),
+                head_reserve,
+                connector,
+            ));
+        }
+        if let Some(quic) = quic {
+            if let Some((connection, receiver)) = quic.server {
+                tokio::spawn(Self::start_quic_server(
+                    worker.worker("quic_server"),
+                    quic.endpoint.clone(),
+                    connection,
+                    receiver,
+                    context.clone(),
+                    buf_sender.clone().unwrap(),
+                    head_reserve,
+                ));
+            }
+            if let Some(receiver) = quic.punch_receiver {
+                tokio::spawn(Self::start_quic_punch(
+                    worker.worker("quic_punch"),
+                    context.clone(),
+                    quic.endpoint,
+                    receiver,
+                    buf_sender.clone().unwrap(),
+                    head_reserve,
+                ));
+            }
+        }
+        if let Some((tcp_listener, receiver)) = tcp_punch {
+            tokio::spawn(Self::start_tcp_punch(
+                worker.worker("tcp_punch"),
+                context.clone(),
+                tcp_listener,
+                receiver,
                 buf_sender.clone().unwrap(),
                 head_reserve,
             ));
@@ -631,6 +1581,20 @@ impl Channel {
         let id = 1 + udp.as_raw_fd() as usize;
         context.inner.udp_map.insert(id, udp.clone());
         match buf_sender {
+            //对称网络下的临时端口数量多，只在主通道上批量接收
+            #[cfg(target_os = "linux")]
+            buf_sender if is_core => {
+                Self::recv_batch(
+                    &mut worker,
+                    &context,
+                    &udp,
+                    id,
+                    &handler,
+                    buf_sender,
+                    head_reserve,
+                )
+                .await;
+            }
             None => {
                 let mut buf = [0; 4096];
                 loop {
@@ -638,7 +1602,9 @@ impl Channel {
                         rs=udp.recv_from(&mut buf[head_reserve..])=>{
                               match rs {
                                 Ok((len, addr)) => {
-                                    handler.handle(&mut buf, head_reserve, head_reserve + len, RouteKey::new(id, addr), &context).await;
+                                    if let Some((end, addr)) = context.socks5_decode(&mut buf, head_reserve, head_reserve + len, addr) {
+                                        handler.handle(&mut buf, head_reserve, end, RouteKey::new(id, addr), &context).await;
+                                    }
                                 }
                                 Err(e) => {
                                     log::error!("{:?}",e)
@@ -677,9 +1643,11 @@ impl Channel {
                     rs=udp.recv_from(&mut buf[head_reserve..])=>{
                          match rs {
                             Ok((len, addr)) => {
-                                if !buf_sender.send((buf,head_reserve,head_reserve+len,RouteKey::new(id, addr))).await{
-                                     log::error!("udp buf_sender发送数据失败");
-                                     break;
+                                if let Some((end, addr)) = context.socks5_decode(&mut buf[..], head_reserve, head_reserve + len, addr) {
+                                    if !buf_sender.send((buf,head_reserve,end,RouteKey::new(id, addr))).await{
+                                         log::error!("udp buf_sender发送数据失败");
+                                         break;
+                                    }
                                 }
                             }
                             Err(e) => {
@@ -718,4 +1686,86 @@ impl Channel {
             worker.stop_all();
         }
     }
+    /// 批量接收，gro合并的数据拆分后和逐个接收一样处理
+    #[cfg(target_os = "linux")]
+    async fn recv_batch(
+        worker: &mut VntWorker,
+        context: &Context,
+        udp: &UdpSocket,
+        id: usize,
+        handler: &ChannelDataHandler,
+        mut buf_sender: Option<BufSenderGroup>,
+        head_reserve: usize,
+    ) {
+        let mut status_receiver = context.inner.status_receiver.clone();
+        let mut batch = RecvBatch::new(udp);
+        let handler = handler.batch();
+        let mut buf = [0; 4096];
+        loop {
+            tokio::select! {
+                rs=batch.recv(udp)=>{
+                    if let Err(e) = rs {
+                        log::error!("{:?}",e);
+                        continue;
+                    }
+                }
+                changed=status_receiver.changed()=>{
+                    match changed {
+                        Ok(_) => {
+                            if *status_receiver.borrow() == Status::Close {
+                                break;
+                            }
+                        }
+                        Err(_) => {
+                            break;
+                        }
+                    }
+                    continue;
+                }
+                _=worker.stop_wait()=>{
+                    break;
+                }
+            }
+            for (data, addr) in batch.packets() {
+                if head_reserve + data.len() > buf.len() {
+                    continue;
+                }
+                let end = head_reserve + data.len();
+                match &mut buf_sender {
+                    None => {
+                        buf[head_reserve..end].copy_from_slice(data);
+                        if let Some((end, addr)) =
+                            context.socks5_decode(&mut buf, head_reserve, end, addr)
+                        {
+                            handler
+                                .handle(
+                                    &mut buf,
+                                    head_reserve,
+                                    end,
+                                    RouteKey::new(id, addr),
+                                    context,
+                                )
+                                .await;
+                        }
+                    }
+                    Some(buf_sender) => {
+                        let mut block = POOL.alloc(4096);
+                        block[head_reserve..end].copy_from_slice(data);
+                        if let Some((end, addr)) =
+                            context.socks5_decode(&mut block[..], head_reserve, end, addr)
+                        {
+                            if !buf_sender
+                                .send((block, head_reserve, end, RouteKey::new(id, addr)))
+                                .await
+                            {
+                                log::error!("udp buf_sender发送数据失败");
+                                return;
+                            }
+                        }
+                    }
+                }
+            }
+            handler.flush();
+        }
+    }
 }
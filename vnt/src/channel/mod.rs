@@ -1,9 +1,17 @@
 use std::net::SocketAddr;
 
+pub mod batch;
 pub mod channel;
 pub mod idle;
+pub mod proxy;
 pub mod punch;
+pub mod quic_channel;
+pub mod replay_window;
 pub mod sender;
+pub mod session;
+pub mod stats;
+pub mod tcp_channel;
+pub mod ws_channel;
 
 #[derive(Copy, Clone, Eq, PartialEq)]
 pub enum Status {
@@ -12,12 +20,37 @@ pub enum Status {
     Close,
 }
 
+/// 通道使用的传输协议
+#[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
+pub enum ConnectProtocol {
+    Udp,
+    /// tcp打洞建立的连接
+    Tcp,
+    /// quic连接，数据使用quic数据报发送
+    Quic,
+}
+
+impl ConnectProtocol {
+    pub fn is_udp(&self) -> bool {
+        *self == ConnectProtocol::Udp
+    }
+    pub fn is_tcp(&self) -> bool {
+        *self == ConnectProtocol::Tcp
+    }
+    pub fn is_quic(&self) -> bool {
+        *self == ConnectProtocol::Quic
+    }
+}
+
 #[derive(Copy, Clone, Debug)]
 pub struct Route {
+    protocol: ConnectProtocol,
     index: usize,
     pub addr: SocketAddr,
     pub metric: u8,
     pub rt: i64,
+    /// 探测到的路径mtu，即对端能收到的最大数据包长度，0表示未探测
+    pub mtu: u16,
 }
 
 #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
@@ -29,29 +62,38 @@ pub struct RouteSortKey {
 impl Route {
     pub fn new(index: usize, addr: SocketAddr, metric: u8, rt: i64) -> Self {
         Self {
+            protocol: ConnectProtocol::Udp,
             index,
             addr,
             metric,
             rt,
+            mtu: 0,
         }
     }
     pub fn from(route_key: RouteKey, metric: u8, rt: i64) -> Self {
         Self {
+            protocol: route_key.protocol,
             index: route_key.index,
             addr: route_key.addr,
             metric,
             rt,
+            mtu: 0,
         }
     }
     pub fn route_key(&self) -> RouteKey {
         RouteKey {
+            protocol: self.protocol,
             index: self.index,
             addr: self.addr,
         }
     }
+    pub fn protocol(&self) -> ConnectProtocol {
+        self.protocol
+    }
+    /// 直连优先，中继路由之间(服务端中继、客户端中继)只比较延迟
     pub fn sort_key(&self) -> RouteSortKey {
         RouteSortKey {
-            metric: self.metric,
+            metric: self.metric.min(2),
             rt: self.rt,
         }
     }
@@ -62,12 +104,38 @@ impl Route {
 
 #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Hash, Debug)]
 pub struct RouteKey {
+    protocol: ConnectProtocol,
     index: usize,
     pub addr: SocketAddr,
 }
 
 impl RouteKey {
     pub(crate) fn new(index: usize, addr: SocketAddr) -> Self {
-        Self { index, addr }
+        Self {
+            protocol: ConnectProtocol::Udp,
+            index,
+            addr,
+        }
+    }
+    pub(crate) fn new_tcp(index: usize, addr: SocketAddr) -> Self {
+        Self {
+            protocol: ConnectProtocol::Tcp,
+            index,
+            addr,
+        }
+    }
+    pub(crate) fn new_quic(index: usize, addr: SocketAddr) -> Self {
+        Self {
+            protocol: ConnectProtocol::Quic,
+            index,
+            addr,
+        }
+    }
+    pub fn protocol(&self) -> ConnectProtocol {
+        self.protocol
+    }
+    /// 通道序号，同一地址的多个通道用序号区分
+    pub fn index(&self) -> usize {
+        self.index
     }
 }
@@ -5,13 +5,23 @@ use std::str::FromStr;
 use std::time::Duration;
 
 use rand::prelude::SliceRandom;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
 
 use crate::channel::channel::Context;
 
-#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+/// 对方顺序分配端口时，预测的端口数
+const PREDICT_PORT_NUM: usize = 32;
+/// 生日攻击打洞后，过一段时间检查是否建立了直连
+const BIRTHDAY_CHECK_DELAY: Duration = Duration::from_secs(5);
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
 pub enum PunchModel {
+    #[serde(rename = "ipv4")]
     IPv4,
+    #[serde(rename = "ipv6")]
     IPv6,
+    #[serde(rename = "all")]
     All,
 }
 
@@ -35,6 +45,14 @@ pub struct NatInfo {
     pub local_ipv4_addr: SocketAddrV4,
     pub ipv6_addr: SocketAddrV6,
     pub nat_type: NatType,
+    /// 通过UPnP、NAT-PMP/PCP在路由器上映射的地址
+    pub mapped_addr: Option<SocketAddrV4>,
+    /// RFC 5780 探测到的NAT行为
+    pub behavior: NatBehavior,
+    /// tcp打洞监听的端口，0表示不支持
+    pub tcp_port: u16,
+    /// quic端点的端口，0表示不支持
+    pub quic_port: u16,
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug, Hash)]
@@ -43,6 +61,59 @@ pub enum NatType {
     Cone,
 }
 
+/// NAT映射或过滤规则依赖的对端信息，RFC 4787
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Hash, Default)]
+pub enum NatDependency {
+    #[default]
+    Unknown,
+    /// 和对端无关
+    EndpointIndependent,
+    /// 和对端ip有关
+    AddressDependent,
+    /// 和对端ip、端口都有关
+    AddressAndPortDependent,
+}
+
+/// NAT分配公网端口的方式
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash, Default)]
+pub enum PortAllocation {
+    #[default]
+    Unknown,
+    /// 公网端口和本地端口相同
+    Preserving,
+    /// 按固定步长递增(或递减)分配
+    Sequential,
+    Random,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash, Default)]
+pub struct NatBehavior {
+    pub mapping: NatDependency,
+    pub filtering: NatDependency,
+    /// 是否支持回环(hairpin)，同一NAT下的设备能否通过公网地址互相访问
+    pub hairpin: bool,
+    pub port_allocation: PortAllocation,
+    /// 顺序分配时相邻两次映射的端口差
+    pub port_step: i16,
+}
+
+impl NatBehavior {
+    /// 预测接下来会分配的端口，映射和对端无关时端口不会变化，不需要预测
+    pub fn predict_ports(&self, public_port: u16, count: usize) -> Vec<u16> {
+        if self.mapping == NatDependency::EndpointIndependent
+            || self.port_allocation != PortAllocation::Sequential
+            || self.port_step == 0
+        {
+            return Vec::new();
+        }
+        (1..=count as i32)
+            .map(|i| public_port as i32 + i * self.port_step as i32)
+            .filter(|port| *port > 0 && *port <= u16::MAX as i32)
+            .map(|port| port as u16)
+            .collect()
+    }
+}
+
 impl NatInfo {
     pub fn new(
         mut public_ips: Vec<Ipv4Addr>,
@@ -60,8 +131,28 @@ impl NatInfo {
             local_ipv4_addr,
             ipv6_addr,
             nat_type,
+            mapped_addr: None,
+            behavior: NatBehavior::default(),
+            tcp_port: 0,
+            quic_port: 0,
         }
     }
+    pub fn with_mapped_addr(mut self, mapped_addr: Option<SocketAddrV4>) -> Self {
+        self.mapped_addr = mapped_addr;
+        self
+    }
+    pub fn with_behavior(mut self, behavior: NatBehavior) -> Self {
+        self.behavior = behavior;
+        self
+    }
+    pub fn with_tcp_port(mut self, tcp_port: u16) -> Self {
+        self.tcp_port = tcp_port;
+        self
+    }
+    pub fn with_quic_port(mut self, quic_port: u16) -> Self {
+        self.quic_port = quic_port;
+        self
+    }
 }
 
 #[derive(Clone)]
@@ -88,14 +179,49 @@ impl Punch {
 }
 
 impl Punch {
+    /// tcp打洞，双方同时向对方发起连接，NAT上的映射建立后连接可以直接完成
+    fn punch_tcp(&self, buf: &[u8], nat_info: &NatInfo) {
+        if !nat_info.local_ipv4_addr.ip().is_unspecified() {
+            let addr = SocketAddrV4::new(*nat_info.local_ipv4_addr.ip(), nat_info.tcp_port);
+            self.context.punch_tcp(buf, SocketAddr::V4(addr));
+        }
+        for ip in &nat_info.public_ips {
+            // tcp映射的端口未知，尝试和udp映射的端口相同的情况
+            self.context.punch_tcp(
+                buf,
+                SocketAddr::V4(SocketAddrV4::new(*ip, nat_info.tcp_port)),
+            );
+            if nat_info.public_port != nat_info.tcp_port {
+                self.context.punch_tcp(
+                    buf,
+                    SocketAddr::V4(SocketAddrV4::new(*ip, nat_info.public_port)),
+                );
+            }
+        }
+    }
+    /// quic打洞，双方同时发起连接，quic端点使用独立的端口
+    fn punch_quic(&self, buf: &[u8], nat_info: &NatInfo) {
+        if !nat_info.local_ipv4_addr.ip().is_unspecified() {
+            let addr = SocketAddrV4::new(*nat_info.local_ipv4_addr.ip(), nat_info.quic_port);
+            self.context.punch_quic(buf, SocketAddr::V4(addr));
+        }
+        for ip in &nat_info.public_ips {
+            // 假设NAT保持端口不变
+            self.context.punch_quic(
+                buf,
+                SocketAddr::V4(SocketAddrV4::new(*ip, nat_info.quic_port)),
+            );
+        }
+    }
     pub async fn punch(&mut self, buf: &[u8], id: Ipv4Addr, nat_info: NatInfo) -> io::Result<()> {
         if !self.context.need_punch(&id) {
             return Ok(());
         }
+        self.context.record_punch();
         if !nat_info.local_ipv4_addr.ip().is_unspecified() && nat_info.local_ipv4_addr.port() != 0 {
             let _ = self
                 .context
-                .send_main_udp(buf, SocketAddr::V4(nat_info.local_ipv4_addr))
+                .send_punch_udp(buf, SocketAddr::V4(nat_info.local_ipv4_addr))
                 .await;
         }
         if self.punch_model != PunchModel::IPv4
@@ -104,13 +230,41 @@ impl Punch {
         {
             let rs = self
                 .context
-                .send_main_udp(buf, SocketAddr::V6(nat_info.ipv6_addr))
+                .send_punch_udp(buf, SocketAddr::V6(nat_info.ipv6_addr))
                 .await;
             log::info!("发送到ipv6地址:{:?},rs={:?}", nat_info.ipv6_addr, rs);
             if rs.is_ok() && self.punch_model == PunchModel::IPv6 {
                 return Ok(());
             }
         }
+        if nat_info.tcp_port != 0 && self.punch_model != PunchModel::IPv6 {
+            self.punch_tcp(buf, &nat_info);
+        }
+        if nat_info.quic_port != 0 && self.punch_model != PunchModel::IPv6 {
+            self.punch_quic(buf, &nat_info);
+        }
+        if let Some(mapped_addr) = nat_info.mapped_addr {
+            // 映射的端口是固定的，不管对方是什么类型的NAT都可以直接发送
+            let addr = SocketAddr::V4(mapped_addr);
+            if self.context.is_cone() {
+                self.context.send_punch_udp(buf, addr).await?;
+            } else {
+                self.context.send_all(buf, addr).await?;
+            }
+        }
+        let predict_ports = nat_info
+            .behavior
+            .predict_ports(nat_info.public_port, PREDICT_PORT_NUM);
+        if !predict_ports.is_empty() {
+            // 对方顺序分配端口，新的映射大概率落在最近分配的端口之后
+            self.punch_symmetric(
+                &predict_ports,
+                buf,
+                &nat_info.public_ips,
+                predict_ports.len() * nat_info.public_ips.len() + 1,
+            )
+            .await?;
+        }
         match nat_info.nat_type {
             NatType::Symmetric => {
                 // 假设对方绑定n个端口，通过NAT对外映射出n个 公网ip:公网端口，自己随机尝试k次的情况下
@@ -164,11 +318,13 @@ impl Punch {
                 self.port_index.insert(id, index);
             }
             NatType::Cone => {
-                let is_cone = self.context.is_cone();
+                // 对方的过滤规则和对端无关时，从任意端口发送的数据都能到达，不需要使用全部端口
+                let is_cone = self.context.is_cone()
+                    || nat_info.behavior.filtering == NatDependency::EndpointIndependent;
                 for ip in nat_info.public_ips {
                     let addr = SocketAddr::V4(SocketAddrV4::new(ip, nat_info.public_port));
                     if is_cone {
-                        self.context.send_main_udp(buf, addr).await?;
+                        self.context.send_punch_udp(buf, addr).await?;
                     } else {
                         //只有一方是对称，则对称方要使用全部端口发送数据，符合上述计算的概率
                         self.context.send_all(buf, addr).await?;
@@ -180,6 +336,42 @@ impl Punch {
         Ok(())
     }
 
+    /// 双方都是对称网络时，同时从多个端口向对方的随机端口发送数据。
+    /// 双方各有n个映射、各发送m个包，对方NAT的过滤只和ip相关时，碰上的概率约为 1-(1-n/65535)^(2m)
+    pub async fn birthday_punch(
+        &self,
+        buf: &[u8],
+        id: Ipv4Addr,
+        nat_info: &NatInfo,
+        probes: usize,
+    ) -> io::Result<()> {
+        if probes == 0 || nat_info.public_ips.is_empty() || !self.context.need_punch(&id) {
+            return Ok(());
+        }
+        self.context.record_birthday_attempt();
+        let addrs: Vec<SocketAddr> = {
+            let mut rng = rand::thread_rng();
+            (0..probes)
+                .map(|index| {
+                    let ip = nat_info.public_ips[index % nat_info.public_ips.len()];
+                    SocketAddr::V4(SocketAddrV4::new(ip, rng.gen_range(1024..=u16::MAX)))
+                })
+                .collect()
+        };
+        self.context.send_spray(buf, &addrs).await?;
+        let context = self.context.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(BIRTHDAY_CHECK_DELAY).await;
+            if context.need_punch(&id) {
+                log::info!("生日攻击打洞未成功:{}", id);
+            } else {
+                log::info!("生日攻击打洞成功:{}", id);
+                context.record_birthday_success();
+            }
+        });
+        Ok(())
+    }
+
     async fn punch_symmetric(
         &self,
         ports: &[u16],
@@ -195,7 +387,7 @@ impl Punch {
                     return Ok(());
                 }
                 let addr = SocketAddr::V4(SocketAddrV4::new(*pub_ip, *port));
-                self.context.send_main_udp(buf, addr).await?;
+                self.context.send_punch_udp(buf, addr).await?;
                 tokio::time::sleep(Duration::from_millis(2)).await;
             }
         }
@@ -0,0 +1,155 @@
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use bytes::Bytes;
+use quinn::{ClientConfig, Connection, Endpoint, IdleTimeout, ServerConfig, TransportConfig};
+use tokio::sync::mpsc::Receiver;
+
+/// 应用层协议协商标识，服务端和客户端需一致
+const ALPN: &[u8] = b"vnt";
+const SERVER_NAME: &str = "vnt";
+/// quic打洞时连接的超时时间
+pub const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(5);
+const IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// quic通道，和服务端的连接以及对端之间的连接共用一个端点
+pub struct QuicChannel {
+    pub endpoint: Endpoint,
+    /// 和服务端的连接，以及发往服务端的数据
+    pub server: Option<(Connection, Receiver<Vec<u8>>)>,
+    /// 向对端发起连接的请求
+    pub punch_receiver: Option<Receiver<(SocketAddr, Vec<u8>)>>,
+}
+
+/// 创建quic端点，同时作为服务端和客户端。
+/// 数据本身已经按原有方式加密，tls只用于建立连接，因此使用自签名证书且不校验对方证书
+pub fn endpoint(port: u16) -> io::Result<Endpoint> {
+    let mut transport = TransportConfig::default();
+    transport.keep_alive_interval(Some(KEEP_ALIVE_INTERVAL));
+    transport.max_idle_timeout(Some(
+        IdleTimeout::try_from(IDLE_TIMEOUT).map_err(|e| other_err(format!("{:?}", e)))?,
+    ));
+    transport.datagram_receive_buffer_size(Some(1024 * 1024));
+    let transport = Arc::new(transport);
+
+    let cert = rcgen::generate_simple_self_signed(vec![SERVER_NAME.to_string()])
+        .map_err(|e| other_err(format!("generate cert {:?}", e)))?;
+    let cert_der = cert
+        .serialize_der()
+        .map_err(|e| other_err(format!("serialize cert {:?}", e)))?;
+    let key_der = cert.serialize_private_key_der();
+    let mut server_crypto = rustls::ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(
+            vec![rustls::Certificate(cert_der)],
+            rustls::PrivateKey(key_der),
+        )
+        .map_err(|e| other_err(format!("server config {:?}", e)))?;
+    server_crypto.alpn_protocols = vec![ALPN.to_vec()];
+    let mut server_config = ServerConfig::with_crypto(Arc::new(server_crypto));
+    server_config.transport_config(transport.clone());
+
+    let mut client_crypto = rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(Arc::new(SkipServerVerification))
+        .with_no_client_auth();
+    client_crypto.alpn_protocols = vec![ALPN.to_vec()];
+    let mut client_config = ClientConfig::new(Arc::new(client_crypto));
+    client_config.transport_config(transport);
+
+    let mut endpoint = Endpoint::server(
+        server_config,
+        SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, port)),
+    )?;
+    endpoint.set_default_client_config(client_config);
+    Ok(endpoint)
+}
+
+pub async fn connect(endpoint: &Endpoint, addr: SocketAddr) -> io::Result<Connection> {
+    let connecting = endpoint
+        .connect(addr, SERVER_NAME)
+        .map_err(|e| other_err(format!("{:?}", e)))?;
+    connecting.await.map_err(io::Error::from)
+}
+
+/// 握手、注册阶段使用，发送一个数据报并等待回复
+pub async fn send_recv(
+    connection: &Connection,
+    send_buf: &[u8],
+    recv_buf: &mut [u8],
+    timeout: Duration,
+) -> io::Result<usize> {
+    send(connection, send_buf)?;
+    let data = match tokio::time::timeout(timeout, connection.read_datagram()).await {
+        Ok(rs) => rs.map_err(io::Error::from)?,
+        Err(_) => return Err(io::Error::from(io::ErrorKind::TimedOut)),
+    };
+    if data.len() > recv_buf.len() {
+        return Err(other_err("too long".to_string()));
+    }
+    recv_buf[..data.len()].copy_from_slice(&data);
+    Ok(data.len())
+}
+
+pub fn send(connection: &Connection, buf: &[u8]) -> io::Result<()> {
+    connection
+        .send_datagram(Bytes::copy_from_slice(buf))
+        .map_err(|e| other_err(format!("{:?}", e)))
+}
+
+/// 向连接写数据，每个数据包对应一个数据报，不会因为丢包阻塞后续数据。
+/// skip为数据头部需要跳过的字节数，接收端关闭时返回Ok，连接断开时返回Err
+pub async fn write_loop(
+    connection: &Connection,
+    receiver: &mut Receiver<Vec<u8>>,
+    skip: usize,
+) -> io::Result<()> {
+    loop {
+        tokio::select! {
+            rs=receiver.recv()=>{
+                let data = match rs {
+                    Some(data) => data,
+                    None => break,
+                };
+                if data.len() < skip {
+                    continue;
+                }
+                if let Err(e) = send(connection, &data[skip..]) {
+                    log::debug!("quic发送失败 {} {:?}", connection.remote_address(), e);
+                }
+            }
+            e=connection.closed()=>{
+                return Err(io::Error::from(e));
+            }
+        }
+    }
+    connection.close(0u32.into(), b"");
+    Ok(())
+}
+
+fn other_err(msg: String) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, msg)
+}
+
+/// 不校验服务端证书，quic只用来穿过udp限制，不提供身份认证。
+/// 对端身份依赖vnt本身的加密：-W和服务端的rsa握手、-w的密码，
+/// 不加密时中间人可以冒充服务端或者其他客户端
+struct SkipServerVerification;
+
+impl rustls::client::ServerCertVerifier for SkipServerVerification {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &rustls::Certificate,
+        _intermediates: &[rustls::Certificate],
+        _server_name: &rustls::ServerName,
+        _scts: &mut dyn Iterator<Item = &[u8]>,
+        _ocsp_response: &[u8],
+        _now: SystemTime,
+    ) -> Result<rustls::client::ServerCertVerified, rustls::Error> {
+        Ok(rustls::client::ServerCertVerified::assertion())
+    }
+}
@@ -0,0 +1,162 @@
+/// 防重放窗口的大小，超出窗口的旧序号直接丢弃
+pub const REPLAY_WINDOW_SIZE: u32 = 1024;
+
+const BLOCKS: usize = (REPLAY_WINDOW_SIZE / 64) as usize;
+
+/// 滑动窗口防重放，参考IPsec/WireGuard
+///
+/// 序号是u32，按序列号算术比较，回绕后依然可用
+pub struct ReplayWindow {
+    //收到的最大序号
+    top: u32,
+    init: bool,
+    bitmap: [u64; BLOCKS],
+}
+
+impl Default for ReplayWindow {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ReplayWindow {
+    pub fn new() -> Self {
+        Self {
+            top: 0,
+            init: false,
+            bitmap: [0; BLOCKS],
+        }
+    }
+    /// 只接受不小于floor的序号，floor之前的序号都当作已经收到
+    ///
+    /// 密钥协商时对端告知当前序号，对端重启后序号变小也能继续通信，之前的数据包仍然不能重放
+    pub fn with_floor(floor: u32) -> Self {
+        Self {
+            top: floor.wrapping_sub(1),
+            init: true,
+            bitmap: [u64::MAX; BLOCKS],
+        }
+    }
+    /// 检查序号并记录，重复或者过旧的序号返回false
+    ///
+    /// 只能在数据包认证通过后调用，否则伪造的序号会推动窗口
+    pub fn check_and_update(&mut self, seq: u32) -> bool {
+        if !self.init {
+            self.init = true;
+            self.top = seq;
+            self.bitmap = [0; BLOCKS];
+            self.set(seq);
+            return true;
+        }
+        let diff = seq.wrapping_sub(self.top) as i32;
+        if diff > 0 {
+            let diff = diff as u32;
+            if diff >= REPLAY_WINDOW_SIZE {
+                self.bitmap = [0; BLOCKS];
+            } else {
+                //清除窗口滑过的位置
+                for i in 1..=diff {
+                    self.clear(self.top.wrapping_add(i));
+                }
+            }
+            self.top = seq;
+            self.set(seq);
+            true
+        } else {
+            let back = self.top.wrapping_sub(seq);
+            if back >= REPLAY_WINDOW_SIZE || self.get(seq) {
+                return false;
+            }
+            self.set(seq);
+            true
+        }
+    }
+    fn index(seq: u32) -> (usize, u64) {
+        let bit = seq % REPLAY_WINDOW_SIZE;
+        ((bit / 64) as usize, 1u64 << (bit % 64))
+    }
+    fn get(&self, seq: u32) -> bool {
+        let (index, mask) = Self::index(seq);
+        self.bitmap[index] & mask != 0
+    }
+    fn set(&mut self, seq: u32) {
+        let (index, mask) = Self::index(seq);
+        self.bitmap[index] |= mask;
+    }
+    fn clear(&mut self, seq: u32) {
+        let (index, mask) = Self::index(seq);
+        self.bitmap[index] &= !mask;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn in_window() {
+        let mut window = ReplayWindow::new();
+        assert!(window.check_and_update(2000));
+        assert!(window.check_and_update(2002));
+        // 窗口内乱序到达
+        assert!(window.check_and_update(2001));
+        assert!(window.check_and_update(2002 - REPLAY_WINDOW_SIZE + 1));
+    }
+
+    #[test]
+    fn duplicate() {
+        let mut window = ReplayWindow::new();
+        assert!(window.check_and_update(100));
+        assert!(window.check_and_update(98));
+        assert!(!window.check_and_update(100));
+        assert!(!window.check_and_update(98));
+    }
+
+    #[test]
+    fn too_old() {
+        let mut window = ReplayWindow::new();
+        assert!(window.check_and_update(5000));
+        assert!(!window.check_and_update(5000 - REPLAY_WINDOW_SIZE));
+        assert!(!window.check_and_update(1));
+        assert!(window.check_and_update(5000 - REPLAY_WINDOW_SIZE + 1));
+    }
+
+    #[test]
+    fn shift() {
+        let mut window = ReplayWindow::new();
+        assert!(window.check_and_update(10));
+        assert!(window.check_and_update(11));
+        // 小幅滑动，滑过的位置被清除，旧的记录仍然保留
+        assert!(window.check_and_update(10 + REPLAY_WINDOW_SIZE - 1));
+        assert!(!window.check_and_update(11));
+        assert!(window.check_and_update(12));
+        // 大幅滑动，整个窗口清空
+        assert!(window.check_and_update(20 + 3 * REPLAY_WINDOW_SIZE));
+        assert!(!window.check_and_update(12));
+        assert!(window.check_and_update(21 + 2 * REPLAY_WINDOW_SIZE));
+    }
+
+    #[test]
+    fn wrapping() {
+        let mut window = ReplayWindow::new();
+        assert!(window.check_and_update(u32::MAX - 1));
+        assert!(window.check_and_update(2));
+        assert!(window.check_and_update(u32::MAX));
+        assert!(!window.check_and_update(u32::MAX - 1));
+    }
+
+    #[test]
+    fn floor() {
+        let mut window = ReplayWindow::with_floor(2000);
+        assert!(!window.check_and_update(1999));
+        assert!(!window.check_and_update(2000 - REPLAY_WINDOW_SIZE / 2));
+        assert!(!window.check_and_update(1));
+        assert!(window.check_and_update(2001));
+        assert!(window.check_and_update(2000));
+        assert!(!window.check_and_update(2000));
+        // 重启后的对端序号从更小的值开始
+        let mut window = ReplayWindow::with_floor(7);
+        assert!(window.check_and_update(7));
+        assert!(window.check_and_update(8));
+    }
+}
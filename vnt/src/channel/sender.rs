@@ -1,14 +1,59 @@
-use crate::channel::channel::Context;
+use std::io;
+use std::net::Ipv4Addr;
 use std::ops::Deref;
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+
+use crate::channel::batch::BATCH_SIZE;
+use crate::channel::channel::Context;
+use crate::channel::RouteKey;
+
+type BatchQueue = Arc<Mutex<Vec<(Vec<u8>, RouteKey)>>>;
 
 #[derive(Clone)]
 pub struct ChannelSender {
     context: Context,
+    /// 批量发送的队列，None表示直接发送
+    batch: Option<BatchQueue>,
 }
 
 impl ChannelSender {
     pub fn new(context: Context) -> Self {
-        Self { context }
+        Self {
+            context,
+            batch: None,
+        }
+    }
+    /// 使用独立队列的发送器，udp直连的数据先放入队列，调用flush时批量发送
+    pub fn batch(&self) -> Self {
+        Self {
+            context: self.context.clone(),
+            batch: Some(Arc::new(Mutex::new(Vec::with_capacity(BATCH_SIZE)))),
+        }
+    }
+    /// 覆盖Context::send_by_id，批量模式下找不到路由时同样返回错误
+    pub async fn send_by_id(&self, buf: &[u8], id: &Ipv4Addr) -> io::Result<usize> {
+        if let Some(batch) = &self.batch {
+            let route_key = self.context.route_key_by_id(id)?;
+            if self.context.batch_udp(&route_key).is_some() {
+                batch.lock().push((buf.to_vec(), route_key));
+                return Ok(buf.len());
+            }
+            return self.context.send_by_key(buf, &route_key).await;
+        }
+        self.context.send_by_id(buf, id).await
+    }
+    pub async fn flush(&self) {
+        if let Some(batch) = &self.batch {
+            let list = std::mem::take(&mut *batch.lock());
+            if list.is_empty() {
+                return;
+            }
+            if let Err(e) = self.context.send_batch(&list).await {
+                log::warn!("批量发送失败:{:?}", e);
+            }
+        }
     }
 }
 
@@ -0,0 +1,219 @@
+use std::time::Instant;
+
+use crate::channel::replay_window::ReplayWindow;
+use crate::cipher::{Cipher, KeyExchange};
+
+/// 和单个对端协商出的会话密钥
+///
+/// 按轮次的奇偶保存最近两把密钥，数据包头部携带奇偶位，轮换期间两把密钥都可以解密
+pub struct PeerSession {
+    keys: [Option<Cipher>; 2],
+    //每把密钥单独的防重放窗口，换密钥后窗口重新开始
+    windows: [ReplayWindow; 2],
+    //最新协商出的轮次
+    recv_epoch: u32,
+    //发送使用的轮次，响应方在收到对端使用新密钥的数据后才切换
+    send_epoch: u32,
+    //最新密钥的协商时间
+    time: Instant,
+    //发起方等待响应的临时密钥
+    pending: Option<Pending>,
+}
+
+/// 发起方等待响应的一次协商
+struct Pending {
+    key_exchange: KeyExchange,
+    epoch: u32,
+    nonce: [u8; 16],
+    time: Instant,
+}
+
+impl Default for PeerSession {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PeerSession {
+    pub fn new() -> Self {
+        Self {
+            keys: [None, None],
+            windows: [ReplayWindow::new(), ReplayWindow::new()],
+            recv_epoch: 0,
+            send_epoch: 0,
+            time: Instant::now(),
+            pending: None,
+        }
+    }
+    pub fn is_established(&self) -> bool {
+        self.keys[(self.send_epoch & 1) as usize].is_some()
+    }
+    pub fn epoch(&self) -> u32 {
+        self.recv_epoch
+    }
+    pub fn time(&self) -> Instant {
+        self.time
+    }
+    /// 下一次协商使用的轮次，大于之前所有的轮次，并且奇偶和当前轮次相反，避免覆盖正在使用的密钥。
+    ///
+    /// 以当前时间(秒)作为下限，本端重启后轮次也不会回退，对端不会当作重放拒绝
+    pub fn next_epoch(&self, now_secs: u32) -> u32 {
+        let last = match &self.pending {
+            Some(pending) => pending.epoch.max(self.recv_epoch),
+            None => self.recv_epoch,
+        };
+        let epoch = last.wrapping_add(1).max(now_secs);
+        if epoch & 1 == self.recv_epoch & 1 {
+            epoch.wrapping_add(1)
+        } else {
+            epoch
+        }
+    }
+    /// 响应方只接受比当前更新的轮次，重放的协商请求不能覆盖正在使用的密钥
+    pub fn is_fresh(&self, epoch: u32) -> bool {
+        epoch > self.recv_epoch
+    }
+    /// 发送使用的密钥和奇偶位
+    pub fn send_cipher(&self) -> Option<(&Cipher, u8)> {
+        let parity = (self.send_epoch & 1) as u8;
+        self.keys[parity as usize].as_ref().map(|c| (c, parity))
+    }
+    pub fn recv_cipher(&self, parity: u8) -> Option<&Cipher> {
+        self.keys[(parity & 1) as usize].as_ref()
+    }
+    /// 检查使用对应密钥解密后的序号，重放的数据返回false
+    pub fn check_replay(&mut self, parity: u8, seq: u32) -> bool {
+        self.windows[(parity & 1) as usize].check_and_update(seq)
+    }
+    /// 对端已经开始使用最新的密钥，发送也切换过去
+    pub fn need_switch(&self, parity: u8) -> bool {
+        self.send_epoch != self.recv_epoch && (self.recv_epoch & 1) as u8 == parity & 1
+    }
+    pub fn switch(&mut self) {
+        self.send_epoch = self.recv_epoch;
+    }
+    /// 保存新的会话密钥，initiator表示是否为发起方
+    pub fn update(&mut self, epoch: u32, cipher: Cipher, initiator: bool) {
+        let had_key = self.is_established();
+        self.keys[(epoch & 1) as usize] = Some(cipher);
+        self.windows[(epoch & 1) as usize] = ReplayWindow::new();
+        self.recv_epoch = epoch;
+        if initiator || !had_key || self.send_epoch & 1 == epoch & 1 {
+            self.send_epoch = epoch;
+        }
+        self.time = Instant::now();
+        self.pending = None;
+    }
+    /// 等待中的协商发起的时间
+    pub fn pending_time(&self) -> Option<Instant> {
+        self.pending.as_ref().map(|pending| pending.time)
+    }
+    pub fn set_pending(&mut self, key_exchange: KeyExchange, epoch: u32, nonce: [u8; 16]) {
+        self.pending = Some(Pending {
+            key_exchange,
+            epoch,
+            nonce,
+            time: Instant::now(),
+        });
+    }
+    /// 取出和响应匹配的临时密钥，轮次和随机数都要和请求一致
+    pub fn take_pending(&mut self, epoch: u32, nonce: &[u8; 16]) -> Option<KeyExchange> {
+        if let Some(pending) = &self.pending {
+            if pending.epoch == epoch && pending.nonce == *nonce {
+                return self.pending.take().map(|pending| pending.key_exchange);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cipher(key: u8) -> Cipher {
+        Cipher::new_key([key; 32], String::new()).unwrap()
+    }
+
+    #[test]
+    fn next_epoch_parity() {
+        let mut session = PeerSession::new();
+        let epoch = session.next_epoch(0);
+        assert_eq!(epoch & 1, 1);
+        session.update(epoch, cipher(1), true);
+        let next = session.next_epoch(0);
+        assert!(next > epoch);
+        assert_ne!(next & 1, epoch & 1);
+        // 时间作为下限，奇偶仍然和当前轮次相反
+        let next = session.next_epoch(1_000_000);
+        assert!(next >= 1_000_000);
+        assert_ne!(next & 1, epoch & 1);
+        // 等待中的轮次也不会重复使用
+        session.set_pending(KeyExchange::new(), next, [0; 16]);
+        let retry = session.next_epoch(0);
+        assert!(retry > next);
+        assert_ne!(retry & 1, epoch & 1);
+    }
+
+    #[test]
+    fn stale_epoch() {
+        let mut session = PeerSession::new();
+        assert!(session.is_fresh(1));
+        session.update(5, cipher(1), false);
+        assert!(!session.is_fresh(5));
+        assert!(!session.is_fresh(3));
+        assert!(session.is_fresh(6));
+    }
+
+    #[test]
+    fn take_pending_checks_nonce() {
+        let mut session = PeerSession::new();
+        session.set_pending(KeyExchange::new(), 3, [1; 16]);
+        assert!(session.pending_time().is_some());
+        assert!(session.take_pending(3, &[2; 16]).is_none());
+        assert!(session.take_pending(4, &[1; 16]).is_none());
+        assert!(session.take_pending(3, &[1; 16]).is_some());
+        assert!(session.take_pending(3, &[1; 16]).is_none());
+    }
+
+    #[test]
+    fn initiator_switch() {
+        let mut session = PeerSession::new();
+        session.update(1, cipher(1), true);
+        assert!(session.is_established());
+        assert_eq!(session.send_cipher().unwrap().1, 1);
+        // 发起方拿到新密钥立即切换
+        session.update(2, cipher(2), true);
+        assert_eq!(session.send_cipher().unwrap().1, 0);
+        assert!(session.recv_cipher(1).is_some());
+        assert!(!session.need_switch(0));
+    }
+
+    #[test]
+    fn responder_switch() {
+        let mut session = PeerSession::new();
+        // 没有密钥时直接使用
+        session.update(1, cipher(1), false);
+        assert_eq!(session.send_cipher().unwrap().1, 1);
+        // 响应方等到对端使用新密钥后再切换
+        session.update(2, cipher(2), false);
+        assert_eq!(session.send_cipher().unwrap().1, 1);
+        assert!(!session.need_switch(1));
+        assert!(session.need_switch(0));
+        session.switch();
+        assert_eq!(session.send_cipher().unwrap().1, 0);
+        assert!(!session.need_switch(0));
+    }
+
+    #[test]
+    fn replay_window_per_key() {
+        let mut session = PeerSession::new();
+        session.update(1, cipher(1), true);
+        assert!(session.check_replay(1, 10));
+        assert!(!session.check_replay(1, 10));
+        // 另一把密钥的窗口独立
+        session.update(2, cipher(2), true);
+        assert!(session.check_replay(0, 10));
+        assert!(!session.check_replay(1, 10));
+    }
+}
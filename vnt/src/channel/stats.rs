@@ -0,0 +1,221 @@
+use std::net::Ipv4Addr;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use dashmap::DashMap;
+
+use crate::channel::RouteKey;
+
+#[derive(Default)]
+struct Counter {
+    tx_bytes: AtomicU64,
+    tx_packets: AtomicU64,
+    rx_bytes: AtomicU64,
+    rx_packets: AtomicU64,
+    decrypt_failures: AtomicU64,
+    drops: AtomicU64,
+}
+
+impl Counter {
+    fn tx(&self, len: usize) {
+        self.tx_bytes.fetch_add(len as u64, Ordering::Relaxed);
+        self.tx_packets.fetch_add(1, Ordering::Relaxed);
+    }
+    fn rx(&self, len: usize) {
+        self.rx_bytes.fetch_add(len as u64, Ordering::Relaxed);
+        self.rx_packets.fetch_add(1, Ordering::Relaxed);
+    }
+    fn load(&self) -> TrafficStats {
+        TrafficStats {
+            tx_bytes: self.tx_bytes.load(Ordering::Relaxed),
+            tx_packets: self.tx_packets.load(Ordering::Relaxed),
+            rx_bytes: self.rx_bytes.load(Ordering::Relaxed),
+            rx_packets: self.rx_packets.load(Ordering::Relaxed),
+            decrypt_failures: self.decrypt_failures.load(Ordering::Relaxed),
+            drops: self.drops.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// 流量统计快照
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct TrafficStats {
+    pub tx_bytes: u64,
+    pub tx_packets: u64,
+    pub rx_bytes: u64,
+    pub rx_packets: u64,
+    pub decrypt_failures: u64,
+    pub drops: u64,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct VntStats {
+    /// 打洞次数
+    pub punch_attempts: u64,
+    /// 打洞发送的数据包数量，不计入通道统计
+    pub punch_packets: u64,
+    /// 生日攻击打洞次数
+    pub birthday_attempts: u64,
+    /// 生日攻击打洞后建立了直连的次数
+    pub birthday_successes: u64,
+    /// 按对端虚拟ip统计
+    pub peers: Vec<(Ipv4Addr, TrafficStats)>,
+    /// 按通道统计，包含和服务端的通信
+    pub routes: Vec<(RouteKey, TrafficStats)>,
+}
+
+impl VntStats {
+    pub fn peer(&self, ip: &Ipv4Addr) -> Option<TrafficStats> {
+        self.peers.iter().find(|(k, _)| k == ip).map(|(_, v)| *v)
+    }
+    pub fn route(&self, route_key: &RouteKey) -> Option<TrafficStats> {
+        self.routes
+            .iter()
+            .find(|(k, _)| k == route_key)
+            .map(|(_, v)| *v)
+    }
+}
+
+/// 收发数据统计，统计的是通道上的报文长度(加密后)
+///
+/// 只统计设备列表中的对端，伪造来源的数据包不会让统计表无限增长
+pub struct Stats {
+    peers: DashMap<Ipv4Addr, Counter>,
+    routes: DashMap<RouteKey, Counter>,
+    punch_attempts: AtomicU64,
+    punch_packets: AtomicU64,
+    birthday_attempts: AtomicU64,
+    birthday_successes: AtomicU64,
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        Self {
+            peers: DashMap::with_capacity(16),
+            routes: DashMap::with_capacity(16),
+            punch_attempts: AtomicU64::new(0),
+            punch_packets: AtomicU64::new(0),
+            birthday_attempts: AtomicU64::new(0),
+            birthday_successes: AtomicU64::new(0),
+        }
+    }
+    fn peer<F: FnOnce(&Counter)>(&self, id: &Ipv4Addr, f: F) {
+        if let Some(counter) = self.peers.get(id) {
+            f(counter.value());
+        }
+    }
+    /// 根据设备列表增删对端的统计
+    pub fn update_peers(&self, ids: &[Ipv4Addr]) {
+        self.peers.retain(|id, _| ids.contains(id));
+        for id in ids {
+            if !self.peers.contains_key(id) {
+                self.peers.entry(*id).or_default();
+            }
+        }
+    }
+    fn route<F: FnOnce(&Counter)>(&self, route_key: &RouteKey, f: F) {
+        if let Some(counter) = self.routes.get(route_key) {
+            f(counter.value());
+            return;
+        }
+        f(self.routes.entry(*route_key).or_default().value())
+    }
+    /// 通道不再被任何对端使用时移除统计
+    pub fn remove_route(&self, route_key: &RouteKey) {
+        self.routes.remove(route_key);
+    }
+    /// 发送的数据，buf为完整的vnt数据包，发往服务端和广播的数据不计入对端
+    pub fn tx(&self, buf: &[u8], route_key: &RouteKey) {
+        self.route(route_key, |c| c.tx(buf.len()));
+        if let Some(id) = peer_id(buf, 8) {
+            self.peer(&id, |c| c.tx(buf.len()));
+        }
+    }
+    /// 接收的数据，len为解密前的长度，只在校验通过后调用
+    pub fn rx(&self, len: usize, route_key: &RouteKey, id: Option<&Ipv4Addr>) {
+        self.route(route_key, |c| c.rx(len));
+        if let Some(id) = id {
+            self.peer(id, |c| c.rx(len));
+        }
+    }
+    pub fn decrypt_failure(&self, id: &Ipv4Addr) {
+        self.peer(id, |c| {
+            c.decrypt_failures.fetch_add(1, Ordering::Relaxed);
+        });
+    }
+    pub fn drop_packet(&self, id: &Ipv4Addr) {
+        self.peer(id, |c| {
+            c.drops.fetch_add(1, Ordering::Relaxed);
+        });
+    }
+    pub fn punch_attempt(&self) {
+        self.punch_attempts.fetch_add(1, Ordering::Relaxed);
+    }
+    pub fn punch_packet(&self) {
+        self.punch_packets.fetch_add(1, Ordering::Relaxed);
+    }
+    pub fn birthday_attempt(&self) {
+        self.birthday_attempts.fetch_add(1, Ordering::Relaxed);
+    }
+    pub fn birthday_success(&self) {
+        self.birthday_successes.fetch_add(1, Ordering::Relaxed);
+    }
+    pub fn load(&self) -> VntStats {
+        VntStats {
+            punch_attempts: self.punch_attempts.load(Ordering::Relaxed),
+            punch_packets: self.punch_packets.load(Ordering::Relaxed),
+            birthday_attempts: self.birthday_attempts.load(Ordering::Relaxed),
+            birthday_successes: self.birthday_successes.load(Ordering::Relaxed),
+            peers: self.peers.iter().map(|v| (*v.key(), v.load())).collect(),
+            routes: self.routes.iter().map(|v| (*v.key(), v.load())).collect(),
+        }
+    }
+}
+
+/// 从vnt协议头取对端ip，offset为8取目的地址
+fn peer_id(buf: &[u8], offset: usize) -> Option<Ipv4Addr> {
+    if buf.len() < 12 || buf[0] & 0x50 == 0x50 {
+        //网关数据
+        return None;
+    }
+    let ip = Ipv4Addr::new(
+        buf[offset],
+        buf[offset + 1],
+        buf[offset + 2],
+        buf[offset + 3],
+    );
+    if ip.is_broadcast() || ip.is_multicast() || ip.is_unspecified() {
+        return None;
+    }
+    Some(ip)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_peer() {
+        let stats = Stats::default();
+        let route_key = RouteKey::new(0, "127.0.0.1:29872".parse().unwrap());
+        let known = Ipv4Addr::new(10, 26, 0, 2);
+        stats.update_peers(&[known]);
+        stats.rx(100, &route_key, Some(&known));
+        stats.rx(100, &route_key, Some(&Ipv4Addr::new(10, 26, 0, 9)));
+        stats.decrypt_failure(&Ipv4Addr::new(10, 26, 0, 8));
+        let load = stats.load();
+        assert_eq!(load.peers.len(), 1);
+        assert_eq!(load.peer(&known).unwrap().rx_bytes, 100);
+        assert_eq!(load.route(&route_key).unwrap().rx_packets, 2);
+        // 离开设备列表后移除
+        stats.update_peers(&[]);
+        assert!(stats.load().peers.is_empty());
+        stats.remove_route(&route_key);
+        assert!(stats.load().routes.is_empty());
+    }
+}
@@ -0,0 +1,105 @@
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::time::Duration;
+
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use tokio::net::tcp::OwnedWriteHalf;
+use tokio::net::{TcpListener, TcpSocket, TcpStream};
+use tokio::sync::mpsc::Receiver;
+
+use crate::channel::proxy::ProxyConfig;
+use crate::channel::ws_channel;
+
+/// tcp打洞时连接的超时时间
+pub const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+/// 对端连接长时间没有数据发送时关闭，心跳会定期通过所有可用的路由发送
+const IDLE_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub trait AsyncStream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> AsyncStream for T {}
+
+/// 和服务端的字节流连接，可以是tcp或者websocket
+pub type ServerStream = Box<dyn AsyncStream>;
+
+/// 建立和服务端的字节流连接，重连时使用相同的方式
+#[derive(Clone)]
+pub struct ServerConnector {
+    local_port: u16,
+    ws: Option<String>,
+    proxy: Option<ProxyConfig>,
+}
+
+impl ServerConnector {
+    pub fn new(local_port: u16, ws: Option<String>, proxy: Option<ProxyConfig>) -> Self {
+        Self {
+            local_port,
+            ws,
+            proxy,
+        }
+    }
+    pub async fn connect(&self, addr: SocketAddr) -> io::Result<ServerStream> {
+        if let Some(url) = &self.ws {
+            let stream = ws_channel::connect(url, self.proxy.as_ref()).await?;
+            return Ok(Box::new(stream));
+        }
+        if let Some(proxy) = &self.proxy {
+            let stream = proxy.connect(&addr.ip().to_string(), addr.port()).await?;
+            let _ = stream.set_nodelay(true);
+            return Ok(Box::new(stream));
+        }
+        let stream = connect(addr, self.local_port).await?;
+        Ok(Box::new(stream))
+    }
+}
+
+/// 绑定tcp端口，允许端口复用。
+/// 连接服务端、监听和打洞使用同一个端口，这样NAT上的映射和服务端看到的端口一致
+pub fn bind(port: u16) -> io::Result<TcpSocket> {
+    let socket = TcpSocket::new_v4()?;
+    socket.set_reuseaddr(true)?;
+    #[cfg(all(unix, not(target_os = "solaris"), not(target_os = "illumos")))]
+    socket.set_reuseport(true)?;
+    socket.bind(SocketAddr::V4(SocketAddrV4::new(
+        Ipv4Addr::UNSPECIFIED,
+        port,
+    )))?;
+    Ok(socket)
+}
+
+pub fn listen(port: u16) -> io::Result<TcpListener> {
+    bind(port)?.listen(128)
+}
+
+/// 从指定端口发起连接，绑定失败时使用随机端口
+pub async fn connect(addr: SocketAddr, port: u16) -> io::Result<TcpStream> {
+    match bind(port) {
+        Ok(socket) => socket.connect(addr).await,
+        Err(e) => {
+            log::warn!("绑定tcp端口{}失败:{:?}", port, e);
+            TcpStream::connect(addr).await
+        }
+    }
+}
+
+/// 向对端连接写数据，长度放在头部的后两个字节，和服务端的tcp通道格式一致
+pub async fn write_loop(
+    mut tcp_w: OwnedWriteHalf,
+    mut receiver: Receiver<Vec<u8>>,
+) -> io::Result<()> {
+    let mut head = [0; 4];
+    loop {
+        let data = match tokio::time::timeout(IDLE_TIMEOUT, receiver.recv()).await {
+            Ok(Some(data)) => data,
+            Ok(None) | Err(_) => {
+                break;
+            }
+        };
+        let len = data.len();
+        head[2] = (len >> 8) as u8;
+        head[3] = (len & 0xFF) as u8;
+        tcp_w.write_all(&head).await?;
+        tcp_w.write_all(&data).await?;
+    }
+    tcp_w.shutdown().await
+}
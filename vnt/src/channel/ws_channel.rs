@@ -0,0 +1,132 @@
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_util::{Sink, Stream};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{MaybeTlsStream, WebSocketStream};
+
+use crate::channel::proxy::ProxyConfig;
+
+/// 通过websocket连接服务端，wss使用tls，可以经过http代理
+pub async fn connect(url: &str, proxy: Option<&ProxyConfig>) -> io::Result<WsStream> {
+    let (_, host, port) = parse_url(url)?;
+    let request = url
+        .into_client_request()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    let stream = match proxy {
+        Some(proxy) => proxy.connect(&host, port).await?,
+        None => TcpStream::connect((host.as_str(), port)).await?,
+    };
+    let _ = stream.set_nodelay(true);
+    let (ws, _) = tokio_tungstenite::client_async_tls(request, stream)
+        .await
+        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+    Ok(WsStream::new(ws))
+}
+
+/// 解析websocket地址，返回(是否使用tls,host,port)
+pub fn parse_url(url: &str) -> io::Result<(bool, String, u16)> {
+    let invalid = || {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("invalid websocket url '{}'", url),
+        )
+    };
+    let request = url.into_client_request().map_err(|_| invalid())?;
+    let uri = request.uri();
+    let tls = match uri.scheme_str() {
+        Some("ws") => false,
+        Some("wss") => true,
+        _ => return Err(invalid()),
+    };
+    let host = uri
+        .host()
+        .ok_or_else(invalid)?
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .to_string();
+    let port = uri.port_u16().unwrap_or(if tls { 443 } else { 80 });
+    Ok((tls, host, port))
+}
+
+/// 把websocket适配成字节流，原有tcp通道的长度前缀格式放在二进制消息中传输
+pub struct WsStream {
+    ws: WebSocketStream<MaybeTlsStream<TcpStream>>,
+    read_buf: Vec<u8>,
+    read_pos: usize,
+}
+
+impl WsStream {
+    fn new(ws: WebSocketStream<MaybeTlsStream<TcpStream>>) -> Self {
+        Self {
+            ws,
+            read_buf: Vec::new(),
+            read_pos: 0,
+        }
+    }
+}
+
+fn ws_err(e: tokio_tungstenite::tungstenite::Error) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, e)
+}
+
+impl AsyncRead for WsStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        loop {
+            if self.read_pos < self.read_buf.len() {
+                let len = buf.remaining().min(self.read_buf.len() - self.read_pos);
+                let start = self.read_pos;
+                buf.put_slice(&self.read_buf[start..start + len]);
+                self.read_pos += len;
+                return Poll::Ready(Ok(()));
+            }
+            match Pin::new(&mut self.ws).poll_next(cx) {
+                Poll::Ready(Some(Ok(Message::Binary(data)))) => {
+                    self.read_buf = data;
+                    self.read_pos = 0;
+                }
+                Poll::Ready(Some(Ok(Message::Close(_)))) | Poll::Ready(None) => {
+                    return Poll::Ready(Ok(()));
+                }
+                // ping由tungstenite自动回复，其他消息忽略
+                Poll::Ready(Some(Ok(_))) => {}
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Err(ws_err(e))),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl AsyncWrite for WsStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match Pin::new(&mut self.ws).poll_ready(cx) {
+            Poll::Ready(Ok(())) => {}
+            Poll::Ready(Err(e)) => return Poll::Ready(Err(ws_err(e))),
+            Poll::Pending => return Poll::Pending,
+        }
+        Pin::new(&mut self.ws)
+            .start_send(Message::Binary(buf.to_vec()))
+            .map_err(ws_err)?;
+        Poll::Ready(Ok(buf.len()))
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.ws).poll_flush(cx).map_err(ws_err)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.ws).poll_close(cx).map_err(ws_err)
+    }
+}
@@ -1,8 +1,8 @@
 use std::io;
 
 use aes::cipher::{block_padding::Pkcs7, BlockDecryptMut, BlockEncryptMut, KeyIvInit};
-use rand::RngCore;
 
+use crate::cipher::next_sequence;
 use crate::cipher::Finger;
 use crate::protocol::body::AesCbcSecretBody;
 use crate::protocol::{NetPacket, HEAD_LEN};
@@ -121,7 +121,7 @@ impl AesCbcCipher {
         //先扩充随机数
         let mut secret_body =
             AesCbcSecretBody::new(net_packet.payload_mut(), self.finger.is_some())?;
-        secret_body.set_random(rand::thread_rng().next_u32());
+        secret_body.set_random(next_sequence());
         let p_len = secret_body.en_body().len();
         net_packet.set_data_len_max();
         let rs = match &self.cipher {
@@ -1,8 +1,8 @@
+use crate::cipher::next_sequence;
 use crate::cipher::Finger;
 use crate::protocol::body::AesCbcSecretBody;
 use crate::protocol::{NetPacket, HEAD_LEN};
 use aes::cipher::{block_padding::Pkcs7, BlockDecryptMut, BlockEncryptMut, KeyInit};
-use rand::RngCore;
 use std::io;
 
 type Aes128EcbEnc = ecb::Encryptor<aes::Aes128>;
@@ -120,7 +120,7 @@ impl AesEcbCipher {
 
         let mut secret_body =
             AesCbcSecretBody::new(net_packet.payload_mut(), self.finger.is_some())?;
-        secret_body.set_random(rand::thread_rng().next_u32());
+        secret_body.set_random(next_sequence());
         let p_len = secret_body.en_body().len();
         net_packet.set_data_len_max();
         let rs = match &self.cipher {
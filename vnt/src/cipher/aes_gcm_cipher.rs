@@ -3,10 +3,10 @@ use std::io;
 use aes_gcm::aead::consts::{U12, U16};
 use aes_gcm::aead::generic_array::GenericArray;
 use aes_gcm::{AeadInPlace, Aes128Gcm, Aes256Gcm, Key, KeyInit, Nonce, Tag};
-use rand::RngCore;
 
 use crate::cipher::finger::Finger;
-use crate::protocol::{body::SecretBody, body::ENCRYPTION_RESERVED, NetPacket};
+use crate::cipher::next_sequence;
+use crate::protocol::{body::SecretBody, body::SECRET_BODY_RESERVED, NetPacket};
 
 #[derive(Clone)]
 pub struct AesGcmCipher {
@@ -44,8 +44,8 @@ impl AesGcmCipher {
             //未加密的数据直接丢弃
             return Err(io::Error::new(io::ErrorKind::Other, "not encrypt"));
         }
-        if net_packet.payload().len() < ENCRYPTION_RESERVED {
-            log::error!("数据异常,长度小于{}", ENCRYPTION_RESERVED);
+        if net_packet.payload().len() < SECRET_BODY_RESERVED {
+            log::error!("数据异常,长度小于{}", SECRET_BODY_RESERVED);
             return Err(io::Error::new(io::ErrorKind::Other, "data err"));
         }
         let mut nonce_raw = [0; 12];
@@ -81,7 +81,7 @@ impl AesGcmCipher {
             ));
         }
         net_packet.set_encrypt_flag(false);
-        net_packet.set_data_len(net_packet.data_len() - ENCRYPTION_RESERVED)?;
+        net_packet.set_data_len(net_packet.data_len() - SECRET_BODY_RESERVED)?;
         return Ok(());
     }
     /// net_packet 必须预留足够长度
@@ -90,7 +90,7 @@ impl AesGcmCipher {
         &self,
         net_packet: &mut NetPacket<B>,
     ) -> io::Result<()> {
-        if net_packet.reserve() < ENCRYPTION_RESERVED {
+        if net_packet.reserve() < SECRET_BODY_RESERVED {
             return Err(io::Error::new(io::ErrorKind::Other, "too short"));
         }
         let mut nonce_raw = [0; 12];
@@ -101,10 +101,10 @@ impl AesGcmCipher {
         nonce_raw[10] = net_packet.is_gateway() as u8;
         nonce_raw[11] = net_packet.source_ttl();
         let nonce: &GenericArray<u8, U12> = Nonce::from_slice(&nonce_raw);
-        let data_len = net_packet.data_len() + ENCRYPTION_RESERVED;
+        let data_len = net_packet.data_len() + SECRET_BODY_RESERVED;
         net_packet.set_data_len(data_len)?;
         let mut secret_body = SecretBody::new(net_packet.payload_mut(), self.finger.is_some())?;
-        secret_body.set_random(rand::thread_rng().next_u32());
+        secret_body.set_random(next_sequence());
         let rs = match &self.cipher {
             AesGcmEnum::AES128GCM(aes_gcm) => {
                 aes_gcm.encrypt_in_place_detached(nonce, &[], secret_body.body_mut())
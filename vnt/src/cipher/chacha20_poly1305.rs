@@ -0,0 +1,249 @@
+use std::io;
+
+use chacha20poly1305::aead::generic_array::GenericArray;
+use chacha20poly1305::{AeadInPlace, ChaCha20Poly1305, Key, KeyInit, Tag, XChaCha20Poly1305};
+use rand::RngCore;
+
+use crate::cipher::finger::Finger;
+use crate::cipher::next_sequence;
+use crate::protocol::{body::SecretBody, body::SECRET_BODY_RESERVED, NetPacket};
+
+/// 没有aes指令集的设备上，chacha20_poly1305比aes_gcm快很多
+///
+/// 每个数据包使用随机生成的nonce并随数据包发送，不会出现相同密钥下nonce重复，
+/// xchacha20_poly1305的nonce有24字节，随机生成也不用担心碰撞。
+/// 头部信息作为附加数据参与认证，修改来源、目的等字段后无法解密
+#[derive(Clone)]
+pub struct ChaCha20Poly1305Cipher {
+    key: [u8; 32],
+    pub(crate) cipher: ChaChaEnum,
+    pub(crate) finger: Option<Finger>,
+}
+
+#[derive(Clone)]
+pub enum ChaChaEnum {
+    ChaCha20Poly1305(ChaCha20Poly1305),
+    XChaCha20Poly1305(XChaCha20Poly1305),
+}
+
+impl ChaCha20Poly1305Cipher {
+    pub fn new_256(key: [u8; 32], finger: Option<Finger>) -> Self {
+        let cipher_key: &Key = &key.into();
+        Self {
+            key,
+            cipher: ChaChaEnum::ChaCha20Poly1305(ChaCha20Poly1305::new(cipher_key)),
+            finger,
+        }
+    }
+    pub fn new_x256(key: [u8; 32], finger: Option<Finger>) -> Self {
+        let cipher_key: &Key = &key.into();
+        Self {
+            key,
+            cipher: ChaChaEnum::XChaCha20Poly1305(XChaCha20Poly1305::new(cipher_key)),
+            finger,
+        }
+    }
+    /// 使用新的密钥创建相同类型的加密器
+    pub fn with_key(&self, key: [u8; 32]) -> Self {
+        match self.cipher {
+            ChaChaEnum::ChaCha20Poly1305(_) => Self::new_256(key, self.finger.clone()),
+            ChaChaEnum::XChaCha20Poly1305(_) => Self::new_x256(key, self.finger.clone()),
+        }
+    }
+    pub fn key(&self) -> &[u8] {
+        &self.key
+    }
+    pub fn nonce_len(&self) -> usize {
+        match self.cipher {
+            ChaChaEnum::ChaCha20Poly1305(_) => 12,
+            ChaChaEnum::XChaCha20Poly1305(_) => 24,
+        }
+    }
+    /// 加密后增加的长度
+    pub fn reserved(&self) -> usize {
+        SECRET_BODY_RESERVED + self.nonce_len()
+    }
+
+    pub fn decrypt_ipv4<B: AsRef<[u8]> + AsMut<[u8]>>(
+        &self,
+        net_packet: &mut NetPacket<B>,
+    ) -> io::Result<()> {
+        if !net_packet.is_encrypt() {
+            //未加密的数据直接丢弃
+            return Err(io::Error::other("not encrypt"));
+        }
+        let reserved = self.reserved();
+        if net_packet.payload().len() < reserved {
+            log::error!("数据异常,长度小于{}", reserved);
+            return Err(io::Error::other("data err"));
+        }
+        let head_nonce = head_nonce(net_packet);
+        let nonce_len = self.nonce_len();
+        let payload = net_packet.payload_mut();
+        let len = payload.len();
+        if let Some(finger) = &self.finger {
+            let finger = finger.calculate_finger(&head_nonce, &payload[..len - 12]);
+            if finger[..] != payload[len - 12..] {
+                return Err(io::Error::other("finger err"));
+            }
+        }
+        let (en_body, nonce) = payload[..len - 12].split_at_mut(len - 12 - nonce_len);
+        let mut secret_body = SecretBody::new(en_body, false)?;
+        let tag = Tag::clone_from_slice(secret_body.tag());
+        let rs = match &self.cipher {
+            ChaChaEnum::ChaCha20Poly1305(cipher) => cipher.decrypt_in_place_detached(
+                GenericArray::from_slice(nonce),
+                &head_nonce,
+                secret_body.body_mut(),
+                &tag,
+            ),
+            ChaChaEnum::XChaCha20Poly1305(cipher) => cipher.decrypt_in_place_detached(
+                GenericArray::from_slice(nonce),
+                &head_nonce,
+                secret_body.body_mut(),
+                &tag,
+            ),
+        };
+        if let Err(e) = rs {
+            return Err(io::Error::other(format!("解密失败:{}", e)));
+        }
+        net_packet.set_encrypt_flag(false);
+        net_packet.set_data_len(net_packet.data_len() - reserved)?;
+        Ok(())
+    }
+    /// net_packet 必须预留足够长度
+    /// data_len是有效载荷的长度
+    pub fn encrypt_ipv4<B: AsRef<[u8]> + AsMut<[u8]>>(
+        &self,
+        net_packet: &mut NetPacket<B>,
+    ) -> io::Result<()> {
+        let reserved = self.reserved();
+        if net_packet.reserve() < reserved {
+            return Err(io::Error::other("too short"));
+        }
+        let head_nonce = head_nonce(net_packet);
+        let nonce_len = self.nonce_len();
+        let data_len = net_packet.data_len() + reserved;
+        net_packet.set_data_len(data_len)?;
+        let payload = net_packet.payload_mut();
+        let len = payload.len();
+        let (en_body, nonce) = payload[..len - 12].split_at_mut(len - 12 - nonce_len);
+        rand::thread_rng().fill_bytes(nonce);
+        let mut secret_body = SecretBody::new(en_body, false)?;
+        secret_body.set_random(next_sequence());
+        let rs = match &self.cipher {
+            ChaChaEnum::ChaCha20Poly1305(cipher) => cipher.encrypt_in_place_detached(
+                GenericArray::from_slice(nonce),
+                &head_nonce,
+                secret_body.body_mut(),
+            ),
+            ChaChaEnum::XChaCha20Poly1305(cipher) => cipher.encrypt_in_place_detached(
+                GenericArray::from_slice(nonce),
+                &head_nonce,
+                secret_body.body_mut(),
+            ),
+        };
+        match rs {
+            Ok(tag) => {
+                secret_body.set_tag(tag.as_slice())?;
+                if let Some(finger) = &self.finger {
+                    let finger = finger.calculate_finger(&head_nonce, &payload[..len - 12]);
+                    payload[len - 12..].copy_from_slice(&finger);
+                } else {
+                    payload[len - 12..].fill(0);
+                }
+                net_packet.set_encrypt_flag(true);
+                Ok(())
+            }
+            Err(e) => Err(io::Error::other(format!("加密失败:{}", e))),
+        }
+    }
+}
+
+/// 计算finger和认证附加数据使用的头部信息，和服务端校验finger时一致
+fn head_nonce<B: AsRef<[u8]>>(net_packet: &NetPacket<B>) -> [u8; 12] {
+    let mut nonce_raw = [0; 12];
+    nonce_raw[0..4].copy_from_slice(&net_packet.source().octets());
+    nonce_raw[4..8].copy_from_slice(&net_packet.destination().octets());
+    nonce_raw[8] = net_packet.protocol().into();
+    nonce_raw[9] = net_packet.transport_protocol();
+    nonce_raw[10] = net_packet.is_gateway() as u8;
+    nonce_raw[11] = net_packet.source_ttl();
+    nonce_raw
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::body::ENCRYPTION_RESERVED;
+    use crate::protocol::{Protocol, HEAD_LEN};
+
+    fn packet(data: &[u8]) -> NetPacket<Vec<u8>> {
+        let mut buf = vec![0u8; HEAD_LEN + data.len() + ENCRYPTION_RESERVED];
+        buf[HEAD_LEN..HEAD_LEN + data.len()].copy_from_slice(data);
+        let mut packet = NetPacket::new_encrypt(buf).unwrap();
+        packet.set_protocol(Protocol::IpTurn);
+        packet.set_source([10, 26, 0, 2].into());
+        packet.set_destination([10, 26, 0, 3].into());
+        packet
+    }
+
+    fn round_trip(cipher: &ChaCha20Poly1305Cipher) {
+        let data = b"hello chacha";
+        let mut p1 = packet(data);
+        let mut p2 = packet(data);
+        cipher.encrypt_ipv4(&mut p1).unwrap();
+        cipher.encrypt_ipv4(&mut p2).unwrap();
+        assert_eq!(p1.data_len(), HEAD_LEN + data.len() + cipher.reserved());
+        // 相同头部和数据每次加密的结果不同
+        assert_ne!(p1.buffer(), p2.buffer());
+        if let Some(finger) = &cipher.finger {
+            finger.check_finger(&p1).unwrap();
+        }
+        cipher.decrypt_ipv4(&mut p1).unwrap();
+        assert_eq!(p1.payload(), data);
+    }
+
+    #[test]
+    fn chacha20_round_trip() {
+        round_trip(&ChaCha20Poly1305Cipher::new_256([3; 32], None));
+        round_trip(&ChaCha20Poly1305Cipher::new_256(
+            [3; 32],
+            Some(Finger::new("token")),
+        ));
+    }
+
+    #[test]
+    fn xchacha20_round_trip() {
+        round_trip(&ChaCha20Poly1305Cipher::new_x256([3; 32], None));
+        round_trip(&ChaCha20Poly1305Cipher::new_x256(
+            [3; 32],
+            Some(Finger::new("token")),
+        ));
+    }
+
+    #[test]
+    fn tampered_nonce() {
+        let cipher = ChaCha20Poly1305Cipher::new_x256([3; 32], None);
+        let mut p = packet(b"hello");
+        cipher.encrypt_ipv4(&mut p).unwrap();
+        let len = p.data_len();
+        // nonce的最后一个字节
+        p.buffer_mut()[len - 13] ^= 1;
+        assert!(cipher.decrypt_ipv4(&mut p).is_err());
+    }
+
+    #[test]
+    fn tampered_header() {
+        for cipher in [
+            ChaCha20Poly1305Cipher::new_256([3; 32], None),
+            ChaCha20Poly1305Cipher::new_x256([3; 32], None),
+        ] {
+            let mut p = packet(b"hello");
+            cipher.encrypt_ipv4(&mut p).unwrap();
+            // 伪造来源
+            p.set_source([10, 26, 0, 4].into());
+            assert!(cipher.decrypt_ipv4(&mut p).is_err());
+        }
+    }
+}
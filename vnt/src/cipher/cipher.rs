@@ -1,20 +1,44 @@
 use crate::cipher::aes_ecb::AesEcbCipher;
 #[cfg(not(feature = "ring-cipher"))]
 use crate::cipher::aes_gcm_cipher::AesGcmCipher;
+#[cfg(not(feature = "ring-cipher"))]
+use crate::cipher::chacha20_poly1305::ChaCha20Poly1305Cipher;
 #[cfg(feature = "ring-cipher")]
 use crate::cipher::ring_aes_gcm_cipher::AesGcmCipher;
+#[cfg(feature = "ring-cipher")]
+use crate::cipher::ring_chacha20_poly1305::ChaCha20Poly1305Cipher;
 use crate::cipher::{aes_cbc, Finger};
+use crate::protocol::body::SECRET_BODY_RESERVED;
 use crate::protocol::NetPacket;
 use aes_cbc::AesCbcCipher;
+use serde::{Deserialize, Serialize};
 use sha2::Digest;
 use std::io;
 use std::str::FromStr;
 
-#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+#[derive(Copy, Clone, Eq, PartialEq, Debug, Serialize, Deserialize)]
 pub enum CipherModel {
+    #[serde(rename = "aes_gcm")]
     AesGcm,
+    #[serde(rename = "aes_cbc")]
     AesCbc,
+    #[serde(rename = "aes_ecb")]
     AesEcb,
+    #[serde(rename = "chacha20_poly1305")]
+    ChaCha20Poly1305,
+    #[serde(rename = "xchacha20_poly1305")]
+    XChaCha20Poly1305,
+}
+
+impl CipherModel {
+    /// 加密数据额外携带的nonce长度
+    pub fn nonce_len(&self) -> usize {
+        match self {
+            CipherModel::ChaCha20Poly1305 => 12,
+            CipherModel::XChaCha20Poly1305 => 24,
+            _ => 0,
+        }
+    }
 }
 
 impl FromStr for CipherModel {
@@ -25,6 +49,8 @@ impl FromStr for CipherModel {
             "aes_gcm" => Ok(CipherModel::AesGcm),
             "aes_cbc" => Ok(CipherModel::AesCbc),
             "aes_ecb" => Ok(CipherModel::AesEcb),
+            "chacha20_poly1305" => Ok(CipherModel::ChaCha20Poly1305),
+            "xchacha20_poly1305" => Ok(CipherModel::XChaCha20Poly1305),
             _ => Err(format!("not match '{}'", s)),
         }
     }
@@ -35,6 +61,7 @@ pub enum Cipher {
     AesGcm((AesGcmCipher, Vec<u8>)),
     AesCbc(AesCbcCipher),
     AesEcb(AesEcbCipher),
+    ChaCha20Poly1305(ChaCha20Poly1305Cipher),
     None,
 }
 
@@ -77,6 +104,23 @@ impl Cipher {
                         Cipher::AesEcb(aes)
                     }
                 }
+                CipherModel::ChaCha20Poly1305 | CipherModel::XChaCha20Poly1305 => {
+                    //chacha20只有256位密钥，密码少于8位时和aes一样只使用前128位
+                    let key = if password.len() < 8 {
+                        let mut short_key = [0u8; 32];
+                        short_key[..16].copy_from_slice(&key[..16]);
+                        short_key[16..].copy_from_slice(&key[..16]);
+                        short_key
+                    } else {
+                        key
+                    };
+                    let chacha = if model == CipherModel::XChaCha20Poly1305 {
+                        ChaCha20Poly1305Cipher::new_x256(key, finger)
+                    } else {
+                        ChaCha20Poly1305Cipher::new_256(key, finger)
+                    };
+                    Cipher::ChaCha20Poly1305(chacha)
+                }
             }
         } else {
             Cipher::None
@@ -96,6 +140,23 @@ impl Cipher {
             _ => Err(io::Error::new(io::ErrorKind::Other, "key error")),
         }
     }
+    /// 使用协商出的会话密钥创建相同模式的加密器，指纹沿用当前配置
+    pub fn new_session(&self, key: [u8; 32]) -> Cipher {
+        match self {
+            Cipher::AesGcm((aes_gcm, _)) => Cipher::AesGcm((
+                AesGcmCipher::new_256(key, aes_gcm.finger.clone()),
+                key.to_vec(),
+            )),
+            Cipher::AesCbc(aes_cbc) => {
+                Cipher::AesCbc(AesCbcCipher::new_256(key, aes_cbc.finger.clone()))
+            }
+            Cipher::AesEcb(aes_ecb) => {
+                Cipher::AesEcb(AesEcbCipher::new_256(key, aes_ecb.finger.clone()))
+            }
+            Cipher::ChaCha20Poly1305(chacha) => Cipher::ChaCha20Poly1305(chacha.with_key(key)),
+            Cipher::None => Cipher::None,
+        }
+    }
     pub fn decrypt_ipv4<B: AsRef<[u8]> + AsMut<[u8]>>(
         &self,
         net_packet: &mut NetPacket<B>,
@@ -104,6 +165,7 @@ impl Cipher {
             Cipher::AesGcm((aes_gcm, _)) => aes_gcm.decrypt_ipv4(net_packet),
             Cipher::AesCbc(aes_cbc) => aes_cbc.decrypt_ipv4(net_packet),
             Cipher::AesEcb(aes_ecb) => aes_ecb.decrypt_ipv4(net_packet),
+            Cipher::ChaCha20Poly1305(chacha) => chacha.decrypt_ipv4(net_packet),
             Cipher::None => {
                 if net_packet.is_encrypt() {
                     return Err(io::Error::new(io::ErrorKind::Other, "not key"));
@@ -120,14 +182,28 @@ impl Cipher {
             Cipher::AesGcm((aes_gcm, _)) => aes_gcm.encrypt_ipv4(net_packet),
             Cipher::AesCbc(aes_cbc) => aes_cbc.encrypt_ipv4(net_packet),
             Cipher::AesEcb(aes_ecb) => aes_ecb.encrypt_ipv4(net_packet),
+            Cipher::ChaCha20Poly1305(chacha) => chacha.encrypt_ipv4(net_packet),
             Cipher::None => Ok(()),
         }
     }
+    /// 加密后数据包最多增加的长度
+    pub fn reserved(&self) -> usize {
+        match self {
+            Cipher::None => 0,
+            Cipher::ChaCha20Poly1305(chacha) => chacha.reserved(),
+            _ => SECRET_BODY_RESERVED,
+        }
+    }
+    /// 是否认证数据内容，aes_cbc/aes_ecb没有认证，序号可以被篡改，不能用于防重放
+    pub fn is_authenticated(&self) -> bool {
+        matches!(self, Cipher::AesGcm(_) | Cipher::ChaCha20Poly1305(_))
+    }
     pub fn check_finger<B: AsRef<[u8]>>(&self, net_packet: &NetPacket<B>) -> io::Result<()> {
         let finger = match self {
             Cipher::AesGcm((aes_gcm, _)) => aes_gcm.finger.as_ref(),
             Cipher::AesCbc(aes_cbc) => aes_cbc.finger.as_ref(),
             Cipher::AesEcb(aes_ecb) => aes_ecb.finger.as_ref(),
+            Cipher::ChaCha20Poly1305(chacha) => chacha.finger.as_ref(),
             Cipher::None => None,
         };
         if let Some(finger) = finger {
@@ -141,6 +217,7 @@ impl Cipher {
             Cipher::AesGcm((_, key)) => Some(key),
             Cipher::AesCbc(aes_cbc) => Some(aes_cbc.key()),
             Cipher::AesEcb(aes_ecb) => Some(aes_ecb.key()),
+            Cipher::ChaCha20Poly1305(chacha) => Some(chacha.key()),
             Cipher::None => None,
         }
     }
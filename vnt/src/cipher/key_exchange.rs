@@ -0,0 +1,185 @@
+use std::net::Ipv4Addr;
+
+use rand::rngs::OsRng;
+use sha2::Digest;
+use x25519_dalek::{EphemeralSecret, PublicKey};
+
+/// 会话密钥的有效期，超过后由发起方重新协商
+pub const REKEY_INTERVAL_SECS: u64 = 10 * 60;
+
+/// 一次密钥交换的本地临时密钥，交换完成后即丢弃，保证前向安全
+pub struct KeyExchange {
+    secret: EphemeralSecret,
+    public: PublicKey,
+}
+
+impl Default for KeyExchange {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl KeyExchange {
+    pub fn new() -> Self {
+        let secret = EphemeralSecret::random_from_rng(OsRng);
+        let public = PublicKey::from(&secret);
+        Self { secret, public }
+    }
+    pub fn public_key(&self) -> [u8; 32] {
+        self.public.to_bytes()
+    }
+    /// 和对端公钥计算共享密钥，再结合密码派生出会话密钥
+    ///
+    /// initiator_public、responder_public按发起方、响应方顺序传入，保证两端结果一致，
+    /// nonce为请求携带的随机数
+    pub fn session_key(
+        self,
+        peer_public: [u8; 32],
+        auth_key: &[u8],
+        initiator_public: &[u8; 32],
+        responder_public: &[u8; 32],
+        epoch: u32,
+        nonce: &[u8; 16],
+    ) -> Option<[u8; 32]> {
+        let shared = self.secret.diffie_hellman(&PublicKey::from(peer_public));
+        if !shared.was_contributory() {
+            //对端使用了低阶点，共享密钥不可信
+            return None;
+        }
+        let mut hasher = sha2::Sha256::new();
+        hasher.update(b"vnt-session-key");
+        hasher.update(shared.as_bytes());
+        hasher.update(auth_key);
+        hasher.update(initiator_public);
+        hasher.update(responder_public);
+        hasher.update(epoch.to_be_bytes());
+        hasher.update(nonce);
+        Some(hasher.finalize().into())
+    }
+}
+
+/// 密钥交换的认证码，只有持有相同密码的节点才能计算出
+pub fn auth_code(
+    auth_key: &[u8],
+    public_key: &[u8; 32],
+    epoch: u32,
+    nonce: &[u8; 16],
+    sequence: u32,
+    source: Ipv4Addr,
+    destination: Ipv4Addr,
+) -> [u8; 32] {
+    let mut hasher = sha2::Sha256::new();
+    hasher.update(b"vnt-key-exchange");
+    hasher.update(auth_key);
+    hasher.update(public_key);
+    hasher.update(epoch.to_be_bytes());
+    hasher.update(nonce);
+    hasher.update(sequence.to_be_bytes());
+    hasher.update(source.octets());
+    hasher.update(destination.octets());
+    hasher.finalize().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn session_key_agree() {
+        let initiator = KeyExchange::new();
+        let responder = KeyExchange::new();
+        let initiator_public = initiator.public_key();
+        let responder_public = responder.public_key();
+        let nonce = [7u8; 16];
+        let k1 = initiator
+            .session_key(
+                responder_public,
+                b"password",
+                &initiator_public,
+                &responder_public,
+                3,
+                &nonce,
+            )
+            .unwrap();
+        let k2 = responder
+            .session_key(
+                initiator_public,
+                b"password",
+                &initiator_public,
+                &responder_public,
+                3,
+                &nonce,
+            )
+            .unwrap();
+        assert_eq!(k1, k2);
+    }
+
+    #[test]
+    fn session_key_bind_nonce() {
+        let initiator = KeyExchange::new();
+        let responder = KeyExchange::new();
+        let initiator_public = initiator.public_key();
+        let responder_public = responder.public_key();
+        let k1 = initiator
+            .session_key(
+                responder_public,
+                b"password",
+                &initiator_public,
+                &responder_public,
+                3,
+                &[1; 16],
+            )
+            .unwrap();
+        let k2 = responder
+            .session_key(
+                initiator_public,
+                b"password",
+                &initiator_public,
+                &responder_public,
+                3,
+                &[2; 16],
+            )
+            .unwrap();
+        assert_ne!(k1, k2);
+    }
+
+    #[test]
+    fn low_order_public_key() {
+        let key_exchange = KeyExchange::new();
+        let public = key_exchange.public_key();
+        assert!(key_exchange
+            .session_key([0; 32], b"", &public, &[0; 32], 1, &[0; 16])
+            .is_none());
+    }
+
+    #[test]
+    fn auth_code_fields() {
+        let source = Ipv4Addr::new(10, 26, 0, 2);
+        let destination = Ipv4Addr::new(10, 26, 0, 3);
+        let auth = auth_code(b"password", &[1; 32], 3, &[1; 16], 9, source, destination);
+        assert_eq!(
+            auth,
+            auth_code(b"password", &[1; 32], 3, &[1; 16], 9, source, destination)
+        );
+        assert_ne!(
+            auth,
+            auth_code(b"password", &[1; 32], 4, &[1; 16], 9, source, destination)
+        );
+        assert_ne!(
+            auth,
+            auth_code(b"password", &[1; 32], 3, &[2; 16], 9, source, destination)
+        );
+        assert_ne!(
+            auth,
+            auth_code(b"other", &[1; 32], 3, &[1; 16], 9, source, destination)
+        );
+        assert_ne!(
+            auth,
+            auth_code(b"password", &[1; 32], 3, &[1; 16], 9, destination, source)
+        );
+        assert_ne!(
+            auth,
+            auth_code(b"password", &[1; 32], 3, &[1; 16], 10, source, destination)
+        );
+    }
+}
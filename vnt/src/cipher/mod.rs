@@ -1,14 +1,49 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use rand::RngCore;
+
+use crate::protocol::NetPacket;
+
 mod aes_cbc;
 mod aes_ecb;
 #[cfg(not(feature = "ring-cipher"))]
 mod aes_gcm_cipher;
+#[cfg(not(feature = "ring-cipher"))]
+mod chacha20_poly1305;
 mod cipher;
 mod finger;
+mod key_exchange;
 #[cfg(feature = "ring-cipher")]
 mod ring_aes_gcm_cipher;
+#[cfg(feature = "ring-cipher")]
+mod ring_chacha20_poly1305;
 mod rsa_cipher;
 
 pub use cipher::Cipher;
 pub use cipher::CipherModel;
 pub use finger::Finger;
+pub use key_exchange::{auth_code, KeyExchange, REKEY_INTERVAL_SECS};
 pub use rsa_cipher::RsaCipher;
+
+lazy_static::lazy_static! {
+    static ref SEQUENCE: AtomicU32 = AtomicU32::new(rand::thread_rng().next_u32());
+}
+
+/// 加密数据体中random位置填充的序号，接收方用于防重放
+pub(crate) fn next_sequence() -> u32 {
+    SEQUENCE.fetch_add(1, Ordering::Relaxed)
+}
+
+/// 下一个要使用的序号，密钥协商时告知对端，对端以此重建防重放窗口
+pub(crate) fn current_sequence() -> u32 {
+    SEQUENCE.load(Ordering::Relaxed)
+}
+
+/// 解密后的序号，位于有效载荷之后
+pub(crate) fn decrypted_sequence<B: AsRef<[u8]>>(net_packet: &NetPacket<B>) -> Option<u32> {
+    let start = net_packet.data_len();
+    net_packet
+        .raw_buffer()
+        .get(start..start + 4)
+        .map(|v| u32::from_be_bytes(v.try_into().unwrap()))
+}
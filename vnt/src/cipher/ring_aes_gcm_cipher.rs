@@ -1,10 +1,10 @@
 use crate::cipher::Finger;
-use rand::RngCore;
+use crate::cipher::next_sequence;
 use ring::aead;
 use ring::aead::{LessSafeKey, UnboundKey};
 use std::io;
 
-use crate::protocol::body::{SecretBody, ENCRYPTION_RESERVED};
+use crate::protocol::body::{SecretBody, SECRET_BODY_RESERVED};
 use crate::protocol::NetPacket;
 
 #[derive(Clone)]
@@ -58,8 +58,8 @@ impl AesGcmCipher {
             //未加密的数据直接丢弃
             return Err(io::Error::new(io::ErrorKind::Other, "not encrypt"));
         }
-        if net_packet.payload().len() < ENCRYPTION_RESERVED {
-            log::error!("数据异常,长度小于{}", ENCRYPTION_RESERVED);
+        if net_packet.payload().len() < SECRET_BODY_RESERVED {
+            log::error!("数据异常,长度小于{}", SECRET_BODY_RESERVED);
             return Err(io::Error::new(io::ErrorKind::Other, "data err"));
         }
         let mut nonce_raw = [0; 12];
@@ -93,7 +93,7 @@ impl AesGcmCipher {
             ));
         }
         net_packet.set_encrypt_flag(false);
-        net_packet.set_data_len(net_packet.data_len() - ENCRYPTION_RESERVED)?;
+        net_packet.set_data_len(net_packet.data_len() - SECRET_BODY_RESERVED)?;
         return Ok(());
     }
     /// net_packet 必须预留足够长度
@@ -111,10 +111,10 @@ impl AesGcmCipher {
         nonce_raw[10] = net_packet.is_gateway() as u8;
         nonce_raw[11] = net_packet.source_ttl();
         let nonce = aead::Nonce::assume_unique_for_key(nonce_raw);
-        let data_len = net_packet.data_len() + ENCRYPTION_RESERVED;
+        let data_len = net_packet.data_len() + SECRET_BODY_RESERVED;
         net_packet.set_data_len(data_len)?;
         let mut secret_body = SecretBody::new(net_packet.payload_mut(), self.finger.is_some())?;
-        secret_body.set_random(rand::thread_rng().next_u32());
+        secret_body.set_random(next_sequence());
 
         let rs = match &self.cipher {
             AesGcmEnum::AesGCM128(cipher, _) => {
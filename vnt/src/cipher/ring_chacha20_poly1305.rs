@@ -0,0 +1,299 @@
+use crate::cipher::next_sequence;
+use crate::cipher::Finger;
+use rand::RngCore;
+use ring::aead;
+use ring::aead::{LessSafeKey, UnboundKey};
+use std::io;
+
+use crate::protocol::body::{SecretBody, SECRET_BODY_RESERVED};
+use crate::protocol::NetPacket;
+
+/// 没有aes指令集的设备上，chacha20_poly1305比aes_gcm快很多
+///
+/// 每个数据包使用随机生成的nonce并随数据包发送，不会出现相同密钥下nonce重复。
+/// ring不支持xchacha20_poly1305，先用hchacha20从密钥和nonce前16字节派生子密钥，
+/// 再用nonce后8字节做chacha20_poly1305。头部信息作为附加数据参与认证
+pub struct ChaCha20Poly1305Cipher {
+    key: [u8; 32],
+    extended: bool,
+    pub(crate) cipher: LessSafeKey,
+    pub(crate) finger: Option<Finger>,
+}
+
+impl Clone for ChaCha20Poly1305Cipher {
+    fn clone(&self) -> Self {
+        self.with_key(self.key)
+    }
+}
+
+impl ChaCha20Poly1305Cipher {
+    pub fn new_256(key: [u8; 32], finger: Option<Finger>) -> Self {
+        let cipher = LessSafeKey::new(UnboundKey::new(&aead::CHACHA20_POLY1305, &key).unwrap());
+        Self {
+            key,
+            extended: false,
+            cipher,
+            finger,
+        }
+    }
+    pub fn new_x256(key: [u8; 32], finger: Option<Finger>) -> Self {
+        Self {
+            extended: true,
+            ..Self::new_256(key, finger)
+        }
+    }
+    /// 使用新的密钥创建相同类型的加密器
+    pub fn with_key(&self, key: [u8; 32]) -> Self {
+        if self.extended {
+            Self::new_x256(key, self.finger.clone())
+        } else {
+            Self::new_256(key, self.finger.clone())
+        }
+    }
+    pub fn key(&self) -> &[u8] {
+        &self.key
+    }
+    pub fn nonce_len(&self) -> usize {
+        if self.extended {
+            24
+        } else {
+            12
+        }
+    }
+    /// 加密后增加的长度
+    pub fn reserved(&self) -> usize {
+        SECRET_BODY_RESERVED + self.nonce_len()
+    }
+    /// xchacha20使用派生的子密钥，返回None时直接使用当前密钥
+    fn sub_key(&self, nonce: &[u8]) -> (Option<LessSafeKey>, aead::Nonce) {
+        if self.extended {
+            let sub_key = hchacha20(&self.key, nonce[..16].try_into().unwrap());
+            let cipher =
+                LessSafeKey::new(UnboundKey::new(&aead::CHACHA20_POLY1305, &sub_key).unwrap());
+            let mut nonce_raw = [0; 12];
+            nonce_raw[4..].copy_from_slice(&nonce[16..]);
+            (Some(cipher), aead::Nonce::assume_unique_for_key(nonce_raw))
+        } else {
+            let nonce = aead::Nonce::assume_unique_for_key(nonce.try_into().unwrap());
+            (None, nonce)
+        }
+    }
+    pub fn decrypt_ipv4<B: AsRef<[u8]> + AsMut<[u8]>>(
+        &self,
+        net_packet: &mut NetPacket<B>,
+    ) -> io::Result<()> {
+        if !net_packet.is_encrypt() {
+            //未加密的数据直接丢弃
+            return Err(io::Error::other("not encrypt"));
+        }
+        let reserved = self.reserved();
+        if net_packet.payload().len() < reserved {
+            log::error!("数据异常,长度小于{}", reserved);
+            return Err(io::Error::other("data err"));
+        }
+        let head_nonce = head_nonce(net_packet);
+        let nonce_len = self.nonce_len();
+        let payload = net_packet.payload_mut();
+        let len = payload.len();
+        if let Some(finger) = &self.finger {
+            let finger = finger.calculate_finger(&head_nonce, &payload[..len - 12]);
+            if finger[..] != payload[len - 12..] {
+                return Err(io::Error::other("ring chacha20 finger err"));
+            }
+        }
+        let (en_body, nonce) = payload[..len - 12].split_at_mut(len - 12 - nonce_len);
+        let (sub_key, nonce) = self.sub_key(nonce);
+        let cipher = sub_key.as_ref().unwrap_or(&self.cipher);
+        let mut secret_body = SecretBody::new(en_body, false)?;
+        if let Err(e) = cipher.open_in_place(
+            nonce,
+            aead::Aad::from(&head_nonce),
+            secret_body.en_body_mut(),
+        ) {
+            return Err(io::Error::other(format!("解密失败:{}", e)));
+        }
+        net_packet.set_encrypt_flag(false);
+        net_packet.set_data_len(net_packet.data_len() - reserved)?;
+        Ok(())
+    }
+    /// net_packet 必须预留足够长度
+    /// data_len是有效载荷的长度
+    pub fn encrypt_ipv4<B: AsRef<[u8]> + AsMut<[u8]>>(
+        &self,
+        net_packet: &mut NetPacket<B>,
+    ) -> io::Result<()> {
+        let reserved = self.reserved();
+        if net_packet.reserve() < reserved {
+            return Err(io::Error::other("too short"));
+        }
+        let head_nonce = head_nonce(net_packet);
+        let nonce_len = self.nonce_len();
+        let data_len = net_packet.data_len() + reserved;
+        net_packet.set_data_len(data_len)?;
+        let payload = net_packet.payload_mut();
+        let len = payload.len();
+        let (en_body, nonce) = payload[..len - 12].split_at_mut(len - 12 - nonce_len);
+        rand::thread_rng().fill_bytes(nonce);
+        let (sub_key, nonce) = self.sub_key(nonce);
+        let cipher = sub_key.as_ref().unwrap_or(&self.cipher);
+        let mut secret_body = SecretBody::new(en_body, false)?;
+        secret_body.set_random(next_sequence());
+        let rs = cipher.seal_in_place_separate_tag(
+            nonce,
+            aead::Aad::from(&head_nonce),
+            secret_body.body_mut(),
+        );
+        match rs {
+            Ok(tag) => {
+                secret_body.set_tag(tag.as_ref())?;
+                if let Some(finger) = &self.finger {
+                    let finger = finger.calculate_finger(&head_nonce, &payload[..len - 12]);
+                    payload[len - 12..].copy_from_slice(&finger);
+                } else {
+                    payload[len - 12..].fill(0);
+                }
+                net_packet.set_encrypt_flag(true);
+                Ok(())
+            }
+            Err(e) => Err(io::Error::other(format!("加密失败:{}", e))),
+        }
+    }
+}
+
+/// 计算finger和认证附加数据使用的头部信息，和服务端校验finger时一致
+fn head_nonce<B: AsRef<[u8]>>(net_packet: &NetPacket<B>) -> [u8; 12] {
+    let mut nonce_raw = [0; 12];
+    nonce_raw[0..4].copy_from_slice(&net_packet.source().octets());
+    nonce_raw[4..8].copy_from_slice(&net_packet.destination().octets());
+    nonce_raw[8] = net_packet.protocol().into();
+    nonce_raw[9] = net_packet.transport_protocol();
+    nonce_raw[10] = net_packet.is_gateway() as u8;
+    nonce_raw[11] = net_packet.source_ttl();
+    nonce_raw
+}
+
+/// https://datatracker.ietf.org/doc/html/draft-irtf-cfrg-xchacha 2.2节
+fn hchacha20(key: &[u8; 32], nonce: &[u8; 16]) -> [u8; 32] {
+    let mut state = [0u32; 16];
+    state[..4].copy_from_slice(&[0x61707865, 0x3320646e, 0x79622d32, 0x6b206574]);
+    for (i, chunk) in key.chunks_exact(4).enumerate() {
+        state[4 + i] = u32::from_le_bytes(chunk.try_into().unwrap());
+    }
+    for (i, chunk) in nonce.chunks_exact(4).enumerate() {
+        state[12 + i] = u32::from_le_bytes(chunk.try_into().unwrap());
+    }
+    for _ in 0..10 {
+        quarter_round(&mut state, 0, 4, 8, 12);
+        quarter_round(&mut state, 1, 5, 9, 13);
+        quarter_round(&mut state, 2, 6, 10, 14);
+        quarter_round(&mut state, 3, 7, 11, 15);
+        quarter_round(&mut state, 0, 5, 10, 15);
+        quarter_round(&mut state, 1, 6, 11, 12);
+        quarter_round(&mut state, 2, 7, 8, 13);
+        quarter_round(&mut state, 3, 4, 9, 14);
+    }
+    let mut out = [0u8; 32];
+    for (i, word) in state[..4].iter().chain(state[12..].iter()).enumerate() {
+        out[i * 4..i * 4 + 4].copy_from_slice(&word.to_le_bytes());
+    }
+    out
+}
+
+fn quarter_round(state: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(16);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(12);
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(8);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(7);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::body::ENCRYPTION_RESERVED;
+    use crate::protocol::{Protocol, HEAD_LEN};
+
+    #[test]
+    fn hchacha20_vector() {
+        let mut key = [0u8; 32];
+        for (i, v) in key.iter_mut().enumerate() {
+            *v = i as u8;
+        }
+        let nonce = [
+            0x00, 0x00, 0x00, 0x09, 0x00, 0x00, 0x00, 0x4a, 0x00, 0x00, 0x00, 0x00, 0x31, 0x41,
+            0x59, 0x27,
+        ];
+        let expect = [
+            0x82, 0x41, 0x3b, 0x42, 0x27, 0xb2, 0x7b, 0xfe, 0xd3, 0x0e, 0x42, 0x50, 0x8a, 0x87,
+            0x7d, 0x73, 0xa0, 0xf9, 0xe4, 0xd5, 0x8a, 0x74, 0xa8, 0x53, 0xc1, 0x2e, 0xc4, 0x13,
+            0x26, 0xd3, 0xec, 0xdc,
+        ];
+        assert_eq!(hchacha20(&key, &nonce), expect);
+    }
+
+    fn packet(data: &[u8]) -> NetPacket<Vec<u8>> {
+        let mut buf = vec![0u8; HEAD_LEN + data.len() + ENCRYPTION_RESERVED];
+        buf[HEAD_LEN..HEAD_LEN + data.len()].copy_from_slice(data);
+        let mut packet = NetPacket::new_encrypt(buf).unwrap();
+        packet.set_protocol(Protocol::IpTurn);
+        packet.set_source([10, 26, 0, 2].into());
+        packet.set_destination([10, 26, 0, 3].into());
+        packet
+    }
+
+    #[test]
+    fn round_trip() {
+        for cipher in [
+            ChaCha20Poly1305Cipher::new_256([3; 32], Some(Finger::new("token"))),
+            ChaCha20Poly1305Cipher::new_x256([3; 32], Some(Finger::new("token"))),
+        ] {
+            let mut p = packet(b"hello chacha");
+            cipher.encrypt_ipv4(&mut p).unwrap();
+            cipher.finger.as_ref().unwrap().check_finger(&p).unwrap();
+            cipher.decrypt_ipv4(&mut p).unwrap();
+            assert_eq!(p.payload(), b"hello chacha");
+        }
+    }
+
+    #[test]
+    fn tampered_header() {
+        for cipher in [
+            ChaCha20Poly1305Cipher::new_256([3; 32], None),
+            ChaCha20Poly1305Cipher::new_x256([3; 32], None),
+        ] {
+            let mut p = packet(b"hello");
+            cipher.encrypt_ipv4(&mut p).unwrap();
+            // 伪造目的
+            p.set_destination([10, 26, 0, 4].into());
+            assert!(cipher.decrypt_ipv4(&mut p).is_err());
+        }
+    }
+
+    /// 和RustCrypto的xchacha20_poly1305结果一致
+    #[cfg(feature = "chacha20poly1305")]
+    #[test]
+    fn xchacha20_compatible() {
+        use chacha20poly1305::aead::generic_array::GenericArray;
+        use chacha20poly1305::{AeadInPlace, KeyInit, XChaCha20Poly1305};
+        let cipher = ChaCha20Poly1305Cipher::new_x256([3; 32], None);
+        let mut p = packet(b"hello xchacha");
+        cipher.encrypt_ipv4(&mut p).unwrap();
+        let payload = p.payload().to_vec();
+        let len = payload.len();
+        let nonce = &payload[len - 12 - 24..len - 12];
+        let tag = &payload[len - 12 - 24 - 16..len - 12 - 24];
+        let mut body = payload[..len - 12 - 24 - 16].to_vec();
+        XChaCha20Poly1305::new(&[3; 32].into())
+            .decrypt_in_place_detached(
+                GenericArray::from_slice(nonce),
+                &head_nonce(&p),
+                &mut body,
+                GenericArray::from_slice(tag),
+            )
+            .unwrap();
+        assert_eq!(&body[..13], b"hello xchacha");
+    }
+}
@@ -0,0 +1,72 @@
+use std::net::Ipv4Addr;
+
+use tokio::sync::broadcast;
+
+use crate::channel::punch::NatInfo;
+use crate::channel::{Route, RouteKey};
+use crate::handle::{ConnectStatus, PeerDeviceInfo, PeerDeviceStatus};
+
+/// 事件通道的容量，订阅方处理太慢时会丢失最旧的事件
+pub const EVENT_CAPACITY: usize = 128;
+
+/// vnt运行过程中的状态变化，通过Vnt::subscribe订阅，避免轮询
+#[derive(Clone, Debug)]
+pub enum VntEvent {
+    /// 和服务端的连接状态变化
+    ConnectStatus(ConnectStatus),
+    /// 对端上线
+    PeerOnline(PeerDeviceInfo),
+    /// 对端离线或者从设备列表中移除
+    PeerOffline(PeerDeviceInfo),
+    /// 新增路由
+    RouteAdd(Ipv4Addr, Route),
+    /// 路由移除
+    RouteRemove(Ipv4Addr, RouteKey),
+    /// NAT类型或者公网地址变化
+    NatChange(NatInfo),
+}
+
+pub type EventSender = broadcast::Sender<VntEvent>;
+pub type EventReceiver = broadcast::Receiver<VntEvent>;
+
+pub fn event_channel() -> EventSender {
+    let (sender, _) = broadcast::channel(EVENT_CAPACITY);
+    sender
+}
+
+/// 没有订阅者时发送会失败，直接忽略
+pub fn send_event(sender: &EventSender, event: VntEvent) {
+    let _ = sender.send(event);
+}
+
+/// 对比新旧设备列表，生成上线和离线事件
+pub fn peer_change_events(old: &[PeerDeviceInfo], new: &[PeerDeviceInfo]) -> Vec<VntEvent> {
+    let mut events = Vec::new();
+    for info in new {
+        let old_status = old
+            .iter()
+            .find(|v| v.virtual_ip == info.virtual_ip)
+            .map(|v| v.status);
+        if old_status == Some(info.status) {
+            continue;
+        }
+        match info.status {
+            PeerDeviceStatus::Online => events.push(VntEvent::PeerOnline(info.clone())),
+            PeerDeviceStatus::Offline => {
+                if old_status.is_some() {
+                    events.push(VntEvent::PeerOffline(info.clone()))
+                }
+            }
+        }
+    }
+    for info in old {
+        if info.status == PeerDeviceStatus::Online
+            && !new.iter().any(|v| v.virtual_ip == info.virtual_ip)
+        {
+            let mut info = info.clone();
+            info.status = PeerDeviceStatus::Offline;
+            events.push(VntEvent::PeerOffline(info));
+        }
+    }
+    events
+}
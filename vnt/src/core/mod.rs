@@ -1,5 +1,7 @@
+use std::collections::HashMap;
+use std::future::Future;
 use std::io;
-use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr, SocketAddrV4};
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -7,36 +9,51 @@ use crossbeam_utils::atomic::AtomicCell;
 use dashmap::DashMap;
 use parking_lot::Mutex;
 use rand::Rng;
-use tokio::net::{TcpStream, UdpSocket};
+use tokio::net::UdpSocket;
 use tokio::sync::mpsc::channel;
 
 use crate::channel::channel::{Channel, Context};
 use crate::channel::idle::Idle;
-use crate::channel::punch::{NatInfo, Punch, PunchModel};
+use crate::channel::proxy::{ProxyKind, Socks5Udp};
+use crate::channel::punch::{NatInfo, Punch};
+use crate::channel::quic_channel::{self, QuicChannel};
 use crate::channel::sender::ChannelSender;
+use crate::channel::stats::VntStats;
+use crate::channel::tcp_channel::{self, ServerConnector, ServerStream};
 use crate::channel::{Route, RouteKey};
-use crate::cipher::{Cipher, CipherModel, RsaCipher};
+use crate::cipher::{Cipher, RsaCipher};
+use crate::core::event::EventReceiver;
 use crate::core::status::VntStatusManger;
 use crate::error::Error;
 use crate::external_route::{AllowExternalRoute, ExternalRoute};
+use crate::handle::connect_handler::ConnectState;
 use crate::handle::handshake_handler::HandshakeEnum;
+use crate::handle::punch_handler::PunchSender;
 use crate::handle::recv_handler::ChannelDataHandler;
 use crate::handle::registration_handler::{RegResponse, ReqEnum};
 #[cfg(any(target_os = "linux", target_os = "macos", target_os = "windows"))]
 use crate::handle::tun_tap::tap_handler;
 use crate::handle::tun_tap::tun_handler;
 use crate::handle::{
-    handshake_handler, heartbeat_handler, punch_handler, registration_handler, ConnectStatus,
-    CurrentDeviceInfo, PeerDeviceInfo,
+    connect_handler, handshake_handler, heartbeat_handler, key_exchange_handler, pmtu_handler,
+    punch_handler, registration_handler, route_advertise_handler, ConnectStatus, CurrentDeviceInfo,
+    PeerDeviceInfo,
 };
 use crate::igmp_server::IgmpServer;
 use crate::nat::NatTest;
 use crate::tun_tap_device;
 use crate::tun_tap_device::{DeviceReader, DeviceWriter};
 
+mod config;
+pub mod event;
 pub mod status;
 pub mod sync;
 
+pub use config::{
+    parse_in_ip, parse_out_ip, Config, ConfigBuilder, ConfigError, DEFAULT_ENCRYPT_MTU,
+    DEFAULT_MTU, DEFAULT_SERVER, DEFAULT_STUN_SERVER,
+};
+
 #[derive(Clone)]
 pub struct Vnt {
     config: Config,
@@ -49,19 +66,28 @@ pub struct Vnt {
     /// 1. 网络中的虚拟ip列表
     device_list: Arc<Mutex<(u16, Vec<PeerDeviceInfo>)>>,
     nat_test: NatTest,
-    connect_status: Arc<AtomicCell<ConnectStatus>>,
+    connect_state: ConnectState,
     peer_nat_info_map: Arc<DashMap<Ipv4Addr, NatInfo>>,
+    in_external_route: ExternalRoute,
+    out_external_route: AllowExternalRoute,
+    ip_proxy: bool,
 }
 
 pub struct VntUtil {
     config: Config,
     main_channel: UdpSocket,
     main_channel_ipv6: Option<UdpSocket>,
-    main_tcp_channel: Option<TcpStream>,
+    main_tcp_channel: Option<ServerStream>,
+    quic_endpoint: Option<quinn::Endpoint>,
+    main_quic_channel: Option<quinn::Connection>,
+    /// udp通过socks5代理转发
+    socks5_udp: Option<Socks5Udp>,
     response: Option<RegResponse>,
     iface: Option<(DeviceWriter, DeviceReader)>,
     server_cipher: Cipher,
     rsa_cipher: Option<RsaCipher>,
+    /// 探测过的服务端公钥，切换服务端时使用
+    server_rsa: HashMap<SocketAddr, RsaCipher>,
 }
 
 impl VntUtil {
@@ -86,38 +112,186 @@ impl VntUtil {
             main_channel,
             main_channel_ipv6,
             main_tcp_channel: None,
+            quic_endpoint: None,
+            main_quic_channel: None,
+            socks5_udp: None,
             response: None,
             iface: None,
             server_cipher,
             rsa_cipher: None,
+            server_rsa: HashMap::new(),
         })
     }
     ///链接
     pub async fn connect(&mut self) -> io::Result<()> {
-        if self.config.tcp {
-            let tcp = TcpStream::connect(self.config.server_address).await?;
+        if self.config.tcp || self.config.ws.is_some() || self.http_proxy() {
+            let connector = self.server_connector();
+            // websocket只有一个地址，不需要选择服务端
+            let tcp = if self.config.server_list.len() > 1 && self.config.ws.is_none() {
+                self.connect_fastest(move |addr| {
+                    let connector = connector.clone();
+                    async move { connector.connect(addr).await }
+                })
+                .await?
+            } else {
+                connector.connect(self.config.server_address).await?
+            };
             let _ = self.main_tcp_channel.insert(tcp);
+        } else if self.config.quic {
+            let endpoint = quic_channel::endpoint(0)?;
+            let connection = if self.config.server_list.len() > 1 {
+                let endpoint = endpoint.clone();
+                self.connect_fastest(move |addr| {
+                    let endpoint = endpoint.clone();
+                    async move { quic_channel::connect(&endpoint, addr).await }
+                })
+                .await?
+            } else {
+                quic_channel::connect(&endpoint, self.config.server_address).await?
+            };
+            let _ = self.main_quic_channel.insert(connection);
+            let _ = self.quic_endpoint.insert(endpoint);
+        } else if let Some(proxy) = &self.config.proxy {
+            let socks5_udp = proxy.udp_associate().await?;
+            log::info!("udp经过socks5代理转发 {}", socks5_udp.relay_addr());
+            let _ = self.socks5_udp.insert(socks5_udp);
         }
         Ok(())
     }
+    /// http代理不能转发udp，只能使用tcp连接服务端
+    fn http_proxy(&self) -> bool {
+        matches!(&self.config.proxy, Some(proxy) if proxy.kind == ProxyKind::Http)
+    }
+    /// tcp和udp使用相同的端口号，方便对端打洞
+    fn local_port(&self) -> u16 {
+        self.main_channel
+            .local_addr()
+            .map(|addr| addr.port())
+            .unwrap_or(0)
+    }
+    fn server_connector(&self) -> ServerConnector {
+        ServerConnector::new(
+            self.local_port(),
+            self.config.ws.clone(),
+            self.config.proxy.clone(),
+        )
+    }
+    /// 同时连接所有服务端，使用最先建立连接的
+    async fn connect_fastest<T, F, Fut>(&mut self, connect: F) -> io::Result<T>
+    where
+        T: Send + 'static,
+        F: Fn(SocketAddr) -> Fut,
+        Fut: Future<Output = io::Result<T>> + Send + 'static,
+    {
+        let mut set = tokio::task::JoinSet::new();
+        for (addr, server_address_str) in resolve_server_list(&self.config.server_list).await {
+            let connect = connect(addr);
+            set.spawn(async move {
+                let rs = tokio::time::timeout(Duration::from_secs(5), connect).await;
+                (addr, server_address_str, rs)
+            });
+        }
+        while let Some(rs) = set.join_next().await {
+            match rs {
+                Ok((addr, server_address_str, Ok(Ok(connection)))) => {
+                    log::info!("选择服务端 {}({})", server_address_str, addr);
+                    self.config.server_address = addr;
+                    self.config.server_address_str = server_address_str;
+                    return Ok(connection);
+                }
+                Ok((addr, _, Ok(Err(e)))) => {
+                    log::warn!("连接服务端 {} 失败:{}", addr, e);
+                }
+                Ok((addr, _, Err(_))) => {
+                    log::warn!("连接服务端 {} 超时", addr);
+                }
+                Err(e) => {
+                    log::warn!("{:?}", e);
+                }
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("connect server failed {:?}", self.config.server_list),
+        ))
+    }
 
     ///握手 用于获取公钥
     pub async fn handshake(&mut self) -> Result<Option<RsaCipher>, HandshakeEnum> {
-        let rsa_cipher = handshake_handler::handshake(
-            &self.main_channel,
-            self.main_tcp_channel.as_mut(),
-            self.config.server_address,
-            self.config.server_encrypt,
-        )
-        .await?;
+        let selected = if self.main_tcp_channel.is_none()
+            && self.main_quic_channel.is_none()
+            && self.socks5_udp.is_none()
+            && self.config.server_list.len() > 1
+        {
+            self.select_server().await
+        } else {
+            None
+        };
+        let rsa_cipher = match selected {
+            // 探测时已经完成了握手
+            Some(rsa_cipher) => rsa_cipher,
+            None => {
+                handshake_handler::handshake(
+                    &self.main_channel,
+                    self.main_tcp_channel.as_mut(),
+                    self.main_quic_channel.as_ref(),
+                    self.socks5_udp.as_ref(),
+                    self.config.server_address,
+                    self.config.server_encrypt,
+                )
+                .await?
+            }
+        };
+        if let Some(rsa_cipher) = &rsa_cipher {
+            self.server_rsa
+                .insert(self.config.server_address, rsa_cipher.clone());
+        }
         self.rsa_cipher = rsa_cipher.clone();
         Ok(rsa_cipher)
     }
+    /// 探测所有服务端，选择延迟最低的，都没有响应时返回None
+    async fn select_server(&mut self) -> Option<Option<RsaCipher>> {
+        let server_list = resolve_server_list(&self.config.server_list).await;
+        let addr_list: Vec<SocketAddr> = server_list.iter().map(|(addr, _)| *addr).collect();
+        let probe_list =
+            handshake_handler::probe(&self.main_channel, &addr_list, self.config.server_encrypt)
+                .await;
+        for (addr, _, rsa_cipher) in &probe_list {
+            if let Some(rsa_cipher) = rsa_cipher {
+                self.server_rsa.insert(*addr, rsa_cipher.clone());
+            }
+        }
+        let (addr, rtt, rsa_cipher) = match probe_list.into_iter().next() {
+            Some(selected) => selected,
+            None => {
+                log::warn!("所有服务端均无响应 {:?}", self.config.server_list);
+                return None;
+            }
+        };
+        let (_, server_address_str) = server_list.into_iter().find(|(v, _)| *v == addr)?;
+        log::info!("选择服务端 {}({}) 延迟:{:?}", server_address_str, addr, rtt);
+        self.config.server_address = addr;
+        self.config.server_address_str = server_address_str;
+        Some(rsa_cipher)
+    }
+    /// 探测到的所有服务端公钥指纹
+    pub fn server_fingerprints(&self) -> Vec<(SocketAddr, String)> {
+        self.server_rsa
+            .iter()
+            .filter_map(|(addr, rsa)| rsa.finger().ok().map(|finger| (*addr, finger)))
+            .collect()
+    }
+    /// 当前选择的服务端
+    pub fn server_address(&self) -> SocketAddr {
+        self.config.server_address
+    }
     /// 加密握手 用于同步密钥
     pub async fn secret_handshake(&mut self) -> Result<(), HandshakeEnum> {
         handshake_handler::secret_handshake(
             &self.main_channel,
             self.main_tcp_channel.as_mut(),
+            self.main_quic_channel.as_ref(),
+            self.socks5_udp.as_ref(),
             self.config.server_address,
             self.rsa_cipher.as_ref().unwrap(),
             &self.server_cipher,
@@ -130,6 +304,8 @@ impl VntUtil {
         match registration_handler::registration(
             &self.main_channel,
             self.main_tcp_channel.as_mut(),
+            self.main_quic_channel.as_ref(),
+            self.socks5_udp.as_ref(),
             &self.server_cipher,
             self.config.server_address,
             self.config.token.clone(),
@@ -176,16 +352,7 @@ impl VntUtil {
             }
             tun_tap_device::DeviceType::Tun
         };
-        let mtu = match self.config.mtu {
-            None => {
-                if self.config.password.is_none() {
-                    1450
-                } else {
-                    1420
-                }
-            }
-            Some(mtu) => mtu,
-        };
+        let mtu = self.config.mtu;
         let in_ips = self
             .config
             .in_ips
@@ -200,7 +367,13 @@ impl VntUtil {
             response.virtual_gateway,
             in_ips,
             mtu,
+            // linux下每个并行任务使用一个网卡队列
+            self.config.parallel,
+            self.config.offload,
         )?;
+        if let Some(virtual_ipv6) = response.virtual_ipv6 {
+            device_writer.set_ipv6(virtual_ipv6, response.virtual_ipv6_prefix)?;
+        }
         let _ = self.iface.insert((device_writer, device_reader));
         Ok(driver_info)
     }
@@ -229,25 +402,95 @@ impl VntUtil {
         let virtual_ip = response.virtual_ip;
         let virtual_gateway = response.virtual_gateway;
         let virtual_netmask = response.virtual_netmask;
-        let current_device = Arc::new(AtomicCell::new(CurrentDeviceInfo::new(
-            virtual_ip,
-            virtual_gateway,
-            virtual_netmask,
-            config.server_address,
-        )));
+        let current_device = Arc::new(AtomicCell::new(
+            CurrentDeviceInfo::new(
+                virtual_ip,
+                virtual_gateway,
+                virtual_netmask,
+                config.server_address,
+            )
+            .with_ipv6(response.virtual_ipv6, response.virtual_ipv6_prefix),
+        ));
 
         let (cone_sender, cone_receiver) = channel(3);
         let (symmetric_sender, symmetric_receiver) = channel(2);
+        let (birthday_sender, birthday_receiver) = if config.birthday_probes > 0 && !config.relay {
+            let (birthday_sender, birthday_receiver) = channel(4);
+            (Some(birthday_sender), Some(birthday_receiver))
+        } else {
+            (None, None)
+        };
         let (tcp_sender, tcp) = if let Some(main_tcp_channel) = self.main_tcp_channel {
             let (tcp_sender, tcp_receiver) = channel::<Vec<u8>>(100);
-            (Some(tcp_sender), Some((main_tcp_channel, tcp_receiver)))
+            let connector = ServerConnector::new(
+                self.main_channel.local_addr().map_or(0, |addr| addr.port()),
+                config.ws.clone(),
+                config.proxy.clone(),
+            );
+            (
+                Some(tcp_sender),
+                Some((main_tcp_channel, tcp_receiver, connector)),
+            )
+        } else {
+            (None, None)
+        };
+        // quic和tcp一样通过队列发往服务端
+        let (tcp_sender, quic_server) = if let Some(main_quic_channel) = self.main_quic_channel {
+            let (quic_sender, quic_receiver) = channel::<Vec<u8>>(100);
+            (Some(quic_sender), Some((main_quic_channel, quic_receiver)))
+        } else {
+            (tcp_sender, None)
+        };
+        let (quic_punch_sender, quic) = if let Some(endpoint) = self.quic_endpoint {
+            let (quic_punch_sender, punch_receiver) = if config.relay {
+                (None, None)
+            } else {
+                let (quic_punch_sender, quic_punch_receiver) = channel(16);
+                (Some(quic_punch_sender), Some(quic_punch_receiver))
+            };
+            (
+                quic_punch_sender,
+                Some(QuicChannel {
+                    endpoint,
+                    server: quic_server,
+                    punch_receiver,
+                }),
+            )
         } else {
             (None, None)
         };
+        let quic_port = match &quic {
+            Some(quic) if !config.relay => quic.endpoint.local_addr().map_or(0, |v| v.port()),
+            _ => 0,
+        };
+        // 监听和udp相同的端口号，接受对端的tcp打洞连接
+        let (tcp_punch_sender, tcp_punch) = if config.relay {
+            (None, None)
+        } else {
+            let local_port = self.main_channel.local_addr().map(|addr| addr.port());
+            match local_port.and_then(tcp_channel::listen) {
+                Ok(tcp_listener) => {
+                    let (tcp_punch_sender, tcp_punch_receiver) = channel(16);
+                    (
+                        Some(tcp_punch_sender),
+                        Some((tcp_listener, tcp_punch_receiver)),
+                    )
+                }
+                Err(e) => {
+                    log::warn!("tcp打洞监听失败,不使用tcp打洞:{:?}", e);
+                    (None, None)
+                }
+            }
+        };
+        let tcp_punch_enable = tcp_punch.is_some();
         let context = Context::new(
             Arc::new(self.main_channel),
             self.main_channel_ipv6.map(|v| Arc::new(v)),
             tcp_sender,
+            tcp_punch_sender,
+            quic_punch_sender,
+            self.socks5_udp,
+            config.fragment,
             current_device.clone(),
             1,
         );
@@ -258,16 +501,16 @@ impl VntUtil {
         let register = Arc::new(registration_handler::Register::new(
             self.server_cipher.clone(),
             channel_sender.clone(),
-            config.server_address,
             config.token.clone(),
             config.device_id.clone(),
             config.name.clone(),
             config.password.is_some(),
         ));
+        context.update_ipv6_table(&response.device_info_list);
         let device_list: Arc<Mutex<(u16, Vec<PeerDeviceInfo>)>> =
             Arc::new(Mutex::new((response.epoch, response.device_info_list)));
         let peer_nat_info_map: Arc<DashMap<Ipv4Addr, NatInfo>> = Arc::new(DashMap::new());
-        let connect_status = Arc::new(AtomicCell::new(ConnectStatus::Connected));
+        let connect_state = ConnectState::new(ConnectStatus::Registered, context.event_sender());
 
         let local_port = context.main_local_ipv4_port().unwrap_or(0);
 
@@ -281,13 +524,13 @@ impl VntUtil {
             response.public_port,
             local_ipv4_addr,
             ipv6_addr,
+            tcp_punch_enable,
+            quic_port,
+            context.event_sender(),
         )
         .await;
-        let in_external_route = if config.in_ips.is_empty() {
-            None
-        } else {
-            Some(ExternalRoute::new(config.in_ips))
-        };
+        // 始终创建路由表，方便运行中重新加载
+        let in_external_route = ExternalRoute::new(config.in_ips);
         let (tcp_proxy, udp_proxy, ip_proxy_map) = if config.out_ips.is_empty() {
             (None, None, None)
         } else {
@@ -299,6 +542,7 @@ impl VntUtil {
             .await?;
             (Some(tcp_proxy), Some(udp_proxy), Some(ip_proxy_map))
         };
+        let ip_proxy = ip_proxy_map.is_some();
         let out_external_route = AllowExternalRoute::new(config.out_ips);
 
         let igmp_server = if config.simulate_multicast {
@@ -315,7 +559,7 @@ impl VntUtil {
                 device_writer.clone(),
                 igmp_server.clone(),
                 current_device.clone(),
-                in_external_route,
+                Some(in_external_route.clone()),
                 ip_proxy_map.clone(),
                 client_cipher.clone(),
                 self.server_cipher.clone(),
@@ -329,7 +573,7 @@ impl VntUtil {
                 device_writer.clone(),
                 igmp_server.clone(),
                 current_device.clone(),
-                in_external_route,
+                Some(in_external_route.clone()),
                 ip_proxy_map.clone(),
                 client_cipher.clone(),
                 self.server_cipher.clone(),
@@ -345,7 +589,7 @@ impl VntUtil {
             device_writer.clone(),
             igmp_server.clone(),
             current_device.clone(),
-            in_external_route,
+            Some(in_external_route.clone()),
             ip_proxy_map.clone(),
             client_cipher.clone(),
             self.server_cipher.clone(),
@@ -361,15 +605,14 @@ impl VntUtil {
             nat_test.clone(),
             igmp_server,
             device_writer.clone(),
-            connect_status.clone(),
+            connect_state.clone(),
             peer_nat_info_map.clone(),
             ip_proxy_map,
-            out_external_route,
-            cone_sender,
-            symmetric_sender,
+            out_external_route.clone(),
+            PunchSender::new(cone_sender, symmetric_sender, birthday_sender),
             client_cipher.clone(),
             self.server_cipher.clone(),
-            self.rsa_cipher.clone(),
+            Arc::new(Mutex::new(self.server_rsa)),
             config.relay,
             config.token.clone(),
         );
@@ -379,7 +622,16 @@ impl VntUtil {
             let relay = config.relay;
             tokio::spawn(async move {
                 channel
-                    .start(channel_worker, tcp, 14, 65, relay, config.parallel)
+                    .start(
+                        channel_worker,
+                        tcp,
+                        tcp_punch,
+                        quic,
+                        14,
+                        config.symmetric_channel_num,
+                        relay,
+                        config.parallel,
+                    )
                     .await
             });
         }
@@ -393,10 +645,45 @@ impl VntUtil {
                 channel_sender.clone(),
                 device_list.clone(),
                 current_device.clone(),
-                config.server_address_str,
                 client_cipher.clone(),
                 self.server_cipher.clone(),
             );
+            // 连接维护，超时后重新握手注册
+            connect_handler::start(
+                vnt_status_manager.worker("connect"),
+                channel_sender.clone(),
+                connect_state.clone(),
+                current_device.clone(),
+                config.server_address_str.clone(),
+                config.server_list.clone(),
+                config.server_encrypt,
+            );
+            if !config.relay {
+                // 路由通告，用于客户端中继
+                route_advertise_handler::start(
+                    vnt_status_manager.worker("route_advertise"),
+                    channel_sender.clone(),
+                    current_device.clone(),
+                    client_cipher.clone(),
+                );
+                // 直连路由的路径mtu探测
+                pmtu_handler::start(
+                    vnt_status_manager.worker("pmtu"),
+                    channel_sender.clone(),
+                    current_device.clone(),
+                    client_cipher.clone(),
+                );
+            }
+            if config.password.is_some() {
+                // 会话密钥协商
+                key_exchange_handler::start(
+                    vnt_status_manager.worker("key_exchange"),
+                    channel_sender.clone(),
+                    device_list.clone(),
+                    current_device.clone(),
+                    client_cipher.clone(),
+                );
+            }
             // 空闲检查
             heartbeat_handler::start_idle(
                 vnt_status_manager.worker("idle"),
@@ -405,6 +692,14 @@ impl VntUtil {
             );
             if !config.relay {
                 // 打洞处理
+                if config.port_mapping {
+                    // 在路由器上映射端口，提高打洞成功率
+                    crate::nat::port_mapping::start(
+                        vnt_status_manager.worker("port_mapping"),
+                        nat_test.clone(),
+                        local_port,
+                    );
+                }
                 punch_handler::start(
                     vnt_status_manager.worker("cone_receiver"),
                     cone_receiver,
@@ -412,6 +707,16 @@ impl VntUtil {
                     current_device.clone(),
                     client_cipher.clone(),
                 );
+                if let Some(birthday_receiver) = birthday_receiver {
+                    punch_handler::start_birthday(
+                        vnt_status_manager.worker("birthday_receiver"),
+                        birthday_receiver,
+                        punch.clone(),
+                        current_device.clone(),
+                        client_cipher.clone(),
+                        config.birthday_probes,
+                    );
+                }
                 punch_handler::start(
                     vnt_status_manager.worker("symmetric_receiver"),
                     symmetric_receiver,
@@ -426,6 +731,7 @@ impl VntUtil {
                     channel_sender,
                     current_device,
                     client_cipher.clone(),
+                    config.birthday_probes > 0,
                 ));
             }
         }
@@ -453,8 +759,11 @@ impl VntUtil {
             device_writer,
             nat_test,
             device_list,
-            connect_status,
+            connect_state,
             peer_nat_info_map,
+            in_external_route,
+            out_external_route,
+            ip_proxy,
         })
     }
 }
@@ -476,7 +785,7 @@ impl Vnt {
         self.peer_nat_info_map.get(ip).map(|e| e.value().clone())
     }
     pub fn connection_status(&self) -> ConnectStatus {
-        self.connect_status.load()
+        self.connect_state.status()
     }
     pub fn nat_info(&self) -> NatInfo {
         self.nat_test.nat_info()
@@ -490,21 +799,73 @@ impl Vnt {
     pub fn route(&self, ip: &Ipv4Addr) -> Option<Route> {
         self.context.route_one(ip)
     }
+    /// 按虚拟ipv4或者虚拟ipv6查找到对端的路由
+    pub fn route_by_ip(&self, ip: &IpAddr) -> Option<Route> {
+        self.context.route_one(&self.context.ip_to_id(ip)?)
+    }
     pub fn route_key(&self, route_key: &RouteKey) -> Option<Ipv4Addr> {
         self.context.route_to_id(route_key)
     }
     pub fn route_table(&self) -> Vec<(Ipv4Addr, Route)> {
         self.context.route_table_one()
     }
+    /// 订阅运行过程中的事件
+    pub fn subscribe(&self) -> EventReceiver {
+        self.context.subscribe()
+    }
+    /// 按对端和通道统计的收发数据
+    pub fn stats(&self) -> VntStats {
+        self.context.stats()
+    }
+    pub fn replay_drop_count(&self) -> u64 {
+        self.context.replay_drop_count()
+    }
+    /// 重新加载运行中可以变更的配置：-i/-o 网段和stun服务器，
+    /// 其余配置需要重启才能生效
+    pub fn reload(&self, config: &Config) {
+        // 新增网段的系统路由只在创建网卡时添加
+        for (dest, mask, _) in &config.in_ips {
+            if !self
+                .config
+                .in_ips
+                .iter()
+                .any(|(d, m, _)| *m == *mask && (*d & *m) == (*dest & *mask))
+            {
+                log::warn!(
+                    "新增网段{}/{}需要重启后才会添加系统路由",
+                    Ipv4Addr::from(*dest & *mask),
+                    Ipv4Addr::from(*mask)
+                );
+            }
+        }
+        self.in_external_route.update(config.in_ips.clone());
+        if !self.ip_proxy && !config.out_ips.is_empty() {
+            log::warn!("启动时未开启ip代理，-o 网段需要重启后才能生效");
+        }
+        self.out_external_route.update(config.out_ips.clone());
+        self.nat_test.update_stun_server(config.stun_server.clone());
+        log::info!("重新加载配置完成");
+    }
     pub fn stop(&self) -> io::Result<()> {
         self.context.close();
         self.vnt_status_manager.stop_all();
         self.device_writer.close()?;
         let virtual_gateway = self.current_device.load().virtual_gateway;
-        let _ = std::net::UdpSocket::bind("0.0.0.0:0")?.send_to(
-            &[0],
-            SocketAddr::V4(SocketAddrV4::new(virtual_gateway, 10000)),
-        );
+        // 多队列网卡按流选择队列，使用不同的源端口多发几次，尽量唤醒每个队列的读取线程
+        #[cfg(target_os = "linux")]
+        let wake_num = if self.device_writer.queue_num() > 1 {
+            self.device_writer.queue_num() * 4
+        } else {
+            1
+        };
+        #[cfg(not(target_os = "linux"))]
+        let wake_num = 1;
+        for _ in 0..wake_num {
+            let _ = std::net::UdpSocket::bind("0.0.0.0:0")?.send_to(
+                &[0],
+                SocketAddr::V4(SocketAddrV4::new(virtual_gateway, 10000)),
+            );
+        }
         Ok(())
     }
     pub async fn wait_stop(&mut self) {
@@ -530,79 +891,22 @@ impl Drop for Vnt {
     }
 }
 
-#[derive(Clone, Debug)]
-pub struct Config {
-    pub tap: bool,
-    pub token: String,
-    pub device_id: String,
-    pub name: String,
-    pub server_address: SocketAddr,
-    pub server_address_str: String,
-    pub stun_server: Vec<String>,
-    pub in_ips: Vec<(u32, u32, Ipv4Addr)>,
-    pub out_ips: Vec<(u32, u32)>,
-    pub password: Option<String>,
-    pub simulate_multicast: bool,
-    pub mtu: Option<u16>,
-    pub tcp: bool,
-    pub ip: Option<Ipv4Addr>,
-    pub relay: bool,
-    pub server_encrypt: bool,
-    pub parallel: usize,
-    pub cipher_model: CipherModel,
-    pub finger: bool,
-    pub punch_model: PunchModel,
-}
-
-impl Config {
-    pub fn new(
-        tap: bool,
-        token: String,
-        device_id: String,
-        name: String,
-        server_address: SocketAddr,
-        server_address_str: String,
-        mut stun_server: Vec<String>,
-        in_ips: Vec<(u32, u32, Ipv4Addr)>,
-        out_ips: Vec<(u32, u32)>,
-        password: Option<String>,
-        simulate_multicast: bool,
-        mtu: Option<u16>,
-        tcp: bool,
-        ip: Option<Ipv4Addr>,
-        relay: bool,
-        server_encrypt: bool,
-        parallel: usize,
-        cipher_model: CipherModel,
-        finger: bool,
-        punch_model: PunchModel,
-    ) -> Self {
-        for x in stun_server.iter_mut() {
-            if !x.contains(":") {
-                x.push_str(":3478");
+/// 解析服务端地址列表，解析失败的忽略
+async fn resolve_server_list(server_list: &[String]) -> Vec<(SocketAddr, String)> {
+    let mut list = Vec::with_capacity(server_list.len());
+    for server_address_str in server_list {
+        match tokio::net::lookup_host(server_address_str).await {
+            Ok(mut addr) => {
+                if let Some(addr) = addr.next() {
+                    if !list.iter().any(|(v, _)| *v == addr) {
+                        list.push((addr, server_address_str.clone()));
+                    }
+                }
+            }
+            Err(e) => {
+                log::warn!("解析服务端地址失败 {}:{:?}", server_address_str, e);
             }
-        }
-        Self {
-            tap,
-            token,
-            device_id,
-            name,
-            server_address,
-            server_address_str,
-            stun_server,
-            in_ips,
-            out_ips,
-            password,
-            simulate_multicast,
-            mtu,
-            tcp,
-            ip,
-            relay,
-            server_encrypt,
-            parallel,
-            cipher_model,
-            finger,
-            punch_model,
         }
     }
+    list
 }
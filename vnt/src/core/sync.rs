@@ -1,11 +1,13 @@
 use crate::cipher::RsaCipher;
+use crate::core::event::{EventReceiver, VntEvent};
 use crate::core::{Config, Vnt, VntUtil};
 use crate::handle::handshake_handler::HandshakeEnum;
 use crate::handle::registration_handler::{RegResponse, ReqEnum};
 use std::io;
 use std::ops::Deref;
 use std::time::Duration;
-use tokio::runtime::Runtime;
+use tokio::runtime::{Handle, Runtime};
+use tokio::sync::broadcast::error::RecvError;
 
 pub struct VntUtilSync {
     vnt_util: VntUtil,
@@ -15,6 +17,15 @@ pub struct VntUtilSync {
 pub struct VntSync {
     vnt: Vnt,
     runtime: Runtime,
+    //vnt运行使用的runtime
+    handle: Handle,
+}
+
+/// 同步方式读取事件，给jni等非异步环境使用
+pub struct VntEventSync {
+    vnt: Vnt,
+    receiver: EventReceiver,
+    handle: Handle,
 }
 
 impl VntUtilSync {
@@ -47,6 +58,7 @@ impl VntUtilSync {
     }
     pub fn build(self) -> crate::Result<VntSync> {
         let runtime = self.runtime;
+        let handle = runtime.handle().clone();
         let vnt = runtime.block_on(self.vnt_util.build())?;
         {
             let mut vnt = vnt.clone();
@@ -58,6 +70,7 @@ impl VntUtilSync {
                 .enable_all()
                 .build()
                 .unwrap(),
+            handle,
         })
     }
 }
@@ -70,6 +83,44 @@ impl VntSync {
         self.runtime
             .block_on(self.vnt.wait_stop_ms(Duration::from_millis(ms)))
     }
+    /// 订阅事件，和vnt共用runtime，不会为每个订阅创建新的runtime
+    pub fn subscribe_sync(&self) -> io::Result<VntEventSync> {
+        Ok(VntEventSync {
+            vnt: self.vnt.clone(),
+            receiver: self.vnt.subscribe(),
+            handle: self.handle.clone(),
+        })
+    }
+    /// 在vnt的runtime的阻塞线程池中执行，用于回调等会阻塞的任务
+    pub fn spawn_blocking<F: FnOnce() + Send + 'static>(&self, f: F) {
+        self.handle.spawn_blocking(f);
+    }
+}
+
+impl VntEventSync {
+    /// 阻塞等待下一个事件，停止后返回None，不能在异步任务中调用
+    pub fn next_event(&mut self) -> Option<VntEvent> {
+        let vnt = &mut self.vnt;
+        let receiver = &mut self.receiver;
+        self.handle.block_on(async {
+            loop {
+                tokio::select! {
+                    _=vnt.wait_stop()=>{
+                        return None;
+                    }
+                    rs=receiver.recv()=>{
+                        match rs {
+                            Ok(event) => return Some(event),
+                            Err(RecvError::Lagged(n)) => {
+                                log::warn!("事件处理太慢,丢失{}个事件", n);
+                            }
+                            Err(RecvError::Closed) => return None,
+                        }
+                    }
+                }
+            }
+        })
+    }
 }
 
 impl Deref for VntSync {
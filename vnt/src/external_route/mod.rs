@@ -1,48 +1,58 @@
 use std::net::Ipv4Addr;
 use std::sync::Arc;
 
+use parking_lot::RwLock;
+
 // 目标ip，子网掩码，网关
 
 #[derive(Clone)]
 pub struct ExternalRoute {
-    route_table: Arc<Vec<(u32, u32, Ipv4Addr)>>,
+    route_table: Arc<RwLock<Vec<(u32, u32, Ipv4Addr)>>>,
 }
 
 impl ExternalRoute {
     pub fn new(route_table: Vec<(u32, u32, Ipv4Addr)>) -> Self {
         Self {
-            route_table: Arc::new(route_table),
+            route_table: Arc::new(RwLock::new(route_table)),
         }
     }
     pub fn route(&self, ip: &Ipv4Addr) -> Option<Ipv4Addr> {
         let ip = u32::from_be_bytes(ip.octets());
-        for (dest, mask, gateway) in self.route_table.iter() {
+        for (dest, mask, gateway) in self.route_table.read().iter() {
             if *mask & ip == *mask & *dest {
                 return Some(*gateway);
             }
         }
         None
     }
+    /// 运行中替换路由表
+    pub fn update(&self, route_table: Vec<(u32, u32, Ipv4Addr)>) {
+        *self.route_table.write() = route_table;
+    }
 }
 
 #[derive(Clone)]
 pub struct AllowExternalRoute {
-    route_table: Arc<Vec<(u32, u32)>>,
+    route_table: Arc<RwLock<Vec<(u32, u32)>>>,
 }
 
 impl AllowExternalRoute {
     pub fn new(route_table: Vec<(u32, u32)>) -> Self {
         Self {
-            route_table: Arc::new(route_table),
+            route_table: Arc::new(RwLock::new(route_table)),
         }
     }
     pub fn allow(&self, ip: &Ipv4Addr) -> bool {
         let ip = u32::from_be_bytes(ip.octets());
-        for (dest, mask) in self.route_table.iter() {
+        for (dest, mask) in self.route_table.read().iter() {
             if *mask & ip == *mask & *dest {
                 return true;
             }
         }
         false
     }
+    /// 运行中替换允许转发的网段
+    pub fn update(&self, route_table: Vec<(u32, u32)>) {
+        *self.route_table.write() = route_table;
+    }
 }
@@ -0,0 +1,210 @@
+use std::io;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crossbeam_utils::atomic::AtomicCell;
+
+use crate::channel::sender::ChannelSender;
+use crate::core::event::{send_event, EventSender, VntEvent};
+use crate::core::status::VntWorker;
+use crate::handle::handshake_handler::handshake_req;
+use crate::handle::{ConnectStatus, CurrentDeviceInfo};
+use crate::util::backoff::Backoff;
+
+/// 超过这个时间没有收到服务端数据则进入Degraded，心跳间隔为5秒
+const DEGRADED_TIMEOUT: Duration = Duration::from_secs(12);
+/// 超过这个时间没有收到服务端数据则重新握手注册
+const RECONNECT_TIMEOUT: Duration = Duration::from_secs(25);
+const BACKOFF_MIN: Duration = Duration::from_secs(2);
+const BACKOFF_MAX: Duration = Duration::from_secs(60);
+
+/// 和服务端的连接状态机，状态变化时发送ConnectStatus事件
+#[derive(Clone)]
+pub struct ConnectState {
+    status: Arc<AtomicCell<ConnectStatus>>,
+    last_recv: Arc<AtomicCell<Instant>>,
+    event_sender: EventSender,
+}
+
+impl ConnectState {
+    pub fn new(status: ConnectStatus, event_sender: EventSender) -> Self {
+        Self {
+            status: Arc::new(AtomicCell::new(status)),
+            last_recv: Arc::new(AtomicCell::new(Instant::now())),
+            event_sender,
+        }
+    }
+    pub fn status(&self) -> ConnectStatus {
+        self.status.load()
+    }
+    pub fn set(&self, status: ConnectStatus) {
+        let old = self.status.swap(status);
+        if old != status {
+            log::info!("连接状态变化 {:?} -> {:?}", old, status);
+            send_event(&self.event_sender, VntEvent::ConnectStatus(status));
+        }
+    }
+    /// 收到服务端的数据
+    pub fn server_alive(&self) {
+        self.last_recv.store(Instant::now());
+        if self
+            .status
+            .compare_exchange(ConnectStatus::Degraded, ConnectStatus::Registered)
+            .is_ok()
+        {
+            log::info!(
+                "连接状态变化 {:?} -> {:?}",
+                ConnectStatus::Degraded,
+                ConnectStatus::Registered
+            );
+            send_event(
+                &self.event_sender,
+                VntEvent::ConnectStatus(ConnectStatus::Registered),
+            );
+        }
+    }
+    /// 注册成功
+    pub fn registered(&self) {
+        self.last_recv.store(Instant::now());
+        self.set(ConnectStatus::Registered);
+    }
+    fn idle(&self) -> Duration {
+        self.last_recv.load().elapsed()
+    }
+}
+
+/// 连接维护任务，检测服务端超时并按指数退避重新握手、注册，
+/// 配置了多个服务端时，当前服务端连接失败后依次切换
+pub fn start(
+    mut worker: VntWorker,
+    sender: ChannelSender,
+    state: ConnectState,
+    current_device: Arc<AtomicCell<CurrentDeviceInfo>>,
+    server_address_str: String,
+    server_list: Vec<String>,
+    server_encrypt: bool,
+) {
+    tokio::spawn(async move {
+        tokio::select! {
+             _=worker.stop_wait()=>{
+                    return;
+             }
+            rs=start_(sender, state, current_device, server_address_str, server_list, server_encrypt)=>{
+                if let Err(e) = rs {
+                    log::warn!("连接维护任务停止:{:?}", e);
+                }
+            }
+        }
+        worker.stop_all();
+    });
+}
+
+async fn start_(
+    sender: ChannelSender,
+    state: ConnectState,
+    current_device: Arc<AtomicCell<CurrentDeviceInfo>>,
+    server_address_str: String,
+    mut server_list: Vec<String>,
+    server_encrypt: bool,
+) -> io::Result<()> {
+    log::info!("启动连接维护任务");
+    if !server_list.contains(&server_address_str) {
+        server_list.insert(0, server_address_str.clone());
+    }
+    let mut index = server_list
+        .iter()
+        .position(|v| v == &server_address_str)
+        .unwrap_or(0);
+    let mut backoff = Backoff::new(BACKOFF_MIN, BACKOFF_MAX);
+    let mut next_attempt = Instant::now();
+    // 本次断线后的重试次数
+    let mut attempts = 0usize;
+    loop {
+        if sender.is_close() {
+            return Ok(());
+        }
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        match state.status() {
+            ConnectStatus::Registered => {
+                backoff.reset();
+                attempts = 0;
+                if state.idle() > DEGRADED_TIMEOUT {
+                    state.set(ConnectStatus::Degraded);
+                }
+            }
+            ConnectStatus::Degraded => {
+                if state.idle() > RECONNECT_TIMEOUT {
+                    state.set(ConnectStatus::Reconnecting);
+                    next_attempt = Instant::now();
+                }
+            }
+            ConnectStatus::Connecting
+            | ConnectStatus::Handshaking
+            | ConnectStatus::Reconnecting => {
+                let now = Instant::now();
+                if now < next_attempt {
+                    continue;
+                }
+                if attempts > 0 && server_list.len() > 1 {
+                    // 上一次重试失败，切换到下一个服务端
+                    index = (index + 1) % server_list.len();
+                    log::info!("切换服务端 {}", server_list[index]);
+                }
+                attempts += 1;
+                // 所有服务端都尝试过一轮后才增加等待时间
+                let wait = if attempts.is_multiple_of(server_list.len()) {
+                    backoff.next_delay()
+                } else {
+                    BACKOFF_MIN
+                };
+                next_attempt = now + wait;
+                update_server_address(&sender, &current_device, &server_list[index]).await;
+                let current_dev = current_device.load();
+                log::info!(
+                    "重新握手 server:{},下次重试间隔:{:?}",
+                    current_dev.connect_server,
+                    wait
+                );
+                state.set(ConnectStatus::Handshaking);
+                // 握手响应由recv_handler处理，继续完成密钥同步和注册
+                if let Err(e) =
+                    handshake_req(&sender, current_dev.connect_server, server_encrypt).await
+                {
+                    log::warn!("发送握手请求失败:{:?}", e);
+                }
+            }
+        }
+    }
+}
+
+/// 重连前重新解析服务端地址，域名对应的ip可能已经变化，
+/// 地址变化后通知tcp、quic的服务端连接重连到新地址
+async fn update_server_address(
+    sender: &ChannelSender,
+    current_device: &AtomicCell<CurrentDeviceInfo>,
+    server_address_str: &str,
+) {
+    let addr = match tokio::net::lookup_host(server_address_str).await {
+        Ok(mut addr) => match addr.next() {
+            Some(addr) => addr,
+            None => return,
+        },
+        Err(e) => {
+            log::warn!("解析服务端地址失败 {}:{:?}", server_address_str, e);
+            return;
+        }
+    };
+    let current_dev = current_device.load();
+    if addr != current_dev.connect_server {
+        log::info!(
+            "服务端地址变化,旧地址:{}，新地址:{}",
+            current_dev.connect_server,
+            addr
+        );
+        let mut tmp = current_dev;
+        tmp.connect_server = addr;
+        if current_device.compare_exchange(current_dev, tmp).is_ok() {
+            sender.server_changed();
+        }
+    }
+}
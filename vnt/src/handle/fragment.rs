@@ -0,0 +1,305 @@
+use std::collections::HashMap;
+use std::io;
+use std::net::Ipv4Addr;
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+
+use crate::channel::channel::Context;
+use crate::cipher::Cipher;
+use crate::protocol::body::ENCRYPTION_RESERVED;
+use crate::protocol::ip_turn_packet::{FragmentPacket, FRAGMENT_HEAD_LEN};
+use crate::protocol::{ip_turn_packet, NetPacket, HEAD_LEN};
+
+/// 未探测到路径mtu时，超过这个长度(udp载荷)的数据包分片发送
+pub const DEFAULT_FRAGMENT_MTU: usize = 1400;
+/// 分片未到齐的超时时间
+const FRAGMENT_TIMEOUT: Duration = Duration::from_secs(5);
+/// 每个客户端最多同时重组的数据包数量，超过时丢弃最早的
+const MAX_PENDING: usize = 32;
+/// 单个数据包最多的分片数量
+const MAX_FRAGMENT_NUM: u8 = 64;
+/// 重组后的数据包最大长度
+const MAX_PACKET_LEN: usize = 65535;
+
+static FRAGMENT_ID: AtomicU16 = AtomicU16::new(0);
+
+/// 开启分片时，数据包超过目标路由的路径mtu(未探测时使用默认值)返回分片使用的mtu，
+/// tcp是流式传输，不需要分片
+pub fn fragment_mtu(
+    context: &Context,
+    dest_id: &Ipv4Addr,
+    data_len: usize,
+    cipher: &Cipher,
+) -> Option<usize> {
+    if !context.is_fragment() {
+        return None;
+    }
+    let mtu = if let Some(route) = context.route_one(dest_id) {
+        if route.route_key().protocol().is_tcp() {
+            return None;
+        }
+        if route.mtu == 0 {
+            DEFAULT_FRAGMENT_MTU
+        } else {
+            route.mtu as usize
+        }
+    } else {
+        DEFAULT_FRAGMENT_MTU
+    };
+    if data_len + cipher.reserved() > mtu {
+        Some(mtu)
+    } else {
+        None
+    }
+}
+
+/// 将未加密的数据包按mtu分片，每个分片复制原数据包的头部并单独加密
+pub fn fragment<B: AsRef<[u8]>>(
+    context: &Context,
+    cipher: &Cipher,
+    net_packet: &NetPacket<B>,
+    mtu: usize,
+) -> io::Result<Vec<NetPacket<Vec<u8>>>> {
+    let dest_id = net_packet.destination();
+    // 使用会话密钥时也是相同的加密方式，预留长度一致
+    let mut list = split(net_packet, mtu, cipher.reserved())?;
+    for packet in list.iter_mut() {
+        context.encrypt_by_id(&dest_id, cipher, packet)?;
+    }
+    Ok(list)
+}
+
+/// 按mtu切分数据包，encrypt_reserved为加密后增加的长度，返回的分片还未加密
+fn split<B: AsRef<[u8]>>(
+    net_packet: &NetPacket<B>,
+    mtu: usize,
+    encrypt_reserved: usize,
+) -> io::Result<Vec<NetPacket<Vec<u8>>>> {
+    let reserved = HEAD_LEN + FRAGMENT_HEAD_LEN + encrypt_reserved;
+    if mtu <= reserved {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "mtu too small"));
+    }
+    let payload = net_packet.payload();
+    let chunks: Vec<&[u8]> = payload.chunks(mtu - reserved).collect();
+    if chunks.len() > MAX_FRAGMENT_NUM as usize {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "too many fragments",
+        ));
+    }
+    let id = FRAGMENT_ID.fetch_add(1, Ordering::Relaxed);
+    let mut list = Vec::with_capacity(chunks.len());
+    for (index, chunk) in chunks.iter().enumerate() {
+        let buf = vec![0u8; HEAD_LEN + FRAGMENT_HEAD_LEN + chunk.len() + ENCRYPTION_RESERVED];
+        let mut packet = NetPacket::new_encrypt(buf)?;
+        packet.buffer_mut()[..HEAD_LEN].copy_from_slice(&net_packet.buffer()[..HEAD_LEN]);
+        packet.set_transport_protocol(ip_turn_packet::Protocol::Fragment.into());
+        let mut fragment_packet = FragmentPacket::unchecked(packet.payload_mut());
+        fragment_packet.set_id(id);
+        fragment_packet.set_index(index as u8);
+        fragment_packet.set_total(chunks.len() as u8);
+        fragment_packet.set_protocol(net_packet.transport_protocol());
+        fragment_packet.set_data(chunk)?;
+        list.push(packet);
+    }
+    Ok(list)
+}
+
+struct Pending {
+    time: Instant,
+    protocol: u8,
+    received: usize,
+    len: usize,
+    parts: Vec<Option<Vec<u8>>>,
+}
+
+/// 分片重组缓存，按来源客户端和分片id区分
+#[derive(Clone)]
+pub struct FragmentBuffer {
+    inner: Arc<Mutex<FragmentBufferInner>>,
+}
+
+struct FragmentBufferInner {
+    clean_time: Instant,
+    table: HashMap<Ipv4Addr, HashMap<u16, Pending>>,
+}
+
+impl Default for FragmentBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl FragmentBuffer {
+    pub fn new() -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(FragmentBufferInner {
+                clean_time: Instant::now(),
+                table: HashMap::new(),
+            })),
+        }
+    }
+    /// 所有分片到齐时返回原上层协议和拼接后的数据
+    pub fn push(&self, source: Ipv4Addr, packet: FragmentPacket<&[u8]>) -> Option<(u8, Vec<u8>)> {
+        let total = packet.total();
+        if total > MAX_FRAGMENT_NUM {
+            return None;
+        }
+        let mut guard = self.inner.lock();
+        let inner = &mut *guard;
+        if inner.clean_time.elapsed() > FRAGMENT_TIMEOUT {
+            //清理不再发送分片的客户端
+            inner.clean_time = Instant::now();
+            inner.table.retain(|_, map| {
+                map.retain(|_, pending| pending.time.elapsed() < FRAGMENT_TIMEOUT);
+                !map.is_empty()
+            });
+        }
+        let map = inner.table.entry(source).or_default();
+        map.retain(|_, pending| pending.time.elapsed() < FRAGMENT_TIMEOUT);
+        let id = packet.id();
+        if let Some(pending) = map.get(&id) {
+            if pending.parts.len() != total as usize || pending.protocol != packet.protocol() {
+                //分片id已经重复使用
+                map.remove(&id);
+            }
+        }
+        if !map.contains_key(&id) && map.len() >= MAX_PENDING {
+            if let Some(oldest) = map
+                .iter()
+                .min_by_key(|(_, pending)| pending.time)
+                .map(|(id, _)| *id)
+            {
+                map.remove(&oldest);
+            }
+        }
+        let pending = map.entry(id).or_insert_with(|| Pending {
+            time: Instant::now(),
+            protocol: packet.protocol(),
+            received: 0,
+            len: 0,
+            parts: vec![None; total as usize],
+        });
+        let part = &mut pending.parts[packet.index() as usize];
+        if part.is_none() {
+            pending.received += 1;
+            pending.len += packet.data().len();
+            *part = Some(packet.data().to_vec());
+        }
+        if pending.len > MAX_PACKET_LEN {
+            map.remove(&id);
+            return None;
+        }
+        if pending.received < pending.parts.len() {
+            return None;
+        }
+        let pending = map.remove(&id)?;
+        let mut data = Vec::with_capacity(pending.len);
+        for part in pending.parts.into_iter().flatten() {
+            data.extend_from_slice(&part);
+        }
+        Some((pending.protocol, data))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::body::SECRET_BODY_RESERVED;
+
+    fn part(id: u16, index: u8, total: u8, data: &[u8]) -> Vec<u8> {
+        let mut buf = vec![0u8; FRAGMENT_HEAD_LEN + data.len()];
+        let mut packet = FragmentPacket::unchecked(&mut buf[..]);
+        packet.set_id(id);
+        packet.set_index(index);
+        packet.set_total(total);
+        packet.set_protocol(4);
+        packet.set_data(data).unwrap();
+        buf
+    }
+
+    fn push(buffer: &FragmentBuffer, buf: &[u8]) -> Option<(u8, Vec<u8>)> {
+        let source = Ipv4Addr::new(10, 26, 0, 2);
+        buffer.push(source, FragmentPacket::new(buf).unwrap())
+    }
+
+    #[test]
+    fn split_out_of_order() {
+        let data: Vec<u8> = (0..3000).map(|i| i as u8).collect();
+        let mut buf = vec![0u8; HEAD_LEN + data.len() + ENCRYPTION_RESERVED];
+        buf[HEAD_LEN..HEAD_LEN + data.len()].copy_from_slice(&data);
+        let mut net_packet = NetPacket::new_encrypt(buf).unwrap();
+        net_packet.set_transport_protocol(ip_turn_packet::Protocol::Ipv4.into());
+        let list = split(&net_packet, 1000, SECRET_BODY_RESERVED).unwrap();
+        assert_eq!(list.len(), 4);
+        assert!(list
+            .iter()
+            .all(|p| p.buffer().len() + SECRET_BODY_RESERVED <= 1000));
+        let buffer = FragmentBuffer::default();
+        for packet in list[1..].iter().rev() {
+            assert!(push(&buffer, packet.payload()).is_none());
+        }
+        let (protocol, rs) = push(&buffer, list[0].payload()).unwrap();
+        let ipv4: u8 = ip_turn_packet::Protocol::Ipv4.into();
+        assert_eq!(protocol, ipv4);
+        assert_eq!(rs, data);
+        assert!(split(
+            &net_packet,
+            HEAD_LEN + FRAGMENT_HEAD_LEN + SECRET_BODY_RESERVED,
+            SECRET_BODY_RESERVED
+        )
+        .is_err());
+        assert!(split(&net_packet, 80, SECRET_BODY_RESERVED).is_err());
+    }
+
+    #[test]
+    fn duplicate() {
+        let buffer = FragmentBuffer::new();
+        assert!(push(&buffer, &part(1, 0, 2, b"ab")).is_none());
+        assert!(push(&buffer, &part(1, 0, 2, b"ab")).is_none());
+        assert_eq!(push(&buffer, &part(1, 1, 2, b"cd")).unwrap().1, b"abcd");
+        // 重组完成后的重复分片不会再次输出
+        assert!(push(&buffer, &part(1, 1, 2, b"cd")).is_none());
+    }
+
+    #[test]
+    fn timeout() {
+        let buffer = FragmentBuffer::new();
+        assert!(push(&buffer, &part(1, 0, 2, b"ab")).is_none());
+        for map in buffer.inner.lock().table.values_mut() {
+            for pending in map.values_mut() {
+                pending.time = Instant::now().checked_sub(FRAGMENT_TIMEOUT).unwrap();
+            }
+        }
+        assert!(push(&buffer, &part(1, 1, 2, b"cd")).is_none());
+        assert_eq!(push(&buffer, &part(1, 0, 2, b"ef")).unwrap().1, b"efcd");
+    }
+
+    #[test]
+    fn id_reuse() {
+        let buffer = FragmentBuffer::new();
+        assert!(push(&buffer, &part(1, 0, 2, b"ab")).is_none());
+        // 分片数量不同，说明id已经被新的数据包使用
+        assert!(push(&buffer, &part(1, 0, 3, b"12")).is_none());
+        assert!(push(&buffer, &part(1, 1, 3, b"34")).is_none());
+        assert_eq!(push(&buffer, &part(1, 2, 3, b"56")).unwrap().1, b"123456");
+    }
+
+    #[test]
+    fn max_pending() {
+        let buffer = FragmentBuffer::new();
+        for id in 0..MAX_PENDING as u16 {
+            assert!(push(&buffer, &part(id, 0, 2, b"ab")).is_none());
+        }
+        for map in buffer.inner.lock().table.values_mut() {
+            map.get_mut(&0).unwrap().time -= Duration::from_secs(1);
+        }
+        assert!(push(&buffer, &part(MAX_PENDING as u16, 0, 2, b"ab")).is_none());
+        assert!(push(&buffer, &part(1, 1, 2, b"cd")).is_some());
+        // 最早的被丢弃
+        assert!(push(&buffer, &part(0, 1, 2, b"cd")).is_none());
+    }
+}
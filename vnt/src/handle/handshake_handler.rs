@@ -1,16 +1,24 @@
+use std::io;
 use std::net::SocketAddr;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use protobuf::Message;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::{TcpStream, UdpSocket};
+use tokio::net::UdpSocket;
 
 use crate::channel::channel::Context;
+use crate::channel::proxy::Socks5Udp;
+use crate::channel::quic_channel;
+use crate::channel::tcp_channel::ServerStream;
 use crate::cipher::{Cipher, RsaCipher};
 use crate::proto::message::{HandshakeRequest, HandshakeResponse, SecretHandshakeRequest};
 use crate::protocol::body::ENCRYPTION_RESERVED;
 use crate::protocol::{service_packet, NetPacket, Protocol, Version, MAX_TTL};
 
+/// 探测多个服务端时等待响应的时间
+const PROBE_TIMEOUT: Duration = Duration::from_millis(1000);
+
+#[derive(Debug)]
 pub enum HandshakeEnum {
     NotSecret,
     KeyError,
@@ -57,7 +65,9 @@ fn secret_handshake_request_packet(
 /// 第一次握手，拿到公钥
 pub async fn handshake(
     main_channel: &UdpSocket,
-    main_tcp_channel: Option<&mut TcpStream>,
+    main_tcp_channel: Option<&mut ServerStream>,
+    main_quic_channel: Option<&quinn::Connection>,
+    socks5_udp: Option<&Socks5Udp>,
     server_address: SocketAddr,
     secret: bool,
 ) -> Result<Option<RsaCipher>, HandshakeEnum> {
@@ -67,12 +77,18 @@ pub async fn handshake(
     let len = send_recv(
         main_channel,
         main_tcp_channel,
+        main_quic_channel,
+        socks5_udp,
         server_address,
         send_buf,
         &mut recv_buf,
     )
     .await?;
-    let net_packet = match NetPacket::new(&recv_buf[..len]) {
+    parse_handshake_response(&recv_buf[..len], secret)
+}
+
+fn parse_handshake_response(buf: &[u8], secret: bool) -> Result<Option<RsaCipher>, HandshakeEnum> {
+    let net_packet = match NetPacket::new(buf) {
         Ok(net_packet) => net_packet,
         Err(e) => {
             return Err(HandshakeEnum::Other(format!("net_packet {}", e)));
@@ -136,13 +152,78 @@ pub async fn handshake(
     }
 }
 
+/// 同时向多个服务端发送握手请求，按响应延迟从低到高排序，没有响应的服务端不返回
+pub async fn probe(
+    main_channel: &UdpSocket,
+    server_list: &[SocketAddr],
+    secret: bool,
+) -> Vec<(SocketAddr, Duration, Option<RsaCipher>)> {
+    let request_packet = handshake_request_packet(secret).unwrap();
+    let start = Instant::now();
+    for server_address in server_list {
+        if let Err(e) = main_channel
+            .send_to(request_packet.buffer(), *server_address)
+            .await
+        {
+            log::warn!("探测服务端 {} 失败:{}", server_address, e);
+        }
+    }
+    let mut list = Vec::with_capacity(server_list.len());
+    let mut recv_buf = [0u8; 10240];
+    let deadline = start + PROBE_TIMEOUT;
+    while list.len() < server_list.len() {
+        let (len, addr) =
+            match tokio::time::timeout_at(deadline.into(), main_channel.recv_from(&mut recv_buf))
+                .await
+            {
+                Ok(Ok(rs)) => rs,
+                Ok(Err(e)) => {
+                    log::warn!("探测服务端 接收失败:{}", e);
+                    break;
+                }
+                Err(_) => break,
+            };
+        let rtt = start.elapsed();
+        if !server_list.contains(&addr) || list.iter().any(|(v, _, _)| *v == addr) {
+            continue;
+        }
+        match parse_handshake_response(&recv_buf[..len], secret) {
+            Ok(rsa_cipher) => {
+                log::info!("探测服务端 {} 延迟:{:?}", addr, rtt);
+                list.push((addr, rtt, rsa_cipher));
+            }
+            Err(e) => {
+                log::warn!("探测服务端 {} 响应错误:{:?}", addr, e);
+            }
+        }
+    }
+    // 按接收顺序入列，延迟已经是升序
+    list
+}
+
 async fn send_recv(
     main_channel: &UdpSocket,
-    main_tcp_channel: Option<&mut TcpStream>,
+    main_tcp_channel: Option<&mut ServerStream>,
+    main_quic_channel: Option<&quinn::Connection>,
+    socks5_udp: Option<&Socks5Udp>,
     server_address: SocketAddr,
     send_buf: &[u8],
     recv_buf: &mut [u8],
 ) -> Result<usize, HandshakeEnum> {
+    if let Some(main_quic_channel) = main_quic_channel {
+        return match quic_channel::send_recv(
+            main_quic_channel,
+            send_buf,
+            recv_buf,
+            Duration::from_millis(300),
+        )
+        .await
+        {
+            Ok(len) => Ok(len),
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => Err(HandshakeEnum::Timeout),
+            Err(e) => Err(HandshakeEnum::Other(format!("quic error:{}", e))),
+        };
+    }
     if let Some(main_tcp_channel) = main_tcp_channel {
         let mut head = [0; 4];
         let len = send_buf.len();
@@ -154,6 +235,9 @@ async fn send_recv(
         if let Err(e) = main_tcp_channel.write_all(send_buf).await {
             return Err(HandshakeEnum::Other(format!("send error:{}", e)));
         }
+        if let Err(e) = main_tcp_channel.flush().await {
+            return Err(HandshakeEnum::Other(format!("send error:{}", e)));
+        }
         if let Err(e) = main_tcp_channel.read_exact(&mut head).await {
             return Err(HandshakeEnum::Other(format!("read error:{}", e)));
         }
@@ -166,12 +250,24 @@ async fn send_recv(
         }
         Ok(len)
     } else {
-        if let Err(e) = main_channel.send_to(send_buf, server_address).await {
+        let rs = match socks5_udp {
+            Some(socks5_udp) => {
+                socks5_udp
+                    .send_to(main_channel, send_buf, server_address)
+                    .await
+            }
+            None => main_channel.send_to(send_buf, server_address).await,
+        };
+        if let Err(e) = rs {
             return Err(HandshakeEnum::Other(format!("send error:{}", e)));
         }
-        match tokio::time::timeout(Duration::from_millis(300), main_channel.recv_from(recv_buf))
-            .await
-        {
+        let recv = async {
+            match socks5_udp {
+                Some(socks5_udp) => socks5_udp.recv_from(main_channel, recv_buf).await,
+                None => main_channel.recv_from(recv_buf).await,
+            }
+        };
+        match tokio::time::timeout(Duration::from_millis(300), recv).await {
             Ok(rs) => match rs {
                 Ok((len, addr)) => {
                     if server_address != addr {
@@ -191,9 +287,12 @@ async fn send_recv(
 }
 
 /// 第二次握手，同步对称密钥，后续将使用对称加密
+#[allow(clippy::too_many_arguments)]
 pub async fn secret_handshake(
     main_channel: &UdpSocket,
-    main_tcp_channel: Option<&mut TcpStream>,
+    main_tcp_channel: Option<&mut ServerStream>,
+    main_quic_channel: Option<&quinn::Connection>,
+    socks5_udp: Option<&Socks5Udp>,
     server_address: SocketAddr,
     rsa_cipher: &RsaCipher,
     server_cipher: &Cipher,
@@ -214,6 +313,8 @@ pub async fn secret_handshake(
     let len = send_recv(
         main_channel,
         main_tcp_channel,
+        main_quic_channel,
+        socks5_udp,
         server_address,
         send_buf,
         &mut recv_buf,
@@ -260,3 +361,16 @@ pub async fn secret_handshake_req(
     }
     Ok(())
 }
+
+/// 运行中重新握手，响应在recv_handler中处理
+pub async fn handshake_req(
+    context: &Context,
+    server_address: SocketAddr,
+    secret: bool,
+) -> crate::Result<()> {
+    let request_packet = handshake_request_packet(secret)?;
+    context
+        .send_main(request_packet.buffer(), server_address)
+        .await?;
+    Ok(())
+}
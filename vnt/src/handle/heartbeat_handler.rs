@@ -1,5 +1,5 @@
 use std::io;
-use std::net::{Ipv4Addr, ToSocketAddrs};
+use std::net::Ipv4Addr;
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -47,7 +47,6 @@ pub fn start_heartbeat(
     sender: ChannelSender,
     device_list: Arc<Mutex<(u16, Vec<PeerDeviceInfo>)>>,
     current_device: Arc<AtomicCell<CurrentDeviceInfo>>,
-    server_address_str: String,
     client_cipher: Cipher,
     server_cipher: Cipher,
 ) {
@@ -56,7 +55,7 @@ pub fn start_heartbeat(
              _=worker.stop_wait()=>{
                     return;
              }
-            rs=start_heartbeat_(sender, device_list, current_device,server_address_str,client_cipher,server_cipher)=>{
+            rs=start_heartbeat_(sender, device_list, current_device,client_cipher,server_cipher)=>{
                 if let Err(e) = rs {
                     log::warn!("心跳任务停止:{:?}", e);
                 }
@@ -74,7 +73,7 @@ fn heartbeat_packet(
     gateway: bool,
     src: Ipv4Addr,
     dest: Ipv4Addr,
-) -> NetPacket<[u8; 48]> {
+) -> NetPacket<[u8; 12 + 4 + ENCRYPTION_RESERVED]> {
     let mut net_packet = NetPacket::new_encrypt([0u8; 12 + 4 + ENCRYPTION_RESERVED]).unwrap();
     net_packet.set_version(Version::V1);
     net_packet.set_protocol(Protocol::Control);
@@ -101,7 +100,6 @@ async fn start_heartbeat_(
     sender: ChannelSender,
     device_list: Arc<Mutex<(u16, Vec<PeerDeviceInfo>)>>,
     current_device: Arc<AtomicCell<CurrentDeviceInfo>>,
-    server_address_str: String,
     client_cipher: Cipher,
     server_cipher: Cipher,
 ) -> io::Result<()> {
@@ -111,7 +109,7 @@ async fn start_heartbeat_(
         if sender.is_close() {
             return Ok(());
         }
-        let mut current_dev = current_device.load();
+        let current_dev = current_device.load();
         //如果和服务端使用tcp连接，则维持udp洞的频率要更高些
         if (sender.is_main_tcp() && count % 2 == 0) || (!sender.is_main_tcp() && count % 20 == 1) {
             let mut packet = NetPacket::new_encrypt([0; 12 + ENCRYPTION_RESERVED])?;
@@ -127,24 +125,6 @@ async fn start_heartbeat_(
                 .send_main_udp(packet.buffer(), current_dev.connect_server)
                 .await;
         }
-        if count % 20 == 19 {
-            if let Ok(mut addr) = server_address_str.to_socket_addrs() {
-                if let Some(addr) = addr.next() {
-                    if addr != current_dev.connect_server {
-                        let mut tmp = current_dev.clone();
-                        tmp.connect_server = addr;
-                        log::info!(
-                            "服务端地址变化,旧地址:{}，新地址:{}",
-                            current_dev.connect_server,
-                            addr
-                        );
-                        if current_device.compare_exchange(current_dev, tmp).is_ok() {
-                            current_dev.connect_server = addr;
-                        }
-                    }
-                }
-            }
-        }
         let src = current_dev.virtual_ip();
         let server_packet = heartbeat_packet(
             MAX_TTL,
@@ -0,0 +1,369 @@
+use std::io;
+use std::net::Ipv4Addr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crossbeam_utils::atomic::AtomicCell;
+use parking_lot::Mutex;
+
+use crate::channel::channel::Context;
+use crate::channel::sender::ChannelSender;
+use crate::channel::RouteKey;
+use crate::cipher::{auth_code, current_sequence, Cipher, KeyExchange, REKEY_INTERVAL_SECS};
+use crate::core::status::VntWorker;
+use crate::handle::{CurrentDeviceInfo, PeerDeviceInfo, PeerDeviceStatus};
+use crate::protocol::body::ENCRYPTION_RESERVED;
+use crate::protocol::control_packet::{KeyExchangePacket, KEY_EXCHANGE_LEN};
+use crate::protocol::{control_packet, NetPacket, Protocol, Version, MAX_TTL};
+
+/// 发起协商后等待响应的时间，超时后重新发起
+const PENDING_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// 会话密钥协商任务，虚拟ip小的一方负责发起协商和定期轮换，
+/// 没有会话密钥时(例如本端刚重启)不论虚拟ip大小都会发起
+///
+/// 只有设置了密码才启用，密码只用于认证协商过程，数据使用协商出的会话密钥加密
+pub fn start(
+    mut worker: VntWorker,
+    sender: ChannelSender,
+    device_list: Arc<Mutex<(u16, Vec<PeerDeviceInfo>)>>,
+    current_device: Arc<AtomicCell<CurrentDeviceInfo>>,
+    client_cipher: Cipher,
+) {
+    tokio::spawn(async move {
+        tokio::select! {
+             _=worker.stop_wait()=>{
+                    return;
+             }
+            rs=start_(sender, device_list, current_device, client_cipher)=>{
+                if let Err(e) = rs {
+                    log::warn!("会话密钥协商任务停止:{:?}", e);
+                }
+            }
+        }
+        worker.stop_all();
+    });
+}
+
+async fn start_(
+    sender: ChannelSender,
+    device_list: Arc<Mutex<(u16, Vec<PeerDeviceInfo>)>>,
+    current_device: Arc<AtomicCell<CurrentDeviceInfo>>,
+    client_cipher: Cipher,
+) -> io::Result<()> {
+    log::info!("启动会话密钥协商任务");
+    loop {
+        if sender.is_close() {
+            return Ok(());
+        }
+        let current_dev = current_device.load();
+        let peers: Vec<Ipv4Addr> = {
+            device_list
+                .lock()
+                .1
+                .iter()
+                .filter(|info| info.status == PeerDeviceStatus::Online && info.client_secret)
+                .map(|info| info.virtual_ip)
+                .collect()
+        };
+        for peer_ip in peers {
+            let established = sender
+                .inner
+                .session_table
+                .get(&peer_ip)
+                .is_some_and(|session| session.is_established());
+            if !is_initiator(current_dev.virtual_ip(), peer_ip, established) {
+                continue;
+            }
+            if let Err(e) = request(&sender, &client_cipher, &current_dev, peer_ip).await {
+                log::warn!("会话密钥协商失败 peer_ip:{},{:?}", peer_ip, e);
+            }
+        }
+        tokio::time::sleep(Duration::from_secs(3)).await;
+    }
+}
+
+/// 虚拟ip小的一方负责协商和轮换，没有会话密钥时双方都可以发起，
+/// 避免一方重启后另一方还在使用旧密钥，重启的一方无法解密
+fn is_initiator(current_ip: Ipv4Addr, peer_ip: Ipv4Addr, established: bool) -> bool {
+    current_ip < peer_ip || !established
+}
+
+/// 没有会话密钥或者密钥过期时发起协商
+async fn request(
+    context: &Context,
+    client_cipher: &Cipher,
+    current_device: &CurrentDeviceInfo,
+    peer_ip: Ipv4Addr,
+) -> io::Result<()> {
+    let (public_key, epoch, nonce) = {
+        let mut session = context.inner.session_table.entry(peer_ip).or_default();
+        if let Some(time) = session.pending_time() {
+            if time.elapsed() < PENDING_TIMEOUT {
+                return Ok(());
+            }
+        } else if session.is_established()
+            && session.time().elapsed() < Duration::from_secs(REKEY_INTERVAL_SECS)
+        {
+            return Ok(());
+        }
+        let key_exchange = KeyExchange::new();
+        let public_key = key_exchange.public_key();
+        let now_secs = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs() as u32);
+        let epoch = session.next_epoch(now_secs);
+        let nonce: [u8; 16] = rand::random();
+        session.set_pending(key_exchange, epoch, nonce);
+        (public_key, epoch, nonce)
+    };
+    let net_packet = key_exchange_packet(
+        client_cipher,
+        control_packet::Protocol::KeyExchangeRequest,
+        current_device.virtual_ip(),
+        peer_ip,
+        &public_key,
+        epoch,
+        &nonce,
+    )?;
+    if context
+        .send_by_id(net_packet.buffer(), &peer_ip)
+        .await
+        .is_err()
+    {
+        context
+            .send_main(net_packet.buffer(), current_device.connect_server)
+            .await?;
+    }
+    Ok(())
+}
+
+fn key_exchange_packet(
+    client_cipher: &Cipher,
+    protocol: control_packet::Protocol,
+    source: Ipv4Addr,
+    destination: Ipv4Addr,
+    public_key: &[u8; 32],
+    epoch: u32,
+    nonce: &[u8; 16],
+) -> io::Result<NetPacket<Vec<u8>>> {
+    let auth_key = client_cipher.key().unwrap_or(&[]);
+    let sequence = current_sequence();
+    let auth = auth_code(
+        auth_key,
+        public_key,
+        epoch,
+        nonce,
+        sequence,
+        source,
+        destination,
+    );
+    let mut net_packet =
+        NetPacket::new_encrypt(vec![0u8; 12 + KEY_EXCHANGE_LEN + ENCRYPTION_RESERVED])?;
+    net_packet.set_version(Version::V1);
+    net_packet.set_protocol(Protocol::Control);
+    net_packet.set_transport_protocol(protocol.into());
+    net_packet.first_set_ttl(MAX_TTL);
+    net_packet.set_source(source);
+    net_packet.set_destination(destination);
+    {
+        let mut packet = KeyExchangePacket::new(net_packet.payload_mut())?;
+        packet.set_public_key(public_key);
+        packet.set_epoch(epoch);
+        packet.set_nonce(nonce);
+        packet.set_sequence(sequence);
+        packet.set_auth(&auth);
+    }
+    //协商包本身使用密码加密
+    client_cipher.encrypt_ipv4(&mut net_packet)?;
+    Ok(net_packet)
+}
+
+fn check_auth(
+    client_cipher: &Cipher,
+    packet: &KeyExchangePacket<&[u8]>,
+    source: Ipv4Addr,
+    destination: Ipv4Addr,
+) -> io::Result<()> {
+    let auth_key = client_cipher.key().unwrap_or(&[]);
+    let auth = auth_code(
+        auth_key,
+        &packet.public_key(),
+        packet.epoch(),
+        &packet.nonce(),
+        packet.sequence(),
+        source,
+        destination,
+    );
+    if auth[..] != *packet.auth() {
+        return Err(io::Error::other("key exchange auth error"));
+    }
+    Ok(())
+}
+
+/// 收到协商请求，生成临时密钥并响应
+pub async fn handle_request(
+    context: &Context,
+    client_cipher: &Cipher,
+    current_device: &CurrentDeviceInfo,
+    source: Ipv4Addr,
+    packet: KeyExchangePacket<&[u8]>,
+    route_key: &RouteKey,
+) -> io::Result<()> {
+    check_auth(client_cipher, &packet, source, current_device.virtual_ip())?;
+    let epoch = packet.epoch();
+    let nonce = packet.nonce();
+    let initiator_public = packet.public_key();
+    let key_exchange = KeyExchange::new();
+    let responder_public = key_exchange.public_key();
+    {
+        let mut session = context.inner.session_table.entry(source).or_default();
+        if session.pending_time().is_some() && current_device.virtual_ip() < source {
+            //双方同时发起时以虚拟ip小的一方的请求为准，对方收到请求后会放弃自己的请求
+            return Ok(());
+        }
+        if !session.is_fresh(epoch) {
+            //重放或者过期的请求，不能覆盖正在使用的密钥
+            return Err(io::Error::other(format!(
+                "stale key exchange epoch:{},current:{}",
+                epoch,
+                session.epoch()
+            )));
+        }
+        let auth_key = client_cipher.key().unwrap_or(&[]);
+        let key = key_exchange
+            .session_key(
+                initiator_public,
+                auth_key,
+                &initiator_public,
+                &responder_public,
+                epoch,
+                &nonce,
+            )
+            .ok_or_else(|| io::Error::other("invalid public key"))?;
+        session.update(epoch, client_cipher.new_session(key), false);
+    }
+    //对端可能已经重启，以对端告知的序号为起点，之前的数据包仍然不能重放
+    context.reset_replay_window(source, packet.sequence());
+    log::info!("会话密钥协商完成 peer_ip:{},epoch:{}", source, epoch);
+    let net_packet = key_exchange_packet(
+        client_cipher,
+        control_packet::Protocol::KeyExchangeResponse,
+        current_device.virtual_ip(),
+        source,
+        &responder_public,
+        epoch,
+        &nonce,
+    )?;
+    context.send_by_key(net_packet.buffer(), route_key).await?;
+    Ok(())
+}
+
+/// 收到协商响应，使用等待中的临时密钥计算会话密钥
+pub fn handle_response(
+    context: &Context,
+    client_cipher: &Cipher,
+    current_device: &CurrentDeviceInfo,
+    source: Ipv4Addr,
+    packet: KeyExchangePacket<&[u8]>,
+) -> io::Result<()> {
+    check_auth(client_cipher, &packet, source, current_device.virtual_ip())?;
+    let epoch = packet.epoch();
+    let nonce = packet.nonce();
+    let responder_public = packet.public_key();
+    let mut session = if let Some(session) = context.inner.session_table.get_mut(&source) {
+        session
+    } else {
+        return Ok(());
+    };
+    let key_exchange = if let Some(key_exchange) = session.take_pending(epoch, &nonce) {
+        key_exchange
+    } else {
+        //重复或者过期的响应
+        return Ok(());
+    };
+    let initiator_public = key_exchange.public_key();
+    let auth_key = client_cipher.key().unwrap_or(&[]);
+    let key = key_exchange
+        .session_key(
+            responder_public,
+            auth_key,
+            &initiator_public,
+            &responder_public,
+            epoch,
+            &nonce,
+        )
+        .ok_or_else(|| io::Error::other("invalid public key"))?;
+    session.update(epoch, client_cipher.new_session(key), true);
+    drop(session);
+    context.reset_replay_window(source, packet.sequence());
+    log::info!("会话密钥协商完成 peer_ip:{},epoch:{}", source, epoch);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel::session::PeerSession;
+
+    fn session_cipher(key: [u8; 32]) -> Cipher {
+        Cipher::new_key([7; 32], String::new())
+            .unwrap()
+            .new_session(key)
+    }
+
+    #[test]
+    fn restart() {
+        let low = Ipv4Addr::new(10, 26, 0, 2);
+        let high = Ipv4Addr::new(10, 26, 0, 3);
+        let now_secs = 1_000_000;
+        // 已经协商过的两端
+        let mut low_session = PeerSession::new();
+        let epoch = low_session.next_epoch(now_secs);
+        low_session.update(epoch, session_cipher([1; 32]), true);
+        assert!(is_initiator(low, high, true));
+        assert!(!is_initiator(high, low, true));
+        // 虚拟ip大的一方重启，没有会话密钥也要发起协商
+        let mut high_session = PeerSession::new();
+        assert!(is_initiator(high, low, high_session.is_established()));
+        let new_epoch = high_session.next_epoch(now_secs + 10);
+        assert!(low_session.is_fresh(new_epoch));
+        let initiator = KeyExchange::new();
+        let responder = KeyExchange::new();
+        let (initiator_public, responder_public) = (initiator.public_key(), responder.public_key());
+        let nonce = [3; 16];
+        let key = responder
+            .session_key(
+                initiator_public,
+                &[7; 32],
+                &initiator_public,
+                &responder_public,
+                new_epoch,
+                &nonce,
+            )
+            .unwrap();
+        low_session.update(new_epoch, session_cipher(key), false);
+        let key = initiator
+            .session_key(
+                responder_public,
+                &[7; 32],
+                &initiator_public,
+                &responder_public,
+                new_epoch,
+                &nonce,
+            )
+            .unwrap();
+        high_session.update(new_epoch, session_cipher(key), true);
+        // 重启的一方立即使用新密钥，对方收到后切换
+        let (_, parity) = high_session.send_cipher().unwrap();
+        assert!(low_session.recv_cipher(parity).is_some());
+        if low_session.need_switch(parity) {
+            low_session.switch();
+        }
+        assert_eq!(low_session.send_cipher().unwrap().1, parity);
+        assert_eq!(
+            low_session.send_cipher().unwrap().0.key(),
+            high_session.send_cipher().unwrap().0.key()
+        );
+    }
+}
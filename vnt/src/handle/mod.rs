@@ -1,10 +1,15 @@
-use std::net::{Ipv4Addr, SocketAddr};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
 
+pub mod connect_handler;
+pub mod fragment;
 pub mod handshake_handler;
 pub mod heartbeat_handler;
+pub mod key_exchange_handler;
+pub mod pmtu_handler;
 pub mod punch_handler;
 pub mod recv_handler;
 pub mod registration_handler;
+pub mod route_advertise_handler;
 pub mod tun_tap;
 
 pub fn now_time() -> u64 {
@@ -22,9 +27,21 @@ fn check_dest(dest: Ipv4Addr, virtual_netmask: Ipv4Addr, virtual_network: Ipv4Ad
         == u32::from_be_bytes(virtual_network.octets())
 }
 
+/// 服务端下发的ipv6地址，长度不为16则视为未分配
+pub(crate) fn ipv6_from_bytes(bytes: &[u8]) -> Option<Ipv6Addr> {
+    let octets: [u8; 16] = bytes.try_into().ok()?;
+    let ip = Ipv6Addr::from(octets);
+    if ip.is_unspecified() {
+        None
+    } else {
+        Some(ip)
+    }
+}
+
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub struct PeerDeviceInfo {
     pub virtual_ip: Ipv4Addr,
+    pub virtual_ipv6: Option<Ipv6Addr>,
     pub name: String,
     pub status: PeerDeviceStatus,
     pub client_secret: bool,
@@ -34,11 +51,16 @@ impl PeerDeviceInfo {
     pub fn new(virtual_ip: Ipv4Addr, name: String, status: u8, client_secret: bool) -> Self {
         Self {
             virtual_ip,
+            virtual_ipv6: None,
             name,
             status: PeerDeviceStatus::from(status),
             client_secret,
         }
     }
+    pub fn with_ipv6(mut self, virtual_ipv6: Option<Ipv6Addr>) -> Self {
+        self.virtual_ipv6 = virtual_ipv6;
+        self
+    }
 }
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Ord, PartialOrd)]
@@ -65,10 +87,35 @@ impl From<u8> for PeerDeviceStatus {
     }
 }
 
+/// 和服务端的连接状态
+///
+/// Connecting -> Handshaking -> Registered <-> Degraded -> Reconnecting -> Handshaking -> Registered
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum ConnectStatus {
+    /// 首次连接
     Connecting,
-    Connected,
+    /// 正在和服务端握手、同步密钥
+    Handshaking,
+    /// 注册成功
+    Registered,
+    /// 一段时间没有收到服务端的数据，p2p通道仍然可用
+    Degraded,
+    /// 服务端长时间无响应或者要求重新注册，按退避间隔重新握手
+    Reconnecting,
+}
+
+impl ConnectStatus {
+    /// 旧版本的名称，等同于Registered
+    #[deprecated(note = "use ConnectStatus::Registered")]
+    #[allow(non_upper_case_globals)]
+    pub const Connected: ConnectStatus = ConnectStatus::Registered;
+    /// 是否已经注册到服务端
+    pub fn online(&self) -> bool {
+        match self {
+            ConnectStatus::Registered | ConnectStatus::Degraded => true,
+            _ => false,
+        }
+    }
 }
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
@@ -82,6 +129,9 @@ pub struct CurrentDeviceInfo {
     pub broadcast_address: Ipv4Addr,
     //链接的服务器地址
     pub connect_server: SocketAddr,
+    //虚拟ipv6，服务端未分配时为None
+    virtual_ipv6: Option<Ipv6Addr>,
+    pub virtual_ipv6_prefix: u8,
 }
 
 impl CurrentDeviceInfo {
@@ -104,8 +154,15 @@ impl CurrentDeviceInfo {
             virtual_network,
             broadcast_address,
             connect_server,
+            virtual_ipv6: None,
+            virtual_ipv6_prefix: 0,
         }
     }
+    pub fn with_ipv6(mut self, virtual_ipv6: Option<Ipv6Addr>, prefix: u8) -> Self {
+        self.virtual_ipv6 = virtual_ipv6;
+        self.virtual_ipv6_prefix = prefix;
+        self
+    }
     #[inline]
     pub fn virtual_ip(&self) -> Ipv4Addr {
         self.virtual_ip
@@ -114,4 +171,21 @@ impl CurrentDeviceInfo {
     pub fn virtual_gateway(&self) -> Ipv4Addr {
         self.virtual_gateway
     }
+    #[inline]
+    pub fn virtual_ipv6(&self) -> Option<Ipv6Addr> {
+        self.virtual_ipv6
+    }
+    /// 是否和当前设备的ipv6在一个网段
+    pub fn is_ipv6_network(&self, dest: &Ipv6Addr) -> bool {
+        if let Some(ip) = self.virtual_ipv6 {
+            let prefix = self.virtual_ipv6_prefix.min(128) as u32;
+            if prefix == 0 {
+                return false;
+            }
+            let mask = u128::MAX << (128 - prefix);
+            u128::from(ip) & mask == u128::from(*dest) & mask
+        } else {
+            false
+        }
+    }
 }
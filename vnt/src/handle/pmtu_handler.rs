@@ -0,0 +1,147 @@
+use std::collections::HashMap;
+use std::io;
+use std::net::Ipv4Addr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crossbeam_utils::atomic::AtomicCell;
+
+use crate::channel::sender::ChannelSender;
+use crate::channel::{Route, RouteKey};
+use crate::cipher::Cipher;
+use crate::core::status::VntWorker;
+use crate::handle::CurrentDeviceInfo;
+use crate::protocol::{control_packet, NetPacket, Protocol, Version};
+
+/// 常见的链路mtu，依次为以太网、PPPoE、各种隧道，最小为ipv4要求的576
+const LINK_MTU_LIST: [u16; 9] = [1500, 1492, 1480, 1460, 1420, 1400, 1280, 1200, 576];
+/// 每个长度发送的次数，避免偶然丢包导致结果偏小
+const PROBE_COUNT: usize = 2;
+/// 等待探测响应的时间
+const PROBE_WAIT: Duration = Duration::from_secs(2);
+/// 探测成功后重新探测的间隔，路径可能发生变化
+const PROBE_INTERVAL: Duration = Duration::from_secs(600);
+/// 探测失败后重试的间隔
+const RETRY_INTERVAL: Duration = Duration::from_secs(30);
+const CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// 路径mtu探测任务，向直连的客户端发送不同长度的填充包，
+/// 对端回复收到的长度，取最大值作为该路由的mtu
+pub fn start(
+    mut worker: VntWorker,
+    sender: ChannelSender,
+    current_device: Arc<AtomicCell<CurrentDeviceInfo>>,
+    client_cipher: Cipher,
+) {
+    tokio::spawn(async move {
+        tokio::select! {
+             _=worker.stop_wait()=>{
+                    return;
+             }
+            rs=start_(sender, current_device, client_cipher)=>{
+                if let Err(e) = rs {
+                    log::warn!("路径mtu探测任务停止:{:?}", e);
+                }
+            }
+        }
+        worker.stop_all();
+    });
+}
+
+async fn start_(
+    sender: ChannelSender,
+    current_device: Arc<AtomicCell<CurrentDeviceInfo>>,
+    client_cipher: Cipher,
+) -> io::Result<()> {
+    log::info!("启动路径mtu探测任务");
+    let mut probe_time: HashMap<(RouteKey, Ipv4Addr), Instant> = HashMap::new();
+    loop {
+        if sender.is_close() {
+            return Ok(());
+        }
+        tokio::time::sleep(CHECK_INTERVAL).await;
+        let current_dev = current_device.load();
+        let route_table: Vec<(Ipv4Addr, Route)> = sender
+            .direct_route_table_one()
+            .into_iter()
+            // tcp是流式传输，quic由自身处理分包，只探测udp路由
+            .filter(|(ip, route)| {
+                *ip != current_dev.virtual_gateway && route.route_key().protocol().is_udp()
+            })
+            .collect();
+        probe_time.retain(|(route_key, ip), _| {
+            route_table
+                .iter()
+                .any(|(v, route)| v == ip && route.route_key() == *route_key)
+        });
+        let mut probe_list = Vec::new();
+        for (peer_ip, route) in route_table {
+            let key = (route.route_key(), peer_ip);
+            let interval = if route.mtu == 0 {
+                RETRY_INTERVAL
+            } else {
+                PROBE_INTERVAL
+            };
+            if probe_time
+                .get(&key)
+                .map_or(false, |time| time.elapsed() < interval)
+            {
+                continue;
+            }
+            probe_time.insert(key, Instant::now());
+            sender.start_mtu_probe(peer_ip, route.route_key());
+            if let Err(e) = probe(&sender, &client_cipher, &current_dev, peer_ip, &route).await {
+                log::warn!("路径mtu探测失败 peer_ip:{},{:?}", peer_ip, e);
+            }
+            probe_list.push((peer_ip, route));
+        }
+        if probe_list.is_empty() {
+            continue;
+        }
+        tokio::time::sleep(PROBE_WAIT).await;
+        for (peer_ip, route) in probe_list {
+            let mtu = sender.finish_mtu_probe(peer_ip, route.route_key());
+            if mtu != 0 && mtu != route.mtu {
+                log::info!(
+                    "路径mtu peer_ip:{},route:{:?},mtu:{}",
+                    peer_ip,
+                    route.route_key(),
+                    mtu
+                );
+                sender.set_route_mtu(&peer_ip, &route.route_key(), mtu);
+            }
+        }
+    }
+}
+
+async fn probe(
+    sender: &ChannelSender,
+    client_cipher: &Cipher,
+    current_device: &CurrentDeviceInfo,
+    peer_ip: Ipv4Addr,
+    route: &Route,
+) -> io::Result<()> {
+    // ip头和udp头
+    let head_len = if route.addr.is_ipv4() { 20 + 8 } else { 40 + 8 };
+    for link_mtu in LINK_MTU_LIST {
+        let len = link_mtu as usize - head_len;
+        // 加密后不超过探测的长度，实际长度以对端收到的为准
+        let mut net_packet = NetPacket::new0(len - client_cipher.reserved(), vec![0u8; len])?;
+        net_packet.set_version(Version::V1);
+        net_packet.set_protocol(Protocol::Control);
+        net_packet.set_transport_protocol(control_packet::Protocol::MtuProbe.into());
+        // 只发给直连的客户端，不需要转发
+        net_packet.first_set_ttl(1);
+        net_packet.set_source(current_device.virtual_ip());
+        net_packet.set_destination(peer_ip);
+        client_cipher.encrypt_ipv4(&mut net_packet)?;
+        for _ in 0..PROBE_COUNT {
+            // 超过本地网卡mtu时可能发送失败，忽略
+            let _ = sender
+                .send_by_key(net_packet.buffer(), &route.route_key())
+                .await;
+        }
+        tokio::time::sleep(Duration::from_millis(2)).await;
+    }
+    Ok(())
+}
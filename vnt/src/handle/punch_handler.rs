@@ -1,4 +1,4 @@
-use crate::channel::punch::{NatInfo, Punch};
+use crate::channel::punch::{NatInfo, NatType, Punch};
 use crate::channel::sender::ChannelSender;
 use crate::cipher::Cipher;
 use crate::core::status::VntWorker;
@@ -15,7 +15,53 @@ use std::io;
 use std::net::Ipv4Addr;
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::sync::mpsc::Receiver;
+use tokio::sync::mpsc::{Receiver, Sender};
+use tokio::time::Instant;
+
+/// 回复方收到打洞请求后，等待这么久再开始生日攻击打洞，留出时间让请求方收到回复
+pub const BIRTHDAY_DELAY: Duration = Duration::from_secs(2);
+
+/// 按对端的nat类型把打洞请求交给对应的打洞任务
+#[derive(Clone)]
+pub struct PunchSender {
+    cone_sender: Sender<(Ipv4Addr, NatInfo)>,
+    symmetric_sender: Sender<(Ipv4Addr, NatInfo)>,
+    /// 生日攻击打洞，附带开始时间
+    birthday_sender: Option<Sender<(Ipv4Addr, NatInfo, Instant)>>,
+}
+
+impl PunchSender {
+    pub fn new(
+        cone_sender: Sender<(Ipv4Addr, NatInfo)>,
+        symmetric_sender: Sender<(Ipv4Addr, NatInfo)>,
+        birthday_sender: Option<Sender<(Ipv4Addr, NatInfo, Instant)>>,
+    ) -> Self {
+        Self {
+            cone_sender,
+            symmetric_sender,
+            birthday_sender,
+        }
+    }
+    pub fn punch(&self, peer_ip: Ipv4Addr, peer_nat_info: NatInfo) -> bool {
+        match peer_nat_info.nat_type {
+            NatType::Symmetric => self
+                .symmetric_sender
+                .try_send((peer_ip, peer_nat_info))
+                .is_ok(),
+            NatType::Cone => self.cone_sender.try_send((peer_ip, peer_nat_info)).is_ok(),
+        }
+    }
+    /// 是否开启了生日攻击打洞
+    pub fn is_birthday(&self) -> bool {
+        self.birthday_sender.is_some()
+    }
+    /// 未开启生日攻击时忽略
+    pub fn birthday(&self, peer_ip: Ipv4Addr, peer_nat_info: &NatInfo, start: Instant) {
+        if let Some(sender) = &self.birthday_sender {
+            let _ = sender.try_send((peer_ip, peer_nat_info.clone(), start));
+        }
+    }
+}
 
 pub fn start(
     mut worker: VntWorker,
@@ -64,6 +110,16 @@ async fn start_(
     peer_ip: Ipv4Addr,
     nat_info: NatInfo,
 ) -> io::Result<()> {
+    let packet = punch_request(client_cipher, current_device, peer_ip)?;
+    log::info!("发起打洞，目标:{:?},{:?}", peer_ip, nat_info);
+    punch.punch(packet.buffer(), peer_ip, nat_info).await
+}
+
+fn punch_request(
+    client_cipher: &Cipher,
+    current_device: &Arc<AtomicCell<CurrentDeviceInfo>>,
+    peer_ip: Ipv4Addr,
+) -> io::Result<NetPacket<[u8; 12 + ENCRYPTION_RESERVED]>> {
     let mut packet = NetPacket::new_encrypt([0u8; 12 + ENCRYPTION_RESERVED])?;
     packet.set_version(Version::V1);
     packet.first_set_ttl(1);
@@ -71,9 +127,53 @@ async fn start_(
     packet.set_transport_protocol(control_packet::Protocol::PunchRequest.into());
     packet.set_source(current_device.load().virtual_ip());
     packet.set_destination(peer_ip);
-    log::info!("发起打洞，目标:{:?},{:?}", peer_ip, nat_info);
     client_cipher.encrypt_ipv4(&mut packet)?;
-    punch.punch(packet.buffer(), peer_ip, nat_info).await
+    Ok(packet)
+}
+
+/// 生日攻击打洞，双方约定时间后同时从多个端口发送
+pub fn start_birthday(
+    mut worker: VntWorker,
+    receiver: Receiver<(Ipv4Addr, NatInfo, Instant)>,
+    punch: Punch,
+    current_device: Arc<AtomicCell<CurrentDeviceInfo>>,
+    client_cipher: Cipher,
+    probes: usize,
+) {
+    tokio::spawn(async move {
+        tokio::select! {
+            _=start_birthday_(receiver, punch, current_device,client_cipher,probes)=>{}
+            _=worker.stop_wait()=>{
+                return;
+            }
+        }
+        worker.stop_all();
+    });
+}
+
+async fn start_birthday_(
+    mut receiver: Receiver<(Ipv4Addr, NatInfo, Instant)>,
+    punch: Punch,
+    current_device: Arc<AtomicCell<CurrentDeviceInfo>>,
+    client_cipher: Cipher,
+    probes: usize,
+) {
+    log::info!("启动生日攻击打洞任务");
+    while let Some((peer_ip, nat_info, start)) = receiver.recv().await {
+        tokio::time::sleep_until(start).await;
+        log::info!("生日攻击打洞，目标:{:?},{:?}", peer_ip, nat_info);
+        let rs = match punch_request(&client_cipher, &current_device, peer_ip) {
+            Ok(packet) => {
+                punch
+                    .birthday_punch(packet.buffer(), peer_ip, &nat_info, probes)
+                    .await
+            }
+            Err(e) => Err(e),
+        };
+        if let Err(e) = rs {
+            log::warn!("生日攻击打洞异常 {:?}", e);
+        }
+    }
 }
 
 pub async fn start_punch(
@@ -83,6 +183,7 @@ pub async fn start_punch(
     sender: ChannelSender,
     current_device: Arc<AtomicCell<CurrentDeviceInfo>>,
     client_cipher: Cipher,
+    birthday: bool,
 ) {
     let mut num = 0;
     let sleep_time = [3, 5, 7, 11, 13, 17, 19, 23, 29];
@@ -93,7 +194,7 @@ pub async fn start_punch(
         }
         tokio::select! {
             rs= start_punch_(Duration::from_secs(sleep_time[num % sleep_time.len()]),&nat_test, &device_list,
-                &sender, &current_device,&client_cipher)=>{
+                &sender, &current_device,&client_cipher,birthday)=>{
                  if let Err(e) = rs {
                     log::warn!("打洞处理任务异常 {:?}", e);
                 }
@@ -113,6 +214,7 @@ async fn start_punch_(
     sender: &ChannelSender,
     current_device: &Arc<AtomicCell<CurrentDeviceInfo>>,
     client_cipher: &Cipher,
+    birthday: bool,
 ) -> crate::Result<()> {
     let current_device = current_device.load();
     let nat_info = nat_test.nat_info();
@@ -135,6 +237,7 @@ async fn start_punch_(
             current_device.virtual_ip(),
             &nat_info,
             info.virtual_ip,
+            birthday,
         )
         .unwrap();
         let _ = sender
@@ -150,9 +253,12 @@ pub fn punch_packet(
     virtual_ip: Ipv4Addr,
     nat_info: &NatInfo,
     dest: Ipv4Addr,
+    birthday: bool,
 ) -> crate::Result<NetPacket<Vec<u8>>> {
     let mut punch_reply = PunchInfo::new();
     punch_reply.reply = false;
+    // 自己是对称网络时才需要生日攻击，最终是否使用由对方决定
+    punch_reply.birthday = birthday && nat_info.nat_type == NatType::Symmetric;
     punch_reply.public_ip_list = nat_info
         .public_ips
         .iter()
@@ -166,7 +272,19 @@ pub fn punch_packet(
         punch_reply.ipv6_port = nat_info.ipv6_addr.port() as u32;
         punch_reply.ipv6 = nat_info.ipv6_addr.ip().octets().to_vec();
     }
+    if let Some(mapped_addr) = nat_info.mapped_addr {
+        punch_reply.mapped_ip = u32::from_be_bytes(mapped_addr.ip().octets());
+        punch_reply.mapped_port = mapped_addr.port() as u32;
+    }
     punch_reply.nat_type = protobuf::EnumOrUnknown::new(PunchNatType::from(nat_info.nat_type));
+    punch_reply.mapping = protobuf::EnumOrUnknown::new(nat_info.behavior.mapping.into());
+    punch_reply.filtering = protobuf::EnumOrUnknown::new(nat_info.behavior.filtering.into());
+    punch_reply.hairpin = nat_info.behavior.hairpin;
+    punch_reply.port_allocation =
+        protobuf::EnumOrUnknown::new(nat_info.behavior.port_allocation.into());
+    punch_reply.port_step = nat_info.behavior.port_step as i32;
+    punch_reply.tcp_port = nat_info.tcp_port as u32;
+    punch_reply.quic_port = nat_info.quic_port as u32;
     let bytes = punch_reply.write_to_bytes()?;
     let mut net_packet = NetPacket::new_encrypt(vec![0u8; 12 + bytes.len() + ENCRYPTION_RESERVED])?;
     net_packet.set_version(Version::V1);
@@ -1,37 +1,51 @@
-use std::net::{Ipv4Addr, Ipv6Addr, SocketAddrV4, SocketAddrV6};
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
 use std::sync::Arc;
+use std::time::Duration;
 
 use crossbeam_utils::atomic::AtomicCell;
 use dashmap::DashMap;
 use parking_lot::Mutex;
 use protobuf::Message;
-use tokio::sync::mpsc::Sender;
+use tokio::time::Instant;
 
 use packet::icmp::icmp::HeaderOther;
 use packet::icmp::{icmp, Kind};
 use packet::ip::ipv4;
 use packet::ip::ipv4::packet::IpV4Packet;
+use packet::ip::ipv6::packet::IpV6Packet;
 
 use crate::channel::channel::Context;
-use crate::channel::punch::{NatInfo, NatType};
+use crate::channel::punch::{NatBehavior, NatInfo, NatType};
 use crate::channel::{Route, RouteKey};
 use crate::cipher::{Cipher, RsaCipher};
+use crate::core::event::peer_change_events;
 use crate::error::Error;
 use crate::external_route::AllowExternalRoute;
+use crate::handle::connect_handler::ConnectState;
+use crate::handle::fragment::{self, FragmentBuffer};
 use crate::handle::handshake_handler::secret_handshake_req;
+use crate::handle::key_exchange_handler;
+use crate::handle::punch_handler::{PunchSender, BIRTHDAY_DELAY};
 use crate::handle::registration_handler::Register;
-use crate::handle::{ConnectStatus, CurrentDeviceInfo, PeerDeviceInfo, PeerDeviceStatus};
+use crate::handle::route_advertise_handler;
+use crate::handle::{
+    ipv6_from_bytes, ConnectStatus, CurrentDeviceInfo, PeerDeviceInfo, PeerDeviceStatus,
+};
 use crate::igmp_server::IgmpServer;
 use crate::ip_proxy::IpProxyMap;
 use crate::nat;
 use crate::nat::NatTest;
-use crate::proto::message::{DeviceList, PunchInfo, PunchNatType, RegistrationResponse};
+use crate::proto::message::{
+    DeviceList, HandshakeResponse, PunchInfo, PunchNatType, RegistrationResponse,
+};
 use crate::protocol::body::ENCRYPTION_RESERVED;
 use crate::protocol::control_packet::ControlPacket;
 use crate::protocol::error_packet::InErrorPacket;
+use crate::protocol::ip_turn_packet::FragmentPacket;
 use crate::protocol::{
     control_packet, ip_turn_packet, other_turn_packet, service_packet, NetPacket, Protocol,
-    Version, MAX_TTL,
+    Version, HEAD_LEN, MAX_TTL,
 };
 use crate::tun_tap_device::DeviceWriter;
 
@@ -43,20 +57,23 @@ pub struct ChannelDataHandler {
     nat_test: NatTest,
     igmp_server: Option<IgmpServer>,
     device_writer: DeviceWriter,
-    connect_status: Arc<AtomicCell<ConnectStatus>>,
+    connect_state: ConnectState,
     peer_nat_info_map: Arc<DashMap<Ipv4Addr, NatInfo>>,
     ip_proxy_map: Option<IpProxyMap>,
     out_external_route: AllowExternalRoute,
-    cone_sender: Sender<(Ipv4Addr, NatInfo)>,
-    symmetric_sender: Sender<(Ipv4Addr, NatInfo)>,
+    punch_sender: PunchSender,
     client_cipher: Cipher,
     server_cipher: Cipher,
-    rsa_cipher: Option<RsaCipher>,
+    /// 服务端地址对应的公钥
+    server_rsa: Arc<Mutex<HashMap<SocketAddr, RsaCipher>>>,
     relay: bool,
     token: String,
+    /// 分片重组缓存
+    fragment_buffer: FragmentBuffer,
 }
 
 impl ChannelDataHandler {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         current_device: Arc<AtomicCell<CurrentDeviceInfo>>,
         device_list: Arc<Mutex<(u16, Vec<PeerDeviceInfo>)>>,
@@ -64,15 +81,14 @@ impl ChannelDataHandler {
         nat_test: NatTest,
         igmp_server: Option<IgmpServer>,
         device_writer: DeviceWriter,
-        connect_status: Arc<AtomicCell<ConnectStatus>>,
+        connect_state: ConnectState,
         peer_nat_info_map: Arc<DashMap<Ipv4Addr, NatInfo>>,
         ip_proxy_map: Option<IpProxyMap>,
         out_external_route: AllowExternalRoute,
-        cone_sender: Sender<(Ipv4Addr, NatInfo)>,
-        symmetric_sender: Sender<(Ipv4Addr, NatInfo)>,
+        punch_sender: PunchSender,
         client_cipher: Cipher,
         server_cipher: Cipher,
-        rsa_cipher: Option<RsaCipher>,
+        server_rsa: Arc<Mutex<HashMap<SocketAddr, RsaCipher>>>,
         relay: bool,
         token: String,
     ) -> Self {
@@ -83,17 +99,30 @@ impl ChannelDataHandler {
             nat_test,
             igmp_server,
             device_writer,
-            connect_status,
+            connect_state,
             peer_nat_info_map,
             ip_proxy_map,
             out_external_route,
-            cone_sender,
-            symmetric_sender,
+            punch_sender,
             client_cipher,
             server_cipher,
-            rsa_cipher,
+            server_rsa,
             relay,
             token,
+            fragment_buffer: FragmentBuffer::new(),
+        }
+    }
+    /// 网卡开启offload时合并写入的tcp分段，处理完一批数据后需要调用flush
+    #[cfg(target_os = "linux")]
+    pub fn batch(&self) -> Self {
+        let mut handler = self.clone();
+        handler.device_writer = self.device_writer.batch();
+        handler
+    }
+    #[cfg(target_os = "linux")]
+    pub fn flush(&self) {
+        if let Err(e) = self.device_writer.flush() {
+            log::warn!("写入网卡失败:{:?}", e);
         }
     }
 }
@@ -122,7 +151,12 @@ impl ChannelDataHandler {
         context: &Context,
     ) -> crate::Result<()> {
         let mut net_packet = NetPacket::new(&mut buf[14..])?;
+        //解密前的长度，即实际收到的数据包长度
+        let recv_len = net_packet.data_len();
         if net_packet.ttl() == 0 || net_packet.source_ttl() < net_packet.ttl() {
+            if !net_packet.is_gateway() {
+                context.record_drop(&net_packet.source());
+            }
             return Ok(());
         }
         let source = net_packet.source();
@@ -138,6 +172,7 @@ impl ChannelDataHandler {
         {
             //校验指纹，不需要解密
             self.client_cipher.check_finger(&net_packet)?;
+            context.record_rx(recv_len, route_key, None);
             net_packet.set_ttl(net_packet.ttl() - 1);
             let ttl = net_packet.ttl();
             if ttl > 0 {
@@ -147,6 +182,8 @@ impl ChannelDataHandler {
                         context
                             .send_by_key(net_packet.buffer(), &route.route_key())
                             .await?;
+                    } else {
+                        context.record_drop(&source);
                     }
                 } else if (ttl > 1 || destination == current_device.virtual_gateway())
                     && source != current_device.virtual_gateway()
@@ -164,7 +201,14 @@ impl ChannelDataHandler {
                 && net_packet.transport_protocol()
                     == crate::protocol::error_packet::Protocol::NoKey.into()
             {
-                if let Some(rsa_cipher) = &self.rsa_cipher {
+                let rsa_cipher = self
+                    .server_rsa
+                    .lock()
+                    .get(&current_device.connect_server)
+                    .cloned();
+                if let Some(rsa_cipher) = &rsa_cipher {
+                    //服务端丢失了密钥，重新同步
+                    self.connect_state.set(ConnectStatus::Handshaking);
                     secret_handshake_req(
                         context,
                         current_device.connect_server,
@@ -174,159 +218,251 @@ impl ChannelDataHandler {
                     )
                     .await?;
                 }
+            } else if net_packet.protocol() == Protocol::Service
+                && net_packet.transport_protocol()
+                    == service_packet::Protocol::HandshakeResponse.into()
+            {
+                //握手响应不加密
+                self.handshake_response(context, current_device, net_packet.payload())
+                    .await?;
+                context.record_rx(recv_len, route_key, None);
             } else {
                 //服务端解密
                 self.server_cipher.decrypt_ipv4(&mut net_packet)?;
+                context.record_rx(recv_len, route_key, None);
+                self.connect_state.server_alive();
                 let data_len = net_packet.data_len();
                 self.server_packet_handle(context, current_device, buf, data_len, route_key)
                     .await?;
             }
             return Ok(());
         }
-        self.client_cipher.decrypt_ipv4(&mut net_packet)?;
+        context.decrypt_by_id(
+            &source,
+            &current_device.virtual_ip(),
+            &self.client_cipher,
+            &mut net_packet,
+        )?;
+        context.record_rx(recv_len, route_key, Some(&source));
         match net_packet.protocol() {
             Protocol::IpTurn => {
-                match ip_turn_packet::Protocol::from(net_packet.transport_protocol()) {
-                    ip_turn_packet::Protocol::Ipv4 => {
-                        let mut ipv4 = IpV4Packet::new(net_packet.payload_mut())?;
-                        match ipv4.protocol() {
-                            ipv4::protocol::Protocol::Igmp => {
-                                if let Some(igmp_server) = &self.igmp_server {
-                                    igmp_server.handle(ipv4.payload(), source)?;
-                                }
-                                return Ok(());
-                            }
-                            ipv4::protocol::Protocol::Icmp => {
-                                if ipv4.destination_ip() == destination {
-                                    let mut icmp_packet =
-                                        icmp::IcmpPacket::new(ipv4.payload_mut())?;
-                                    if icmp_packet.kind() == Kind::EchoRequest {
-                                        //开启ping
-                                        icmp_packet.set_kind(Kind::EchoReply);
-                                        icmp_packet.update_checksum();
-                                        ipv4.set_source_ip(destination);
-                                        ipv4.set_destination_ip(source);
-                                        ipv4.update_checksum();
-                                        net_packet.set_source(destination);
-                                        net_packet.set_destination(source);
-                                        //不管加不加密，和接收到的数据长度都一致
-                                        self.client_cipher.encrypt_ipv4(&mut net_packet)?;
-                                        context.send_by_key(net_packet.buffer(), route_key).await?;
-                                        return Ok(());
+                let data_len = net_packet.data_len();
+                if net_packet.transport_protocol() == ip_turn_packet::Protocol::Fragment.into() {
+                    let fragment_packet = FragmentPacket::new(net_packet.payload())?;
+                    if let Some((protocol, data)) =
+                        self.fragment_buffer.push(source, fragment_packet)
+                    {
+                        //重组为完整的数据包，头部和分片一致，尾部预留加密的长度
+                        let len = HEAD_LEN + data.len();
+                        let mut buf = vec![0u8; 14 + len + ENCRYPTION_RESERVED];
+                        buf[14..14 + HEAD_LEN].copy_from_slice(&net_packet.buffer()[..HEAD_LEN]);
+                        buf[14 + HEAD_LEN..14 + len].copy_from_slice(&data);
+                        NetPacket::new0(len, &mut buf[14..])?.set_transport_protocol(protocol);
+                        self.ip_turn(context, current_device, &mut buf, len, route_key)
+                            .await?;
+                    }
+                    return Ok(());
+                }
+                self.ip_turn(context, current_device, buf, data_len, route_key)
+                    .await?;
+            }
+            Protocol::Service => {}
+            Protocol::Error => {}
+            Protocol::Control => {
+                self.control(
+                    context,
+                    current_device,
+                    source,
+                    net_packet,
+                    recv_len,
+                    route_key,
+                )
+                .await?;
+            }
+            Protocol::OtherTurn => {
+                self.other_turn(context, current_device, source, net_packet, route_key)
+                    .await?;
+            }
+            Protocol::UnKnow(e) => {
+                log::info!("不支持的协议:{}", e);
+            }
+        }
+        Ok(())
+    }
+
+    /// 客户端之间转发的ip数据，buf包含14字节的头部，data_len为解密后的数据包长度
+    async fn ip_turn(
+        &self,
+        context: &Context,
+        current_device: CurrentDeviceInfo,
+        buf: &mut [u8],
+        data_len: usize,
+        route_key: &RouteKey,
+    ) -> crate::Result<()> {
+        let mut net_packet = NetPacket::new0(data_len, &mut buf[14..])?;
+        let source = net_packet.source();
+        let destination = net_packet.destination();
+        let not_broadcast = !destination.is_broadcast()
+            && !destination.is_multicast()
+            && destination != current_device.broadcast_address;
+        match ip_turn_packet::Protocol::from(net_packet.transport_protocol()) {
+            ip_turn_packet::Protocol::Ipv4 => {
+                let mut ipv4 = IpV4Packet::new(net_packet.payload_mut())?;
+                match ipv4.protocol() {
+                    ipv4::protocol::Protocol::Igmp => {
+                        if let Some(igmp_server) = &self.igmp_server {
+                            igmp_server.handle(ipv4.payload(), source)?;
+                        }
+                        return Ok(());
+                    }
+                    ipv4::protocol::Protocol::Icmp => {
+                        if ipv4.destination_ip() == destination {
+                            let mut icmp_packet = icmp::IcmpPacket::new(ipv4.payload_mut())?;
+                            if icmp_packet.kind() == Kind::EchoRequest {
+                                //开启ping
+                                icmp_packet.set_kind(Kind::EchoReply);
+                                icmp_packet.update_checksum();
+                                ipv4.set_source_ip(destination);
+                                ipv4.set_destination_ip(source);
+                                ipv4.update_checksum();
+                                net_packet.set_source(destination);
+                                net_packet.set_destination(source);
+                                if let Some(mtu) = fragment::fragment_mtu(
+                                    context,
+                                    &source,
+                                    net_packet.data_len(),
+                                    &self.client_cipher,
+                                ) {
+                                    //请求是分片传输的，回复也需要分片
+                                    let list = fragment::fragment(
+                                        context,
+                                        &self.client_cipher,
+                                        &net_packet,
+                                        mtu,
+                                    )?;
+                                    for packet in list {
+                                        context.send_by_key(packet.buffer(), route_key).await?;
                                     }
+                                    return Ok(());
                                 }
+                                //不管加不加密，和接收到的数据长度都一致
+                                context.encrypt_by_id(
+                                    &source,
+                                    &self.client_cipher,
+                                    &mut net_packet,
+                                )?;
+                                context.send_by_key(net_packet.buffer(), route_key).await?;
+                                return Ok(());
                             }
-                            _ => {}
                         }
-                        if not_broadcast && ipv4.destination_ip() != destination {
-                            if let Some(ip_proxy_map) = &self.ip_proxy_map {
-                                if self.out_external_route.allow(&ipv4.destination_ip()) {
-                                    match ipv4.protocol() {
-                                        ipv4::protocol::Protocol::Tcp => {
-                                            let dest_ip = ipv4.destination_ip();
-                                            //转发到代理目标地址
-                                            let mut tcp_packet = packet::tcp::tcp::TcpPacket::new(
-                                                source,
-                                                destination,
-                                                ipv4.payload_mut(),
-                                            )?;
-                                            let source_port = tcp_packet.source_port();
-                                            let dest_port = tcp_packet.destination_port();
-                                            tcp_packet
-                                                .set_destination_port(ip_proxy_map.tcp_proxy_port);
-                                            tcp_packet.update_checksum();
-                                            ipv4.set_destination_ip(destination);
-                                            ipv4.update_checksum();
-                                            let key = SocketAddrV4::new(source, source_port);
-                                            //https://github.com/crossbeam-rs/crossbeam/issues/1023
-                                            ip_proxy_map
-                                                .tcp_proxy_map
-                                                .insert(key, SocketAddrV4::new(dest_ip, dest_port));
-                                        }
-                                        ipv4::protocol::Protocol::Udp => {
-                                            let dest_ip = ipv4.destination_ip();
-                                            //转发到代理目标地址
-                                            let mut udp_packet = packet::udp::udp::UdpPacket::new(
-                                                source,
-                                                destination,
-                                                ipv4.payload_mut(),
-                                            )?;
-                                            let source_port = udp_packet.source_port();
-                                            let dest_port = udp_packet.destination_port();
-                                            udp_packet
-                                                .set_destination_port(ip_proxy_map.udp_proxy_port);
-                                            udp_packet.update_checksum();
-                                            ipv4.set_destination_ip(destination);
-                                            ipv4.update_checksum();
-                                            let key = SocketAddrV4::new(source, source_port);
+                    }
+                    _ => {}
+                }
+                if not_broadcast && ipv4.destination_ip() != destination {
+                    if let Some(ip_proxy_map) = &self.ip_proxy_map {
+                        if self.out_external_route.allow(&ipv4.destination_ip()) {
+                            match ipv4.protocol() {
+                                ipv4::protocol::Protocol::Tcp => {
+                                    let dest_ip = ipv4.destination_ip();
+                                    //转发到代理目标地址
+                                    let mut tcp_packet = packet::tcp::tcp::TcpPacket::new(
+                                        source,
+                                        destination,
+                                        ipv4.payload_mut(),
+                                    )?;
+                                    let source_port = tcp_packet.source_port();
+                                    let dest_port = tcp_packet.destination_port();
+                                    tcp_packet.set_destination_port(ip_proxy_map.tcp_proxy_port);
+                                    tcp_packet.update_checksum();
+                                    ipv4.set_destination_ip(destination);
+                                    ipv4.update_checksum();
+                                    let key = SocketAddrV4::new(source, source_port);
+                                    //https://github.com/crossbeam-rs/crossbeam/issues/1023
+                                    ip_proxy_map
+                                        .tcp_proxy_map
+                                        .insert(key, SocketAddrV4::new(dest_ip, dest_port));
+                                }
+                                ipv4::protocol::Protocol::Udp => {
+                                    let dest_ip = ipv4.destination_ip();
+                                    //转发到代理目标地址
+                                    let mut udp_packet = packet::udp::udp::UdpPacket::new(
+                                        source,
+                                        destination,
+                                        ipv4.payload_mut(),
+                                    )?;
+                                    let source_port = udp_packet.source_port();
+                                    let dest_port = udp_packet.destination_port();
+                                    udp_packet.set_destination_port(ip_proxy_map.udp_proxy_port);
+                                    udp_packet.update_checksum();
+                                    ipv4.set_destination_ip(destination);
+                                    ipv4.update_checksum();
+                                    let key = SocketAddrV4::new(source, source_port);
+                                    ip_proxy_map
+                                        .udp_proxy_map
+                                        .insert(key, SocketAddrV4::new(dest_ip, dest_port));
+                                }
+                                ipv4::protocol::Protocol::Icmp => {
+                                    let dest_ip = ipv4.destination_ip();
+                                    //转发到代理目标地址
+                                    let icmp_packet = icmp::IcmpPacket::new(ipv4.payload())?;
+                                    match icmp_packet.header_other() {
+                                        HeaderOther::Identifier(id, seq) => {
                                             ip_proxy_map
-                                                .udp_proxy_map
-                                                .insert(key, SocketAddrV4::new(dest_ip, dest_port));
-                                        }
-                                        ipv4::protocol::Protocol::Icmp => {
-                                            let dest_ip = ipv4.destination_ip();
-                                            //转发到代理目标地址
-                                            let icmp_packet =
-                                                icmp::IcmpPacket::new(ipv4.payload())?;
-                                            match icmp_packet.header_other() {
-                                                HeaderOther::Identifier(id, seq) => {
-                                                    ip_proxy_map
-                                                        .icmp_proxy_map
-                                                        .insert((dest_ip, id, seq), source);
-                                                    ip_proxy_map
-                                                        .send_icmp(ipv4.payload(), &dest_ip)?;
-                                                }
-                                                _ => {
-                                                    log::warn!(
-                                                        "不支持的ip代理Icmp协议:{}",
-                                                        destination
-                                                    );
-                                                    return Err(Error::Warn(
-                                                        "不支持的ip代理Icmp协议".to_string(),
-                                                    ));
-                                                }
-                                            }
+                                                .icmp_proxy_map
+                                                .insert((dest_ip, id, seq), source);
+                                            ip_proxy_map.send_icmp(ipv4.payload(), &dest_ip)?;
                                         }
                                         _ => {
-                                            log::warn!("不支持的ip代理ipv4协议:{}", destination);
+                                            log::warn!("不支持的ip代理Icmp协议:{}", destination);
                                             return Err(Error::Warn(
-                                                "不支持的ip代理ipv4协议".to_string(),
+                                                "不支持的ip代理Icmp协议".to_string(),
                                             ));
                                         }
                                     }
-                                } else {
-                                    log::warn!("没有ip代理规则:{}", destination);
-                                    return Err(Error::Warn("没有ip代理规则".to_string()));
                                 }
-                            } else {
-                                log::warn!("不支持ip代理:{}", destination);
-                                return Err(Error::Warn("不支持ip代理".to_string()));
+                                _ => {
+                                    log::warn!("不支持的ip代理ipv4协议:{}", destination);
+                                    return Err(Error::Warn("不支持的ip代理ipv4协议".to_string()));
+                                }
                             }
+                        } else {
+                            log::warn!("没有ip代理规则:{}", destination);
+                            return Err(Error::Warn("没有ip代理规则".to_string()));
                         }
-
-                        //传输协议12字节
-                        self.device_writer.write_ipv4(&mut buf[12..])?;
-                        return Ok(());
+                    } else {
+                        log::warn!("不支持ip代理:{}", destination);
+                        return Err(Error::Warn("不支持ip代理".to_string()));
                     }
-                    ip_turn_packet::Protocol::Ipv4Broadcast => {
-                        //客户端不帮忙转发广播包，所以不会出现这种类型的数据
-                    }
-                    ip_turn_packet::Protocol::Unknown(_) => {}
                 }
+
+                //传输协议12字节
+                self.device_writer.write_ipv4(&mut buf[12..14 + data_len])?;
+                return Ok(());
             }
-            Protocol::Service => {}
-            Protocol::Error => {}
-            Protocol::Control => {
-                self.control(context, current_device, source, net_packet, route_key)
-                    .await?;
+            ip_turn_packet::Protocol::Ipv6 => {
+                let ipv6 = IpV6Packet::new(net_packet.payload())?;
+                if context.ipv6_to_id(&ipv6.source_ip()) != Some(source) {
+                    //来源ipv6必须属于发送的节点，防止冒用其他节点的虚拟ipv6
+                    log::warn!("ipv6来源不匹配:{},{}", ipv6.source_ip(), source);
+                    return Err(Error::Warn("ipv6来源不匹配".to_string()));
+                }
+                let dest_ipv6 = ipv6.destination_ip();
+                if !dest_ipv6.is_multicast() && current_device.virtual_ipv6() != Some(dest_ipv6) {
+                    //ipv6不支持ip代理
+                    log::warn!("不支持ipv6代理:{}", dest_ipv6);
+                    return Err(Error::Warn("不支持ipv6代理".to_string()));
+                }
+                //传输协议12字节
+                self.device_writer.write_ipv6(&mut buf[12..14 + data_len])?;
+                return Ok(());
             }
-            Protocol::OtherTurn => {
-                self.other_turn(context, current_device, source, net_packet, route_key)
-                    .await?;
+            ip_turn_packet::Protocol::Ipv4Broadcast => {
+                //客户端不帮忙转发广播包，所以不会出现这种类型的数据
             }
-            Protocol::UnKnow(e) => {
-                log::info!("不支持的协议:{}", e);
+            ip_turn_packet::Protocol::Fragment => {
+                //分片在调用前已经重组
             }
+            ip_turn_packet::Protocol::Unknown(_) => {}
         }
         Ok(())
     }
@@ -373,6 +509,7 @@ impl ChannelDataHandler {
         current_device: CurrentDeviceInfo,
         source: Ipv4Addr,
         mut net_packet: NetPacket<&mut [u8]>,
+        recv_len: usize,
         route_key: &RouteKey,
     ) -> crate::Result<()> {
         let metric = net_packet.source_ttl() - net_packet.ttl() + 1;
@@ -449,6 +586,54 @@ impl ChannelDataHandler {
                         .update_addr(addr_packet.ipv4(), addr_packet.port())
                 }
             }
+            ControlPacket::KeyExchangeRequest(key_exchange_packet) => {
+                key_exchange_handler::handle_request(
+                    context,
+                    &self.client_cipher,
+                    &current_device,
+                    source,
+                    key_exchange_packet,
+                    route_key,
+                )
+                .await?;
+            }
+            ControlPacket::KeyExchangeResponse(key_exchange_packet) => {
+                key_exchange_handler::handle_response(
+                    context,
+                    &self.client_cipher,
+                    &current_device,
+                    source,
+                    key_exchange_packet,
+                )?;
+            }
+            ControlPacket::RouteAdvertise(route_advertise_packet) => {
+                if self.relay {
+                    return Ok(());
+                }
+                route_advertise_handler::handle(
+                    context,
+                    &current_device,
+                    source,
+                    route_key,
+                    route_advertise_packet,
+                );
+            }
+            ControlPacket::MtuProbe => {
+                //回应收到的长度，对端以此作为路径mtu
+                let mut packet = NetPacket::new_encrypt([0; 12 + 2 + ENCRYPTION_RESERVED])?;
+                packet.set_version(Version::V1);
+                packet.set_protocol(Protocol::Control);
+                packet.set_transport_protocol(control_packet::Protocol::MtuProbeResponse.into());
+                packet.first_set_ttl(1);
+                packet.set_source(current_device.virtual_ip());
+                packet.set_destination(source);
+                control_packet::MtuPacket::new(packet.payload_mut())?.set_mtu(recv_len as u16);
+                self.client_cipher.encrypt_ipv4(&mut packet)?;
+                context.send_by_key(packet.buffer(), route_key).await?;
+            }
+            ControlPacket::MtuProbeResponse(mtu_packet) => {
+                context.update_mtu_probe(source, *route_key, mtu_packet.mtu());
+            }
         }
         Ok(())
     }
@@ -489,8 +674,33 @@ impl ChannelDataHandler {
                     local_ipv4_addr,
                     ipv6_addr,
                     punch_info.nat_type.enum_value_or_default().into(),
-                );
+                )
+                .with_mapped_addr(if punch_info.mapped_port != 0 {
+                    Some(SocketAddrV4::new(
+                        Ipv4Addr::from(punch_info.mapped_ip.to_be_bytes()),
+                        punch_info.mapped_port as u16,
+                    ))
+                } else {
+                    None
+                })
+                .with_behavior(NatBehavior {
+                    mapping: punch_info.mapping.enum_value_or_default().into(),
+                    filtering: punch_info.filtering.enum_value_or_default().into(),
+                    hairpin: punch_info.hairpin,
+                    port_allocation: punch_info.port_allocation.enum_value_or_default().into(),
+                    port_step: punch_info.port_step.clamp(i16::MIN as i32, i16::MAX as i32) as i16,
+                })
+                .with_tcp_port(punch_info.tcp_port as u16)
+                .with_quic_port(punch_info.quic_port as u16);
                 self.peer_nat_info_map.insert(source, peer_nat_info.clone());
+                // 双方都是对称网络时，约定同时开始生日攻击打洞
+                let birthday = self.punch_sender.is_birthday()
+                    && punch_info.birthday
+                    && peer_nat_info.nat_type == NatType::Symmetric
+                    && self.nat_test.nat_info().nat_type == NatType::Symmetric;
+                let server_rt = context
+                    .route_one(&current_device.virtual_gateway)
+                    .map_or(0, |route| route.rt.clamp(0, u32::MAX as i64) as u32);
                 if !punch_info.reply {
                     let mut punch_reply = PunchInfo::new();
                     punch_reply.reply = true;
@@ -504,6 +714,14 @@ impl ChannelDataHandler {
                     punch_reply.public_port_range = nat_info.public_port_range as u32;
                     punch_reply.nat_type =
                         protobuf::EnumOrUnknown::new(PunchNatType::from(nat_info.nat_type));
+                    punch_reply.mapping =
+                        protobuf::EnumOrUnknown::new(nat_info.behavior.mapping.into());
+                    punch_reply.filtering =
+                        protobuf::EnumOrUnknown::new(nat_info.behavior.filtering.into());
+                    punch_reply.hairpin = nat_info.behavior.hairpin;
+                    punch_reply.port_allocation =
+                        protobuf::EnumOrUnknown::new(nat_info.behavior.port_allocation.into());
+                    punch_reply.port_step = nat_info.behavior.port_step as i32;
                     punch_reply.local_ip =
                         u32::from_be_bytes(nat_info.local_ipv4_addr.ip().octets());
                     punch_reply.local_port = nat_info.local_ipv4_addr.port() as u32;
@@ -511,6 +729,22 @@ impl ChannelDataHandler {
                         punch_reply.ipv6 = nat_info.ipv6_addr.ip().octets().to_vec();
                         punch_reply.ipv6_port = nat_info.ipv6_addr.port() as u32;
                     }
+                    if let Some(mapped_addr) = nat_info.mapped_addr {
+                        punch_reply.mapped_ip = u32::from_be_bytes(mapped_addr.ip().octets());
+                        punch_reply.mapped_port = mapped_addr.port() as u32;
+                    }
+                    punch_reply.tcp_port = nat_info.tcp_port as u32;
+                    punch_reply.quic_port = nat_info.quic_port as u32;
+                    if birthday {
+                        punch_reply.birthday = true;
+                        punch_reply.birthday_delay = BIRTHDAY_DELAY.as_millis() as u32;
+                        punch_reply.server_rt = server_rt;
+                        self.punch_sender.birthday(
+                            source,
+                            &peer_nat_info,
+                            Instant::now() + BIRTHDAY_DELAY,
+                        );
+                    }
                     let bytes = punch_reply.write_to_bytes()?;
                     let mut punch_packet =
                         NetPacket::new_encrypt(vec![0u8; 12 + bytes.len() + ENCRYPTION_RESERVED])?;
@@ -533,14 +767,22 @@ impl ChannelDataHandler {
                     //     let _ = context.try_send_main_udp(packet.buffer(),
                     //                               SocketAddr::V4(SocketAddrV4::new(peer_nat_info.local_ip, peer_nat_info.local_port)));
                     // }
-                    if self.punch(source, peer_nat_info).await {
+                    if self.punch_sender.punch(source, peer_nat_info) {
                         self.client_cipher.encrypt_ipv4(&mut punch_packet)?;
                         context
                             .send_by_key(punch_packet.buffer(), route_key)
                             .await?;
                     }
                 } else {
-                    self.punch(source, peer_nat_info).await;
+                    if birthday && punch_info.birthday_delay > 0 {
+                        // 回复经过服务端转发，减去转发耗费的时间，尽量和对方同时开始
+                        let delay = (punch_info.birthday_delay as u64)
+                            .saturating_sub((punch_info.server_rt as u64 + server_rt as u64) / 2);
+                        let delay = Duration::from_millis(delay).min(BIRTHDAY_DELAY * 5);
+                        self.punch_sender
+                            .birthday(source, &peer_nat_info, Instant::now() + delay);
+                    }
+                    self.punch_sender.punch(source, peer_nat_info);
                 }
             }
             other_turn_packet::Protocol::Unknown(e) => {
@@ -549,15 +791,6 @@ impl ChannelDataHandler {
         }
         Ok(())
     }
-    async fn punch(&self, peer_ip: Ipv4Addr, peer_nat_info: NatInfo) -> bool {
-        match peer_nat_info.nat_type {
-            NatType::Symmetric => self
-                .symmetric_sender
-                .try_send((peer_ip, peer_nat_info))
-                .is_ok(),
-            NatType::Cone => self.cone_sender.try_send((peer_ip, peer_nat_info)).is_ok(),
-        }
-    }
 }
 
 /// 处理服务端数据
@@ -608,7 +841,9 @@ impl ChannelDataHandler {
                             _ => {}
                         }
                     }
+                    ip_turn_packet::Protocol::Ipv6 => {}
                     ip_turn_packet::Protocol::Ipv4Broadcast => {}
+                    ip_turn_packet::Protocol::Fragment => {}
                     ip_turn_packet::Protocol::Unknown(_) => {}
                 }
             }
@@ -677,7 +912,7 @@ impl ChannelDataHandler {
                         let local_ipv4_addr = nat::local_ipv4_addr(local_port);
                         let local_port = context.main_local_ipv6_port().unwrap_or(0);
                         let ipv6_addr = nat::local_ipv6_addr(local_port);
-                       let nat_info =  nat_test
+                        let nat_info = nat_test
                             .re_test(
                                 Ipv4Addr::from(response.public_ip),
                                 response.public_port as u16,
@@ -690,7 +925,12 @@ impl ChannelDataHandler {
                 }
                 let new_ip = Ipv4Addr::from(response.virtual_ip);
                 let current_ip = current_device.virtual_ip();
-                if current_ip != new_ip {
+                let new_ipv6 = ipv6_from_bytes(&response.virtual_ipv6);
+                let new_ipv6_prefix = response.virtual_ipv6_prefix as u8;
+                if current_ip != new_ip
+                    || current_device.virtual_ipv6() != new_ipv6
+                    || current_device.virtual_ipv6_prefix != new_ipv6_prefix
+                {
                     // ip发生变化
                     log::info!("ip发生变化,old_ip:{:?},new_ip:{:?}", current_ip, new_ip);
                     #[cfg(any(target_os = "linux", target_os = "macos", target_os = "windows"))]
@@ -701,19 +941,29 @@ impl ChannelDataHandler {
                     let virtual_gateway = Ipv4Addr::from(response.virtual_gateway);
                     let virtual_netmask = Ipv4Addr::from(response.virtual_netmask);
                     #[cfg(any(target_os = "linux", target_os = "macos", target_os = "windows"))]
-                    self.device_writer.change_ip(
-                        virtual_ip,
-                        virtual_netmask,
-                        virtual_gateway,
-                        old_netmask,
-                        old_gateway,
-                    )?;
+                    if current_ip != new_ip {
+                        self.device_writer.change_ip(
+                            virtual_ip,
+                            virtual_netmask,
+                            virtual_gateway,
+                            old_netmask,
+                            old_gateway,
+                        )?;
+                    }
+                    #[cfg(any(target_os = "linux", target_os = "macos", target_os = "windows"))]
+                    if let Some(ipv6) = new_ipv6 {
+                        if current_device.virtual_ipv6() != new_ipv6 {
+                            log::info!("ipv6发生变化,new_ipv6:{}/{}", ipv6, new_ipv6_prefix);
+                            self.device_writer.set_ipv6(ipv6, new_ipv6_prefix)?;
+                        }
+                    }
                     let new_current_device = CurrentDeviceInfo::new(
                         virtual_ip,
                         virtual_gateway,
                         virtual_netmask,
                         current_device.connect_server,
-                    );
+                    )
+                    .with_ipv6(new_ipv6, new_ipv6_prefix);
                     if let Err(e) = self
                         .current_device
                         .compare_exchange(current_device, new_current_device)
@@ -721,7 +971,17 @@ impl ChannelDataHandler {
                         log::warn!("替换失败:{:?}", e);
                     }
                 }
-                self.connect_status.store(ConnectStatus::Connected);
+                self.connect_state.registered();
+            }
+            service_packet::Protocol::SecretHandshakeResponse => {
+                //密钥同步完成，重新注册
+                self.register
+                    .fast_register(
+                        current_device.connect_server,
+                        current_device.virtual_ip,
+                        false,
+                    )
+                    .await?;
             }
             service_packet::Protocol::PollDeviceList => {}
             service_packet::Protocol::PushDeviceList => {
@@ -736,8 +996,12 @@ impl ChannelDataHandler {
                             info.device_status as u8,
                             info.client_secret,
                         )
+                        .with_ipv6(ipv6_from_bytes(&info.virtual_ipv6))
                     })
                     .collect();
+                context.update_ipv6_table(&ip_list);
+                context.update_session_table(&ip_list);
+                context.update_stats_peers(&ip_list);
                 let route = Route::from(*route_key, 2, 199);
                 for x in &ip_list {
                     if x.status == PeerDeviceStatus::Online {
@@ -746,6 +1010,9 @@ impl ChannelDataHandler {
                 }
                 let mut dev = self.device_list.lock();
                 if dev.0 != device_list_t.epoch as u16 {
+                    for event in peer_change_events(&dev.1, &ip_list) {
+                        context.send_event(event);
+                    }
                     dev.0 = device_list_t.epoch as u16;
                     dev.1 = ip_list;
                 }
@@ -757,6 +1024,63 @@ impl ChannelDataHandler {
         }
         Ok(())
     }
+    async fn handshake_response(
+        &self,
+        context: &Context,
+        current_device: CurrentDeviceInfo,
+        payload: &[u8],
+    ) -> crate::Result<()> {
+        let response = HandshakeResponse::parse_from_bytes(payload)?;
+        let server_address = current_device.connect_server;
+        if !matches!(self.server_cipher, Cipher::None) {
+            if !response.secret {
+                log::error!("服务端不再支持加密");
+                return Ok(());
+            }
+            let known = self.server_rsa.lock().get(&server_address).cloned();
+            let rsa_cipher = match known {
+                Some(rsa_cipher) => {
+                    // 公钥变化时需要用户重新确认指纹，不自动信任
+                    if rsa_cipher.finger()? != response.key_finger {
+                        log::error!(
+                            "服务端{}公钥指纹变化,请确认后重新启动,新指纹:{}",
+                            server_address,
+                            response.key_finger
+                        );
+                        return Ok(());
+                    }
+                    rsa_cipher
+                }
+                None => {
+                    // 启动时没有探测到的服务端，第一次连接时记录公钥
+                    let rsa_cipher = RsaCipher::new(&response.public_key)?;
+                    let finger = rsa_cipher.finger()?;
+                    if finger != response.key_finger {
+                        log::error!("服务端{}公钥指纹错误", server_address);
+                        return Ok(());
+                    }
+                    log::warn!("首次连接服务端{},公钥指纹:{}", server_address, finger);
+                    self.server_rsa
+                        .lock()
+                        .insert(server_address, rsa_cipher.clone());
+                    rsa_cipher
+                }
+            };
+            secret_handshake_req(
+                context,
+                server_address,
+                &rsa_cipher,
+                &self.server_cipher,
+                self.token.clone(),
+            )
+            .await?;
+        } else {
+            self.register
+                .fast_register(server_address, current_device.virtual_ip, false)
+                .await?;
+        }
+        Ok(())
+    }
     async fn error(
         &self,
         _context: &Context,
@@ -777,9 +1101,13 @@ impl ChannelDataHandler {
                     dev.0 = 0;
                 }
 
-                self.connect_status.store(ConnectStatus::Connecting);
+                self.connect_state.set(ConnectStatus::Reconnecting);
                 self.register
-                    .fast_register(current_device.virtual_ip)
+                    .fast_register(
+                        current_device.connect_server,
+                        current_device.virtual_ip,
+                        false,
+                    )
                     .await?;
             }
             InErrorPacket::AddressExhausted => {
@@ -791,6 +1119,15 @@ impl ChannelDataHandler {
             }
             InErrorPacket::IpAlreadyExists => {
                 log::error!("IpAlreadyExists");
+                if !self.connect_state.status().online() {
+                    // 重连时原虚拟ip已被占用，允许重新分配
+                    self.register
+                        .register_allow_ip_change(
+                            current_device.connect_server,
+                            current_device.virtual_ip,
+                        )
+                        .await?;
+                }
             }
             InErrorPacket::InvalidIp => {
                 log::error!("InvalidIp");
@@ -1,13 +1,17 @@
 use crossbeam_utils::atomic::AtomicCell;
-use std::net::{Ipv4Addr, SocketAddr};
+use std::io;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
 use std::time::{Duration, Instant};
 
+use crate::channel::proxy::Socks5Udp;
+use crate::channel::quic_channel;
 use crate::channel::sender::ChannelSender;
+use crate::channel::tcp_channel::ServerStream;
 use crate::cipher::Cipher;
-use crate::handle::PeerDeviceInfo;
+use crate::handle::{ipv6_from_bytes, PeerDeviceInfo};
 use protobuf::Message;
 use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::{TcpStream, UdpSocket};
+use tokio::net::UdpSocket;
 
 use crate::proto::message::{RegistrationRequest, RegistrationResponse};
 use crate::protocol::body::ENCRYPTION_RESERVED;
@@ -33,12 +37,16 @@ pub struct RegResponse {
     pub device_info_list: Vec<PeerDeviceInfo>,
     pub public_ip: Ipv4Addr,
     pub public_port: u16,
+    pub virtual_ipv6: Option<Ipv6Addr>,
+    pub virtual_ipv6_prefix: u8,
 }
 
 ///向中继服务器注册，token标识一个虚拟网关，device_id防止多次注册时得到的ip不一致
 pub async fn registration(
     main_channel: &UdpSocket,
-    main_tcp_channel: Option<&mut TcpStream>,
+    main_tcp_channel: Option<&mut ServerStream>,
+    main_quic_channel: Option<&quinn::Connection>,
+    socks5_udp: Option<&Socks5Udp>,
     server_cipher: &Cipher,
     server_address: SocketAddr,
     token: String,
@@ -60,7 +68,24 @@ pub async fn registration(
     .unwrap();
     let buf = request_packet.buffer();
     let mut recv_buf = [0u8; 10240];
-    let recv_buf = if let Some(main_tcp_channel) = main_tcp_channel {
+    let recv_buf = if let Some(main_quic_channel) = main_quic_channel {
+        match quic_channel::send_recv(
+            main_quic_channel,
+            buf,
+            &mut recv_buf,
+            Duration::from_millis(300),
+        )
+        .await
+        {
+            Ok(len) => &mut recv_buf[..len],
+            Err(e) if e.kind() == io::ErrorKind::TimedOut => {
+                return Err(ReqEnum::Timeout);
+            }
+            Err(e) => {
+                return Err(ReqEnum::Other(format!("quic error:{}", e)));
+            }
+        }
+    } else if let Some(main_tcp_channel) = main_tcp_channel {
         let mut vec = vec![0; 4 + buf.len()];
         let len = buf.len();
         vec[2] = (len >> 8) as u8;
@@ -69,6 +94,9 @@ pub async fn registration(
         if let Err(e) = main_tcp_channel.write_all(&vec).await {
             return Err(ReqEnum::Other(format!("send error:{}", e)));
         }
+        if let Err(e) = main_tcp_channel.flush().await {
+            return Err(ReqEnum::Other(format!("send error:{}", e)));
+        }
         if let Err(e) = main_tcp_channel.read_exact(&mut recv_buf[..4]).await {
             return Err(ReqEnum::Other(format!("read error:{}", e)));
         }
@@ -81,15 +109,20 @@ pub async fn registration(
         }
         &mut recv_buf[4..len]
     } else {
-        if let Err(e) = main_channel.send_to(buf, server_address).await {
+        let rs = match socks5_udp {
+            Some(socks5_udp) => socks5_udp.send_to(main_channel, buf, server_address).await,
+            None => main_channel.send_to(buf, server_address).await,
+        };
+        if let Err(e) = rs {
             return Err(ReqEnum::Other(format!("send error:{}", e)));
         }
-        match tokio::time::timeout(
-            Duration::from_millis(300),
-            main_channel.recv_from(&mut recv_buf),
-        )
-        .await
-        {
+        let recv = async {
+            match socks5_udp {
+                Some(socks5_udp) => socks5_udp.recv_from(main_channel, &mut recv_buf).await,
+                None => main_channel.recv_from(&mut recv_buf).await,
+            }
+        };
+        match tokio::time::timeout(Duration::from_millis(300), recv).await {
             Ok(rs) => match rs {
                 Ok((len, addr)) => {
                     if server_address != addr {
@@ -131,6 +164,7 @@ pub async fn registration(
                                         info.device_status as u8,
                                         info.client_secret,
                                     )
+                                    .with_ipv6(ipv6_from_bytes(&info.virtual_ipv6))
                                 })
                                 .collect();
                             Ok(RegResponse {
@@ -141,6 +175,8 @@ pub async fn registration(
                                 device_info_list,
                                 public_ip: Ipv4Addr::from(response.public_ip),
                                 public_port: response.public_port as u16,
+                                virtual_ipv6: ipv6_from_bytes(&response.virtual_ipv6),
+                                virtual_ipv6_prefix: response.virtual_ipv6_prefix as u8,
                             })
                         }
                         Err(_) => Err(ReqEnum::ServerError("invalid data".to_string())),
@@ -207,7 +243,6 @@ fn registration_request_packet(
 pub struct Register {
     server_cipher: Cipher,
     sender: ChannelSender,
-    server_address: SocketAddr,
     token: String,
     device_id: String,
     name: String,
@@ -219,7 +254,6 @@ impl Register {
     pub fn new(
         server_cipher: Cipher,
         sender: ChannelSender,
-        server_address: SocketAddr,
         token: String,
         device_id: String,
         name: String,
@@ -228,7 +262,6 @@ impl Register {
         Self {
             server_cipher,
             sender,
-            server_address,
             token,
             device_id,
             name,
@@ -236,7 +269,14 @@ impl Register {
             client_secret,
         }
     }
-    pub async fn fast_register(&self, ip: Ipv4Addr) -> crate::Result<()> {
+    /// 服务端地址可能在重连时变化，由调用方传入当前地址，
+    /// 切换服务端时allow_ip_change=false，保持原来的虚拟ip
+    pub async fn fast_register(
+        &self,
+        server_address: SocketAddr,
+        ip: Ipv4Addr,
+        allow_ip_change: bool,
+    ) -> crate::Result<()> {
         let last = self.time.load();
         if last.elapsed() < Duration::from_secs(2)
             || self.time.compare_exchange(last, Instant::now()).is_err()
@@ -244,7 +284,30 @@ impl Register {
             //短时间不重复注册
             return Ok(());
         }
-        log::info!("重新连接");
+        log::info!(
+            "重新连接 server:{},allow_ip_change:{}",
+            server_address,
+            allow_ip_change
+        );
+        self.send_register(server_address, ip, allow_ip_change)
+            .await
+    }
+    /// 原虚拟ip已被占用，允许服务端重新分配
+    pub async fn register_allow_ip_change(
+        &self,
+        server_address: SocketAddr,
+        ip: Ipv4Addr,
+    ) -> crate::Result<()> {
+        self.time.store(Instant::now());
+        log::info!("虚拟ip {} 已被占用,重新分配", ip);
+        self.send_register(server_address, ip, true).await
+    }
+    async fn send_register(
+        &self,
+        server_address: SocketAddr,
+        ip: Ipv4Addr,
+        allow_ip_change: bool,
+    ) -> crate::Result<()> {
         let request_packet = registration_request_packet(
             &self.server_cipher,
             self.token.clone(),
@@ -252,11 +315,11 @@ impl Register {
             self.name.clone(),
             ip,
             false,
-            true,
+            allow_ip_change,
             self.client_secret,
         )?;
         let buf = request_packet.buffer();
-        self.sender.send_main(buf, self.server_address).await?;
+        self.sender.send_main(buf, server_address).await?;
         Ok(())
     }
 }
@@ -0,0 +1,164 @@
+use std::io;
+use std::net::Ipv4Addr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crossbeam_utils::atomic::AtomicCell;
+
+use crate::channel::channel::Context;
+use crate::channel::sender::ChannelSender;
+use crate::channel::{Route, RouteKey};
+use crate::cipher::Cipher;
+use crate::core::status::VntWorker;
+use crate::handle::CurrentDeviceInfo;
+use crate::protocol::body::ENCRYPTION_RESERVED;
+use crate::protocol::control_packet::{RouteAdvertisePacket, ROUTE_ADVERTISE_ITEM_LEN};
+use crate::protocol::{control_packet, NetPacket, Protocol, Version};
+
+/// 只通告跳数小于这个值的路由，收到后加一跳，最多形成经过两个客户端的中继
+const MAX_ADVERTISE_METRIC: u8 = 3;
+/// 单个通告包最多携带的路由数
+const MAX_ADVERTISE_ITEM: usize = 128;
+/// 路由通告间隔，借道传输的路由超过6秒不通信就不再使用
+const ADVERTISE_INTERVAL: Duration = Duration::from_secs(3);
+/// 超过两个通告间隔没有再次通告，说明对方已经无法到达，移除这条中继路由
+const ADVERTISE_EXPIRE: Duration = Duration::from_secs(ADVERTISE_INTERVAL.as_secs() * 2);
+
+/// 路由通告任务，定期把自己能到达的节点告诉直连的客户端，
+/// 两个客户端之间无法打洞时，可以选择延迟低的客户端中继，而不是只能经过服务端中继
+pub fn start(
+    mut worker: VntWorker,
+    sender: ChannelSender,
+    current_device: Arc<AtomicCell<CurrentDeviceInfo>>,
+    client_cipher: Cipher,
+) {
+    tokio::spawn(async move {
+        tokio::select! {
+             _=worker.stop_wait()=>{
+                    return;
+             }
+            rs=start_(sender, current_device, client_cipher)=>{
+                if let Err(e) = rs {
+                    log::warn!("路由通告任务停止:{:?}", e);
+                }
+            }
+        }
+        worker.stop_all();
+    });
+}
+
+async fn start_(
+    sender: ChannelSender,
+    current_device: Arc<AtomicCell<CurrentDeviceInfo>>,
+    client_cipher: Cipher,
+) -> io::Result<()> {
+    log::info!("启动路由通告任务");
+    loop {
+        if sender.is_close() {
+            return Ok(());
+        }
+        tokio::time::sleep(ADVERTISE_INTERVAL).await;
+        sender.expire_advertised_routes(ADVERTISE_EXPIRE);
+        let current_dev = current_device.load();
+        let route_table = sender.route_table_one();
+        for (peer_ip, peer_route) in route_table.iter() {
+            if !peer_route.is_p2p() || *peer_ip == current_dev.virtual_gateway {
+                continue;
+            }
+            let items: Vec<(Ipv4Addr, u8, u16)> = route_table
+                .iter()
+                .filter(|(ip, route)| {
+                    ip != peer_ip
+                        && *ip != current_dev.virtual_gateway
+                        && route.metric < MAX_ADVERTISE_METRIC
+                        && route.addr != current_dev.connect_server
+                        // 水平分割，不把经过对方的路由通告回去
+                        && route.route_key() != peer_route.route_key()
+                })
+                .take(MAX_ADVERTISE_ITEM)
+                .map(|(ip, route)| (*ip, route.metric, route_rt(route)))
+                .collect();
+            if items.is_empty() {
+                continue;
+            }
+            if let Err(e) = advertise(
+                &sender,
+                &client_cipher,
+                &current_dev,
+                *peer_ip,
+                peer_route,
+                &items,
+            )
+            .await
+            {
+                log::warn!("路由通告失败 peer_ip:{},{:?}", peer_ip, e);
+            }
+        }
+    }
+}
+
+async fn advertise(
+    context: &Context,
+    client_cipher: &Cipher,
+    current_device: &CurrentDeviceInfo,
+    peer_ip: Ipv4Addr,
+    peer_route: &Route,
+    items: &[(Ipv4Addr, u8, u16)],
+) -> io::Result<()> {
+    let len = items.len() * ROUTE_ADVERTISE_ITEM_LEN;
+    let mut net_packet = NetPacket::new_encrypt(vec![0u8; 12 + len + ENCRYPTION_RESERVED])?;
+    net_packet.set_version(Version::V1);
+    net_packet.set_protocol(Protocol::Control);
+    net_packet.set_transport_protocol(control_packet::Protocol::RouteAdvertise.into());
+    // 只发给直连的客户端，不需要转发
+    net_packet.first_set_ttl(1);
+    net_packet.set_source(current_device.virtual_ip());
+    net_packet.set_destination(peer_ip);
+    {
+        let mut packet = RouteAdvertisePacket::new(net_packet.payload_mut())?;
+        for (index, (ip, metric, rt)) in items.iter().enumerate() {
+            packet.set_item(index, *ip, *metric, *rt);
+        }
+    }
+    client_cipher.encrypt_ipv4(&mut net_packet)?;
+    context
+        .send_by_key(net_packet.buffer(), &peer_route.route_key())
+        .await?;
+    Ok(())
+}
+
+/// 处理直连客户端的路由通告，把对方能到达的节点加入路由表，
+/// 每次通告都刷新经过对方的路由的跳数和延迟
+pub fn handle(
+    context: &Context,
+    current_device: &CurrentDeviceInfo,
+    source: Ipv4Addr,
+    route_key: &RouteKey,
+    packet: RouteAdvertisePacket<&[u8]>,
+) {
+    let peer_route = match context.route_one(&source) {
+        Some(route) if route.is_p2p() && route.route_key() == *route_key => route,
+        _ => {
+            // 只接受直连客户端的通告
+            return;
+        }
+    };
+    let peer_rt = route_rt(&peer_route) as i64;
+    for (ip, metric, rt) in packet.items() {
+        if ip == source
+            || ip == current_device.virtual_ip
+            || ip == current_device.virtual_gateway
+            || metric == 0
+            || metric >= MAX_ADVERTISE_METRIC
+        {
+            continue;
+        }
+        let route = Route::from(*route_key, metric + 1, peer_rt + rt as i64);
+        context.add_advertised_route(ip, route);
+    }
+}
+
+/// 未测量的路由延迟为199，通告时按这个值计算
+fn route_rt(route: &Route) -> u16 {
+    route.rt.clamp(0, u16::MAX as i64) as u16
+}
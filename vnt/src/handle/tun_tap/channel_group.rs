@@ -1,4 +1,7 @@
 use byte_pool::Block;
+use tokio::sync::mpsc::Receiver;
+
+use crate::channel::batch::BATCH_SIZE;
 
 #[derive(Clone)]
 pub struct BufSenderGroup(
@@ -30,3 +33,21 @@ pub fn buf_channel_group(size: usize) -> (BufSenderGroup, BufReceiverGroup) {
         BufReceiverGroup(buf_receiver_group),
     )
 }
+
+/// 等到一个数据后，继续取出队列中已经到达的数据，处理完后可以一起发送
+pub async fn recv_batch(
+    receiver: &mut Receiver<(Block<'static>, usize, usize)>,
+    list: &mut Vec<(Block<'static>, usize, usize)>,
+) -> bool {
+    match receiver.recv().await {
+        Some(val) => list.push(val),
+        None => return false,
+    }
+    while list.len() < BATCH_SIZE {
+        match receiver.try_recv() {
+            Ok(val) => list.push(val),
+            Err(_) => break,
+        }
+    }
+    true
+}
@@ -2,15 +2,19 @@ use crate::channel::sender::ChannelSender;
 use crate::cipher::Cipher;
 use crate::error::*;
 use crate::external_route::ExternalRoute;
-use crate::handle::{check_dest, CurrentDeviceInfo};
+use crate::handle::{check_dest, fragment, CurrentDeviceInfo};
 use crate::igmp_server::{IgmpServer, Multicast};
 use crate::ip_proxy::IpProxyMap;
 use crate::protocol;
 use crate::protocol::body::ENCRYPTION_RESERVED;
 use crate::protocol::ip_turn_packet::BroadcastPacket;
 use crate::protocol::{ip_turn_packet, NetPacket, Version, MAX_TTL};
+use crate::tun_tap_device::{DeviceReader, DeviceWriter};
+use packet::icmp::icmp::IcmpPacket;
+use packet::icmp::Kind;
 use packet::ip::ipv4::packet::IpV4Packet;
 use packet::ip::ipv4::protocol::Protocol;
+use packet::ip::ipv6::packet::{IpV6Packet, IPV6_HEAD_LEN};
 use packet::tcp::tcp::TcpPacket;
 use packet::udp::udp::UdpPacket;
 use parking_lot::RwLock;
@@ -22,6 +26,22 @@ pub mod channel_group;
 pub mod tap_handler;
 pub mod tun_handler;
 
+/// 按网卡队列拆分读写端，只有linux支持多队列，其他平台只有一个
+fn device_queues(
+    device_reader: DeviceReader,
+    device_writer: &DeviceWriter,
+) -> Vec<(DeviceReader, DeviceWriter)> {
+    #[cfg(any(target_os = "linux", target_os = "macos"))]
+    return device_reader
+        .split()
+        .into_iter()
+        .enumerate()
+        .map(|(index, device_reader)| (device_reader, device_writer.queue(index)))
+        .collect();
+    #[cfg(not(any(target_os = "linux", target_os = "macos")))]
+    vec![(device_reader, device_writer.clone())]
+}
+
 async fn broadcast(
     server_cipher: &Cipher,
     multicast_members: Option<Arc<RwLock<Multicast>>>,
@@ -92,12 +112,106 @@ async fn broadcast(
     Ok(())
 }
 
+/// 不允许分片的ipv4数据包超过路径mtu时，向网卡回复icmp需要分片，
+/// 系统协议栈记录该目标的路径mtu后会用更小的长度重新发送
+fn fragmentation_needed(device_writer: &DeviceWriter, ip_packet: &[u8], mtu: u16) -> Result<()> {
+    let ipv4_packet = IpV4Packet::new(ip_packet)?;
+    //携带原数据包的ip头和前8字节数据
+    let quote_len = (ipv4_packet.header_len() as usize * 4 + 8).min(ip_packet.len());
+    //以太网头14字节+ip头20字节+icmp头8字节
+    let mut buf = vec![0u8; 14 + 20 + 8 + quote_len];
+    {
+        let ip = &mut buf[14..];
+        ip[0] = 0x45;
+        ip[2..4].copy_from_slice(&((20 + 8 + quote_len) as u16).to_be_bytes());
+        ip[8] = 64;
+        let mut out = IpV4Packet::unchecked(ip);
+        out.set_protocol(Protocol::Icmp);
+        out.set_source_ip(ipv4_packet.destination_ip());
+        out.set_destination_ip(ipv4_packet.source_ip());
+        out.update_checksum();
+        let icmp = out.payload_mut();
+        icmp[0] = Kind::DestinationUnreachable.into();
+        //需要分片但设置了不分片
+        icmp[1] = 4;
+        icmp[6..8].copy_from_slice(&mtu.to_be_bytes());
+        icmp[8..].copy_from_slice(&ip_packet[..quote_len]);
+        IcmpPacket::unchecked(icmp).update_checksum();
+    }
+    device_writer.write_ipv4(&mut buf)?;
+    Ok(())
+}
+
+/// ipv6数据包超过路径mtu时，向网卡回复icmpv6数据包过大
+fn packet_too_big(device_writer: &DeviceWriter, ip_packet: &[u8], mtu: u16) -> Result<()> {
+    let ipv6_packet = IpV6Packet::new(ip_packet)?;
+    let src_ip = ipv6_packet.destination_ip();
+    let dest_ip = ipv6_packet.source_ip();
+    //差错报文总长度不超过ipv6的最小mtu
+    let quote_len = ip_packet.len().min(1280 - IPV6_HEAD_LEN - 8);
+    let mut buf = vec![0u8; 14 + IPV6_HEAD_LEN + 8 + quote_len];
+    {
+        let ip = &mut buf[14..];
+        ip[0] = 6 << 4;
+        let mut out = IpV6Packet::unchecked(ip);
+        out.set_payload_length((8 + quote_len) as u16);
+        out.set_next_header(Protocol::Ipv6Icmp);
+        out.set_hop_limit(64);
+        out.set_source_ip(src_ip);
+        out.set_destination_ip(dest_ip);
+        let icmp = out.payload_mut();
+        //Packet Too Big
+        icmp[0] = 2;
+        icmp[4..8].copy_from_slice(&(mtu as u32).to_be_bytes());
+        icmp[8..].copy_from_slice(&ip_packet[..quote_len]);
+        let checksum =
+            packet::ipv6_cal_checksum(icmp, &src_ip, &dest_ip, Protocol::Ipv6Icmp.into());
+        icmp[2..4].copy_from_slice(&checksum.to_be_bytes());
+    }
+    device_writer.write_ipv6(&mut buf)?;
+    Ok(())
+}
+
+/// 超过目标路由的路径mtu时返回ip层可用的mtu
+fn exceed_route_mtu(
+    sender: &ChannelSender,
+    dest_ip: &Ipv4Addr,
+    data_len: usize,
+    client_cipher: &Cipher,
+) -> Option<u16> {
+    let mtu = sender.route_mtu(dest_ip)? as usize;
+    let reserved = 12 + client_cipher.reserved();
+    if data_len + client_cipher.reserved() > mtu && mtu > reserved {
+        Some((mtu - reserved) as u16)
+    } else {
+        None
+    }
+}
+
+/// 分片和完整的数据包一样，优先发到直连地址
+async fn send_fragment(
+    sender: &ChannelSender,
+    list: &[NetPacket<Vec<u8>>],
+    dest_id: &Ipv4Addr,
+    current_device: &CurrentDeviceInfo,
+) -> Result<()> {
+    for packet in list {
+        if sender.send_by_id(packet.buffer(), dest_id).await.is_err() {
+            sender
+                .send_main(packet.buffer(), current_device.connect_server)
+                .await?;
+        }
+    }
+    Ok(())
+}
+
 /// 实现一个原地发送，必须保证是如下结构
 /// |12字节开头|ip报文|至少1024字节结尾|
 ///
 #[inline]
 pub async fn base_handle(
     sender: &ChannelSender,
+    device_writer: &DeviceWriter,
     buf: &mut [u8],
     data_len: usize, //数据总长度=12+ip包长度
     igmp_server: &Option<IgmpServer>,
@@ -253,7 +367,17 @@ pub async fn base_handle(
             _ => {}
         }
     }
-    client_cipher.encrypt_ipv4(&mut net_packet)?;
+    if let Some(mtu) = fragment::fragment_mtu(sender, &dest_ip, data_len, client_cipher) {
+        let list = fragment::fragment(sender, client_cipher, &net_packet, mtu)?;
+        return send_fragment(sender, &list, &dest_ip, &current_device).await;
+    }
+    if let Some(mtu) = exceed_route_mtu(sender, &dest_ip, data_len, client_cipher) {
+        //设置了不分片(DF)时通知发送方，否则由底层网络分片
+        if ipv4_packet_df(net_packet.payload()) && mtu >= 68 {
+            return fragmentation_needed(device_writer, net_packet.payload(), mtu);
+        }
+    }
+    sender.encrypt_by_id(&dest_ip, client_cipher, &mut net_packet)?;
     //优先发到直连到地址
     if sender
         .send_by_id(net_packet.buffer(), &dest_ip)
@@ -266,3 +390,81 @@ pub async fn base_handle(
     }
     return Ok(());
 }
+
+/// ipv6报文的原地发送，结构和base_handle一致
+/// |12字节开头|ipv6报文|至少1024字节结尾|
+///
+/// vnt头部仍然使用虚拟ipv4作为节点标识，目的ipv6通过设备列表映射到对应的节点
+#[inline]
+pub async fn base_handle_ipv6(
+    sender: &ChannelSender,
+    device_writer: &DeviceWriter,
+    buf: &mut [u8],
+    data_len: usize, //数据总长度=12+ip包长度
+    current_device: CurrentDeviceInfo,
+    client_cipher: &Cipher,
+    server_cipher: &Cipher,
+) -> Result<()> {
+    let ipv6_packet = IpV6Packet::new(&buf[12..data_len])?;
+    let src_ip = ipv6_packet.source_ip();
+    let dest_ip = ipv6_packet.destination_ip();
+    if current_device.virtual_ipv6() != Some(src_ip) {
+        //链路本地地址等不在虚拟网络中传输
+        return Ok(());
+    }
+    let mut net_packet = NetPacket::new0(data_len, buf)?;
+    net_packet.set_version(Version::V1);
+    net_packet.set_protocol(protocol::Protocol::IpTurn);
+    net_packet.set_transport_protocol(ip_turn_packet::Protocol::Ipv6.into());
+    net_packet.first_set_ttl(3);
+    net_packet.set_source(current_device.virtual_ip());
+    if dest_ip.is_multicast() {
+        // ipv6没有广播，组播当作广播处理
+        net_packet.set_destination(Ipv4Addr::BROADCAST);
+        client_cipher.encrypt_ipv4(&mut net_packet)?;
+        broadcast(
+            server_cipher,
+            None,
+            sender,
+            &mut net_packet,
+            &current_device,
+        )
+        .await?;
+        return Ok(());
+    }
+    if !current_device.is_ipv6_network(&dest_ip) {
+        return Ok(());
+    }
+    let dest_id = if let Some(dest_id) = sender.ipv6_to_id(&dest_ip) {
+        dest_id
+    } else {
+        return Ok(());
+    };
+    net_packet.set_destination(dest_id);
+    if let Some(mtu) = fragment::fragment_mtu(sender, &dest_id, data_len, client_cipher) {
+        let list = fragment::fragment(sender, client_cipher, &net_packet, mtu)?;
+        return send_fragment(sender, &list, &dest_id, &current_device).await;
+    }
+    if let Some(mtu) = exceed_route_mtu(sender, &dest_id, data_len, client_cipher) {
+        //低于ipv6最小mtu时对方不会处理，只能由底层网络分片
+        if mtu >= 1280 {
+            return packet_too_big(device_writer, net_packet.payload(), mtu);
+        }
+    }
+    sender.encrypt_by_id(&dest_id, client_cipher, &mut net_packet)?;
+    //优先发到直连到地址
+    if sender
+        .send_by_id(net_packet.buffer(), &dest_id)
+        .await
+        .is_err()
+    {
+        sender
+            .send_main(net_packet.buffer(), current_device.connect_server)
+            .await?;
+    }
+    Ok(())
+}
+
+fn ipv4_packet_df(ip_packet: &[u8]) -> bool {
+    IpV4Packet::new(ip_packet).map_or(false, |packet| packet.flags() & 0b010 == 0b010)
+}
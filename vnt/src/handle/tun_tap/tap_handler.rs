@@ -1,4 +1,5 @@
 use byte_pool::BytePool;
+use std::net::Ipv6Addr;
 use std::sync::Arc;
 use std::{io, thread};
 
@@ -12,12 +13,15 @@ use packet::icmp::icmp::IcmpPacket;
 use packet::icmp::Kind;
 use packet::ip::ipv4;
 use packet::ip::ipv4::packet::IpV4Packet;
+use packet::ip::ipv6::packet::{IpV6Packet, IPV6_HEAD_LEN};
 
+use crate::channel::batch::BATCH_SIZE;
 use crate::channel::sender::ChannelSender;
 use crate::cipher::Cipher;
 use crate::core::status::VntWorker;
 use crate::external_route::ExternalRoute;
-use crate::handle::tun_tap::channel_group::{buf_channel_group, BufSenderGroup};
+use crate::handle::tun_tap::channel_group::{buf_channel_group, recv_batch, BufSenderGroup};
+use crate::handle::tun_tap::device_queues;
 use crate::handle::CurrentDeviceInfo;
 use crate::igmp_server::IgmpServer;
 use crate::ip_proxy::IpProxyMap;
@@ -68,55 +72,77 @@ pub fn start(
             })
             .unwrap();
     } else {
-        let (buf_sender, buf_receiver) = buf_channel_group(parallel);
-        for mut buf_receiver in buf_receiver.0 {
-            let sender = sender.clone();
-            let device_writer = device_writer.clone();
-            let igmp_server = igmp_server.clone();
-            let current_device = current_device.clone();
-            let ip_route = ip_route.clone();
-            let ip_proxy_map = ip_proxy_map.clone();
-            let client_cipher = client_cipher.clone();
-            let server_cipher = server_cipher.clone();
-            tokio::spawn(async move {
-                while let Some((mut buf, _, len)) = buf_receiver.recv().await {
-                    match handle(
-                        &mut buf,
-                        len,
-                        &igmp_server,
-                        &current_device,
-                        &device_writer,
-                        &sender,
-                        &ip_route,
-                        &ip_proxy_map,
-                        &client_cipher,
-                        &server_cipher,
-                    )
-                    .await
-                    {
-                        Ok(_) => {}
-                        Err(e) => {
-                            log::warn!("{:?}", e)
+        let queues = device_queues(device_reader, &device_writer);
+        // 多队列时内核按流分配队列，每个队列一个读取线程和一个处理任务，同一个流不会乱序
+        let group_size = if queues.len() > 1 { 1 } else { parallel };
+        let mut workers = vec![worker];
+        for index in 1..queues.len() {
+            workers.push(workers[0].worker(&format!("tap_handler_{}", index)));
+        }
+        for (index, ((device_reader, device_writer), worker)) in
+            queues.into_iter().zip(workers).enumerate()
+        {
+            let (buf_sender, buf_receiver) = buf_channel_group(group_size);
+            for mut buf_receiver in buf_receiver.0 {
+                let sender = sender.clone();
+                let device_writer = device_writer.clone();
+                let igmp_server = igmp_server.clone();
+                let current_device = current_device.clone();
+                let ip_route = ip_route.clone();
+                let ip_proxy_map = ip_proxy_map.clone();
+                let client_cipher = client_cipher.clone();
+                let server_cipher = server_cipher.clone();
+                let sender = sender.batch();
+                tokio::spawn(async move {
+                    let mut list = Vec::with_capacity(BATCH_SIZE);
+                    while recv_batch(&mut buf_receiver, &mut list).await {
+                        for (mut buf, _, len) in list.drain(..) {
+                            match handle(
+                                &mut buf,
+                                len,
+                                &igmp_server,
+                                &current_device,
+                                &device_writer,
+                                &sender,
+                                &ip_route,
+                                &ip_proxy_map,
+                                &client_cipher,
+                                &server_cipher,
+                            )
+                            .await
+                            {
+                                Ok(_) => {}
+                                Err(e) => {
+                                    log::warn!("{:?}", e)
+                                }
+                            }
                         }
+                        sender.flush().await;
                     }
-                }
-            });
+                });
+            }
+            let sender = sender.clone();
+            let name = if index == 0 {
+                "tap_handler".to_string()
+            } else {
+                format!("tap_handler_{}", index)
+            };
+            thread::Builder::new()
+                .name(name)
+                .spawn(move || {
+                    tokio::runtime::Builder::new_current_thread()
+                        .enable_all()
+                        .build()
+                        .unwrap()
+                        .block_on(async move {
+                            if let Err(e) = start_(sender, device_reader, buf_sender).await {
+                                log::warn!("tap:{:?}", e);
+                            }
+                            worker.stop_all();
+                        });
+                })
+                .unwrap();
         }
-        thread::Builder::new()
-            .name("tap_handler".into())
-            .spawn(move || {
-                tokio::runtime::Builder::new_current_thread()
-                    .enable_all()
-                    .build()
-                    .unwrap()
-                    .block_on(async move {
-                        if let Err(e) = start_(sender, device_reader, buf_sender).await {
-                            log::warn!("tap:{:?}", e);
-                        }
-                        worker.stop_all();
-                    });
-            })
-            .unwrap();
     }
 }
 
@@ -174,6 +200,78 @@ async fn start_simple(
     }
 }
 
+/// 邻居请求
+const ICMPV6_NEIGHBOR_SOLICITATION: u8 = 135;
+/// 邻居通告
+const ICMPV6_NEIGHBOR_ADVERTISEMENT: u8 = 136;
+
+/// 回复邻居请求，和arp一样使用虚假的MAC地址，取目标ipv6的后4字节
+fn neighbor_advertisement(
+    device_writer: &DeviceWriter,
+    ethernet_packet: &EthernetPacket<&mut [u8]>,
+    current_device: &CurrentDeviceInfo,
+) -> crate::Result<bool> {
+    let ipv6_packet = IpV6Packet::new(ethernet_packet.payload())?;
+    if ipv6_packet.next_header() != ipv4::protocol::Protocol::Ipv6Icmp {
+        return Ok(false);
+    }
+    let icmp = ipv6_packet.payload();
+    if icmp.len() < 24 || icmp[0] != ICMPV6_NEIGHBOR_SOLICITATION {
+        return Ok(false);
+    }
+    let src_ip = ipv6_packet.source_ip();
+    let target_octets: [u8; 16] = icmp[8..24].try_into().unwrap();
+    let target = Ipv6Addr::from(target_octets);
+    if src_ip.is_unspecified()
+        || current_device.virtual_ipv6() == Some(target)
+        || !current_device.is_ipv6_network(&target)
+    {
+        //重复地址检测和自身地址不回复
+        return Ok(true);
+    }
+    let sender_h = ethernet_packet.source();
+    let fake_mac = [
+        target_octets[12],
+        target_octets[13],
+        target_octets[14],
+        target_octets[15],
+        !sender_h[5],
+        234,
+    ];
+    // 以太网头14字节+ipv6头40字节+邻居通告24字节+目标链路层地址选项8字节
+    let mut out = vec![0u8; 14 + IPV6_HEAD_LEN + 32];
+    let mut out_ethernet_packet = EthernetPacket::unchecked(&mut out[..]);
+    out_ethernet_packet.set_source(&fake_mac);
+    out_ethernet_packet.set_destination(sender_h);
+    out_ethernet_packet.set_protocol(ethernet::protocol::Protocol::Ipv6);
+    let payload = out_ethernet_packet.payload_mut();
+    payload[0] = 6 << 4;
+    let mut out_ipv6_packet = IpV6Packet::unchecked(payload);
+    out_ipv6_packet.set_payload_length(32);
+    out_ipv6_packet.set_next_header(ipv4::protocol::Protocol::Ipv6Icmp);
+    out_ipv6_packet.set_hop_limit(255);
+    out_ipv6_packet.set_source_ip(target);
+    out_ipv6_packet.set_destination_ip(src_ip);
+    let icmp_out = out_ipv6_packet.payload_mut();
+    icmp_out[0] = ICMPV6_NEIGHBOR_ADVERTISEMENT;
+    //Solicited|Override
+    icmp_out[4] = 0x60;
+    icmp_out[8..24].copy_from_slice(&target_octets);
+    //目标链路层地址
+    icmp_out[24] = 2;
+    icmp_out[25] = 1;
+    icmp_out[26..32].copy_from_slice(&fake_mac);
+    let checksum = packet::ipv6_cal_checksum(
+        icmp_out,
+        &target,
+        &src_ip,
+        ipv4::protocol::Protocol::Ipv6Icmp.into(),
+    );
+    icmp_out[2..4].copy_from_slice(&checksum.to_be_bytes());
+    device_writer.write_ethernet_tap(&out)?;
+    Ok(true)
+}
+
 async fn handle(
     buf: &mut [u8],
     len: usize,
@@ -253,6 +351,7 @@ async fn handle(
             // 以太网帧头部14字节，预留12字节
             return crate::handle::tun_tap::base_handle(
                 sender,
+                device_writer,
                 &mut buf[2..],
                 len - 2,
                 igmp_server,
@@ -264,6 +363,22 @@ async fn handle(
             )
             .await;
         }
+        ethernet::protocol::Protocol::Ipv6 => {
+            if neighbor_advertisement(device_writer, &ethernet_packet, &current_device)? {
+                return Ok(());
+            }
+            // 以太网帧头部14字节，预留12字节
+            return crate::handle::tun_tap::base_handle_ipv6(
+                sender,
+                device_writer,
+                &mut buf[2..],
+                len - 2,
+                current_device,
+                client_cipher,
+                server_cipher,
+            )
+            .await;
+        }
         _ => {
             // log::warn!("不支持的二层协议：{:?}",p)
         }
@@ -4,6 +4,7 @@ use std::{io, thread};
 
 use crossbeam_utils::atomic::AtomicCell;
 
+use crate::channel::batch::BATCH_SIZE;
 use crate::channel::sender::ChannelSender;
 use crate::cipher::Cipher;
 use crate::core::status::VntWorker;
@@ -14,10 +15,13 @@ use packet::ip::ipv4::packet::IpV4Packet;
 
 use crate::error::*;
 use crate::external_route::ExternalRoute;
-use crate::handle::tun_tap::channel_group::{buf_channel_group, BufSenderGroup};
+use crate::handle::tun_tap::channel_group::{buf_channel_group, recv_batch, BufSenderGroup};
+use crate::handle::tun_tap::device_queues;
 use crate::handle::CurrentDeviceInfo;
 use crate::igmp_server::IgmpServer;
 use crate::ip_proxy::IpProxyMap;
+#[cfg(target_os = "linux")]
+use crate::tun_tap_device::offload::{Segments, VIRTIO_NET_HDR_LEN};
 use crate::tun_tap_device::{DeviceReader, DeviceWriter};
 lazy_static::lazy_static! {
     static ref POOL:BytePool<Vec<u8>> = BytePool::<Vec<u8>>::new();
@@ -52,6 +56,18 @@ async fn handle(
     client_cipher: &Cipher,
     server_cipher: &Cipher,
 ) -> Result<()> {
+    if len > 12 && data[12] >> 4 == 6 {
+        return crate::handle::tun_tap::base_handle_ipv6(
+            sender,
+            device_writer,
+            data,
+            len,
+            current_device,
+            client_cipher,
+            server_cipher,
+        )
+        .await;
+    }
     let ipv4_packet = if let Ok(ipv4_packet) = IpV4Packet::new(&mut data[12..len]) {
         ipv4_packet
     } else {
@@ -67,6 +83,7 @@ async fn handle(
     }
     return crate::handle::tun_tap::base_handle(
         sender,
+        device_writer,
         data,
         len,
         igmp_server,
@@ -122,57 +139,78 @@ pub async fn start(
             })
             .unwrap();
     } else {
-        let (buf_sender, buf_receiver) = buf_channel_group(parallel);
-        for mut buf_receiver in buf_receiver.0 {
-            let sender = sender.clone();
-            let device_writer = device_writer.clone();
-            let igmp_server = igmp_server.clone();
-            let current_device = current_device.clone();
-            let ip_route = ip_route.clone();
-            let ip_proxy_map = ip_proxy_map.clone();
-            let client_cipher = client_cipher.clone();
-            let server_cipher = server_cipher.clone();
-            tokio::spawn(async move {
-                while let Some((mut buf, start, len)) = buf_receiver.recv().await {
-                    match handle(
-                        &sender,
-                        &mut buf[start..],
-                        len,
-                        &device_writer,
-                        &igmp_server,
-                        current_device.load(),
-                        &ip_route,
-                        &ip_proxy_map,
-                        &client_cipher,
-                        &server_cipher,
-                    )
-                    .await
-                    {
-                        Ok(_) => {}
-                        Err(e) => {
-                            log::warn!("{:?}", e)
+        let queues = device_queues(device_reader, &device_writer);
+        // 多队列时内核按流分配队列，每个队列一个读取线程和一个处理任务，同一个流不会乱序
+        let group_size = if queues.len() > 1 { 1 } else { parallel };
+        let mut workers = vec![worker];
+        for index in 1..queues.len() {
+            workers.push(workers[0].worker(&format!("tun_handler_{}", index)));
+        }
+        for (index, ((device_reader, device_writer), worker)) in
+            queues.into_iter().zip(workers).enumerate()
+        {
+            let (buf_sender, buf_receiver) = buf_channel_group(group_size);
+            for mut buf_receiver in buf_receiver.0 {
+                let sender = sender.clone();
+                let device_writer = device_writer.clone();
+                let igmp_server = igmp_server.clone();
+                let current_device = current_device.clone();
+                let ip_route = ip_route.clone();
+                let ip_proxy_map = ip_proxy_map.clone();
+                let client_cipher = client_cipher.clone();
+                let server_cipher = server_cipher.clone();
+                let sender = sender.batch();
+                tokio::spawn(async move {
+                    let mut list = Vec::with_capacity(BATCH_SIZE);
+                    while recv_batch(&mut buf_receiver, &mut list).await {
+                        for (mut buf, start, len) in list.drain(..) {
+                            match handle(
+                                &sender,
+                                &mut buf[start..],
+                                len,
+                                &device_writer,
+                                &igmp_server,
+                                current_device.load(),
+                                &ip_route,
+                                &ip_proxy_map,
+                                &client_cipher,
+                                &server_cipher,
+                            )
+                            .await
+                            {
+                                Ok(_) => {}
+                                Err(e) => {
+                                    log::warn!("{:?}", e)
+                                }
+                            }
                         }
+                        sender.flush().await;
                     }
-                }
-            });
+                });
+            }
+            let sender = sender.clone();
+            let name = if index == 0 {
+                "tun_handler".to_string()
+            } else {
+                format!("tun_handler_{}", index)
+            };
+            thread::Builder::new()
+                .name(name)
+                .spawn(move || {
+                    tokio::runtime::Builder::new_current_thread()
+                        .enable_all()
+                        .build()
+                        .unwrap()
+                        .block_on(async move {
+                            if let Err(e) = start_(sender, device_reader, buf_sender).await {
+                                log::warn!("stop:{}", e);
+                            }
+                            let _ = device_writer.close();
+                            worker.stop_all();
+                        })
+                })
+                .unwrap();
         }
-
-        thread::Builder::new()
-            .name("tun_handler".into())
-            .spawn(move || {
-                tokio::runtime::Builder::new_current_thread()
-                    .enable_all()
-                    .build()
-                    .unwrap()
-                    .block_on(async move {
-                        if let Err(e) = start_(sender, device_reader, buf_sender).await {
-                            log::warn!("stop:{}", e);
-                        }
-                        let _ = device_writer.close();
-                        worker.stop_all();
-                    })
-            })
-            .unwrap();
     }
 }
 
@@ -181,6 +219,10 @@ async fn start_(
     device_reader: DeviceReader,
     mut buf_sender: BufSenderGroup,
 ) -> io::Result<()> {
+    #[cfg(target_os = "linux")]
+    if device_reader.is_vnet_hdr() {
+        return start_offload(sender, device_reader, buf_sender).await;
+    }
     loop {
         let mut buf = POOL.alloc(4096);
         buf[..12].fill(0);
@@ -212,6 +254,43 @@ async fn start_simple(
     server_cipher: Cipher,
 ) -> io::Result<()> {
     let mut buf = [0; 4096];
+    #[cfg(target_os = "linux")]
+    if device_reader.is_vnet_hdr() {
+        // 开启offload时读取的可能是tcp大包，拆分成不超过mtu的ip包后再处理
+        let mut vnet_buf = vec![0; VIRTIO_NET_HDR_LEN + 65535];
+        loop {
+            if sender.is_close() {
+                return Ok(());
+            }
+            let len = device_reader.read(&mut vnet_buf)?;
+            let mut segments = match Segments::new(&mut vnet_buf[..len]) {
+                Ok(segments) => segments,
+                Err(e) => {
+                    log::warn!("{:?}", e);
+                    continue;
+                }
+            };
+            while let Some(len) = segments.next_segment(&mut buf[12..]) {
+                buf[..12].fill(0);
+                if let Err(e) = handle(
+                    &sender,
+                    &mut buf,
+                    12 + len,
+                    device_writer,
+                    &igmp_server,
+                    current_device.load(),
+                    &ip_route,
+                    &ip_proxy_map,
+                    &client_cipher,
+                    &server_cipher,
+                )
+                .await
+                {
+                    log::warn!("{:?}", e)
+                }
+            }
+        }
+    }
     loop {
         if sender.is_close() {
             return Ok(());
@@ -241,3 +320,40 @@ async fn start_simple(
         }
     }
 }
+
+/// 开启offload时读取的可能是tcp大包，拆分成不超过mtu的ip包后再处理
+#[cfg(target_os = "linux")]
+async fn start_offload(
+    sender: ChannelSender,
+    device_reader: DeviceReader,
+    mut buf_sender: BufSenderGroup,
+) -> io::Result<()> {
+    let mut buf = vec![0; VIRTIO_NET_HDR_LEN + 65535];
+    loop {
+        if sender.is_close() {
+            return Ok(());
+        }
+        let len = device_reader.read(&mut buf)?;
+        let mut segments = match Segments::new(&mut buf[..len]) {
+            Ok(segments) => segments,
+            Err(e) => {
+                log::warn!("{:?}", e);
+                continue;
+            }
+        };
+        loop {
+            let mut block = POOL.alloc(4096);
+            let len = match segments.next_segment(&mut block[12..]) {
+                Some(len) => len,
+                None => break,
+            };
+            block[..12].fill(0);
+            if !buf_sender.send((block, 0, 12 + len)).await {
+                return Err(io::Error::new(
+                    io::ErrorKind::Other,
+                    "tun buf_sender发送失败",
+                ));
+            }
+        }
+    }
+}
@@ -94,9 +94,12 @@ impl IcmpProxy {
                                                         net_packet
                                                             .set_payload(ipv4_packet.buffer)
                                                             .unwrap();
-                                                        if let Err(e) = self
-                                                            .client_cipher
-                                                            .encrypt_ipv4(&mut net_packet)
+                                                        if let Err(e) =
+                                                            self.sender.encrypt_by_id(
+                                                                &dest_ip,
+                                                                &self.client_cipher,
+                                                                &mut net_packet,
+                                                            )
                                                         {
                                                             log::warn!("加密失败:{}", e);
                                                             continue;
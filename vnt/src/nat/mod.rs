@@ -5,9 +5,11 @@ use std::sync::Arc;
 
 use parking_lot::Mutex;
 
-use crate::channel::punch::{NatInfo, NatType};
-use crate::proto::message::PunchNatType;
+use crate::channel::punch::{NatDependency, NatInfo, NatType, PortAllocation};
+use crate::core::event::{send_event, EventSender, VntEvent};
+use crate::proto::message::{PunchNatDependency, PunchNatType, PunchPortAllocation};
 
+pub mod port_mapping;
 mod stun_test;
 
 pub fn local_ipv4() -> io::Result<Ipv4Addr> {
@@ -52,8 +54,12 @@ pub fn local_ipv6_addr(port: u16) -> SocketAddrV6 {
 
 #[derive(Clone)]
 pub struct NatTest {
-    stun_server: Vec<String>,
+    stun_server: Arc<Mutex<Vec<String>>>,
     info: Arc<Mutex<NatInfo>>,
+    mapped_addr: Arc<Mutex<Option<SocketAddrV4>>>,
+    tcp_port: u16,
+    quic_port: u16,
+    event_sender: EventSender,
 }
 
 impl From<NatType> for PunchNatType {
@@ -74,16 +80,62 @@ impl Into<NatType> for PunchNatType {
     }
 }
 
+impl From<NatDependency> for PunchNatDependency {
+    fn from(value: NatDependency) -> Self {
+        match value {
+            NatDependency::Unknown => PunchNatDependency::DependencyUnknown,
+            NatDependency::EndpointIndependent => PunchNatDependency::EndpointIndependent,
+            NatDependency::AddressDependent => PunchNatDependency::AddressDependent,
+            NatDependency::AddressAndPortDependent => PunchNatDependency::AddressAndPortDependent,
+        }
+    }
+}
+
+impl Into<NatDependency> for PunchNatDependency {
+    fn into(self) -> NatDependency {
+        match self {
+            PunchNatDependency::DependencyUnknown => NatDependency::Unknown,
+            PunchNatDependency::EndpointIndependent => NatDependency::EndpointIndependent,
+            PunchNatDependency::AddressDependent => NatDependency::AddressDependent,
+            PunchNatDependency::AddressAndPortDependent => NatDependency::AddressAndPortDependent,
+        }
+    }
+}
+
+impl From<PortAllocation> for PunchPortAllocation {
+    fn from(value: PortAllocation) -> Self {
+        match value {
+            PortAllocation::Unknown => PunchPortAllocation::AllocationUnknown,
+            PortAllocation::Preserving => PunchPortAllocation::Preserving,
+            PortAllocation::Sequential => PunchPortAllocation::Sequential,
+            PortAllocation::Random => PunchPortAllocation::Random,
+        }
+    }
+}
+
+impl Into<PortAllocation> for PunchPortAllocation {
+    fn into(self) -> PortAllocation {
+        match self {
+            PunchPortAllocation::AllocationUnknown => PortAllocation::Unknown,
+            PunchPortAllocation::Preserving => PortAllocation::Preserving,
+            PunchPortAllocation::Sequential => PortAllocation::Sequential,
+            PunchPortAllocation::Random => PortAllocation::Random,
+        }
+    }
+}
+
 impl NatTest {
     pub async fn new(
-        mut stun_server: Vec<String>,
+        stun_server: Vec<String>,
         public_ip: Ipv4Addr,
         public_port: u16,
         local_ipv4_addr: SocketAddrV4,
         ipv6_addr: SocketAddrV6,
+        tcp_punch: bool,
+        quic_port: u16,
+        event_sender: EventSender,
     ) -> NatTest {
-        let server = stun_server[0].clone();
-        stun_server.resize(3, server);
+        let stun_server = Self::fill_stun_server(stun_server);
         let nat_info = Self::re_test_(
             &stun_server,
             public_ip,
@@ -93,10 +145,42 @@ impl NatTest {
         )
         .await;
         let info = Arc::new(Mutex::new(nat_info));
-        NatTest { stun_server, info }
+        NatTest {
+            stun_server: Arc::new(Mutex::new(stun_server)),
+            info,
+            mapped_addr: Arc::new(Mutex::new(None)),
+            tcp_port: if tcp_punch { local_ipv4_addr.port() } else { 0 },
+            quic_port,
+            event_sender,
+        }
+    }
+    fn fill_stun_server(mut stun_server: Vec<String>) -> Vec<String> {
+        let server = stun_server[0].clone();
+        stun_server.resize(3, server);
+        stun_server
+    }
+    /// 更新stun服务器，下次探测时生效
+    pub fn update_stun_server(&self, stun_server: Vec<String>) {
+        if stun_server.is_empty() {
+            return;
+        }
+        *self.stun_server.lock() = Self::fill_stun_server(stun_server);
     }
     pub fn nat_info(&self) -> NatInfo {
-        self.info.lock().clone()
+        let mapped_addr = *self.mapped_addr.lock();
+        self.info
+            .lock()
+            .clone()
+            .with_mapped_addr(mapped_addr)
+            .with_tcp_port(self.tcp_port)
+            .with_quic_port(self.quic_port)
+    }
+    /// 端口映射的地址变化
+    pub fn update_mapped_addr(&self, mapped_addr: Option<SocketAddrV4>) {
+        let old = std::mem::replace(&mut *self.mapped_addr.lock(), mapped_addr);
+        if old != mapped_addr {
+            send_event(&self.event_sender, VntEvent::NatChange(self.nat_info()));
+        }
     }
     pub fn update_addr(&self, ip: Ipv4Addr, port: u16) {
         let mut guard = self.info.lock();
@@ -112,15 +196,27 @@ impl NatTest {
         local_ipv4_addr: SocketAddrV4,
         ipv6_addr: SocketAddrV6,
     ) -> NatInfo {
+        let stun_server = self.stun_server.lock().clone();
         let info = NatTest::re_test_(
-            &self.stun_server,
+            &stun_server,
             public_ip,
             public_port,
             local_ipv4_addr,
             ipv6_addr,
         )
         .await;
-        *self.info.lock() = info.clone();
+        let changed = {
+            let mut guard = self.info.lock();
+            let changed = guard.nat_type != info.nat_type
+                || guard.public_ips != info.public_ips
+                || guard.behavior != info.behavior;
+            *guard = info.clone();
+            changed
+        };
+        let info = info.with_mapped_addr(*self.mapped_addr.lock());
+        if changed {
+            send_event(&self.event_sender, VntEvent::NatChange(info.clone()));
+        }
         info
     }
     async fn re_test_(
@@ -131,7 +227,7 @@ impl NatTest {
         ipv6_addr: SocketAddrV6,
     ) -> NatInfo {
         return match stun_test::stun_test_nat(stun_server.clone()).await {
-            Ok((nat_type, ips, port_range)) => {
+            Ok((nat_type, ips, port_range, behavior)) => {
                 let mut public_ips = Vec::new();
                 public_ips.push(Ipv4Addr::from(public_ip));
                 for ip in ips {
@@ -147,6 +243,7 @@ impl NatTest {
                     ipv6_addr,
                     nat_type,
                 )
+                .with_behavior(behavior)
             }
             Err(e) => {
                 log::warn!("{:?}", e);
@@ -0,0 +1,773 @@
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4};
+use std::time::Duration;
+
+use rand::Rng;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, UdpSocket};
+
+use crate::core::status::VntWorker;
+use crate::nat::NatTest;
+
+/// NAT-PMP和PCP使用的端口
+pub const NAT_PMP_PORT: u16 = 5351;
+/// SSDP组播地址
+pub const SSDP_ADDR: &str = "239.255.255.250:1900";
+/// 请求的映射有效期
+const LIFETIME: Duration = Duration::from_secs(7200);
+/// 映射失败后重试的间隔
+const RETRY_INTERVAL: Duration = Duration::from_secs(300);
+const UDP_PROTOCOL: u8 = 17;
+const IGD_SERVICE_TYPES: [&str; 3] = [
+    "urn:schemas-upnp-org:service:WANIPConnection:2",
+    "urn:schemas-upnp-org:service:WANIPConnection:1",
+    "urn:schemas-upnp-org:service:WANPPPConnection:1",
+];
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum MappingProtocol {
+    Pcp,
+    NatPmp,
+    Upnp,
+}
+
+/// 路由器上的一条UDP端口映射
+#[derive(Clone, Debug)]
+pub struct Mapping {
+    pub protocol: MappingProtocol,
+    pub external: SocketAddrV4,
+    pub internal_port: u16,
+    pub lifetime: Duration,
+    kind: MappingKind,
+}
+
+#[derive(Clone, Debug)]
+enum MappingKind {
+    Pcp {
+        gateway: SocketAddr,
+        local_ip: Ipv4Addr,
+        nonce: [u8; 12],
+    },
+    NatPmp {
+        gateway: SocketAddr,
+    },
+    Upnp(Igd),
+}
+
+/// 网关地址，用于NAT-PMP/PCP
+pub fn default_gateway() -> Option<Ipv4Addr> {
+    #[cfg(target_os = "linux")]
+    {
+        if let Some(gateway) = linux_default_gateway() {
+            return Some(gateway);
+        }
+    }
+    // 其他平台通常网关是所在网段的第一个地址
+    let local_ip = crate::nat::local_ipv4().ok()?;
+    if local_ip.is_unspecified() {
+        return None;
+    }
+    let octets = local_ip.octets();
+    Some(Ipv4Addr::new(octets[0], octets[1], octets[2], 1))
+}
+
+#[cfg(target_os = "linux")]
+fn linux_default_gateway() -> Option<Ipv4Addr> {
+    let route = std::fs::read_to_string("/proc/net/route").ok()?;
+    for line in route.lines().skip(1) {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.len() < 3 || fields[1] != "00000000" {
+            continue;
+        }
+        let gateway = u32::from_str_radix(fields[2], 16).ok()?;
+        if gateway != 0 {
+            // 小端序
+            return Some(Ipv4Addr::from(gateway.to_le_bytes()));
+        }
+    }
+    None
+}
+
+/// 依次尝试PCP、NAT-PMP、UPnP-IGD
+pub async fn map(
+    gateway: Option<Ipv4Addr>,
+    local_ip: Ipv4Addr,
+    internal_port: u16,
+) -> io::Result<Mapping> {
+    if let Some(gateway) = gateway {
+        let gateway = SocketAddr::V4(SocketAddrV4::new(gateway, NAT_PMP_PORT));
+        match pcp_map(gateway, local_ip, internal_port, 0, LIFETIME, None).await {
+            Ok(mapping) => return Ok(mapping),
+            Err(e) => log::info!("PCP端口映射失败:{}", e),
+        }
+        match nat_pmp_map(gateway, internal_port, 0, LIFETIME).await {
+            Ok(mapping) => return Ok(mapping),
+            Err(e) => log::info!("NAT-PMP端口映射失败:{}", e),
+        }
+    }
+    let igd = Igd::discover(SSDP_ADDR.parse().unwrap()).await?;
+    igd.add_port_mapping(local_ip, internal_port, internal_port, LIFETIME)
+        .await
+}
+
+/// 续期，尽量保持相同的外部端口
+pub async fn renew(mapping: &Mapping) -> io::Result<Mapping> {
+    let external_port = mapping.external.port();
+    match &mapping.kind {
+        MappingKind::Pcp {
+            gateway,
+            local_ip,
+            nonce,
+        } => {
+            pcp_map(
+                *gateway,
+                *local_ip,
+                mapping.internal_port,
+                external_port,
+                LIFETIME,
+                Some(*nonce),
+            )
+            .await
+        }
+        MappingKind::NatPmp { gateway } => {
+            nat_pmp_map(*gateway, mapping.internal_port, external_port, LIFETIME).await
+        }
+        MappingKind::Upnp(igd) => {
+            let local_ip = igd.local_ip;
+            igd.add_port_mapping(local_ip, mapping.internal_port, external_port, LIFETIME)
+                .await
+        }
+    }
+}
+
+/// 删除映射，有效期为0即删除
+pub async fn delete(mapping: &Mapping) -> io::Result<()> {
+    match &mapping.kind {
+        MappingKind::Pcp {
+            gateway,
+            local_ip,
+            nonce,
+        } => {
+            pcp_map(
+                *gateway,
+                *local_ip,
+                mapping.internal_port,
+                0,
+                Duration::ZERO,
+                Some(*nonce),
+            )
+            .await?;
+        }
+        MappingKind::NatPmp { gateway } => {
+            nat_pmp_map(*gateway, mapping.internal_port, 0, Duration::ZERO).await?;
+        }
+        MappingKind::Upnp(igd) => {
+            igd.delete_port_mapping(mapping.external.port()).await?;
+        }
+    }
+    Ok(())
+}
+
+/// 发送请求并按RFC 6886的建议重传，超时时间每次加倍
+async fn udp_request(
+    gateway: SocketAddr,
+    request: &[u8],
+    check: impl Fn(&[u8]) -> bool,
+) -> io::Result<Vec<u8>> {
+    let udp = UdpSocket::bind("0.0.0.0:0").await?;
+    udp.connect(gateway).await?;
+    let mut buf = [0u8; 1100];
+    let mut timeout = Duration::from_millis(250);
+    for _ in 0..4 {
+        udp.send(request).await?;
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            match tokio::time::timeout_at(deadline, udp.recv(&mut buf)).await {
+                Ok(Ok(len)) => {
+                    if check(&buf[..len]) {
+                        return Ok(buf[..len].to_vec());
+                    }
+                }
+                Ok(Err(e)) => return Err(e),
+                Err(_) => break,
+            }
+        }
+        timeout *= 2;
+    }
+    Err(io::Error::new(io::ErrorKind::TimedOut, "gateway timeout"))
+}
+
+/// PCP MAP请求(RFC 6887)
+async fn pcp_map(
+    gateway: SocketAddr,
+    local_ip: Ipv4Addr,
+    internal_port: u16,
+    external_port: u16,
+    lifetime: Duration,
+    nonce: Option<[u8; 12]>,
+) -> io::Result<Mapping> {
+    let nonce = nonce.unwrap_or_else(|| rand::thread_rng().gen());
+    let mut request = [0u8; 60];
+    request[0] = 2;
+    request[1] = 1;
+    request[4..8].copy_from_slice(&(lifetime.as_secs() as u32).to_be_bytes());
+    request[8..24].copy_from_slice(&local_ip.to_ipv6_mapped().octets());
+    request[24..36].copy_from_slice(&nonce);
+    request[36] = UDP_PROTOCOL;
+    request[40..42].copy_from_slice(&internal_port.to_be_bytes());
+    request[42..44].copy_from_slice(&external_port.to_be_bytes());
+    request[44..60].copy_from_slice(&Ipv4Addr::UNSPECIFIED.to_ipv6_mapped().octets());
+    let response = udp_request(gateway, &request, |buf| {
+        // 只支持NAT-PMP的网关会回复版本号0
+        buf.len() >= 4 && (buf[0] == 0 || buf[1] == 0x81)
+    })
+    .await?;
+    if response[0] != 2 {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "PCP unsupported",
+        ));
+    }
+    if response[3] != 0 {
+        return Err(io::Error::other(format!("PCP result code {}", response[3])));
+    }
+    if response.len() < 60 || response[24..36] != nonce {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "PCP nonce"));
+    }
+    let lifetime = u32::from_be_bytes(response[4..8].try_into().unwrap());
+    let port = u16::from_be_bytes(response[42..44].try_into().unwrap());
+    let ip: [u8; 16] = response[44..60].try_into().unwrap();
+    let ip = match Ipv6Addr::from(ip).to_ipv4_mapped() {
+        Some(ip) => ip,
+        None => {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "PCP not ipv4"));
+        }
+    };
+    Ok(Mapping {
+        protocol: MappingProtocol::Pcp,
+        external: SocketAddrV4::new(ip, port),
+        internal_port,
+        lifetime: Duration::from_secs(lifetime as u64),
+        kind: MappingKind::Pcp {
+            gateway,
+            local_ip,
+            nonce,
+        },
+    })
+}
+
+/// NAT-PMP映射请求(RFC 6886)，外部地址需要单独获取
+async fn nat_pmp_map(
+    gateway: SocketAddr,
+    internal_port: u16,
+    external_port: u16,
+    lifetime: Duration,
+) -> io::Result<Mapping> {
+    let mut request = [0u8; 12];
+    request[1] = 1;
+    request[4..6].copy_from_slice(&internal_port.to_be_bytes());
+    request[6..8].copy_from_slice(&external_port.to_be_bytes());
+    request[8..12].copy_from_slice(&(lifetime.as_secs() as u32).to_be_bytes());
+    let response = udp_request(gateway, &request, |buf| {
+        buf.len() >= 16 && buf[0] == 0 && buf[1] == 129
+    })
+    .await?;
+    let result = u16::from_be_bytes(response[2..4].try_into().unwrap());
+    if result != 0 {
+        return Err(io::Error::other(format!("NAT-PMP result code {}", result)));
+    }
+    let port = u16::from_be_bytes(response[10..12].try_into().unwrap());
+    let lifetime = u32::from_be_bytes(response[12..16].try_into().unwrap());
+    let ip = if lifetime == 0 {
+        Ipv4Addr::UNSPECIFIED
+    } else {
+        nat_pmp_external_ip(gateway).await?
+    };
+    Ok(Mapping {
+        protocol: MappingProtocol::NatPmp,
+        external: SocketAddrV4::new(ip, port),
+        internal_port,
+        lifetime: Duration::from_secs(lifetime as u64),
+        kind: MappingKind::NatPmp { gateway },
+    })
+}
+
+async fn nat_pmp_external_ip(gateway: SocketAddr) -> io::Result<Ipv4Addr> {
+    let response = udp_request(gateway, &[0, 0], |buf| {
+        buf.len() >= 12 && buf[0] == 0 && buf[1] == 128
+    })
+    .await?;
+    let result = u16::from_be_bytes(response[2..4].try_into().unwrap());
+    if result != 0 {
+        return Err(io::Error::other(format!("NAT-PMP result code {}", result)));
+    }
+    Ok(Ipv4Addr::new(
+        response[8],
+        response[9],
+        response[10],
+        response[11],
+    ))
+}
+
+/// UPnP网关设备
+#[derive(Clone, Debug)]
+pub struct Igd {
+    control_addr: SocketAddr,
+    host: String,
+    control_path: String,
+    service_type: String,
+    local_ip: Ipv4Addr,
+}
+
+impl Igd {
+    /// 通过SSDP发现网关，并从设备描述中找到WANIPConnection服务
+    pub async fn discover(ssdp_addr: SocketAddr) -> io::Result<Igd> {
+        let udp = UdpSocket::bind("0.0.0.0:0").await?;
+        let request = format!(
+            "M-SEARCH * HTTP/1.1\r\nHOST: {}\r\nST: urn:schemas-upnp-org:device:InternetGatewayDevice:1\r\nMAN: \"ssdp:discover\"\r\nMX: 2\r\n\r\n",
+            SSDP_ADDR
+        );
+        let mut buf = [0u8; 2048];
+        let mut location = None;
+        'a: for _ in 0..2 {
+            udp.send_to(request.as_bytes(), ssdp_addr).await?;
+            let deadline = tokio::time::Instant::now() + Duration::from_secs(2);
+            while let Ok(rs) = tokio::time::timeout_at(deadline, udp.recv_from(&mut buf)).await {
+                let (len, _) = rs?;
+                let response = String::from_utf8_lossy(&buf[..len]);
+                if let Some(v) = header(&response, "location") {
+                    location = Some(v.to_string());
+                    break 'a;
+                }
+            }
+        }
+        let location = match location {
+            Some(location) => location,
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    "UPnP gateway not found",
+                ));
+            }
+        };
+        let (control_addr, host, path) = parse_url(&location).await?;
+        let (status, description) =
+            http_request(control_addr, &host, "GET", &path, &[], "").await?;
+        if status != 200 {
+            return Err(io::Error::other(format!(
+                "UPnP description status {}",
+                status
+            )));
+        }
+        let (service_type, control_url) = find_service(&description).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotFound, "UPnP WANIPConnection not found")
+        })?;
+        let (control_addr, host, control_path) = if control_url.starts_with("http") {
+            parse_url(&control_url).await?
+        } else if control_url.starts_with('/') {
+            (control_addr, host, control_url)
+        } else {
+            (control_addr, host, format!("/{}", control_url))
+        };
+        // 使用连接网关的本地地址作为映射的内部地址
+        let local_ip = {
+            let tcp = TcpStream::connect(control_addr).await?;
+            match tcp.local_addr()?.ip() {
+                IpAddr::V4(ip) => ip,
+                IpAddr::V6(_) => {
+                    return Err(io::Error::other("UPnP not ipv4"));
+                }
+            }
+        };
+        Ok(Igd {
+            control_addr,
+            host,
+            control_path,
+            service_type,
+            local_ip,
+        })
+    }
+    async fn soap(&self, action: &str, args: &[(&str, String)]) -> io::Result<String> {
+        let mut body = format!(
+            "<?xml version=\"1.0\"?><s:Envelope xmlns:s=\"http://schemas.xmlsoap.org/soap/envelope/\" s:encodingStyle=\"http://schemas.xmlsoap.org/soap/encoding/\"><s:Body><u:{} xmlns:u=\"{}\">",
+            action, self.service_type
+        );
+        for (k, v) in args {
+            body.push_str(&format!("<{}>{}</{}>", k, v, k));
+        }
+        body.push_str(&format!("</u:{}></s:Body></s:Envelope>", action));
+        let soap_action = format!("\"{}#{}\"", self.service_type, action);
+        let headers = [
+            ("Content-Type", "text/xml; charset=\"utf-8\""),
+            ("SOAPAction", soap_action.as_str()),
+        ];
+        let (status, response) = http_request(
+            self.control_addr,
+            &self.host,
+            "POST",
+            &self.control_path,
+            &headers,
+            &body,
+        )
+        .await?;
+        if status != 200 {
+            let code = tag(&response, "errorCode").unwrap_or_default();
+            return Err(io::Error::other(format!(
+                "UPnP {} status {} error {}",
+                action, status, code
+            )));
+        }
+        Ok(response)
+    }
+    pub async fn external_ip(&self) -> io::Result<Ipv4Addr> {
+        let response = self.soap("GetExternalIPAddress", &[]).await?;
+        tag(&response, "NewExternalIPAddress")
+            .and_then(|v| v.trim().parse().ok())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "NewExternalIPAddress"))
+    }
+    pub async fn add_port_mapping(
+        &self,
+        local_ip: Ipv4Addr,
+        internal_port: u16,
+        external_port: u16,
+        lifetime: Duration,
+    ) -> io::Result<Mapping> {
+        let external_port = if external_port == 0 {
+            internal_port
+        } else {
+            external_port
+        };
+        let mut args = vec![
+            ("NewRemoteHost", String::new()),
+            ("NewExternalPort", external_port.to_string()),
+            ("NewProtocol", "UDP".to_string()),
+            ("NewInternalPort", internal_port.to_string()),
+            ("NewInternalClient", local_ip.to_string()),
+            ("NewEnabled", "1".to_string()),
+            ("NewPortMappingDescription", "vnt".to_string()),
+            ("NewLeaseDuration", lifetime.as_secs().to_string()),
+        ];
+        let mut lifetime = lifetime;
+        if let Err(e) = self.soap("AddPortMapping", &args).await {
+            // 有些网关只支持永久映射(错误码725)
+            log::info!("UPnP映射失败,尝试永久映射:{}", e);
+            args[7].1 = "0".to_string();
+            lifetime = Duration::ZERO;
+            self.soap("AddPortMapping", &args).await?;
+        }
+        let ip = self.external_ip().await?;
+        Ok(Mapping {
+            protocol: MappingProtocol::Upnp,
+            external: SocketAddrV4::new(ip, external_port),
+            internal_port,
+            lifetime,
+            kind: MappingKind::Upnp(self.clone()),
+        })
+    }
+    pub async fn delete_port_mapping(&self, external_port: u16) -> io::Result<()> {
+        let args = [
+            ("NewRemoteHost", String::new()),
+            ("NewExternalPort", external_port.to_string()),
+            ("NewProtocol", "UDP".to_string()),
+        ];
+        self.soap("DeletePortMapping", &args).await?;
+        Ok(())
+    }
+}
+
+fn header<'a>(response: &'a str, name: &str) -> Option<&'a str> {
+    for line in response.lines() {
+        if let Some((k, v)) = line.split_once(':') {
+            if k.trim().eq_ignore_ascii_case(name) {
+                return Some(v.trim());
+            }
+        }
+    }
+    None
+}
+
+fn tag(xml: &str, name: &str) -> Option<String> {
+    let start = format!("<{}>", name);
+    let end = format!("</{}>", name);
+    let begin = xml.find(&start)? + start.len();
+    let len = xml[begin..].find(&end)?;
+    Some(xml[begin..begin + len].to_string())
+}
+
+/// 返回(服务类型,控制地址)
+fn find_service(description: &str) -> Option<(String, String)> {
+    for service_type in IGD_SERVICE_TYPES {
+        for service in description.split("<service>").skip(1) {
+            let service = service.split("</service>").next().unwrap_or_default();
+            if tag(service, "serviceType").as_deref().map(str::trim) == Some(service_type) {
+                let control_url = tag(service, "controlURL")?;
+                return Some((service_type.to_string(), control_url.trim().to_string()));
+            }
+        }
+    }
+    None
+}
+
+/// http://host:port/path 返回(地址,Host头,路径)
+async fn parse_url(url: &str) -> io::Result<(SocketAddr, String, String)> {
+    let rest = url
+        .strip_prefix("http://")
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("url {}", url)))?;
+    let (host, path) = match rest.find('/') {
+        Some(index) => (&rest[..index], &rest[index..]),
+        None => (rest, "/"),
+    };
+    let addr = if host.contains(':') {
+        host.to_string()
+    } else {
+        format!("{}:80", host)
+    };
+    let addr = tokio::net::lookup_host(addr)
+        .await?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("url {}", url)))?;
+    Ok((addr, host.to_string(), path.to_string()))
+}
+
+/// 简单的http请求，使用短连接读取全部响应
+async fn http_request(
+    addr: SocketAddr,
+    host: &str,
+    method: &str,
+    path: &str,
+    headers: &[(&str, &str)],
+    body: &str,
+) -> io::Result<(u16, String)> {
+    let mut request = format!(
+        "{} {} HTTP/1.1\r\nHost: {}\r\nConnection: close\r\nContent-Length: {}\r\n",
+        method,
+        path,
+        host,
+        body.len()
+    );
+    for (k, v) in headers {
+        request.push_str(&format!("{}: {}\r\n", k, v));
+    }
+    request.push_str("\r\n");
+    request.push_str(body);
+    let rs = tokio::time::timeout(Duration::from_secs(5), async {
+        let mut tcp = TcpStream::connect(addr).await?;
+        tcp.write_all(request.as_bytes()).await?;
+        let mut response = Vec::new();
+        tcp.read_to_end(&mut response).await?;
+        Ok::<Vec<u8>, io::Error>(response)
+    })
+    .await;
+    let response = match rs {
+        Ok(rs) => rs?,
+        Err(_) => return Err(io::Error::new(io::ErrorKind::TimedOut, "http timeout")),
+    };
+    let response = String::from_utf8_lossy(&response).to_string();
+    let (head, body) = response
+        .split_once("\r\n\r\n")
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "http response"))?;
+    let status = head
+        .split_whitespace()
+        .nth(1)
+        .and_then(|v| v.parse().ok())
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "http status"))?;
+    let body = match header(head, "transfer-encoding") {
+        Some(v) if v.eq_ignore_ascii_case("chunked") => dechunk(body),
+        _ => body.to_string(),
+    };
+    Ok((status, body))
+}
+
+fn dechunk(mut body: &str) -> String {
+    let mut rs = String::new();
+    while let Some((size, rest)) = body.split_once("\r\n") {
+        let size = match usize::from_str_radix(size.trim(), 16) {
+            Ok(size) => size,
+            Err(_) => break,
+        };
+        if size == 0 || rest.len() < size {
+            break;
+        }
+        rs.push_str(&rest[..size]);
+        body = rest[size..].trim_start_matches("\r\n");
+    }
+    rs
+}
+
+/// 端口映射任务，映射成功后在打洞信息中通告映射地址，到期前续期，停止时删除映射
+pub fn start(mut worker: VntWorker, nat_test: NatTest, internal_port: u16) {
+    tokio::spawn(async move {
+        let mut mapping = None;
+        tokio::select! {
+             _=worker.stop_wait()=>{}
+            rs=start_(&nat_test, internal_port, &mut mapping)=>{
+                if let Err(e) = rs {
+                    log::warn!("端口映射任务停止:{:?}", e);
+                }
+            }
+        }
+        if let Some(mapping) = mapping {
+            match tokio::time::timeout(Duration::from_secs(3), delete(&mapping)).await {
+                Ok(Ok(_)) => log::info!("删除端口映射 {:?}", mapping.external),
+                Ok(Err(e)) => log::warn!("删除端口映射失败:{}", e),
+                Err(_) => log::warn!("删除端口映射超时"),
+            }
+        }
+        worker.stop_all();
+    });
+}
+
+async fn start_(
+    nat_test: &NatTest,
+    internal_port: u16,
+    mapping: &mut Option<Mapping>,
+) -> io::Result<()> {
+    log::info!("启动端口映射任务");
+    let local_ip = crate::nat::local_ipv4()?;
+    loop {
+        let rs = match mapping.as_ref() {
+            Some(old) => renew(old).await,
+            None => map(default_gateway(), local_ip, internal_port).await,
+        };
+        let wait = match rs {
+            Ok(new) => {
+                if new.external.ip().is_private() || new.external.ip().is_unspecified() {
+                    // 多层NAT时映射的地址仍然是内网地址，没有意义
+                    log::info!("端口映射的外部地址不是公网地址:{:?}", new);
+                    nat_test.update_mapped_addr(None);
+                    *mapping = Some(new);
+                    RETRY_INTERVAL
+                } else {
+                    if mapping.as_ref().map(|v| v.external) != Some(new.external) {
+                        log::info!("端口映射成功 {:?}", new);
+                    }
+                    nat_test.update_mapped_addr(Some(new.external));
+                    // 永久映射也定期检查
+                    let wait = if new.lifetime.is_zero() {
+                        LIFETIME / 2
+                    } else {
+                        new.lifetime / 2
+                    };
+                    *mapping = Some(new);
+                    wait.max(Duration::from_secs(30))
+                }
+            }
+            Err(e) => {
+                log::info!("端口映射失败:{}", e);
+                nat_test.update_mapped_addr(None);
+                *mapping = None;
+                RETRY_INTERVAL
+            }
+        };
+        tokio::time::sleep(wait).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 模拟NAT-PMP网关，映射到1.2.3.4的相同端口
+    async fn mock_nat_pmp() -> SocketAddr {
+        let udp = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let addr = udp.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 1100];
+            loop {
+                let (len, peer) = udp.recv_from(&mut buf).await.unwrap();
+                let request = &buf[..len];
+                let response = if request[0] == 2 {
+                    // 不支持PCP
+                    let mut response = [0u8; 8];
+                    response[1] = 0x81;
+                    response[3] = 1;
+                    response.to_vec()
+                } else if request[1] == 0 {
+                    vec![0, 128, 0, 0, 0, 0, 0, 1, 1, 2, 3, 4]
+                } else {
+                    let mut response = vec![0u8; 16];
+                    response[1] = 129;
+                    response[8..10].copy_from_slice(&request[4..6]);
+                    response[10..12].copy_from_slice(&request[4..6]);
+                    response[12..16].copy_from_slice(&request[8..12]);
+                    response
+                };
+                udp.send_to(&response, peer).await.unwrap();
+            }
+        });
+        addr
+    }
+
+    #[tokio::test]
+    async fn nat_pmp() {
+        let gateway = mock_nat_pmp().await;
+        let err = pcp_map(gateway, Ipv4Addr::LOCALHOST, 5000, 0, LIFETIME, None).await;
+        assert!(err.is_err());
+        let mapping = nat_pmp_map(gateway, 5000, 0, LIFETIME).await.unwrap();
+        assert_eq!(mapping.protocol, MappingProtocol::NatPmp);
+        assert_eq!(
+            mapping.external,
+            SocketAddrV4::new(Ipv4Addr::new(1, 2, 3, 4), 5000)
+        );
+        assert_eq!(mapping.lifetime, LIFETIME);
+        delete(&mapping).await.unwrap();
+    }
+
+    /// 模拟IGD，SSDP响应和http服务
+    async fn mock_igd() -> SocketAddr {
+        let tcp = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let http_addr = tcp.local_addr().unwrap();
+        tokio::spawn(async move {
+            loop {
+                let (mut stream, _) = tcp.accept().await.unwrap();
+                let mut buf = vec![0u8; 4096];
+                let len = stream.read(&mut buf).await.unwrap();
+                let request = String::from_utf8_lossy(&buf[..len]).to_string();
+                let body = if request.starts_with("GET") {
+                    "<root><device><serviceList><service><serviceType>urn:schemas-upnp-org:service:WANIPConnection:1</serviceType><controlURL>/ctl</controlURL></service></serviceList></device></root>".to_string()
+                } else if request.contains("GetExternalIPAddress") {
+                    "<NewExternalIPAddress>1.2.3.4</NewExternalIPAddress>".to_string()
+                } else {
+                    String::new()
+                };
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                stream.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        let udp = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let ssdp_addr = udp.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buf = [0u8; 1024];
+            loop {
+                let (_, peer) = udp.recv_from(&mut buf).await.unwrap();
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nLOCATION: http://{}/desc.xml\r\n\r\n",
+                    http_addr
+                );
+                udp.send_to(response.as_bytes(), peer).await.unwrap();
+            }
+        });
+        ssdp_addr
+    }
+
+    #[tokio::test]
+    async fn upnp() {
+        let ssdp_addr = mock_igd().await;
+        let igd = Igd::discover(ssdp_addr).await.unwrap();
+        assert_eq!(igd.control_path, "/ctl");
+        let mapping = igd
+            .add_port_mapping(Ipv4Addr::LOCALHOST, 5000, 0, LIFETIME)
+            .await
+            .unwrap();
+        assert_eq!(mapping.protocol, MappingProtocol::Upnp);
+        assert_eq!(
+            mapping.external,
+            SocketAddrV4::new(Ipv4Addr::new(1, 2, 3, 4), 5000)
+        );
+        delete(&mapping).await.unwrap();
+    }
+}
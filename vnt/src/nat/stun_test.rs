@@ -3,11 +3,18 @@ use std::io;
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
 use std::time::Duration;
 
-use crate::channel::punch::NatType;
+use crate::channel::punch::{NatBehavior, NatDependency, NatType, PortAllocation};
 use stun_format::Attr;
 use tokio::net::UdpSocket;
 
-pub async fn stun_test_nat(stun_servers: Vec<String>) -> io::Result<(NatType, Vec<Ipv4Addr>, u16)> {
+/// RFC 5780 OTHER-ADDRESS，不支持时使用RFC 3489 CHANGED-ADDRESS
+const ATTR_OTHER_ADDRESS: u16 = 0x802C;
+/// 相邻映射端口差在这个范围内才认为是顺序分配
+const MAX_PORT_STEP: u16 = 32;
+
+pub async fn stun_test_nat(
+    stun_servers: Vec<String>,
+) -> io::Result<(NatType, Vec<Ipv4Addr>, u16, NatBehavior)> {
     let mut h = Vec::new();
     for x in stun_servers {
         let handle = tokio::spawn(test_nat(x));
@@ -16,9 +23,10 @@ pub async fn stun_test_nat(stun_servers: Vec<String>) -> io::Result<(NatType, Ve
     let mut nat_type = NatType::Cone;
     let mut port_range = 0;
     let mut hash_set = HashSet::new();
+    let mut behavior = NatBehavior::default();
     for x in h {
         if let Ok(rs) = x.await {
-            if let Ok((nat_type_t, ip_list_t, port_range_t)) = rs {
+            if let Ok((nat_type_t, ip_list_t, port_range_t, behavior_t)) = rs {
                 if nat_type_t == NatType::Symmetric {
                     nat_type = NatType::Symmetric;
                 }
@@ -28,48 +36,203 @@ pub async fn stun_test_nat(stun_servers: Vec<String>) -> io::Result<(NatType, Ve
                 if port_range < port_range_t {
                     port_range = port_range_t;
                 }
+                merge_behavior(&mut behavior, behavior_t);
+            }
+        }
+    }
+    Ok((
+        nat_type,
+        hash_set.into_iter().collect(),
+        port_range,
+        behavior,
+    ))
+}
+
+/// 多个服务器的结果取最严格的
+fn merge_behavior(behavior: &mut NatBehavior, other: NatBehavior) {
+    behavior.mapping = behavior.mapping.max(other.mapping);
+    behavior.filtering = behavior.filtering.max(other.filtering);
+    behavior.hairpin |= other.hairpin;
+    match (behavior.port_allocation, other.port_allocation) {
+        (PortAllocation::Random, _) | (_, PortAllocation::Unknown) => {}
+        (PortAllocation::Sequential, PortAllocation::Sequential) => {
+            if other.port_step.unsigned_abs() < behavior.port_step.unsigned_abs() {
+                behavior.port_step = other.port_step;
             }
         }
+        (PortAllocation::Sequential, PortAllocation::Preserving) => {}
+        (_, allocation) => {
+            behavior.port_allocation = allocation;
+            behavior.port_step = other.port_step;
+        }
     }
-    Ok((nat_type, hash_set.into_iter().collect(), port_range))
 }
 
-async fn test_nat(stun_server: String) -> io::Result<(NatType, Vec<Ipv4Addr>, u16)> {
+async fn test_nat(stun_server: String) -> io::Result<(NatType, Vec<Ipv4Addr>, u16, NatBehavior)> {
+    let server = match tokio::net::lookup_host(&stun_server)
+        .await?
+        .find(|addr| addr.is_ipv4())
+    {
+        Some(server) => server,
+        None => {
+            return Err(io::Error::other(format!(
+                "stun server {} not ipv4",
+                stun_server
+            )));
+        }
+    };
     let udp = UdpSocket::bind("0.0.0.0:0").await?;
-    udp.connect(stun_server).await?;
     let mut nat_type = NatType::Cone;
     let mut port_range = 0;
     let mut hash_set = HashSet::new();
-    match test_nat_(&udp, true, true).await {
-        Ok((mapped_addr1, changed_addr1)) => {
-            match mapped_addr1.ip() {
-                IpAddr::V4(ip) => {
-                    hash_set.insert(ip);
-                }
-                IpAddr::V6(_) => {}
+    let mut behavior = NatBehavior::default();
+    let (mapped_addr1, other_addr) = match test_nat_(&udp, server, false, false).await {
+        Ok(rs) => rs,
+        Err(_) => {
+            return Ok((nat_type, Vec::new(), port_range, behavior));
+        }
+    };
+    insert_ip(&mut hash_set, mapped_addr1);
+    // 按分配的先后顺序记录公网端口
+    let mut ports = vec![mapped_addr1.port()];
+    let mut preserving = mapped_addr1.port() == udp.local_addr()?.port();
+    if other_addr.ip() != server.ip() && other_addr.port() != server.port() {
+        // 映射规则，向 备用ip:主端口 和 备用ip:备用端口 发送请求，比较映射的地址
+        let mapped_addr2 = test_nat_(
+            &udp,
+            SocketAddr::new(other_addr.ip(), server.port()),
+            false,
+            false,
+        )
+        .await
+        .map(|(addr, _)| addr)
+        .ok();
+        let mapped_addr3 = if mapped_addr2 == Some(mapped_addr1) {
+            Some(mapped_addr1)
+        } else {
+            test_nat_(&udp, other_addr, false, false)
+                .await
+                .map(|(addr, _)| addr)
+                .ok()
+        };
+        for addr in [mapped_addr2, mapped_addr3].into_iter().flatten() {
+            insert_ip(&mut hash_set, addr);
+            port_range = port_range.max(addr.port().abs_diff(mapped_addr1.port()));
+            if !ports.contains(&addr.port()) {
+                ports.push(addr.port());
+            }
+        }
+        behavior.mapping = match (mapped_addr2, mapped_addr3) {
+            (Some(addr2), _) if addr2 == mapped_addr1 => NatDependency::EndpointIndependent,
+            (Some(addr2), Some(addr3)) if addr2 == addr3 => NatDependency::AddressDependent,
+            (Some(_), Some(_)) => NatDependency::AddressAndPortDependent,
+            (None, Some(addr3)) if addr3 == mapped_addr1 => NatDependency::EndpointIndependent,
+            _ => NatDependency::Unknown,
+        };
+        if let Some(addr3) = mapped_addr3 {
+            if addr3 != mapped_addr1 {
+                nat_type = NatType::Symmetric;
             }
-            if udp.connect(changed_addr1).await.is_ok() {
-                if let Ok((mapped_addr2, _)) = test_nat_(&udp, false, false).await {
-                    match mapped_addr2.ip() {
-                        IpAddr::V4(ip) => {
-                            hash_set.insert(ip);
-                        }
-                        IpAddr::V6(_) => {}
-                    }
-                    port_range = mapped_addr2.port().abs_diff(mapped_addr1.port());
-                    if mapped_addr1 != mapped_addr2 {
-                        nat_type = NatType::Symmetric;
-                    }
+        }
+        // 过滤规则，使用新的端口，避免上面的请求影响结果
+        if let Ok(udp) = UdpSocket::bind("0.0.0.0:0").await {
+            if let Ok((mapped_addr, _)) = test_nat_(&udp, server, false, false).await {
+                preserving &= mapped_addr.port() == udp.local_addr()?.port();
+                if !ports.contains(&mapped_addr.port()) {
+                    ports.push(mapped_addr.port());
                 }
+                behavior.filtering = test_filtering(&udp, server).await;
             }
         }
-        Err(_) => {}
     }
-    Ok((nat_type, hash_set.into_iter().collect(), port_range))
+    if preserving {
+        behavior.port_allocation = PortAllocation::Preserving;
+    } else if let Some(step) = port_step(&ports) {
+        behavior.port_allocation = PortAllocation::Sequential;
+        behavior.port_step = step;
+    } else if ports.len() > 1 {
+        behavior.port_allocation = PortAllocation::Random;
+    }
+    behavior.hairpin = test_hairpin(&udp, mapped_addr1).await;
+    Ok((
+        nat_type,
+        hash_set.into_iter().collect(),
+        port_range,
+        behavior,
+    ))
+}
+
+fn insert_ip(hash_set: &mut HashSet<Ipv4Addr>, addr: SocketAddr) {
+    if let IpAddr::V4(ip) = addr.ip() {
+        hash_set.insert(ip);
+    }
+}
+
+/// 端口差都同号并且不大时认为是顺序分配，中间可能夹杂其他连接的映射，取最小的差值作为步长
+fn port_step(ports: &[u16]) -> Option<i16> {
+    if ports.len() < 2 {
+        return None;
+    }
+    let steps: Vec<i32> = ports
+        .windows(2)
+        .map(|v| v[1] as i32 - v[0] as i32)
+        .collect();
+    let same_sign = steps.iter().all(|v| *v > 0) || steps.iter().all(|v| *v < 0);
+    if !same_sign
+        || steps
+            .iter()
+            .any(|v| v.unsigned_abs() > MAX_PORT_STEP as u32)
+    {
+        return None;
+    }
+    steps
+        .into_iter()
+        .min_by_key(|v| v.unsigned_abs())
+        .map(|v| v as i16)
 }
 
+/// 请求服务端从其他地址回复，能收到则说明NAT放行了这些地址
+async fn test_filtering(udp: &UdpSocket, server: SocketAddr) -> NatDependency {
+    if test_nat_(udp, server, true, true).await.is_ok() {
+        return NatDependency::EndpointIndependent;
+    }
+    if test_nat_(udp, server, false, true).await.is_ok() {
+        return NatDependency::AddressDependent;
+    }
+    NatDependency::AddressAndPortDependent
+}
+
+/// 从另一个本地端口向映射地址发送数据，能收到说明支持回环
+async fn test_hairpin(udp: &UdpSocket, mapped_addr: SocketAddr) -> bool {
+    if !mapped_addr.is_ipv4() {
+        return false;
+    }
+    let sender = match UdpSocket::bind("0.0.0.0:0").await {
+        Ok(sender) => sender,
+        Err(_) => return false,
+    };
+    let token: [u8; 16] = rand::random();
+    for _ in 0..2 {
+        if sender.send_to(&token, mapped_addr).await.is_err() {
+            return false;
+        }
+        let mut buf = [0u8; 1500];
+        let deadline = tokio::time::Instant::now() + Duration::from_millis(300);
+        while let Ok(Ok((len, _))) =
+            tokio::time::timeout_at(deadline, udp.recv_from(&mut buf)).await
+        {
+            if buf[..len] == token {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+/// 返回映射地址和服务端的备用地址
 async fn test_nat_(
     udp: &UdpSocket,
+    server: SocketAddr,
     change_ip: bool,
     change_port: bool,
 ) -> io::Result<(SocketAddr, SocketAddr)> {
@@ -77,52 +240,78 @@ async fn test_nat_(
         let mut buf = [0u8; 28];
         let mut msg = stun_format::MsgBuilder::from(buf.as_mut_slice());
         msg.typ(stun_format::MsgType::BindingRequest).unwrap();
-        msg.tid(1).unwrap();
+        msg.tid(rand::random()).unwrap();
         msg.add_attr(Attr::ChangeRequest {
             change_ip,
             change_port,
         })
         .unwrap();
-        udp.send(msg.as_bytes()).await?;
+        let request = msg.as_bytes();
+        let tid: [u8; 12] = request[8..20].try_into().unwrap();
+        udp.send_to(request, server).await?;
+        let deadline = tokio::time::Instant::now() + Duration::from_millis(300);
         let mut buf = [0; 10240];
-        let (len, addr) =
-            match tokio::time::timeout(Duration::from_millis(300), udp.recv_from(&mut buf)).await {
+        loop {
+            let (len, addr) = match tokio::time::timeout_at(deadline, udp.recv_from(&mut buf)).await
+            {
                 Ok(rs) => rs?,
                 Err(_) => {
-                    continue;
+                    break;
                 }
             };
-        let msg = stun_format::Msg::from(&buf[..len]);
-        let mut mapped_addr = None;
-        let mut changed_addr = None;
-        for x in msg.attrs_iter() {
-            match x {
-                Attr::MappedAddress(addr) => {
-                    if mapped_addr.is_none() {
-                        let _ = mapped_addr.insert(stun_addr(addr));
-                    }
-                }
-                Attr::ChangedAddress(addr) => {
-                    if changed_addr.is_none() {
-                        let _ = changed_addr.insert(stun_addr(addr));
-                    }
-                }
-                Attr::XorMappedAddress(addr) => {
-                    if mapped_addr.is_none() {
-                        let _ = mapped_addr.insert(stun_addr(addr));
-                    }
-                }
-                _ => {}
+            if len < 20 || buf[8..20] != tid {
+                continue;
             }
-            if changed_addr.is_some() && mapped_addr.is_some() {
-                return Ok((mapped_addr.unwrap(), changed_addr.unwrap()));
+            // 确认是从要求的地址回复的，服务端不支持变更地址时会从原地址回复
+            if change_ip == (addr.ip() == server.ip())
+                || (change_port && !change_ip && addr.port() == server.port())
+            {
+                continue;
+            }
+            if let Some(rs) = parse_response(&buf[..len], addr) {
+                return Ok(rs);
             }
         }
-        if mapped_addr.is_some() {
-            return Ok((mapped_addr.unwrap(), changed_addr.unwrap_or(addr)));
+    }
+    Err(io::Error::other("stun response err"))
+}
+
+fn parse_response(buf: &[u8], addr: SocketAddr) -> Option<(SocketAddr, SocketAddr)> {
+    let msg = stun_format::Msg::from(buf);
+    let mut mapped_addr = None;
+    let mut changed_addr = other_address(buf);
+    for x in msg.attrs_iter() {
+        match x {
+            Attr::MappedAddress(addr) if mapped_addr.is_none() => {
+                let _ = mapped_addr.insert(stun_addr(addr));
+            }
+            Attr::ChangedAddress(addr) if changed_addr.is_none() => {
+                let _ = changed_addr.insert(stun_addr(addr));
+            }
+            Attr::XorMappedAddress(addr) if mapped_addr.is_none() => {
+                let _ = mapped_addr.insert(stun_addr(addr));
+            }
+            _ => {}
+        }
+    }
+    mapped_addr.map(|mapped_addr| (mapped_addr, changed_addr.unwrap_or(addr)))
+}
+
+/// 解析OTHER-ADDRESS属性，格式和MAPPED-ADDRESS相同
+fn other_address(buf: &[u8]) -> Option<SocketAddr> {
+    let mut index = 20;
+    while index + 4 <= buf.len() {
+        let typ = u16::from_be_bytes([buf[index], buf[index + 1]]);
+        let len = u16::from_be_bytes([buf[index + 2], buf[index + 3]]) as usize;
+        let value = buf.get(index + 4..index + 4 + len)?;
+        if typ == ATTR_OTHER_ADDRESS && len >= 8 && value[1] == 0x01 {
+            let port = u16::from_be_bytes([value[2], value[3]]);
+            let ip = Ipv4Addr::new(value[4], value[5], value[6], value[7]);
+            return Some(SocketAddr::V4(SocketAddrV4::new(ip, port)));
         }
+        index += 4 + len.div_ceil(4) * 4;
     }
-    Err(io::Error::new(io::ErrorKind::Other, "stun response err"))
+    None
 }
 
 fn stun_addr(addr: stun_format::SocketAddr) -> SocketAddr {
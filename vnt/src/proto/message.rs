@@ -1,4 +1,4 @@
-// This file is generated by rust-protobuf 3.2.0. Do not edit
+// This file is generated by rust-protobuf 3.7.2. Do not edit
 // .proto file is parsed by pure
 // @generated
 
@@ -9,7 +9,6 @@
 #![allow(unused_attributes)]
 #![cfg_attr(rustfmt, rustfmt::skip)]
 
-#![allow(box_pointers)]
 #![allow(dead_code)]
 #![allow(missing_docs)]
 #![allow(non_camel_case_types)]
@@ -23,10 +22,10 @@
 
 /// Generated files are compatible only with the same version
 /// of protobuf runtime.
-const _PROTOBUF_VERSION_CHECK: () = ::protobuf::VERSION_3_2_0;
+const _PROTOBUF_VERSION_CHECK: () = ::protobuf::VERSION_3_7_2;
 
-#[derive(PartialEq,Clone,Default,Debug)]
 // @@protoc_insertion_point(message:HandshakeRequest)
+#[derive(PartialEq,Clone,Default,Debug)]
 pub struct HandshakeRequest {
     // message fields
     // @@protoc_insertion_point(field:HandshakeRequest.version)
@@ -165,8 +164,8 @@ impl ::protobuf::reflect::ProtobufValue for HandshakeRequest {
     type RuntimeType = ::protobuf::reflect::rt::RuntimeTypeMessage<Self>;
 }
 
-#[derive(PartialEq,Clone,Default,Debug)]
 // @@protoc_insertion_point(message:HandshakeResponse)
+#[derive(PartialEq,Clone,Default,Debug)]
 pub struct HandshakeResponse {
     // message fields
     // @@protoc_insertion_point(field:HandshakeResponse.version)
@@ -341,8 +340,8 @@ impl ::protobuf::reflect::ProtobufValue for HandshakeResponse {
     type RuntimeType = ::protobuf::reflect::rt::RuntimeTypeMessage<Self>;
 }
 
-#[derive(PartialEq,Clone,Default,Debug)]
 // @@protoc_insertion_point(message:SecretHandshakeRequest)
+#[derive(PartialEq,Clone,Default,Debug)]
 pub struct SecretHandshakeRequest {
     // message fields
     // @@protoc_insertion_point(field:SecretHandshakeRequest.token)
@@ -481,8 +480,8 @@ impl ::protobuf::reflect::ProtobufValue for SecretHandshakeRequest {
     type RuntimeType = ::protobuf::reflect::rt::RuntimeTypeMessage<Self>;
 }
 
-#[derive(PartialEq,Clone,Default,Debug)]
 // @@protoc_insertion_point(message:RegistrationRequest)
+#[derive(PartialEq,Clone,Default,Debug)]
 pub struct RegistrationRequest {
     // message fields
     // @@protoc_insertion_point(field:RegistrationRequest.token)
@@ -729,8 +728,8 @@ impl ::protobuf::reflect::ProtobufValue for RegistrationRequest {
     type RuntimeType = ::protobuf::reflect::rt::RuntimeTypeMessage<Self>;
 }
 
-#[derive(PartialEq,Clone,Default,Debug)]
 // @@protoc_insertion_point(message:RegistrationResponse)
+#[derive(PartialEq,Clone,Default,Debug)]
 pub struct RegistrationResponse {
     // message fields
     // @@protoc_insertion_point(field:RegistrationResponse.virtual_ip)
@@ -749,6 +748,10 @@ pub struct RegistrationResponse {
     pub public_port: u32,
     // @@protoc_insertion_point(field:RegistrationResponse.public_ipv6)
     pub public_ipv6: ::std::vec::Vec<u8>,
+    // @@protoc_insertion_point(field:RegistrationResponse.virtual_ipv6)
+    pub virtual_ipv6: ::std::vec::Vec<u8>,
+    // @@protoc_insertion_point(field:RegistrationResponse.virtual_ipv6_prefix)
+    pub virtual_ipv6_prefix: u32,
     // special fields
     // @@protoc_insertion_point(special_field:RegistrationResponse.special_fields)
     pub special_fields: ::protobuf::SpecialFields,
@@ -766,7 +769,7 @@ impl RegistrationResponse {
     }
 
     fn generated_message_descriptor_data() -> ::protobuf::reflect::GeneratedMessageDescriptorData {
-        let mut fields = ::std::vec::Vec::with_capacity(8);
+        let mut fields = ::std::vec::Vec::with_capacity(10);
         let mut oneofs = ::std::vec::Vec::with_capacity(0);
         fields.push(::protobuf::reflect::rt::v2::make_simpler_field_accessor::<_, _>(
             "virtual_ip",
@@ -808,6 +811,16 @@ impl RegistrationResponse {
             |m: &RegistrationResponse| { &m.public_ipv6 },
             |m: &mut RegistrationResponse| { &mut m.public_ipv6 },
         ));
+        fields.push(::protobuf::reflect::rt::v2::make_simpler_field_accessor::<_, _>(
+            "virtual_ipv6",
+            |m: &RegistrationResponse| { &m.virtual_ipv6 },
+            |m: &mut RegistrationResponse| { &mut m.virtual_ipv6 },
+        ));
+        fields.push(::protobuf::reflect::rt::v2::make_simpler_field_accessor::<_, _>(
+            "virtual_ipv6_prefix",
+            |m: &RegistrationResponse| { &m.virtual_ipv6_prefix },
+            |m: &mut RegistrationResponse| { &mut m.virtual_ipv6_prefix },
+        ));
         ::protobuf::reflect::GeneratedMessageDescriptorData::new_2::<RegistrationResponse>(
             "RegistrationResponse",
             fields,
@@ -850,6 +863,12 @@ impl ::protobuf::Message for RegistrationResponse {
                 66 => {
                     self.public_ipv6 = is.read_bytes()?;
                 },
+                74 => {
+                    self.virtual_ipv6 = is.read_bytes()?;
+                },
+                80 => {
+                    self.virtual_ipv6_prefix = is.read_uint32()?;
+                },
                 tag => {
                     ::protobuf::rt::read_unknown_or_skip_group(tag, is, self.special_fields.mut_unknown_fields())?;
                 },
@@ -887,6 +906,12 @@ impl ::protobuf::Message for RegistrationResponse {
         if !self.public_ipv6.is_empty() {
             my_size += ::protobuf::rt::bytes_size(8, &self.public_ipv6);
         }
+        if !self.virtual_ipv6.is_empty() {
+            my_size += ::protobuf::rt::bytes_size(9, &self.virtual_ipv6);
+        }
+        if self.virtual_ipv6_prefix != 0 {
+            my_size += ::protobuf::rt::uint32_size(10, self.virtual_ipv6_prefix);
+        }
         my_size += ::protobuf::rt::unknown_fields_size(self.special_fields.unknown_fields());
         self.special_fields.cached_size().set(my_size as u32);
         my_size
@@ -917,6 +942,12 @@ impl ::protobuf::Message for RegistrationResponse {
         if !self.public_ipv6.is_empty() {
             os.write_bytes(8, &self.public_ipv6)?;
         }
+        if !self.virtual_ipv6.is_empty() {
+            os.write_bytes(9, &self.virtual_ipv6)?;
+        }
+        if self.virtual_ipv6_prefix != 0 {
+            os.write_uint32(10, self.virtual_ipv6_prefix)?;
+        }
         os.write_unknown_fields(self.special_fields.unknown_fields())?;
         ::std::result::Result::Ok(())
     }
@@ -942,6 +973,8 @@ impl ::protobuf::Message for RegistrationResponse {
         self.public_ip = 0;
         self.public_port = 0;
         self.public_ipv6.clear();
+        self.virtual_ipv6.clear();
+        self.virtual_ipv6_prefix = 0;
         self.special_fields.clear();
     }
 
@@ -955,6 +988,8 @@ impl ::protobuf::Message for RegistrationResponse {
             public_ip: 0,
             public_port: 0,
             public_ipv6: ::std::vec::Vec::new(),
+            virtual_ipv6: ::std::vec::Vec::new(),
+            virtual_ipv6_prefix: 0,
             special_fields: ::protobuf::SpecialFields::new(),
         };
         &instance
@@ -978,8 +1013,8 @@ impl ::protobuf::reflect::ProtobufValue for RegistrationResponse {
     type RuntimeType = ::protobuf::reflect::rt::RuntimeTypeMessage<Self>;
 }
 
-#[derive(PartialEq,Clone,Default,Debug)]
 // @@protoc_insertion_point(message:DeviceInfo)
+#[derive(PartialEq,Clone,Default,Debug)]
 pub struct DeviceInfo {
     // message fields
     // @@protoc_insertion_point(field:DeviceInfo.name)
@@ -990,6 +1025,8 @@ pub struct DeviceInfo {
     pub device_status: u32,
     // @@protoc_insertion_point(field:DeviceInfo.client_secret)
     pub client_secret: bool,
+    // @@protoc_insertion_point(field:DeviceInfo.virtual_ipv6)
+    pub virtual_ipv6: ::std::vec::Vec<u8>,
     // special fields
     // @@protoc_insertion_point(special_field:DeviceInfo.special_fields)
     pub special_fields: ::protobuf::SpecialFields,
@@ -1007,7 +1044,7 @@ impl DeviceInfo {
     }
 
     fn generated_message_descriptor_data() -> ::protobuf::reflect::GeneratedMessageDescriptorData {
-        let mut fields = ::std::vec::Vec::with_capacity(4);
+        let mut fields = ::std::vec::Vec::with_capacity(5);
         let mut oneofs = ::std::vec::Vec::with_capacity(0);
         fields.push(::protobuf::reflect::rt::v2::make_simpler_field_accessor::<_, _>(
             "name",
@@ -1029,6 +1066,11 @@ impl DeviceInfo {
             |m: &DeviceInfo| { &m.client_secret },
             |m: &mut DeviceInfo| { &mut m.client_secret },
         ));
+        fields.push(::protobuf::reflect::rt::v2::make_simpler_field_accessor::<_, _>(
+            "virtual_ipv6",
+            |m: &DeviceInfo| { &m.virtual_ipv6 },
+            |m: &mut DeviceInfo| { &mut m.virtual_ipv6 },
+        ));
         ::protobuf::reflect::GeneratedMessageDescriptorData::new_2::<DeviceInfo>(
             "DeviceInfo",
             fields,
@@ -1059,6 +1101,9 @@ impl ::protobuf::Message for DeviceInfo {
                 32 => {
                     self.client_secret = is.read_bool()?;
                 },
+                42 => {
+                    self.virtual_ipv6 = is.read_bytes()?;
+                },
                 tag => {
                     ::protobuf::rt::read_unknown_or_skip_group(tag, is, self.special_fields.mut_unknown_fields())?;
                 },
@@ -1083,6 +1128,9 @@ impl ::protobuf::Message for DeviceInfo {
         if self.client_secret != false {
             my_size += 1 + 1;
         }
+        if !self.virtual_ipv6.is_empty() {
+            my_size += ::protobuf::rt::bytes_size(5, &self.virtual_ipv6);
+        }
         my_size += ::protobuf::rt::unknown_fields_size(self.special_fields.unknown_fields());
         self.special_fields.cached_size().set(my_size as u32);
         my_size
@@ -1101,6 +1149,9 @@ impl ::protobuf::Message for DeviceInfo {
         if self.client_secret != false {
             os.write_bool(4, self.client_secret)?;
         }
+        if !self.virtual_ipv6.is_empty() {
+            os.write_bytes(5, &self.virtual_ipv6)?;
+        }
         os.write_unknown_fields(self.special_fields.unknown_fields())?;
         ::std::result::Result::Ok(())
     }
@@ -1122,6 +1173,7 @@ impl ::protobuf::Message for DeviceInfo {
         self.virtual_ip = 0;
         self.device_status = 0;
         self.client_secret = false;
+        self.virtual_ipv6.clear();
         self.special_fields.clear();
     }
 
@@ -1131,6 +1183,7 @@ impl ::protobuf::Message for DeviceInfo {
             virtual_ip: 0,
             device_status: 0,
             client_secret: false,
+            virtual_ipv6: ::std::vec::Vec::new(),
             special_fields: ::protobuf::SpecialFields::new(),
         };
         &instance
@@ -1154,8 +1207,8 @@ impl ::protobuf::reflect::ProtobufValue for DeviceInfo {
     type RuntimeType = ::protobuf::reflect::rt::RuntimeTypeMessage<Self>;
 }
 
-#[derive(PartialEq,Clone,Default,Debug)]
 // @@protoc_insertion_point(message:DeviceList)
+#[derive(PartialEq,Clone,Default,Debug)]
 pub struct DeviceList {
     // message fields
     // @@protoc_insertion_point(field:DeviceList.epoch)
@@ -1295,8 +1348,8 @@ impl ::protobuf::reflect::ProtobufValue for DeviceList {
     type RuntimeType = ::protobuf::reflect::rt::RuntimeTypeMessage<Self>;
 }
 
-#[derive(PartialEq,Clone,Default,Debug)]
 // @@protoc_insertion_point(message:PunchInfo)
+#[derive(PartialEq,Clone,Default,Debug)]
 pub struct PunchInfo {
     // message fields
     // @@protoc_insertion_point(field:PunchInfo.public_ip_list)
@@ -1317,6 +1370,30 @@ pub struct PunchInfo {
     pub ipv6: ::std::vec::Vec<u8>,
     // @@protoc_insertion_point(field:PunchInfo.ipv6_port)
     pub ipv6_port: u32,
+    // @@protoc_insertion_point(field:PunchInfo.mapped_ip)
+    pub mapped_ip: u32,
+    // @@protoc_insertion_point(field:PunchInfo.mapped_port)
+    pub mapped_port: u32,
+    // @@protoc_insertion_point(field:PunchInfo.mapping)
+    pub mapping: ::protobuf::EnumOrUnknown<PunchNatDependency>,
+    // @@protoc_insertion_point(field:PunchInfo.filtering)
+    pub filtering: ::protobuf::EnumOrUnknown<PunchNatDependency>,
+    // @@protoc_insertion_point(field:PunchInfo.hairpin)
+    pub hairpin: bool,
+    // @@protoc_insertion_point(field:PunchInfo.port_allocation)
+    pub port_allocation: ::protobuf::EnumOrUnknown<PunchPortAllocation>,
+    // @@protoc_insertion_point(field:PunchInfo.port_step)
+    pub port_step: i32,
+    // @@protoc_insertion_point(field:PunchInfo.birthday)
+    pub birthday: bool,
+    // @@protoc_insertion_point(field:PunchInfo.birthday_delay)
+    pub birthday_delay: u32,
+    // @@protoc_insertion_point(field:PunchInfo.server_rt)
+    pub server_rt: u32,
+    // @@protoc_insertion_point(field:PunchInfo.tcp_port)
+    pub tcp_port: u32,
+    // @@protoc_insertion_point(field:PunchInfo.quic_port)
+    pub quic_port: u32,
     // special fields
     // @@protoc_insertion_point(special_field:PunchInfo.special_fields)
     pub special_fields: ::protobuf::SpecialFields,
@@ -1334,7 +1411,7 @@ impl PunchInfo {
     }
 
     fn generated_message_descriptor_data() -> ::protobuf::reflect::GeneratedMessageDescriptorData {
-        let mut fields = ::std::vec::Vec::with_capacity(9);
+        let mut fields = ::std::vec::Vec::with_capacity(21);
         let mut oneofs = ::std::vec::Vec::with_capacity(0);
         fields.push(::protobuf::reflect::rt::v2::make_vec_simpler_accessor::<_, _>(
             "public_ip_list",
@@ -1381,6 +1458,66 @@ impl PunchInfo {
             |m: &PunchInfo| { &m.ipv6_port },
             |m: &mut PunchInfo| { &mut m.ipv6_port },
         ));
+        fields.push(::protobuf::reflect::rt::v2::make_simpler_field_accessor::<_, _>(
+            "mapped_ip",
+            |m: &PunchInfo| { &m.mapped_ip },
+            |m: &mut PunchInfo| { &mut m.mapped_ip },
+        ));
+        fields.push(::protobuf::reflect::rt::v2::make_simpler_field_accessor::<_, _>(
+            "mapped_port",
+            |m: &PunchInfo| { &m.mapped_port },
+            |m: &mut PunchInfo| { &mut m.mapped_port },
+        ));
+        fields.push(::protobuf::reflect::rt::v2::make_simpler_field_accessor::<_, _>(
+            "mapping",
+            |m: &PunchInfo| { &m.mapping },
+            |m: &mut PunchInfo| { &mut m.mapping },
+        ));
+        fields.push(::protobuf::reflect::rt::v2::make_simpler_field_accessor::<_, _>(
+            "filtering",
+            |m: &PunchInfo| { &m.filtering },
+            |m: &mut PunchInfo| { &mut m.filtering },
+        ));
+        fields.push(::protobuf::reflect::rt::v2::make_simpler_field_accessor::<_, _>(
+            "hairpin",
+            |m: &PunchInfo| { &m.hairpin },
+            |m: &mut PunchInfo| { &mut m.hairpin },
+        ));
+        fields.push(::protobuf::reflect::rt::v2::make_simpler_field_accessor::<_, _>(
+            "port_allocation",
+            |m: &PunchInfo| { &m.port_allocation },
+            |m: &mut PunchInfo| { &mut m.port_allocation },
+        ));
+        fields.push(::protobuf::reflect::rt::v2::make_simpler_field_accessor::<_, _>(
+            "port_step",
+            |m: &PunchInfo| { &m.port_step },
+            |m: &mut PunchInfo| { &mut m.port_step },
+        ));
+        fields.push(::protobuf::reflect::rt::v2::make_simpler_field_accessor::<_, _>(
+            "birthday",
+            |m: &PunchInfo| { &m.birthday },
+            |m: &mut PunchInfo| { &mut m.birthday },
+        ));
+        fields.push(::protobuf::reflect::rt::v2::make_simpler_field_accessor::<_, _>(
+            "birthday_delay",
+            |m: &PunchInfo| { &m.birthday_delay },
+            |m: &mut PunchInfo| { &mut m.birthday_delay },
+        ));
+        fields.push(::protobuf::reflect::rt::v2::make_simpler_field_accessor::<_, _>(
+            "server_rt",
+            |m: &PunchInfo| { &m.server_rt },
+            |m: &mut PunchInfo| { &mut m.server_rt },
+        ));
+        fields.push(::protobuf::reflect::rt::v2::make_simpler_field_accessor::<_, _>(
+            "tcp_port",
+            |m: &PunchInfo| { &m.tcp_port },
+            |m: &mut PunchInfo| { &mut m.tcp_port },
+        ));
+        fields.push(::protobuf::reflect::rt::v2::make_simpler_field_accessor::<_, _>(
+            "quic_port",
+            |m: &PunchInfo| { &m.quic_port },
+            |m: &mut PunchInfo| { &mut m.quic_port },
+        ));
         ::protobuf::reflect::GeneratedMessageDescriptorData::new_2::<PunchInfo>(
             "PunchInfo",
             fields,
@@ -1429,6 +1566,42 @@ impl ::protobuf::Message for PunchInfo {
                 80 => {
                     self.ipv6_port = is.read_uint32()?;
                 },
+                93 => {
+                    self.mapped_ip = is.read_fixed32()?;
+                },
+                96 => {
+                    self.mapped_port = is.read_uint32()?;
+                },
+                104 => {
+                    self.mapping = is.read_enum_or_unknown()?;
+                },
+                112 => {
+                    self.filtering = is.read_enum_or_unknown()?;
+                },
+                120 => {
+                    self.hairpin = is.read_bool()?;
+                },
+                128 => {
+                    self.port_allocation = is.read_enum_or_unknown()?;
+                },
+                136 => {
+                    self.port_step = is.read_sint32()?;
+                },
+                144 => {
+                    self.birthday = is.read_bool()?;
+                },
+                152 => {
+                    self.birthday_delay = is.read_uint32()?;
+                },
+                160 => {
+                    self.server_rt = is.read_uint32()?;
+                },
+                168 => {
+                    self.tcp_port = is.read_uint32()?;
+                },
+                176 => {
+                    self.quic_port = is.read_uint32()?;
+                },
                 tag => {
                     ::protobuf::rt::read_unknown_or_skip_group(tag, is, self.special_fields.mut_unknown_fields())?;
                 },
@@ -1441,7 +1614,7 @@ impl ::protobuf::Message for PunchInfo {
     #[allow(unused_variables)]
     fn compute_size(&self) -> u64 {
         let mut my_size = 0;
-        my_size += 5 * self.public_ip_list.len() as u64;
+        my_size += ::protobuf::rt::vec_packed_fixed32_size(2, &self.public_ip_list);
         if self.public_port != 0 {
             my_size += ::protobuf::rt::uint32_size(3, self.public_port);
         }
@@ -1466,15 +1639,49 @@ impl ::protobuf::Message for PunchInfo {
         if self.ipv6_port != 0 {
             my_size += ::protobuf::rt::uint32_size(10, self.ipv6_port);
         }
+        if self.mapped_ip != 0 {
+            my_size += 1 + 4;
+        }
+        if self.mapped_port != 0 {
+            my_size += ::protobuf::rt::uint32_size(12, self.mapped_port);
+        }
+        if self.mapping != ::protobuf::EnumOrUnknown::new(PunchNatDependency::DependencyUnknown) {
+            my_size += ::protobuf::rt::int32_size(13, self.mapping.value());
+        }
+        if self.filtering != ::protobuf::EnumOrUnknown::new(PunchNatDependency::DependencyUnknown) {
+            my_size += ::protobuf::rt::int32_size(14, self.filtering.value());
+        }
+        if self.hairpin != false {
+            my_size += 1 + 1;
+        }
+        if self.port_allocation != ::protobuf::EnumOrUnknown::new(PunchPortAllocation::AllocationUnknown) {
+            my_size += ::protobuf::rt::int32_size(16, self.port_allocation.value());
+        }
+        if self.port_step != 0 {
+            my_size += ::protobuf::rt::sint32_size(17, self.port_step);
+        }
+        if self.birthday != false {
+            my_size += 2 + 1;
+        }
+        if self.birthday_delay != 0 {
+            my_size += ::protobuf::rt::uint32_size(19, self.birthday_delay);
+        }
+        if self.server_rt != 0 {
+            my_size += ::protobuf::rt::uint32_size(20, self.server_rt);
+        }
+        if self.tcp_port != 0 {
+            my_size += ::protobuf::rt::uint32_size(21, self.tcp_port);
+        }
+        if self.quic_port != 0 {
+            my_size += ::protobuf::rt::uint32_size(22, self.quic_port);
+        }
         my_size += ::protobuf::rt::unknown_fields_size(self.special_fields.unknown_fields());
         self.special_fields.cached_size().set(my_size as u32);
         my_size
     }
 
     fn write_to_with_cached_sizes(&self, os: &mut ::protobuf::CodedOutputStream<'_>) -> ::protobuf::Result<()> {
-        for v in &self.public_ip_list {
-            os.write_fixed32(2, *v)?;
-        };
+        os.write_repeated_packed_fixed32(2, &self.public_ip_list)?;
         if self.public_port != 0 {
             os.write_uint32(3, self.public_port)?;
         }
@@ -1499,6 +1706,42 @@ impl ::protobuf::Message for PunchInfo {
         if self.ipv6_port != 0 {
             os.write_uint32(10, self.ipv6_port)?;
         }
+        if self.mapped_ip != 0 {
+            os.write_fixed32(11, self.mapped_ip)?;
+        }
+        if self.mapped_port != 0 {
+            os.write_uint32(12, self.mapped_port)?;
+        }
+        if self.mapping != ::protobuf::EnumOrUnknown::new(PunchNatDependency::DependencyUnknown) {
+            os.write_enum(13, ::protobuf::EnumOrUnknown::value(&self.mapping))?;
+        }
+        if self.filtering != ::protobuf::EnumOrUnknown::new(PunchNatDependency::DependencyUnknown) {
+            os.write_enum(14, ::protobuf::EnumOrUnknown::value(&self.filtering))?;
+        }
+        if self.hairpin != false {
+            os.write_bool(15, self.hairpin)?;
+        }
+        if self.port_allocation != ::protobuf::EnumOrUnknown::new(PunchPortAllocation::AllocationUnknown) {
+            os.write_enum(16, ::protobuf::EnumOrUnknown::value(&self.port_allocation))?;
+        }
+        if self.port_step != 0 {
+            os.write_sint32(17, self.port_step)?;
+        }
+        if self.birthday != false {
+            os.write_bool(18, self.birthday)?;
+        }
+        if self.birthday_delay != 0 {
+            os.write_uint32(19, self.birthday_delay)?;
+        }
+        if self.server_rt != 0 {
+            os.write_uint32(20, self.server_rt)?;
+        }
+        if self.tcp_port != 0 {
+            os.write_uint32(21, self.tcp_port)?;
+        }
+        if self.quic_port != 0 {
+            os.write_uint32(22, self.quic_port)?;
+        }
         os.write_unknown_fields(self.special_fields.unknown_fields())?;
         ::std::result::Result::Ok(())
     }
@@ -1525,6 +1768,18 @@ impl ::protobuf::Message for PunchInfo {
         self.local_port = 0;
         self.ipv6.clear();
         self.ipv6_port = 0;
+        self.mapped_ip = 0;
+        self.mapped_port = 0;
+        self.mapping = ::protobuf::EnumOrUnknown::new(PunchNatDependency::DependencyUnknown);
+        self.filtering = ::protobuf::EnumOrUnknown::new(PunchNatDependency::DependencyUnknown);
+        self.hairpin = false;
+        self.port_allocation = ::protobuf::EnumOrUnknown::new(PunchPortAllocation::AllocationUnknown);
+        self.port_step = 0;
+        self.birthday = false;
+        self.birthday_delay = 0;
+        self.server_rt = 0;
+        self.tcp_port = 0;
+        self.quic_port = 0;
         self.special_fields.clear();
     }
 
@@ -1539,6 +1794,18 @@ impl ::protobuf::Message for PunchInfo {
             local_port: 0,
             ipv6: ::std::vec::Vec::new(),
             ipv6_port: 0,
+            mapped_ip: 0,
+            mapped_port: 0,
+            mapping: ::protobuf::EnumOrUnknown::from_i32(0),
+            filtering: ::protobuf::EnumOrUnknown::from_i32(0),
+            hairpin: false,
+            port_allocation: ::protobuf::EnumOrUnknown::from_i32(0),
+            port_step: 0,
+            birthday: false,
+            birthday_delay: 0,
+            server_rt: 0,
+            tcp_port: 0,
+            quic_port: 0,
             special_fields: ::protobuf::SpecialFields::new(),
         };
         &instance
@@ -1586,6 +1853,14 @@ impl ::protobuf::Enum for PunchNatType {
         }
     }
 
+    fn from_str(str: &str) -> ::std::option::Option<PunchNatType> {
+        match str {
+            "Symmetric" => ::std::option::Option::Some(PunchNatType::Symmetric),
+            "Cone" => ::std::option::Option::Some(PunchNatType::Cone),
+            _ => ::std::option::Option::None
+        }
+    }
+
     const VALUES: &'static [PunchNatType] = &[
         PunchNatType::Symmetric,
         PunchNatType::Cone,
@@ -1616,6 +1891,150 @@ impl PunchNatType {
     }
 }
 
+#[derive(Clone,Copy,PartialEq,Eq,Debug,Hash)]
+// @@protoc_insertion_point(enum:PunchNatDependency)
+pub enum PunchNatDependency {
+    // @@protoc_insertion_point(enum_value:PunchNatDependency.DependencyUnknown)
+    DependencyUnknown = 0,
+    // @@protoc_insertion_point(enum_value:PunchNatDependency.EndpointIndependent)
+    EndpointIndependent = 1,
+    // @@protoc_insertion_point(enum_value:PunchNatDependency.AddressDependent)
+    AddressDependent = 2,
+    // @@protoc_insertion_point(enum_value:PunchNatDependency.AddressAndPortDependent)
+    AddressAndPortDependent = 3,
+}
+
+impl ::protobuf::Enum for PunchNatDependency {
+    const NAME: &'static str = "PunchNatDependency";
+
+    fn value(&self) -> i32 {
+        *self as i32
+    }
+
+    fn from_i32(value: i32) -> ::std::option::Option<PunchNatDependency> {
+        match value {
+            0 => ::std::option::Option::Some(PunchNatDependency::DependencyUnknown),
+            1 => ::std::option::Option::Some(PunchNatDependency::EndpointIndependent),
+            2 => ::std::option::Option::Some(PunchNatDependency::AddressDependent),
+            3 => ::std::option::Option::Some(PunchNatDependency::AddressAndPortDependent),
+            _ => ::std::option::Option::None
+        }
+    }
+
+    fn from_str(str: &str) -> ::std::option::Option<PunchNatDependency> {
+        match str {
+            "DependencyUnknown" => ::std::option::Option::Some(PunchNatDependency::DependencyUnknown),
+            "EndpointIndependent" => ::std::option::Option::Some(PunchNatDependency::EndpointIndependent),
+            "AddressDependent" => ::std::option::Option::Some(PunchNatDependency::AddressDependent),
+            "AddressAndPortDependent" => ::std::option::Option::Some(PunchNatDependency::AddressAndPortDependent),
+            _ => ::std::option::Option::None
+        }
+    }
+
+    const VALUES: &'static [PunchNatDependency] = &[
+        PunchNatDependency::DependencyUnknown,
+        PunchNatDependency::EndpointIndependent,
+        PunchNatDependency::AddressDependent,
+        PunchNatDependency::AddressAndPortDependent,
+    ];
+}
+
+impl ::protobuf::EnumFull for PunchNatDependency {
+    fn enum_descriptor() -> ::protobuf::reflect::EnumDescriptor {
+        static descriptor: ::protobuf::rt::Lazy<::protobuf::reflect::EnumDescriptor> = ::protobuf::rt::Lazy::new();
+        descriptor.get(|| file_descriptor().enum_by_package_relative_name("PunchNatDependency").unwrap()).clone()
+    }
+
+    fn descriptor(&self) -> ::protobuf::reflect::EnumValueDescriptor {
+        let index = *self as usize;
+        Self::enum_descriptor().value_by_index(index)
+    }
+}
+
+impl ::std::default::Default for PunchNatDependency {
+    fn default() -> Self {
+        PunchNatDependency::DependencyUnknown
+    }
+}
+
+impl PunchNatDependency {
+    fn generated_enum_descriptor_data() -> ::protobuf::reflect::GeneratedEnumDescriptorData {
+        ::protobuf::reflect::GeneratedEnumDescriptorData::new::<PunchNatDependency>("PunchNatDependency")
+    }
+}
+
+#[derive(Clone,Copy,PartialEq,Eq,Debug,Hash)]
+// @@protoc_insertion_point(enum:PunchPortAllocation)
+pub enum PunchPortAllocation {
+    // @@protoc_insertion_point(enum_value:PunchPortAllocation.AllocationUnknown)
+    AllocationUnknown = 0,
+    // @@protoc_insertion_point(enum_value:PunchPortAllocation.Preserving)
+    Preserving = 1,
+    // @@protoc_insertion_point(enum_value:PunchPortAllocation.Sequential)
+    Sequential = 2,
+    // @@protoc_insertion_point(enum_value:PunchPortAllocation.Random)
+    Random = 3,
+}
+
+impl ::protobuf::Enum for PunchPortAllocation {
+    const NAME: &'static str = "PunchPortAllocation";
+
+    fn value(&self) -> i32 {
+        *self as i32
+    }
+
+    fn from_i32(value: i32) -> ::std::option::Option<PunchPortAllocation> {
+        match value {
+            0 => ::std::option::Option::Some(PunchPortAllocation::AllocationUnknown),
+            1 => ::std::option::Option::Some(PunchPortAllocation::Preserving),
+            2 => ::std::option::Option::Some(PunchPortAllocation::Sequential),
+            3 => ::std::option::Option::Some(PunchPortAllocation::Random),
+            _ => ::std::option::Option::None
+        }
+    }
+
+    fn from_str(str: &str) -> ::std::option::Option<PunchPortAllocation> {
+        match str {
+            "AllocationUnknown" => ::std::option::Option::Some(PunchPortAllocation::AllocationUnknown),
+            "Preserving" => ::std::option::Option::Some(PunchPortAllocation::Preserving),
+            "Sequential" => ::std::option::Option::Some(PunchPortAllocation::Sequential),
+            "Random" => ::std::option::Option::Some(PunchPortAllocation::Random),
+            _ => ::std::option::Option::None
+        }
+    }
+
+    const VALUES: &'static [PunchPortAllocation] = &[
+        PunchPortAllocation::AllocationUnknown,
+        PunchPortAllocation::Preserving,
+        PunchPortAllocation::Sequential,
+        PunchPortAllocation::Random,
+    ];
+}
+
+impl ::protobuf::EnumFull for PunchPortAllocation {
+    fn enum_descriptor() -> ::protobuf::reflect::EnumDescriptor {
+        static descriptor: ::protobuf::rt::Lazy<::protobuf::reflect::EnumDescriptor> = ::protobuf::rt::Lazy::new();
+        descriptor.get(|| file_descriptor().enum_by_package_relative_name("PunchPortAllocation").unwrap()).clone()
+    }
+
+    fn descriptor(&self) -> ::protobuf::reflect::EnumValueDescriptor {
+        let index = *self as usize;
+        Self::enum_descriptor().value_by_index(index)
+    }
+}
+
+impl ::std::default::Default for PunchPortAllocation {
+    fn default() -> Self {
+        PunchPortAllocation::AllocationUnknown
+    }
+}
+
+impl PunchPortAllocation {
+    fn generated_enum_descriptor_data() -> ::protobuf::reflect::GeneratedEnumDescriptorData {
+        ::protobuf::reflect::GeneratedEnumDescriptorData::new::<PunchPortAllocation>("PunchPortAllocation")
+    }
+}
+
 static file_descriptor_proto_data: &'static [u8] = b"\
     \n\rmessage.proto\"D\n\x10HandshakeRequest\x12\x18\n\x07version\x18\x01\
     \x20\x01(\tR\x07version\x12\x16\n\x06secret\x18\x02\x20\x01(\x08R\x06sec\
@@ -1630,7 +2049,7 @@ static file_descriptor_proto_data: &'static [u8] = b"\
     \x07is_fast\x18\x04\x20\x01(\x08R\x06isFast\x12\x18\n\x07version\x18\x05\
     \x20\x01(\tR\x07version\x12\x1d\n\nvirtual_ip\x18\x06\x20\x01(\x07R\tvir\
     tualIp\x12&\n\x0fallow_ip_change\x18\x07\x20\x01(\x08R\rallowIpChange\
-    \x12#\n\rclient_secret\x18\x08\x20\x01(\x08R\x0cclientSecret\"\xb3\x02\n\
+    \x12#\n\rclient_secret\x18\x08\x20\x01(\x08R\x0cclientSecret\"\x86\x03\n\
     \x14RegistrationResponse\x12\x1d\n\nvirtual_ip\x18\x01\x20\x01(\x07R\tvi\
     rtualIp\x12'\n\x0fvirtual_gateway\x18\x02\x20\x01(\x07R\x0evirtualGatewa\
     y\x12'\n\x0fvirtual_netmask\x18\x03\x20\x01(\x07R\x0evirtualNetmask\x12\
@@ -1638,21 +2057,38 @@ static file_descriptor_proto_data: &'static [u8] = b"\
     \x18\x05\x20\x03(\x0b2\x0b.DeviceInfoR\x0edeviceInfoList\x12\x1b\n\tpubl\
     ic_ip\x18\x06\x20\x01(\x07R\x08publicIp\x12\x1f\n\x0bpublic_port\x18\x07\
     \x20\x01(\rR\npublicPort\x12\x1f\n\x0bpublic_ipv6\x18\x08\x20\x01(\x0cR\
-    \npublicIpv6\"\x89\x01\n\nDeviceInfo\x12\x12\n\x04name\x18\x01\x20\x01(\
-    \tR\x04name\x12\x1d\n\nvirtual_ip\x18\x02\x20\x01(\x07R\tvirtualIp\x12#\
-    \n\rdevice_status\x18\x03\x20\x01(\rR\x0cdeviceStatus\x12#\n\rclient_sec\
-    ret\x18\x04\x20\x01(\x08R\x0cclientSecret\"Y\n\nDeviceList\x12\x14\n\x05\
-    epoch\x18\x01\x20\x01(\rR\x05epoch\x125\n\x10device_info_list\x18\x02\
-    \x20\x03(\x0b2\x0b.DeviceInfoR\x0edeviceInfoList\"\xa9\x02\n\tPunchInfo\
-    \x12$\n\x0epublic_ip_list\x18\x02\x20\x03(\x07R\x0cpublicIpList\x12\x1f\
-    \n\x0bpublic_port\x18\x03\x20\x01(\rR\npublicPort\x12*\n\x11public_port_\
-    range\x18\x04\x20\x01(\rR\x0fpublicPortRange\x12(\n\x08nat_type\x18\x05\
-    \x20\x01(\x0e2\r.PunchNatTypeR\x07natType\x12\x14\n\x05reply\x18\x06\x20\
-    \x01(\x08R\x05reply\x12\x19\n\x08local_ip\x18\x07\x20\x01(\x07R\x07local\
-    Ip\x12\x1d\n\nlocal_port\x18\x08\x20\x01(\rR\tlocalPort\x12\x12\n\x04ipv\
-    6\x18\t\x20\x01(\x0cR\x04ipv6\x12\x1b\n\tipv6_port\x18\n\x20\x01(\rR\x08\
-    ipv6Port*'\n\x0cPunchNatType\x12\r\n\tSymmetric\x10\0\x12\x08\n\x04Cone\
-    \x10\x01b\x06proto3\
+    \npublicIpv6\x12!\n\x0cvirtual_ipv6\x18\t\x20\x01(\x0cR\x0bvirtualIpv6\
+    \x12.\n\x13virtual_ipv6_prefix\x18\n\x20\x01(\rR\x11virtualIpv6Prefix\"\
+    \xac\x01\n\nDeviceInfo\x12\x12\n\x04name\x18\x01\x20\x01(\tR\x04name\x12\
+    \x1d\n\nvirtual_ip\x18\x02\x20\x01(\x07R\tvirtualIp\x12#\n\rdevice_statu\
+    s\x18\x03\x20\x01(\rR\x0cdeviceStatus\x12#\n\rclient_secret\x18\x04\x20\
+    \x01(\x08R\x0cclientSecret\x12!\n\x0cvirtual_ipv6\x18\x05\x20\x01(\x0cR\
+    \x0bvirtualIpv6\"Y\n\nDeviceList\x12\x14\n\x05epoch\x18\x01\x20\x01(\rR\
+    \x05epoch\x125\n\x10device_info_list\x18\x02\x20\x03(\x0b2\x0b.DeviceInf\
+    oR\x0edeviceInfoList\"\xd7\x05\n\tPunchInfo\x12$\n\x0epublic_ip_list\x18\
+    \x02\x20\x03(\x07R\x0cpublicIpList\x12\x1f\n\x0bpublic_port\x18\x03\x20\
+    \x01(\rR\npublicPort\x12*\n\x11public_port_range\x18\x04\x20\x01(\rR\x0f\
+    publicPortRange\x12(\n\x08nat_type\x18\x05\x20\x01(\x0e2\r.PunchNatTypeR\
+    \x07natType\x12\x14\n\x05reply\x18\x06\x20\x01(\x08R\x05reply\x12\x19\n\
+    \x08local_ip\x18\x07\x20\x01(\x07R\x07localIp\x12\x1d\n\nlocal_port\x18\
+    \x08\x20\x01(\rR\tlocalPort\x12\x12\n\x04ipv6\x18\t\x20\x01(\x0cR\x04ipv\
+    6\x12\x1b\n\tipv6_port\x18\n\x20\x01(\rR\x08ipv6Port\x12\x1b\n\tmapped_i\
+    p\x18\x0b\x20\x01(\x07R\x08mappedIp\x12\x1f\n\x0bmapped_port\x18\x0c\x20\
+    \x01(\rR\nmappedPort\x12-\n\x07mapping\x18\r\x20\x01(\x0e2\x13.PunchNatD\
+    ependencyR\x07mapping\x121\n\tfiltering\x18\x0e\x20\x01(\x0e2\x13.PunchN\
+    atDependencyR\tfiltering\x12\x18\n\x07hairpin\x18\x0f\x20\x01(\x08R\x07h\
+    airpin\x12=\n\x0fport_allocation\x18\x10\x20\x01(\x0e2\x14.PunchPortAllo\
+    cationR\x0eportAllocation\x12\x1b\n\tport_step\x18\x11\x20\x01(\x11R\x08\
+    portStep\x12\x1a\n\x08birthday\x18\x12\x20\x01(\x08R\x08birthday\x12%\n\
+    \x0ebirthday_delay\x18\x13\x20\x01(\rR\rbirthdayDelay\x12\x1b\n\tserver_\
+    rt\x18\x14\x20\x01(\rR\x08serverRt\x12\x19\n\x08tcp_port\x18\x15\x20\x01\
+    (\rR\x07tcpPort\x12\x1b\n\tquic_port\x18\x16\x20\x01(\rR\x08quicPort*'\n\
+    \x0cPunchNatType\x12\r\n\tSymmetric\x10\0\x12\x08\n\x04Cone\x10\x01*w\n\
+    \x12PunchNatDependency\x12\x15\n\x11DependencyUnknown\x10\0\x12\x17\n\
+    \x13EndpointIndependent\x10\x01\x12\x14\n\x10AddressDependent\x10\x02\
+    \x12\x1b\n\x17AddressAndPortDependent\x10\x03*X\n\x13PunchPortAllocation\
+    \x12\x15\n\x11AllocationUnknown\x10\0\x12\x0e\n\nPreserving\x10\x01\x12\
+    \x0e\n\nSequential\x10\x02\x12\n\n\x06Random\x10\x03b\x06proto3\
 ";
 
 /// `FileDescriptorProto` object which was a source for this generated file
@@ -1679,8 +2115,10 @@ pub fn file_descriptor() -> &'static ::protobuf::reflect::FileDescriptor {
             messages.push(DeviceInfo::generated_message_descriptor_data());
             messages.push(DeviceList::generated_message_descriptor_data());
             messages.push(PunchInfo::generated_message_descriptor_data());
-            let mut enums = ::std::vec::Vec::with_capacity(1);
+            let mut enums = ::std::vec::Vec::with_capacity(3);
             enums.push(PunchNatType::generated_enum_descriptor_data());
+            enums.push(PunchNatDependency::generated_enum_descriptor_data());
+            enums.push(PunchPortAllocation::generated_enum_descriptor_data());
             ::protobuf::reflect::GeneratedFileDescriptor::new_generated(
                 file_descriptor_proto(),
                 deps,
@@ -1,6 +1,10 @@
 use std::{fmt, io};
 
-pub const ENCRYPTION_RESERVED: usize = 32;
+/// SecretBody(random、tag、finger)占用的长度，aes、rsa加密后增加的长度
+pub const SECRET_BODY_RESERVED: usize = 32;
+/// 分配缓冲区时预留的长度，按xchacha20_poly1305携带24字节nonce的最大值预留，
+/// 只影响缓冲区大小，实际发送时增加的长度以各加密方式的reserved为准，aes、rsa仍为32
+pub const ENCRYPTION_RESERVED: usize = SECRET_BODY_RESERVED + 24;
 /* aes_gcm加密数据体
   0                                            15                                              31
   0  1  2  3  4  5  6  7  8  9  0  1  2  3  4  5  6  7  8  9  0  1  2  3  4  5  6  7  8  9  0  1
@@ -19,8 +23,22 @@ pub const ENCRYPTION_RESERVED: usize = 32;
  |                                         finger(32)                                          |
  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
 
- 注：finger用于快速校验数据是否被修改，上层可使用token、协议头参与计算finger，
+ 注：random实际填充发送方递增的序号，接收方解密后用于防重放
+    finger用于快速校验数据是否被修改，上层可使用token、协议头参与计算finger，
     确保服务端和客户端都能感知修改(服务端不能解密也能校验指纹)
+
+ chacha20_poly1305、xchacha20_poly1305在tag和finger之间携带每个数据包随机生成的nonce，
+ 分别为12字节和24字节，nonce不加密，finger覆盖nonce。
+ 不像aes_gcm那样从头部派生nonce：序号在加密数据体内，解密前不可见，并且重启后会重新开始，
+ 密码派生的密钥不变时chacha20重复nonce会泄露明文和认证密钥，所以随机生成并随数据包发送。
+ 头部信息作为附加数据参与认证
+ +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+ |                                  数据体、random、tag                                          |
+ +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+ |                                     nonce(96/192)                                           |
+ +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+ |                                        finger(96)                                           |
+ +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
 */
 pub struct SecretBody<B> {
     buffer: B,
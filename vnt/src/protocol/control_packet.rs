@@ -21,6 +21,49 @@ pub enum Protocol {
     ///获取对端看到的地址
     AddrRequest,
     AddrResponse,
+    /// 会话密钥协商请求
+    /*
+     0                                            15                                              31
+     0  1  2  3  4  5  6  7  8  9  0  1  2  3  4  5  6  7  8  9  0  1  2  3  4  5  6  7  8  9  0  1
+    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    |                                     临时公钥(256)                                              |
+    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    |                                     密钥轮次(32)                                               |
+    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    |                                     随机数(128)                                                |
+    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    |                                     发送序号(32)                                               |
+    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    |                                     认证码(256)                                                |
+    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    */
+    KeyExchangeRequest,
+    /// 会话密钥协商响应，内容同请求，随机数和请求的相同
+    ///
+    /// 发送序号是发送方当前的加密序号，接收方以此为起点重建使用密码加密时的防重放窗口
+    KeyExchangeResponse,
+    /// 路由通告，告知直连的客户端自己能到达的节点，可重复多条
+    /*
+     0                                            15                                              31
+     0  1  2  3  4  5  6  7  8  9  0  1  2  3  4  5  6  7  8  9  0  1  2  3  4  5  6  7  8  9  0  1
+    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    |                                     虚拟ip(32)                                                |
+    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    |       跳数(8)         |      unused(8)        |                  延迟(16)                      |
+    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    */
+    RouteAdvertise,
+    /// 路径mtu探测，数据体用随机内容填充到探测的长度
+    MtuProbe,
+    /// 路径mtu探测响应，告知对端收到的探测包长度
+    /*
+     0                                            15
+     0  1  2  3  4  5  6  7  8  9  0  1  2  3  4  5
+    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    |               收到的长度(16)                   |
+    +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    */
+    MtuProbeResponse,
     Unknown(u8),
 }
 
@@ -33,6 +76,11 @@ impl From<u8> for Protocol {
             4 => Protocol::PunchResponse,
             5 => Protocol::AddrRequest,
             6 => Protocol::AddrResponse,
+            7 => Protocol::KeyExchangeRequest,
+            8 => Protocol::KeyExchangeResponse,
+            9 => Protocol::RouteAdvertise,
+            10 => Protocol::MtuProbe,
+            11 => Protocol::MtuProbeResponse,
             val => Protocol::Unknown(val),
         }
     }
@@ -47,6 +95,11 @@ impl Into<u8> for Protocol {
             Protocol::PunchResponse => 4,
             Protocol::AddrRequest => 5,
             Protocol::AddrResponse => 6,
+            Protocol::KeyExchangeRequest => 7,
+            Protocol::KeyExchangeResponse => 8,
+            Protocol::RouteAdvertise => 9,
+            Protocol::MtuProbe => 10,
+            Protocol::MtuProbeResponse => 11,
             Protocol::Unknown(val) => val,
         }
     }
@@ -59,6 +112,11 @@ pub enum ControlPacket<B> {
     PunchResponse,
     AddrRequest,
     AddrResponse(AddrPacket<B>),
+    KeyExchangeRequest(KeyExchangePacket<B>),
+    KeyExchangeResponse(KeyExchangePacket<B>),
+    RouteAdvertise(RouteAdvertisePacket<B>),
+    MtuProbe,
+    MtuProbeResponse(MtuPacket<B>),
 }
 
 impl<B: AsRef<[u8]>> ControlPacket<B> {
@@ -70,6 +128,19 @@ impl<B: AsRef<[u8]>> ControlPacket<B> {
             Protocol::PunchResponse => Ok(ControlPacket::PunchResponse),
             Protocol::AddrRequest => Ok(ControlPacket::AddrRequest),
             Protocol::AddrResponse => Ok(ControlPacket::AddrResponse(AddrPacket::new(buffer)?)),
+            Protocol::KeyExchangeRequest => Ok(ControlPacket::KeyExchangeRequest(
+                KeyExchangePacket::new(buffer)?,
+            )),
+            Protocol::KeyExchangeResponse => Ok(ControlPacket::KeyExchangeResponse(
+                KeyExchangePacket::new(buffer)?,
+            )),
+            Protocol::RouteAdvertise => Ok(ControlPacket::RouteAdvertise(
+                RouteAdvertisePacket::new(buffer)?,
+            )),
+            Protocol::MtuProbe => Ok(ControlPacket::MtuProbe),
+            Protocol::MtuProbeResponse => {
+                Ok(ControlPacket::MtuProbeResponse(MtuPacket::new(buffer)?))
+            }
             Protocol::Unknown(_) => Err(io::Error::new(io::ErrorKind::InvalidData, "Unsupported")),
         }
     }
@@ -157,3 +228,149 @@ impl<B: AsRef<[u8]>> fmt::Debug for AddrPacket<B> {
             .finish()
     }
 }
+
+pub const KEY_EXCHANGE_LEN: usize = 32 + 4 + 16 + 4 + 32;
+
+/// 会话密钥协商
+pub struct KeyExchangePacket<B> {
+    buffer: B,
+}
+
+impl<B: AsRef<[u8]>> KeyExchangePacket<B> {
+    pub fn new(buffer: B) -> io::Result<KeyExchangePacket<B>> {
+        let len = buffer.as_ref().len();
+        if len != KEY_EXCHANGE_LEN {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "len != 88"));
+        }
+        Ok(KeyExchangePacket { buffer })
+    }
+    pub fn public_key(&self) -> [u8; 32] {
+        self.buffer.as_ref()[..32].try_into().unwrap()
+    }
+    pub fn epoch(&self) -> u32 {
+        u32::from_be_bytes(self.buffer.as_ref()[32..36].try_into().unwrap())
+    }
+    pub fn nonce(&self) -> [u8; 16] {
+        self.buffer.as_ref()[36..52].try_into().unwrap()
+    }
+    pub fn sequence(&self) -> u32 {
+        u32::from_be_bytes(self.buffer.as_ref()[52..56].try_into().unwrap())
+    }
+    pub fn auth(&self) -> &[u8] {
+        &self.buffer.as_ref()[56..88]
+    }
+}
+
+impl<B: AsRef<[u8]> + AsMut<[u8]>> KeyExchangePacket<B> {
+    pub fn set_public_key(&mut self, public_key: &[u8; 32]) {
+        self.buffer.as_mut()[..32].copy_from_slice(public_key)
+    }
+    pub fn set_epoch(&mut self, epoch: u32) {
+        self.buffer.as_mut()[32..36].copy_from_slice(&epoch.to_be_bytes())
+    }
+    pub fn set_nonce(&mut self, nonce: &[u8; 16]) {
+        self.buffer.as_mut()[36..52].copy_from_slice(nonce)
+    }
+    pub fn set_sequence(&mut self, sequence: u32) {
+        self.buffer.as_mut()[52..56].copy_from_slice(&sequence.to_be_bytes())
+    }
+    pub fn set_auth(&mut self, auth: &[u8; 32]) {
+        self.buffer.as_mut()[56..88].copy_from_slice(auth)
+    }
+}
+
+impl<B: AsRef<[u8]>> fmt::Debug for KeyExchangePacket<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("KeyExchangePacket")
+            .field("public_key", &self.public_key())
+            .field("epoch", &self.epoch())
+            .field("nonce", &self.nonce())
+            .field("sequence", &self.sequence())
+            .finish()
+    }
+}
+
+pub const ROUTE_ADVERTISE_ITEM_LEN: usize = 8;
+
+/// 路由通告
+pub struct RouteAdvertisePacket<B> {
+    buffer: B,
+}
+
+impl<B: AsRef<[u8]>> RouteAdvertisePacket<B> {
+    pub fn new(buffer: B) -> io::Result<RouteAdvertisePacket<B>> {
+        let len = buffer.as_ref().len();
+        if len % ROUTE_ADVERTISE_ITEM_LEN != 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "len % 8 != 0"));
+        }
+        Ok(RouteAdvertisePacket { buffer })
+    }
+    pub fn len(&self) -> usize {
+        self.buffer.as_ref().len() / ROUTE_ADVERTISE_ITEM_LEN
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// (虚拟ip,跳数,延迟)
+    pub fn item(&self, index: usize) -> (Ipv4Addr, u8, u16) {
+        let start = index * ROUTE_ADVERTISE_ITEM_LEN;
+        let buf = &self.buffer.as_ref()[start..start + ROUTE_ADVERTISE_ITEM_LEN];
+        (
+            Ipv4Addr::new(buf[0], buf[1], buf[2], buf[3]),
+            buf[4],
+            u16::from_be_bytes(buf[6..8].try_into().unwrap()),
+        )
+    }
+    pub fn items(&self) -> impl Iterator<Item = (Ipv4Addr, u8, u16)> + '_ {
+        (0..self.len()).map(|index| self.item(index))
+    }
+}
+
+impl<B: AsRef<[u8]> + AsMut<[u8]>> RouteAdvertisePacket<B> {
+    pub fn set_item(&mut self, index: usize, ip: Ipv4Addr, metric: u8, rt: u16) {
+        let start = index * ROUTE_ADVERTISE_ITEM_LEN;
+        let buf = &mut self.buffer.as_mut()[start..start + ROUTE_ADVERTISE_ITEM_LEN];
+        buf[..4].copy_from_slice(&ip.octets());
+        buf[4] = metric;
+        buf[5] = 0;
+        buf[6..8].copy_from_slice(&rt.to_be_bytes());
+    }
+}
+
+impl<B: AsRef<[u8]>> fmt::Debug for RouteAdvertisePacket<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.items()).finish()
+    }
+}
+
+/// 路径mtu探测响应
+pub struct MtuPacket<B> {
+    buffer: B,
+}
+
+impl<B: AsRef<[u8]>> MtuPacket<B> {
+    pub fn new(buffer: B) -> io::Result<MtuPacket<B>> {
+        let len = buffer.as_ref().len();
+        if len != 2 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "len != 2"));
+        }
+        Ok(MtuPacket { buffer })
+    }
+    pub fn mtu(&self) -> u16 {
+        u16::from_be_bytes(self.buffer.as_ref()[..2].try_into().unwrap())
+    }
+}
+
+impl<B: AsRef<[u8]> + AsMut<[u8]>> MtuPacket<B> {
+    pub fn set_mtu(&mut self, mtu: u16) {
+        self.buffer.as_mut()[..2].copy_from_slice(&mtu.to_be_bytes())
+    }
+}
+
+impl<B: AsRef<[u8]>> fmt::Debug for MtuPacket<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MtuPacket")
+            .field("mtu", &self.mtu())
+            .finish()
+    }
+}
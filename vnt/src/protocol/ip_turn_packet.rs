@@ -4,7 +4,10 @@ use std::net::Ipv4Addr;
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
 pub enum Protocol {
     Ipv4,
+    Ipv6,
     Ipv4Broadcast,
+    /// 超过路径mtu的数据包分片传输
+    Fragment,
     Unknown(u8),
 }
 
@@ -12,7 +15,9 @@ impl From<u8> for Protocol {
     fn from(value: u8) -> Self {
         match value {
             4 => Protocol::Ipv4,
+            6 => Protocol::Ipv6,
             201 => Protocol::Ipv4Broadcast,
+            202 => Protocol::Fragment,
             val => Protocol::Unknown(val),
         }
     }
@@ -22,7 +27,9 @@ impl Into<u8> for Protocol {
     fn into(self) -> u8 {
         match self {
             Protocol::Ipv4 => 4,
+            Protocol::Ipv6 => 6,
             Protocol::Ipv4Broadcast => 201,
+            Protocol::Fragment => 202,
             Protocol::Unknown(val) => val,
         }
     }
@@ -113,3 +120,81 @@ impl<B: AsRef<[u8]> + AsMut<[u8]>> BroadcastPacket<B> {
         Ok(())
     }
 }
+
+/*
+   0                                            15                                              31
+   0  1  2  3  4  5  6  7  8  9  0  1  2  3  4  5  6  7  8  9  0  1  2  3  4  5  6  7  8  9  0  1
+  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+  |                    分片id(16)                  |      分片序号(8)        |      分片总数(8)      |
+  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+  |      原上层协议(8)      |                            数据                                     |
+  +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+  注：每个分片单独加密，所有分片到齐后按序号拼接数据，还原为原上层协议的数据包
+*/
+pub const FRAGMENT_HEAD_LEN: usize = 5;
+
+pub struct FragmentPacket<B> {
+    buffer: B,
+}
+
+impl<B: AsRef<[u8]>> FragmentPacket<B> {
+    pub fn unchecked(buffer: B) -> Self {
+        Self { buffer }
+    }
+    pub fn new(buffer: B) -> io::Result<Self> {
+        let len = buffer.as_ref().len();
+        let packet = Self::unchecked(buffer);
+        if len <= FRAGMENT_HEAD_LEN || packet.index() >= packet.total() {
+            Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "FragmentPacket InvalidData",
+            ))
+        } else {
+            Ok(packet)
+        }
+    }
+}
+
+impl<B: AsRef<[u8]>> FragmentPacket<B> {
+    pub fn id(&self) -> u16 {
+        u16::from_be_bytes(self.buffer.as_ref()[0..2].try_into().unwrap())
+    }
+    pub fn index(&self) -> u8 {
+        self.buffer.as_ref()[2]
+    }
+    pub fn total(&self) -> u8 {
+        self.buffer.as_ref()[3]
+    }
+    pub fn protocol(&self) -> u8 {
+        self.buffer.as_ref()[4]
+    }
+    pub fn data(&self) -> &[u8] {
+        &self.buffer.as_ref()[FRAGMENT_HEAD_LEN..]
+    }
+}
+
+impl<B: AsRef<[u8]> + AsMut<[u8]>> FragmentPacket<B> {
+    pub fn set_id(&mut self, id: u16) {
+        self.buffer.as_mut()[0..2].copy_from_slice(&id.to_be_bytes());
+    }
+    pub fn set_index(&mut self, index: u8) {
+        self.buffer.as_mut()[2] = index;
+    }
+    pub fn set_total(&mut self, total: u8) {
+        self.buffer.as_mut()[3] = total;
+    }
+    pub fn set_protocol(&mut self, protocol: u8) {
+        self.buffer.as_mut()[4] = protocol;
+    }
+    pub fn set_data(&mut self, data: &[u8]) -> io::Result<()> {
+        let buf = self.buffer.as_mut();
+        if FRAGMENT_HEAD_LEN + data.len() != buf.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "data invalid data",
+            ));
+        }
+        buf[FRAGMENT_HEAD_LEN..].copy_from_slice(data);
+        Ok(())
+    }
+}
@@ -6,7 +6,7 @@ use std::{fmt, io};
    0                                            15                                              31
    0  1  2  3  4  5  6  7  8  9  0  1  2  3  4  5  6  7  8  9  0  1  2  3  4  5  6  7  8  9  0  1
   +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
-  |e |s |u |u|   版本(4) |      协议(8)          |      上层协议(8)        | 初始ttl(4) | 生存时间(4) |
+  |e |s |k |p|   版本(4) |      协议(8)          |      上层协议(8)        | 初始ttl(4) | 生存时间(4) |
   +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
   |                                          源ip地址(32)                                         |
   +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
@@ -14,7 +14,11 @@ use std::{fmt, io};
   +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
   |                                           数据体                                              |
   +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
-  注：e为是否加密标志，s为服务端通信包标志，u未使用
+  注：e为是否加密标志，s为服务端通信包标志，k为会话密钥加密标志，p为会话密钥轮次的奇偶位
+  服务端通信包(s)同时会置位p所在的位，所以k、p只在客户端之间的数据包中使用
+  源ip和目的ip只有ipv4，是节点的标识。转发ipv6数据时，先用虚拟ipv6查出对端的虚拟ipv4，
+  再以虚拟ipv4作为目的地址，ipv6地址只在数据体(原始ip包)中携带。
+  服务端按头部的目的ipv4转发，头部不能改为ipv6，接收方会校验来源ipv6属于发送的节点
 */
 pub const HEAD_LEN: usize = 12;
 
@@ -157,6 +161,14 @@ impl<B: AsRef<[u8]>> NetPacket<B> {
     pub fn is_gateway(&self) -> bool {
         self.buffer.as_ref()[0] & 0x50 == 0x50
     }
+    /// 使用会话密钥加密
+    pub fn is_session_key(&self) -> bool {
+        self.buffer.as_ref()[0] & 0x20 == 0x20 && !self.is_gateway()
+    }
+    /// 会话密钥轮次的奇偶位
+    pub fn session_key_parity(&self) -> u8 {
+        (self.buffer.as_ref()[0] >> 4) & 0x01
+    }
     pub fn version(&self) -> Version {
         Version::from(self.buffer.as_ref()[0] & 0x0F)
     }
@@ -203,6 +215,14 @@ impl<B: AsRef<[u8]> + AsMut<[u8]>> NetPacket<B> {
             self.buffer.as_mut()[0] = self.buffer.as_ref()[0] & 0xBF
         };
     }
+    /// 标记会话密钥加密，parity为会话密钥轮次的奇偶位
+    pub fn set_session_key_flag(&mut self, session_key: bool, parity: u8) {
+        if session_key {
+            self.buffer.as_mut()[0] = (self.buffer.as_ref()[0] & 0xCF) | 0x20 | ((parity & 1) << 4)
+        } else {
+            self.buffer.as_mut()[0] = self.buffer.as_ref()[0] & 0xCF
+        };
+    }
     pub fn set_version(&mut self, version: Version) {
         let v: u8 = version.into();
         self.buffer.as_mut()[0] = (self.buffer.as_ref()[0] & 0xF0) | (0x0F & v);
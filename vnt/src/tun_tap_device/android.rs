@@ -21,6 +21,11 @@ impl DeviceWriter {
         let buf = &buf[14..];
         self.write_ipv4_tun(buf)
     }
+    ///写入ipv6数据，头部空了14个字节
+    pub fn write_ipv6(&self, buf: &[u8]) -> io::Result<()> {
+        let buf = &buf[14..];
+        self.write_ipv4_tun(buf)
+    }
     pub fn close(&self) -> io::Result<()> {
         // unsafe {
         //     libc::close(self.0);
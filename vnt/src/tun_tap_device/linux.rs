@@ -2,9 +2,10 @@ use crate::tun_tap_device::linux_mac::DeviceW;
 use crate::tun_tap_device::{DeviceReader, DeviceType, DeviceWriter, DriverInfo};
 use parking_lot::Mutex;
 use std::io;
-use std::net::Ipv4Addr;
+use std::net::{Ipv4Addr, Ipv6Addr};
 use std::process::Command;
 use std::sync::Arc;
+use tun::platform::posix::{Reader, Writer};
 use tun::Device;
 
 pub const TUN_INTERFACE_NAME: &str = "vnt-tun";
@@ -49,6 +50,25 @@ impl DeviceWriter {
         )?;
         return Ok(());
     }
+    pub fn set_ipv6(&self, address: Ipv6Addr, prefix: u8) -> io::Result<()> {
+        let dev = self.lock.lock();
+        let name = dev.name();
+        // 先清理旧的地址，ip变化时避免残留
+        let flush_str = format!("ip -6 addr flush dev {} scope global", name);
+        let _ = Command::new("sh").arg("-c").arg(&flush_str).output();
+        let addr_add_str = format!("ip -6 addr add {}/{} dev {}", address, prefix, name);
+        let addr_add_out = Command::new("sh").arg("-c").arg(&addr_add_str).output()?;
+        if !addr_add_out.status.success() {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!(
+                    "设置ipv6地址失败: cmd:{},out:{:?}",
+                    addr_add_str, addr_add_out
+                ),
+            ));
+        }
+        Ok(())
+    }
 }
 
 pub fn add_route(name: &str, address: Ipv4Addr, netmask: Ipv4Addr) -> io::Result<()> {
@@ -77,6 +97,8 @@ pub fn create_device(
     gateway: Ipv4Addr,
     in_ips: Vec<(Ipv4Addr, Ipv4Addr)>,
     mtu: u16,
+    queues: usize,
+    offload: bool,
 ) -> io::Result<(DeviceWriter, DeviceReader, DriverInfo)> {
     let mut config = tun::Configuration::default();
     let broadcast_address =
@@ -88,7 +110,6 @@ pub fn create_device(
         .netmask(netmask)
         .mtu(mtu.into())
         .broadcast(broadcast_address)
-        // .queues(2) 用多个队列有兼容性问题
         .up();
     match device_type {
         DeviceType::Tun => {
@@ -99,11 +120,40 @@ pub fn create_device(
             config.layer(tun::Layer::L2);
         }
     }
-    let dev = tun::create(&config).expect("tun/tap failed to create");
+    if offload {
+        if device_type == DeviceType::Tun {
+            // 开启virtio头和TSO，可以读写超过mtu的tcp包
+            config.platform(|config| {
+                config.vnet_hdr(true);
+            });
+        } else {
+            log::warn!("tap网卡不支持offload");
+        }
+    }
+    if queues > 1 {
+        // 使用IFF_MULTI_QUEUE，内核按流分散到各个队列
+        config.queues(queues);
+    }
+    let dev = match tun::create(&config) {
+        Ok(dev) => dev,
+        Err(e) if queues > 1 || offload => {
+            // 内核不支持或者残留的网卡不是多队列的，退回单队列并关闭offload
+            log::warn!("创建多队列/offload网卡失败,使用单队列:{:?}", e);
+            config.queues(1);
+            config.platform(|config| {
+                config.vnet_hdr(false);
+            });
+            tun::create(&config).expect("tun/tap failed to create")
+        }
+        Err(e) => panic!("tun/tap failed to create: {:?}", e),
+    };
     let packet_information = dev.has_packet_information();
-    let queue = dev.queue(0).unwrap();
-    let reader = queue.reader();
-    let writer = queue.writer();
+    let vnet_hdr = dev.has_vnet_hdr();
+    let queue_list: Vec<(Reader, Writer)> = (0..queues.max(1))
+        .filter_map(|i| dev.queue(i))
+        .map(|queue| (queue.reader(), queue.writer()))
+        .collect();
+    let (reader, writer) = queue_list[0].clone();
     let name = dev.name();
     for (address, netmask) in &in_ips {
         add_route(name, *address, *netmask)?;
@@ -117,8 +167,8 @@ pub fn create_device(
         Ipv4Addr::from([224, 0, 0, 0]),
         Ipv4Addr::from([240, 0, 0, 0]),
     )?;
-    let device_w = match device_type {
-        DeviceType::Tun => DeviceW::Tun(writer),
+    let mac = match device_type {
+        DeviceType::Tun => None,
         DeviceType::Tap => {
             let get_mac_cmd = format!("cat /sys/class/net/{}/address", name);
             let mac_out = Command::new("sh")
@@ -138,26 +188,42 @@ pub fn create_device(
             for i in 0..6 {
                 mac[i] = u8::from_str_radix(&split.next().unwrap()[..2], 16).unwrap();
             }
-            DeviceW::Tap((writer, mac))
+            Some(mac)
         }
     };
+    let device_w = |writer| match mac {
+        None => DeviceW::Tun(writer),
+        Some(mac) => DeviceW::Tap((writer, mac)),
+    };
     let driver_info = DriverInfo {
         device_type,
         name: name.to_string(),
         version: String::new(),
         mac: None,
     };
-    Ok((
-        DeviceWriter::new(
-            device_w,
-            Arc::new(Mutex::new(dev)),
-            in_ips,
-            address,
-            packet_information,
-        ),
-        DeviceReader::new(reader),
-        driver_info,
-    ))
+    let mut device_writer = DeviceWriter::new(
+        device_w(writer),
+        Arc::new(Mutex::new(dev)),
+        in_ips,
+        address,
+        packet_information,
+    );
+    let mut device_reader = DeviceReader::new(reader);
+    if queue_list.len() > 1 {
+        log::info!("网卡队列数:{}", queue_list.len());
+        device_writer = device_writer.with_queues(
+            queue_list
+                .iter()
+                .map(|(_, w)| device_w(w.clone()))
+                .collect(),
+        );
+        device_reader = device_reader.with_queues(queue_list.into_iter().map(|(r, _)| r).collect());
+    }
+    if vnet_hdr {
+        device_writer = device_writer.with_vnet_hdr();
+        device_reader = device_reader.with_vnet_hdr();
+    }
+    Ok((device_writer, device_reader, driver_info))
 }
 
 pub fn delete_device(_device_type: DeviceType) {
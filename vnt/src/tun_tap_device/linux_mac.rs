@@ -31,9 +31,17 @@ impl DeviceW {
 #[derive(Clone)]
 pub struct DeviceWriter {
     writer: DeviceW,
+    /// 多队列网卡每个队列的写入端，单队列时为空
+    queues: Vec<DeviceW>,
     pub lock: Arc<Mutex<Device>>,
     pub in_ips: Vec<(Ipv4Addr, Ipv4Addr)>,
     packet_information: bool,
+    /// 数据包前面有virtio头
+    #[cfg(target_os = "linux")]
+    vnet_hdr: bool,
+    /// 批量写入的队列，flush时合并tcp分段，None表示直接写入
+    #[cfg(target_os = "linux")]
+    batch: Option<Arc<Mutex<Vec<Vec<u8>>>>>,
 }
 
 impl DeviceWriter {
@@ -46,32 +54,137 @@ impl DeviceWriter {
     ) -> Self {
         Self {
             writer,
+            queues: Vec::new(),
             lock,
             in_ips,
             packet_information,
+            #[cfg(target_os = "linux")]
+            vnet_hdr: false,
+            #[cfg(target_os = "linux")]
+            batch: None,
         }
     }
+    pub fn with_queues(mut self, queues: Vec<DeviceW>) -> Self {
+        self.queues = queues;
+        self
+    }
+    /// 网卡队列数量
+    pub fn queue_num(&self) -> usize {
+        self.queues.len().max(1)
+    }
+    /// 写入指定队列的writer，单队列时和原来的一样
+    pub fn queue(&self, index: usize) -> DeviceWriter {
+        let mut device_writer = self.clone();
+        if let Some(writer) = self.queues.get(index) {
+            device_writer.writer = writer.clone();
+        }
+        device_writer
+    }
+    #[cfg(target_os = "linux")]
+    pub fn with_vnet_hdr(mut self) -> Self {
+        self.vnet_hdr = true;
+        self
+    }
+    /// 开启offload时返回批量写入的writer，处理完一批数据后需要调用flush
+    #[cfg(target_os = "linux")]
+    pub fn batch(&self) -> Self {
+        let mut device_writer = self.clone();
+        if self.vnet_hdr && self.is_tun() {
+            device_writer.batch = Some(Arc::new(Mutex::new(Vec::with_capacity(
+                crate::tun_tap_device::offload::MAX_BATCH,
+            ))));
+        }
+        device_writer
+    }
+    /// 合并队列中连续的tcp分段后写入
+    #[cfg(target_os = "linux")]
+    pub fn flush(&self) -> io::Result<()> {
+        let batch = match &self.batch {
+            Some(batch) => batch,
+            None => return Ok(()),
+        };
+        let list = std::mem::take(&mut *batch.lock());
+        if list.is_empty() {
+            return Ok(());
+        }
+        let writer = match &self.writer {
+            DeviceW::Tun(writer) => writer,
+            DeviceW::Tap(_) => return Err(io::Error::from(io::ErrorKind::Unsupported)),
+        };
+        let mut rs = Ok(());
+        for (hdr, packet) in crate::tun_tap_device::offload::coalesce(list) {
+            if let Err(e) = Self::write_vnet_hdr(writer, &hdr, &packet) {
+                rs = Err(e);
+            }
+        }
+        rs
+    }
+    #[cfg(target_os = "linux")]
+    fn write_vnet_hdr(writer: &Writer, hdr: &[u8], packet: &[u8]) -> io::Result<()> {
+        let len = writer.write_vectored(&[io::IoSlice::new(hdr), io::IoSlice::new(packet)])?;
+        if len != hdr.len() + packet.len() {
+            return Err(io::Error::from(io::ErrorKind::WriteZero));
+        }
+        Ok(())
+    }
 }
 
 impl DeviceWriter {
     pub fn write(packet_information: bool, writer: &Writer, packet: &[u8]) -> io::Result<()> {
+        Self::write0(packet_information, writer, packet, false)
+    }
+    fn write0(
+        packet_information: bool,
+        writer: &Writer,
+        packet: &[u8],
+        ipv6: bool,
+    ) -> io::Result<()> {
         if packet_information {
             let mut buf = Vec::<u8>::with_capacity(4 + packet.len());
             buf.put_u16(0);
             #[cfg(any(target_os = "macos", target_os = "ios"))]
-            buf.put_u16(libc::PF_INET as u16);
+            if ipv6 {
+                buf.put_u16(libc::PF_INET6 as u16);
+            } else {
+                buf.put_u16(libc::PF_INET as u16);
+            }
             #[cfg(any(target_os = "linux", target_os = "android"))]
-            buf.put_u16(libc::ETH_P_IP as u16);
+            if ipv6 {
+                buf.put_u16(libc::ETH_P_IPV6 as u16);
+            } else {
+                buf.put_u16(libc::ETH_P_IP as u16);
+            }
             buf.extend_from_slice(packet);
             writer.write_all(&buf)
         } else {
             writer.write_all(packet)
         }
     }
+    fn write_tun(&self, writer: &Writer, packet: &[u8], ipv6: bool) -> io::Result<()> {
+        #[cfg(target_os = "linux")]
+        if self.vnet_hdr {
+            if let Some(batch) = &self.batch {
+                let mut list = batch.lock();
+                list.push(packet.to_vec());
+                if list.len() < crate::tun_tap_device::offload::MAX_BATCH {
+                    return Ok(());
+                }
+                drop(list);
+                return self.flush();
+            }
+            // virtio头全为0表示不需要分段和计算校验和
+            return Self::write_vnet_hdr(
+                writer,
+                &[0; crate::tun_tap_device::offload::VIRTIO_NET_HDR_LEN],
+                packet,
+            );
+        }
+        Self::write0(self.packet_information, writer, packet, ipv6)
+    }
     ///tun网卡写入ipv4数据
     pub fn write_ipv4_tun(&self, buf: &[u8]) -> io::Result<()> {
         match &self.writer {
-            DeviceW::Tun(writer) => Self::write(self.packet_information, writer, buf),
+            DeviceW::Tun(writer) => self.write_tun(writer, buf, false),
             DeviceW::Tap(_) => Err(io::Error::from(io::ErrorKind::Unsupported)),
         }
     }
@@ -85,7 +198,7 @@ impl DeviceWriter {
     ///写入ipv4数据，头部必须留14字节，给tap写入以太网帧头
     pub fn write_ipv4(&self, buf: &mut [u8]) -> io::Result<()> {
         match &self.writer {
-            DeviceW::Tun(writer) => Self::write(self.packet_information, writer, &buf[14..]),
+            DeviceW::Tun(writer) => self.write_tun(writer, &buf[14..], false),
             DeviceW::Tap((writer, mac)) => {
                 let source_mac = [
                     buf[14 + 12],
@@ -103,14 +216,44 @@ impl DeviceWriter {
             }
         }
     }
+    ///写入ipv6数据，头部必须留14字节，给tap写入以太网帧头
+    pub fn write_ipv6(&self, buf: &mut [u8]) -> io::Result<()> {
+        match &self.writer {
+            DeviceW::Tun(writer) => self.write_tun(writer, &buf[14..], true),
+            DeviceW::Tap((writer, mac)) => {
+                //取源ipv6的后4字节生成mac，和邻居发现的应答保持一致
+                let source_mac = [
+                    buf[14 + 20],
+                    buf[14 + 21],
+                    buf[14 + 22],
+                    buf[14 + 23],
+                    !mac[5],
+                    234,
+                ];
+                let mut ethernet_packet = EthernetPacket::unchecked(buf);
+                ethernet_packet.set_source(&source_mac);
+                ethernet_packet.set_destination(mac);
+                ethernet_packet.set_protocol(ethernet::protocol::Protocol::Ipv6);
+                Self::write(self.packet_information, writer, &ethernet_packet.buffer)
+            }
+        }
+    }
     pub fn close(&self) -> io::Result<()> {
-        unsafe {
-            match &self.writer {
-                DeviceW::Tun(writer) => {
-                    libc::close(writer.as_raw_fd());
-                }
-                DeviceW::Tap((writer, _)) => {
-                    libc::close(writer.as_raw_fd());
+        // 多队列时writer也在queues中
+        let list = if self.queues.is_empty() {
+            std::slice::from_ref(&self.writer)
+        } else {
+            &self.queues[..]
+        };
+        for writer in list {
+            unsafe {
+                match writer {
+                    DeviceW::Tun(writer) => {
+                        libc::close(writer.as_raw_fd());
+                    }
+                    DeviceW::Tap((writer, _)) => {
+                        libc::close(writer.as_raw_fd());
+                    }
                 }
             }
         }
@@ -121,16 +264,64 @@ impl DeviceWriter {
     }
 }
 
-pub struct DeviceReader(Reader);
+pub struct DeviceReader {
+    reader: Reader,
+    /// 多队列网卡每个队列的读取端，单队列时为空
+    queues: Vec<Reader>,
+    /// 数据包前面有virtio头
+    #[cfg(target_os = "linux")]
+    vnet_hdr: bool,
+}
 
 impl DeviceReader {
     pub fn new(device: Reader) -> Self {
-        DeviceReader(device)
+        DeviceReader {
+            reader: device,
+            queues: Vec::new(),
+            #[cfg(target_os = "linux")]
+            vnet_hdr: false,
+        }
+    }
+    #[cfg(target_os = "linux")]
+    pub fn with_vnet_hdr(mut self) -> Self {
+        self.vnet_hdr = true;
+        self
+    }
+    /// 读取的数据前面有virtio头，需要用offload::Segments拆分
+    #[cfg(target_os = "linux")]
+    pub fn is_vnet_hdr(&self) -> bool {
+        self.vnet_hdr
+    }
+    pub fn with_queues(mut self, queues: Vec<Reader>) -> Self {
+        self.queues = queues;
+        self
+    }
+    /// 按队列拆分，每个队列单独读取
+    pub fn split(self) -> Vec<DeviceReader> {
+        if self.queues.is_empty() {
+            vec![self]
+        } else {
+            #[cfg(target_os = "linux")]
+            let vnet_hdr = self.vnet_hdr;
+            self.queues
+                .into_iter()
+                .map(|reader| {
+                    let device_reader = DeviceReader::new(reader);
+                    #[cfg(target_os = "linux")]
+                    let device_reader = if vnet_hdr {
+                        device_reader.with_vnet_hdr()
+                    } else {
+                        device_reader
+                    };
+                    device_reader
+                })
+                .collect()
+        }
     }
 }
 
 impl DeviceReader {
     pub fn read(&self, buf: &mut [u8]) -> io::Result<usize> {
-        self.0.read(buf)
+        self.reader.read(buf)
     }
 }
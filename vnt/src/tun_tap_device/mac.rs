@@ -2,7 +2,7 @@ use crate::tun_tap_device::linux_mac::DeviceW;
 use crate::tun_tap_device::{DeviceReader, DeviceType, DeviceWriter, DriverInfo};
 use parking_lot::Mutex;
 use std::io;
-use std::net::Ipv4Addr;
+use std::net::{Ipv4Addr, Ipv6Addr};
 use std::process::Command;
 use std::sync::Arc;
 use tun::Device;
@@ -44,6 +44,23 @@ impl DeviceWriter {
         )?;
         return Ok(());
     }
+    pub fn set_ipv6(&self, address: Ipv6Addr, prefix: u8) -> io::Result<()> {
+        let dev = self.lock.lock();
+        let up_eth_str: String = format!(
+            "ifconfig {} inet6 {} prefixlen {} alias",
+            dev.name(),
+            address,
+            prefix
+        );
+        let up_eth_out = Command::new("sh").arg("-c").arg(&up_eth_str).output()?;
+        if !up_eth_out.status.success() {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!("设置ipv6地址失败: cmd:{},out:{:?}", up_eth_str, up_eth_out),
+            ));
+        }
+        Ok(())
+    }
 }
 
 pub fn create_device(
@@ -53,6 +70,8 @@ pub fn create_device(
     gateway: Ipv4Addr,
     in_ips: Vec<(Ipv4Addr, Ipv4Addr)>,
     mtu: u16,
+    _queues: usize,
+    _offload: bool,
 ) -> io::Result<(DeviceWriter, DeviceReader, DriverInfo)> {
     match device_type {
         DeviceType::Tun => {}
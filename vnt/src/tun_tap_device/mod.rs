@@ -6,6 +6,8 @@ mod linux;
 mod linux_mac;
 #[cfg(target_os = "macos")]
 mod mac;
+#[cfg(target_os = "linux")]
+pub mod offload;
 #[cfg(target_os = "windows")]
 mod windows;
 
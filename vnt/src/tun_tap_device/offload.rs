@@ -0,0 +1,660 @@
+//! 虚拟网卡开启IFF_VNET_HDR后，读写的每个数据包前面都有virtio_net_hdr，
+//! 读取时内核会给出超过mtu的tcp大包(TSO)，按gso_size分段后再转发，
+//! 写入时把同一个流连续的tcp分段合并成大包，由内核负责分段和校验和
+
+use std::collections::HashMap;
+use std::io;
+
+pub use tun::platform::linux::sys::VIRTIO_NET_HDR_LEN;
+
+/// 合并写入时最多缓存的数据包数量
+pub const MAX_BATCH: usize = 64;
+/// 合并后的ip包最大长度
+const MAX_PACKET_LEN: usize = 65535;
+
+const VIRTIO_NET_HDR_F_NEEDS_CSUM: u8 = 1;
+const VIRTIO_NET_HDR_GSO_NONE: u8 = 0;
+const VIRTIO_NET_HDR_GSO_TCPV4: u8 = 1;
+const VIRTIO_NET_HDR_GSO_TCPV6: u8 = 4;
+const VIRTIO_NET_HDR_GSO_ECN: u8 = 0x80;
+
+const TCP_FIN: u8 = 0x01;
+const TCP_PSH: u8 = 0x08;
+const TCP_ACK: u8 = 0x10;
+const TCP_CWR: u8 = 0x80;
+const TCP_HEAD_LEN: usize = 20;
+/// tcp头中校验和的位置
+const TCP_CSUM_OFFSET: usize = 16;
+
+/// virtio_net_hdr，使用本机字节序
+#[derive(Copy, Clone, Debug, Default)]
+pub struct VirtioNetHdr {
+    flags: u8,
+    gso_type: u8,
+    hdr_len: u16,
+    gso_size: u16,
+    csum_start: u16,
+    csum_offset: u16,
+}
+
+impl VirtioNetHdr {
+    fn decode(buf: &[u8]) -> Self {
+        Self {
+            flags: buf[0],
+            gso_type: buf[1],
+            hdr_len: u16::from_ne_bytes([buf[2], buf[3]]),
+            gso_size: u16::from_ne_bytes([buf[4], buf[5]]),
+            csum_start: u16::from_ne_bytes([buf[6], buf[7]]),
+            csum_offset: u16::from_ne_bytes([buf[8], buf[9]]),
+        }
+    }
+    pub fn encode(&self) -> [u8; VIRTIO_NET_HDR_LEN] {
+        let mut buf = [0; VIRTIO_NET_HDR_LEN];
+        buf[0] = self.flags;
+        buf[1] = self.gso_type;
+        buf[2..4].copy_from_slice(&self.hdr_len.to_ne_bytes());
+        buf[4..6].copy_from_slice(&self.gso_size.to_ne_bytes());
+        buf[6..8].copy_from_slice(&self.csum_start.to_ne_bytes());
+        buf[8..10].copy_from_slice(&self.csum_offset.to_ne_bytes());
+        buf
+    }
+}
+
+/// 从网卡读取的一个数据，拆分成不超过mtu的ip包
+pub struct Segments<'a> {
+    packet: &'a [u8],
+    /// ip头长度
+    ip_len: usize,
+    /// ip头和tcp头的长度，为0时不需要分段
+    hdr_len: usize,
+    gso_size: usize,
+    /// 下一个分段在tcp载荷中的位置
+    offset: usize,
+    index: usize,
+}
+
+impl<'a> Segments<'a> {
+    /// buf为virtio头加上ip包，不需要分段的包在这里补全校验和
+    pub fn new(buf: &'a mut [u8]) -> io::Result<Self> {
+        if buf.len() < VIRTIO_NET_HDR_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "vnet hdr too short",
+            ));
+        }
+        let (hdr, packet) = buf.split_at_mut(VIRTIO_NET_HDR_LEN);
+        let hdr = VirtioNetHdr::decode(hdr);
+        match hdr.gso_type & !VIRTIO_NET_HDR_GSO_ECN {
+            VIRTIO_NET_HDR_GSO_NONE => {
+                if hdr.flags & VIRTIO_NET_HDR_F_NEEDS_CSUM != 0 {
+                    complete_checksum(packet, hdr.csum_start as usize, hdr.csum_offset as usize)?;
+                }
+                Ok(Self {
+                    packet,
+                    ip_len: 0,
+                    hdr_len: 0,
+                    gso_size: 0,
+                    offset: 0,
+                    index: 0,
+                })
+            }
+            VIRTIO_NET_HDR_GSO_TCPV4 | VIRTIO_NET_HDR_GSO_TCPV6 => {
+                let ip_len = ip_header_len(packet)?;
+                if packet.len() < ip_len + TCP_HEAD_LEN {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "tcp too short"));
+                }
+                let tcp_len = (packet[ip_len + 12] >> 4) as usize * 4;
+                if tcp_len < TCP_HEAD_LEN || packet.len() < ip_len + tcp_len {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "tcp too short"));
+                }
+                if hdr.gso_size == 0 {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, "gso_size is 0"));
+                }
+                Ok(Self {
+                    packet,
+                    ip_len,
+                    hdr_len: ip_len + tcp_len,
+                    gso_size: hdr.gso_size as usize,
+                    offset: 0,
+                    index: 0,
+                })
+            }
+            gso_type => Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("gso_type {}", gso_type),
+            )),
+        }
+    }
+    /// 下一个ip包写入buf，返回长度，buf长度不够时丢弃剩余的分段
+    pub fn next_segment(&mut self, buf: &mut [u8]) -> Option<usize> {
+        if self.hdr_len == 0 {
+            if self.index > 0 || self.packet.len() > buf.len() {
+                return None;
+            }
+            self.index += 1;
+            buf[..self.packet.len()].copy_from_slice(self.packet);
+            return Some(self.packet.len());
+        }
+        let payload = &self.packet[self.hdr_len..];
+        if self.index > 0 && self.offset >= payload.len() {
+            return None;
+        }
+        let seg_len = self.gso_size.min(payload.len() - self.offset);
+        let last = self.offset + seg_len >= payload.len();
+        let total = self.hdr_len + seg_len;
+        if total > buf.len() {
+            return None;
+        }
+        let ip_len = self.ip_len;
+        buf[..self.hdr_len].copy_from_slice(&self.packet[..self.hdr_len]);
+        buf[self.hdr_len..total].copy_from_slice(&payload[self.offset..self.offset + seg_len]);
+        let buf = &mut buf[..total];
+        if is_ipv4(buf) {
+            buf[2..4].copy_from_slice(&(total as u16).to_be_bytes());
+            let id = u16::from_be_bytes([buf[4], buf[5]]).wrapping_add(self.index as u16);
+            buf[4..6].copy_from_slice(&id.to_be_bytes());
+            buf[10..12].fill(0);
+            let csum = !fold(sum(&buf[..ip_len], 0));
+            buf[10..12].copy_from_slice(&csum.to_be_bytes());
+        } else {
+            buf[4..6].copy_from_slice(&((total - ip_len) as u16).to_be_bytes());
+        }
+        let pseudo = pseudo_header_sum(buf, ip_len);
+        let tcp = &mut buf[ip_len..];
+        let seq =
+            u32::from_be_bytes([tcp[4], tcp[5], tcp[6], tcp[7]]).wrapping_add(self.offset as u32);
+        tcp[4..8].copy_from_slice(&seq.to_be_bytes());
+        if !last {
+            tcp[13] &= !(TCP_FIN | TCP_PSH);
+        }
+        if self.index > 0 {
+            tcp[13] &= !TCP_CWR;
+        }
+        tcp[TCP_CSUM_OFFSET..TCP_CSUM_OFFSET + 2].fill(0);
+        let csum = !fold(sum(tcp, pseudo));
+        tcp[TCP_CSUM_OFFSET..TCP_CSUM_OFFSET + 2].copy_from_slice(&csum.to_be_bytes());
+        self.offset += seg_len;
+        self.index += 1;
+        Some(total)
+    }
+}
+
+/// 把同一个流连续的tcp分段合并成大包，返回virtio头和ip包，
+/// 其他数据包原样返回，同一个流的数据包保持原来的顺序。
+/// 合并后由内核计算校验和，数据已经过vnt协议的校验，不再检查分段的校验和
+pub fn coalesce(packets: Vec<Vec<u8>>) -> Vec<([u8; VIRTIO_NET_HDR_LEN], Vec<u8>)> {
+    let mut list: Vec<(Option<Group>, Vec<u8>)> = Vec::with_capacity(packets.len());
+    // 每个流可以继续追加的合并包
+    let mut flows: HashMap<FlowKey, usize> = HashMap::new();
+    for packet in packets {
+        let tcp = match TcpInfo::parse(&packet) {
+            Some(tcp) => tcp,
+            None => {
+                list.push((None, packet));
+                continue;
+            }
+        };
+        let key = tcp.flow_key(&packet);
+        if !tcp.can_coalesce() {
+            flows.remove(&key);
+            list.push((None, packet));
+            continue;
+        }
+        if let Some(index) = flows.get(&key).copied() {
+            let (group, head) = &mut list[index];
+            if let Some(group) = group {
+                if group.can_append(head, &tcp, &packet) {
+                    head.extend_from_slice(&packet[tcp.hdr_len()..]);
+                    group.count += 1;
+                    group.next_seq = group.next_seq.wrapping_add(tcp.payload_len as u32);
+                    if tcp.flags & TCP_PSH != 0 {
+                        head[group.ip_len + 13] |= TCP_PSH;
+                    }
+                    if tcp.flags & TCP_PSH != 0 || tcp.payload_len < group.gso_size {
+                        // 比gso_size短的只能是最后一个分段
+                        flows.remove(&key);
+                    }
+                    continue;
+                }
+            }
+        }
+        let group = Group {
+            ip_len: tcp.ip_len,
+            tcp_len: tcp.tcp_len,
+            gso_size: tcp.payload_len,
+            next_seq: tcp.seq.wrapping_add(tcp.payload_len as u32),
+            count: 1,
+        };
+        if tcp.flags & TCP_PSH == 0 {
+            flows.insert(key, list.len());
+        } else {
+            flows.remove(&key);
+        }
+        list.push((Some(group), packet));
+    }
+    list.into_iter()
+        .map(|(group, mut packet)| match group {
+            Some(group) if group.count > 1 => (group.finish(&mut packet), packet),
+            _ => ([0; VIRTIO_NET_HDR_LEN], packet),
+        })
+        .collect()
+}
+
+/// 源地址、目的地址、源端口、目的端口
+type FlowKey = ([u8; 32], u32);
+
+struct TcpInfo {
+    ip_len: usize,
+    tcp_len: usize,
+    payload_len: usize,
+    seq: u32,
+    flags: u8,
+}
+
+impl TcpInfo {
+    /// 只合并没有ip选项、没有分片的ipv4和没有扩展头的ipv6
+    fn parse(packet: &[u8]) -> Option<Self> {
+        if packet.is_empty() {
+            return None;
+        }
+        let ip_len = match packet[0] >> 4 {
+            4 => {
+                if packet.len() < 20
+                    || packet[0] & 0x0f != 5
+                    || packet[9] != 6
+                    || u16::from_be_bytes([packet[2], packet[3]]) as usize != packet.len()
+                    || u16::from_be_bytes([packet[6], packet[7]]) & 0x3fff != 0
+                {
+                    return None;
+                }
+                20
+            }
+            6 => {
+                if packet.len() < 40
+                    || packet[6] != 6
+                    || u16::from_be_bytes([packet[4], packet[5]]) as usize + 40 != packet.len()
+                {
+                    return None;
+                }
+                40
+            }
+            _ => return None,
+        };
+        if packet.len() < ip_len + TCP_HEAD_LEN {
+            return None;
+        }
+        let tcp = &packet[ip_len..];
+        let tcp_len = (tcp[12] >> 4) as usize * 4;
+        if tcp_len < TCP_HEAD_LEN || tcp.len() < tcp_len {
+            return None;
+        }
+        Some(Self {
+            ip_len,
+            tcp_len,
+            payload_len: tcp.len() - tcp_len,
+            seq: u32::from_be_bytes([tcp[4], tcp[5], tcp[6], tcp[7]]),
+            flags: tcp[13],
+        })
+    }
+    fn hdr_len(&self) -> usize {
+        self.ip_len + self.tcp_len
+    }
+    /// 有载荷并且只有ACK和PSH标志的才合并
+    fn can_coalesce(&self) -> bool {
+        self.payload_len > 0 && self.flags & TCP_ACK != 0 && self.flags & !(TCP_ACK | TCP_PSH) == 0
+    }
+    fn flow_key(&self, packet: &[u8]) -> FlowKey {
+        let mut addr = [0; 32];
+        if is_ipv4(packet) {
+            addr[..8].copy_from_slice(&packet[12..20]);
+        } else {
+            addr.copy_from_slice(&packet[8..40]);
+        }
+        let ports = &packet[self.ip_len..self.ip_len + 4];
+        (
+            addr,
+            u32::from_be_bytes([ports[0], ports[1], ports[2], ports[3]]),
+        )
+    }
+}
+
+/// 合并中的tcp包
+struct Group {
+    ip_len: usize,
+    tcp_len: usize,
+    /// 第一个分段的载荷长度
+    gso_size: usize,
+    next_seq: u32,
+    count: usize,
+}
+
+impl Group {
+    /// ip头除长度、标识和校验和外都相同，tcp头除序号、标志和校验和外都相同
+    fn can_append(&self, head: &[u8], tcp: &TcpInfo, packet: &[u8]) -> bool {
+        if tcp.ip_len != self.ip_len
+            || tcp.tcp_len != self.tcp_len
+            || tcp.seq != self.next_seq
+            || tcp.payload_len > self.gso_size
+            || head.len() + tcp.payload_len > MAX_PACKET_LEN
+        {
+            return false;
+        }
+        let same_ip = if is_ipv4(head) {
+            // tos、分片标志、ttl
+            head[1] == packet[1] && head[6] == packet[6] && head[8] == packet[8]
+        } else {
+            // 流量类别、流标签、跳数限制
+            head[..4] == packet[..4] && head[7] == packet[7]
+        };
+        let ip_len = self.ip_len;
+        let tcp_len = self.tcp_len;
+        same_ip
+            // 确认号
+            && head[ip_len + 8..ip_len + 12] == packet[ip_len + 8..ip_len + 12]
+            // 窗口
+            && head[ip_len + 14..ip_len + 16] == packet[ip_len + 14..ip_len + 16]
+            // 选项
+            && head[ip_len + TCP_HEAD_LEN..ip_len + tcp_len]
+                == packet[ip_len + TCP_HEAD_LEN..ip_len + tcp_len]
+    }
+    /// 更新ip头长度，tcp校验和只填伪首部，返回对应的virtio头
+    fn finish(&self, packet: &mut [u8]) -> [u8; VIRTIO_NET_HDR_LEN] {
+        let ip_len = self.ip_len;
+        let len = packet.len();
+        let gso_type = if is_ipv4(packet) {
+            packet[2..4].copy_from_slice(&(len as u16).to_be_bytes());
+            packet[10..12].fill(0);
+            let csum = !fold(sum(&packet[..ip_len], 0));
+            packet[10..12].copy_from_slice(&csum.to_be_bytes());
+            VIRTIO_NET_HDR_GSO_TCPV4
+        } else {
+            packet[4..6].copy_from_slice(&((len - ip_len) as u16).to_be_bytes());
+            VIRTIO_NET_HDR_GSO_TCPV6
+        };
+        let pseudo = fold(pseudo_header_sum(packet, ip_len));
+        let csum_pos = ip_len + TCP_CSUM_OFFSET;
+        packet[csum_pos..csum_pos + 2].copy_from_slice(&pseudo.to_be_bytes());
+        VirtioNetHdr {
+            flags: VIRTIO_NET_HDR_F_NEEDS_CSUM,
+            gso_type,
+            hdr_len: (ip_len + self.tcp_len) as u16,
+            gso_size: self.gso_size as u16,
+            csum_start: ip_len as u16,
+            csum_offset: TCP_CSUM_OFFSET as u16,
+        }
+        .encode()
+    }
+}
+
+fn is_ipv4(packet: &[u8]) -> bool {
+    packet[0] >> 4 == 4
+}
+
+fn ip_header_len(packet: &[u8]) -> io::Result<usize> {
+    match packet.first().map(|v| v >> 4) {
+        Some(4) if packet.len() >= 20 => {
+            let len = (packet[0] & 0x0f) as usize * 4;
+            if len < 20 || packet.len() < len {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "ipv4 head"));
+            }
+            Ok(len)
+        }
+        // 内核不会对带扩展头的ipv6做TSO
+        Some(6) if packet.len() >= 40 && packet[6] == 6 => Ok(40),
+        _ => Err(io::Error::new(io::ErrorKind::InvalidData, "not tcp")),
+    }
+}
+
+/// 内核已经把伪首部的和填在校验和字段，从csum_start开始累加后取反即可
+fn complete_checksum(packet: &mut [u8], csum_start: usize, csum_offset: usize) -> io::Result<()> {
+    let pos = csum_start + csum_offset;
+    if pos + 2 > packet.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "csum out of range",
+        ));
+    }
+    let csum = !fold(sum(&packet[csum_start..], 0));
+    // udp的校验和为0表示没有校验和
+    let csum = if csum == 0 && csum_offset == 6 {
+        0xffff
+    } else {
+        csum
+    };
+    packet[pos..pos + 2].copy_from_slice(&csum.to_be_bytes());
+    Ok(())
+}
+
+/// tcp伪首部的和，长度为ip头之后的全部数据，ipv4可能带选项，不能按ip头长度区分版本
+fn pseudo_header_sum(packet: &[u8], ip_len: usize) -> u64 {
+    let addr = if is_ipv4(packet) {
+        &packet[12..20]
+    } else {
+        &packet[8..40]
+    };
+    sum(addr, 6 + (packet.len() - ip_len) as u64)
+}
+
+fn sum(data: &[u8], mut acc: u64) -> u64 {
+    let mut chunks = data.chunks_exact(2);
+    for chunk in &mut chunks {
+        acc += u16::from_be_bytes([chunk[0], chunk[1]]) as u64;
+    }
+    if let [last] = chunks.remainder() {
+        acc += (*last as u64) << 8;
+    }
+    acc
+}
+
+fn fold(mut acc: u64) -> u16 {
+    while acc > 0xffff {
+        acc = (acc & 0xffff) + (acc >> 16);
+    }
+    acc as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GSO_SIZE: usize = 100;
+
+    /// ipv4时options为ip选项，ipv6时忽略
+    fn tcp_packet(v6: bool, options: &[u8], seq: u32, flags: u8, payload: &[u8]) -> Vec<u8> {
+        let ip_len = if v6 { 40 } else { 20 + options.len() };
+        let mut packet = vec![0u8; ip_len + TCP_HEAD_LEN + payload.len()];
+        let len = packet.len();
+        if v6 {
+            packet[0] = 0x60;
+            packet[4..6].copy_from_slice(&((len - 40) as u16).to_be_bytes());
+            packet[6] = 6;
+            packet[7] = 64;
+            packet[23] = 2;
+            packet[39] = 3;
+        } else {
+            packet[0] = 0x40 | (ip_len / 4) as u8;
+            packet[2..4].copy_from_slice(&(len as u16).to_be_bytes());
+            packet[4..6].copy_from_slice(&7u16.to_be_bytes());
+            packet[6] = 0x40;
+            packet[8] = 64;
+            packet[9] = 6;
+            packet[12..16].copy_from_slice(&[10, 26, 0, 2]);
+            packet[16..20].copy_from_slice(&[10, 26, 0, 3]);
+            packet[20..ip_len].copy_from_slice(options);
+            let csum = !fold(sum(&packet[..ip_len], 0));
+            packet[10..12].copy_from_slice(&csum.to_be_bytes());
+        }
+        let tcp = &mut packet[ip_len..];
+        tcp[..4].copy_from_slice(&[0x03, 0xe8, 0x07, 0xd0]);
+        tcp[4..8].copy_from_slice(&seq.to_be_bytes());
+        tcp[8..12].copy_from_slice(&1u32.to_be_bytes());
+        tcp[12] = 5 << 4;
+        tcp[13] = flags;
+        tcp[14..16].copy_from_slice(&[0xff, 0xff]);
+        tcp[TCP_HEAD_LEN..].copy_from_slice(payload);
+        let pseudo = pseudo(&packet);
+        let csum = !fold(sum(&packet[ip_len..], pseudo));
+        packet[ip_len + TCP_CSUM_OFFSET..ip_len + TCP_CSUM_OFFSET + 2]
+            .copy_from_slice(&csum.to_be_bytes());
+        packet
+    }
+
+    fn ip_len(packet: &[u8]) -> usize {
+        if is_ipv4(packet) {
+            (packet[0] & 0x0f) as usize * 4
+        } else {
+            40
+        }
+    }
+
+    /// 单独计算伪首部，不依赖被测试的实现
+    fn pseudo(packet: &[u8]) -> u64 {
+        let ip_len = ip_len(packet);
+        let addr = if packet[0] >> 4 == 4 {
+            &packet[12..20]
+        } else {
+            &packet[8..40]
+        };
+        sum(addr, 6 + (packet.len() - ip_len) as u64)
+    }
+
+    fn checksum_ok(packet: &[u8]) -> bool {
+        let ip_len = ip_len(packet);
+        if is_ipv4(packet) && fold(sum(&packet[..ip_len], 0)) != 0xffff {
+            return false;
+        }
+        fold(sum(&packet[ip_len..], pseudo(packet))) == 0xffff
+    }
+
+    fn seq(packet: &[u8]) -> u32 {
+        let tcp = &packet[ip_len(packet)..];
+        u32::from_be_bytes([tcp[4], tcp[5], tcp[6], tcp[7]])
+    }
+
+    fn tcp_flags(packet: &[u8]) -> u8 {
+        packet[ip_len(packet) + 13]
+    }
+
+    /// 模拟内核读取的TSO大包
+    fn super_packet(v6: bool, options: &[u8], flags: u8, payload: &[u8]) -> Vec<u8> {
+        let packet = tcp_packet(v6, options, 1000, flags, payload);
+        let ip_len = ip_len(&packet);
+        let hdr = VirtioNetHdr {
+            flags: VIRTIO_NET_HDR_F_NEEDS_CSUM,
+            gso_type: if v6 {
+                VIRTIO_NET_HDR_GSO_TCPV6
+            } else {
+                VIRTIO_NET_HDR_GSO_TCPV4
+            },
+            hdr_len: (ip_len + TCP_HEAD_LEN) as u16,
+            gso_size: GSO_SIZE as u16,
+            csum_start: ip_len as u16,
+            csum_offset: TCP_CSUM_OFFSET as u16,
+        };
+        let mut buf = hdr.encode().to_vec();
+        buf.extend_from_slice(&packet);
+        buf
+    }
+
+    fn split(mut buf: Vec<u8>) -> Vec<Vec<u8>> {
+        let mut segments = Segments::new(&mut buf).unwrap();
+        let mut list = Vec::new();
+        let mut segment = [0; 4096];
+        while let Some(len) = segments.next_segment(&mut segment) {
+            list.push(segment[..len].to_vec());
+        }
+        list
+    }
+
+    fn payload() -> Vec<u8> {
+        (0..250).map(|i| i as u8).collect()
+    }
+
+    #[test]
+    fn split_segments() {
+        let payload = payload();
+        for (v6, options) in [
+            (false, &[][..]),
+            (false, &[1, 1, 1, 0][..]),
+            (true, &[][..]),
+        ] {
+            let flags = TCP_ACK | TCP_PSH | TCP_FIN | TCP_CWR;
+            let list = split(super_packet(v6, options, flags, &payload));
+            assert_eq!(list.len(), 3);
+            let mut data = Vec::new();
+            for (index, packet) in list.iter().enumerate() {
+                assert!(checksum_ok(packet));
+                assert_eq!(seq(packet), 1000 + (index * GSO_SIZE) as u32);
+                if !v6 {
+                    assert_eq!(
+                        u16::from_be_bytes([packet[2], packet[3]]) as usize,
+                        packet.len()
+                    );
+                    assert_eq!(u16::from_be_bytes([packet[4], packet[5]]), 7 + index as u16);
+                }
+                data.extend_from_slice(&packet[ip_len(packet) + TCP_HEAD_LEN..]);
+            }
+            assert_eq!(data, payload);
+            // CWR只保留在第一个分段，PSH和FIN只保留在最后一个分段
+            assert_eq!(tcp_flags(&list[0]), TCP_ACK | TCP_CWR);
+            assert_eq!(tcp_flags(&list[1]), TCP_ACK);
+            assert_eq!(tcp_flags(&list[2]), TCP_ACK | TCP_PSH | TCP_FIN);
+        }
+    }
+
+    #[test]
+    fn coalesce_round_trip() {
+        let payload = payload();
+        for v6 in [false, true] {
+            let list = split(super_packet(v6, &[], TCP_ACK | TCP_PSH, &payload));
+            let rs = coalesce(list.clone());
+            assert_eq!(rs.len(), 1);
+            let (hdr, packet) = &rs[0];
+            let decoded = VirtioNetHdr::decode(hdr);
+            assert_eq!(decoded.gso_size as usize, GSO_SIZE);
+            assert_eq!(decoded.flags, VIRTIO_NET_HDR_F_NEEDS_CSUM);
+            assert_eq!(seq(packet), 1000);
+            assert_eq!(tcp_flags(packet), TCP_ACK | TCP_PSH);
+            if !v6 {
+                assert_eq!(fold(sum(&packet[..20], 0)), 0xffff);
+                assert_eq!(u16::from_be_bytes([packet[4], packet[5]]), 7);
+            }
+            // 再次拆分和合并前的分段相同
+            let mut buf = hdr.to_vec();
+            buf.extend_from_slice(packet);
+            assert_eq!(split(buf), list);
+        }
+    }
+
+    #[test]
+    fn coalesce_flags() {
+        let payload = payload();
+        // 带CWR的第一个分段不合并，后面两个合并
+        let list = split(super_packet(
+            false,
+            &[],
+            TCP_ACK | TCP_CWR | TCP_PSH,
+            &payload,
+        ));
+        let rs = coalesce(list.clone());
+        assert_eq!(rs.len(), 2);
+        assert_eq!(rs[0].0, [0; VIRTIO_NET_HDR_LEN]);
+        assert_eq!(rs[0].1, list[0]);
+        assert_eq!(
+            VirtioNetHdr::decode(&rs[1].0).gso_type,
+            VIRTIO_NET_HDR_GSO_TCPV4
+        );
+        assert_eq!(seq(&rs[1].1), 1000 + GSO_SIZE as u32);
+        // 带FIN的分段不合并
+        let list = split(super_packet(true, &[], TCP_ACK | TCP_FIN, &payload));
+        let rs = coalesce(list.clone());
+        assert_eq!(rs.len(), 2);
+        assert_eq!(rs[1].0, [0; VIRTIO_NET_HDR_LEN]);
+        assert_eq!(rs[1].1, list[2]);
+        // PSH之后的分段开始新的合并
+        let mut list = split(super_packet(false, &[], TCP_ACK | TCP_PSH, &payload));
+        list.extend(split(super_packet(false, &[], TCP_ACK, &payload)));
+        let rs = coalesce(list);
+        assert_eq!(rs.len(), 2);
+    }
+}
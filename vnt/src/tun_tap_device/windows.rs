@@ -3,7 +3,7 @@ use libloading::Library;
 use packet::ethernet;
 use packet::ethernet::packet::EthernetPacket;
 use parking_lot::Mutex;
-use std::net::Ipv4Addr;
+use std::net::{Ipv4Addr, Ipv6Addr};
 use std::os::windows::process::CommandExt;
 use std::sync::Arc;
 use std::time::Duration;
@@ -94,6 +94,41 @@ impl DeviceWriter {
         }
         Ok(())
     }
+    ///写入ipv6数据，头部必须留14字节，给tap写入以太网帧头
+    pub fn write_ipv6(&self, buf: &mut [u8]) -> io::Result<()> {
+        match self.device.as_ref() {
+            Device::Tun(dev) => {
+                let mut packet = dev.allocate_send_packet((buf.len() - 14) as u16)?;
+                packet.bytes_mut().copy_from_slice(&buf[14..]);
+                dev.send_packet(packet);
+            }
+            Device::Tap((dev, mac)) => {
+                //取源ipv6的后4字节生成mac，和邻居发现的应答保持一致
+                let source_mac = [
+                    buf[14 + 20],
+                    buf[14 + 21],
+                    buf[14 + 22],
+                    buf[14 + 23],
+                    !mac[5],
+                    234,
+                ];
+                let mut ethernet_packet = EthernetPacket::unchecked(buf);
+                ethernet_packet.set_source(&source_mac);
+                ethernet_packet.set_destination(mac);
+                ethernet_packet.set_protocol(ethernet::protocol::Protocol::Ipv6);
+                dev.write(&ethernet_packet.buffer)?;
+            }
+        }
+        Ok(())
+    }
+    pub fn set_ipv6(&self, address: Ipv6Addr, prefix: u8) -> io::Result<()> {
+        let _guard = self.lock.lock();
+        let dev: &dyn IFace = match self.device.as_ref() {
+            Device::Tun(dev) => dev as &dyn IFace,
+            Device::Tap((dev, _)) => dev as &dyn IFace,
+        };
+        dev.set_ipv6(address, prefix)
+    }
     pub fn change_ip(
         &self,
         address: Ipv4Addr,
@@ -348,6 +383,8 @@ pub fn create_device(
     gateway: Ipv4Addr,
     in_ips: Vec<(Ipv4Addr, Ipv4Addr)>,
     mtu: u16,
+    _queues: usize,
+    _offload: bool,
 ) -> io::Result<(DeviceWriter, DeviceReader, DriverInfo)> {
     match device_type {
         DeviceType::Tun => create_tun(address, netmask, gateway, in_ips, mtu),
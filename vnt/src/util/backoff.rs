@@ -0,0 +1,30 @@
+use std::time::Duration;
+
+use rand::Rng;
+
+/// 指数退避，每次失败后间隔翻倍，加入少量随机抖动避免多个客户端同时重连
+pub struct Backoff {
+    min: Duration,
+    max: Duration,
+    current: Duration,
+}
+
+impl Backoff {
+    pub fn new(min: Duration, max: Duration) -> Self {
+        Self {
+            min,
+            max,
+            current: min,
+        }
+    }
+    /// 返回本次需要等待的时间
+    pub fn next_delay(&mut self) -> Duration {
+        let current = self.current;
+        self.current = (self.current * 2).min(self.max);
+        let jitter = rand::thread_rng().gen_range(0..=current.as_millis() as u64 / 5);
+        current + Duration::from_millis(jitter)
+    }
+    pub fn reset(&mut self) {
+        self.current = self.min;
+    }
+}
@@ -1 +1,2 @@
+pub mod backoff;
 pub mod wait;
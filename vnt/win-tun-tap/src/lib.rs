@@ -6,7 +6,7 @@ mod route;
 mod tap;
 mod tun;
 use std::io;
-use std::net::Ipv4Addr;
+use std::net::{Ipv4Addr, Ipv6Addr};
 pub use tap::TapDevice;
 pub use tun::*;
 
@@ -32,6 +32,8 @@ pub trait IFace {
     fn set_name(&self, new_name: &str) -> io::Result<()>;
     /// 设置ip
     fn set_ip(&self, address: Ipv4Addr, mask: Ipv4Addr) -> io::Result<()>;
+    /// 设置ipv6
+    fn set_ipv6(&self, address: Ipv6Addr, prefix: u8) -> io::Result<()>;
     /// 设置路由
     fn add_route(
         &self,
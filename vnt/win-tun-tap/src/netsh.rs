@@ -1,5 +1,5 @@
 use std::io;
-use std::net::Ipv4Addr;
+use std::net::{Ipv4Addr, Ipv6Addr};
 use std::os::windows::process::CommandExt;
 
 /// 设置网卡名称
@@ -40,6 +40,27 @@ pub fn set_interface_ip(index: u32, address: &Ipv4Addr, netmask: &Ipv4Addr) -> i
     Ok(())
 }
 
+/// 设置网卡ipv6
+pub fn set_interface_ipv6(index: u32, address: &Ipv6Addr, prefix: u8) -> io::Result<()> {
+    let set_address = format!(
+        "netsh interface ipv6 add address {} {}/{} store=active",
+        index, address, prefix,
+    );
+    let out = std::process::Command::new("cmd")
+        .creation_flags(0x08000000)
+        .arg("/C")
+        .arg(&set_address)
+        .output()?;
+    if !out.status.success() {
+        log::error!("cmd={:?},out={:?}", set_address, out);
+        return Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("设置ipv6地址失败: {:?}", out),
+        ));
+    }
+    Ok(())
+}
+
 pub fn set_interface_mtu(index: u32, mtu: u16) -> io::Result<()> {
     let set_mtu = format!(
         "netsh interface ipv4 set subinterface {}  mtu={} store=persistent",
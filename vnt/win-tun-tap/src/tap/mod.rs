@@ -1,4 +1,4 @@
-use std::net::Ipv4Addr;
+use std::net::{Ipv4Addr, Ipv6Addr};
 use std::{io, time};
 
 use winapi::shared::ifdef::NET_LUID;
@@ -146,6 +146,11 @@ impl IFace for TapDevice {
         netsh::set_interface_ip(index, &address, &mask)
     }
 
+    fn set_ipv6(&self, address: Ipv6Addr, prefix: u8) -> io::Result<()> {
+        let index = self.get_index()?;
+        netsh::set_interface_ipv6(index, &address, prefix)
+    }
+
     fn add_route(
         &self,
         dest: Ipv4Addr,
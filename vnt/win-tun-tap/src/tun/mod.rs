@@ -1,5 +1,5 @@
 use std::io;
-use std::net::Ipv4Addr;
+use std::net::{Ipv4Addr, Ipv6Addr};
 
 use winapi::um::{handleapi, synchapi, winbase, winnt};
 
@@ -210,6 +210,10 @@ impl IFace for TunDevice {
         netsh::set_interface_ip(self.get_index()?, &address, &mask)
     }
 
+    fn set_ipv6(&self, address: Ipv6Addr, prefix: u8) -> io::Result<()> {
+        netsh::set_interface_ipv6(self.get_index()?, &address, prefix)
+    }
+
     fn add_route(
         &self,
         dest: Ipv4Addr,